@@ -0,0 +1,89 @@
+//! Time the commands people run most against a large database, by default 100,000 files with 5,000 tags.
+//!
+//! Run with `cargo bench`. Set `FTAG_BENCH_FILES` and `FTAG_BENCH_TAGS` to try other sizes.
+
+use camino::Utf8PathBuf;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::collections::HashSet;
+use std::time::Instant;
+use std::{env, fs};
+
+use ftag::ftag::{self as core, CaseMode, FtagError, TagQuery};
+use ftag::store::{self, Backend};
+
+/// How many tags each file is given.
+const TAGS_PER_FILE: usize = 5;
+
+/// Read a size from the environment, or use `default`.
+fn size(var: &str, default: usize) -> usize {
+    env::var(var).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+/// Make a database of `files` empty files, each given a few of `tags` tags, in a new directory it then enters.
+fn populate(files: usize, tags: usize) -> Result<(), FtagError> {
+    let dir = env::temp_dir().join(format!("ftag-bench-{}", std::process::id()));
+    fs::create_dir_all(dir.join("files"))?;
+    env::set_current_dir(&dir)?;
+    store::init_store(Backend::Sqlite)?;
+
+    // A fixed sequence spreads the tags the same way on every run
+    let mut seed: u64 = 1;
+    let mut next = move || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) as usize
+    };
+    let mut store = store::open_store()?;
+    store.batch(|tx| {
+        for file in 0..files {
+            let path = format!("files/{}.txt", file);
+            fs::write(&path, "")?;
+            let file_tags: HashSet<String> = (0..TAGS_PER_FILE).map(|_| format!("tag{}", next() % tags)).collect();
+            tx.set_tags(&path, &file_tags)?;
+        }
+        Ok(())
+    })?;
+    drop(store);
+
+    // Every file exists, so get the first prune out of the way instead of timing it
+    core::prune_db()
+}
+
+/// Parse `words` the way `find` would.
+fn queries(words: &[&str]) -> Vec<TagQuery> {
+    core::parse_queries(&words.iter().map(|word| word.to_string()).collect::<Vec<_>>(), CaseMode::Smart, false).unwrap()
+}
+
+fn commands(c: &mut Criterion) {
+    let files = size("FTAG_BENCH_FILES", 100_000);
+    let tags = size("FTAG_BENCH_TAGS", 5_000);
+    let start = Instant::now();
+    populate(files, tags).unwrap();
+    println!("Made {} files with {} tags in {:.1?}", files, tags, start.elapsed());
+
+    let one = queries(&["tag1"]);
+    let two = queries(&["tag1", "tag2"]);
+    let glob = queries(&["tag42*"]);
+    let exclude = queries(&["tag3"]);
+    let added = Utf8PathBuf::from("files/0.txt");
+
+    let mut group = c.benchmark_group(format!("{} files, {} tags", files, tags));
+    group.bench_function("find one tag", |b| b.iter(|| core::find_tags(&one, &[], false).unwrap()));
+    group.bench_function("find two tags", |b| b.iter(|| core::find_tags(&two, &[], false).unwrap()));
+    group.bench_function("find any of two tags", |b| b.iter(|| core::find_tags(&two, &[], true).unwrap()));
+    group.bench_function("find a glob", |b| b.iter(|| core::find_tags(&glob, &[], false).unwrap()));
+    group.bench_function("find excluding a tag", |b| b.iter(|| core::find_tags(&one, &exclude, false).unwrap()));
+    group.bench_function("count one tag", |b| b.iter(|| core::count_tags(&one, &[], false).unwrap()));
+    group.bench_function("list every tag", |b| b.iter(|| core::get_global_tags().unwrap()));
+    group.bench_function("add a tag", |b| b.iter(|| core::add_tags(&added, vec!["bench".to_string()]).unwrap()));
+    group.finish();
+
+    fs::remove_dir_all(env::current_dir().unwrap()).unwrap();
+}
+
+criterion_group! {
+    name = benches;
+    // Searches on the full database take tens of milliseconds, so fewer samples keep a run to a few minutes
+    config = Criterion::default().sample_size(20);
+    targets = commands
+}
+criterion_main!(benches);
@@ -0,0 +1,29 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Just enough of the ftag module for validate.rs to build on its own
+mod ftag {
+    pub const TAG_SEPARATOR: char = '/';
+
+    #[derive(Debug)]
+    pub enum FtagError {
+        InvalidTagError(#[allow(dead_code)] String),
+    }
+}
+
+#[allow(dead_code)]
+#[path = "../../src/validate.rs"]
+mod validate;
+
+use validate::{check_tag, TagValidation};
+
+fuzz_target!(|tag: &str| {
+    assert_eq!(check_tag(tag, TagValidation::Off).unwrap(), tag);
+
+    // A normalized tag is valid, and normalizing it again changes nothing
+    if let Ok(normalized) = check_tag(tag, TagValidation::Normalize) {
+        assert_eq!(check_tag(&normalized, TagValidation::Reject).unwrap(), normalized);
+        assert_eq!(check_tag(&normalized, TagValidation::Normalize).unwrap(), normalized);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/minijson.rs"]
+mod minijson;
+
+fuzz_target!(|json: &str| {
+    // Anything that parses must come back the same after writing it out again
+    if let Ok(tags) = minijson::parse_tags(json) {
+        assert_eq!(minijson::parse_tags(&minijson::serialize_tags(&tags)).unwrap(), tags);
+    }
+});
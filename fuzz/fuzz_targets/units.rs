@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/units.rs"]
+mod units;
+
+use units::{ByteSize, TimeSpan};
+
+fuzz_target!(|text: &str| {
+    if let Ok(size) = text.parse::<ByteSize>() {
+        assert_eq!(size.to_string().parse::<ByteSize>().unwrap(), size);
+    }
+    if let Ok(span) = text.parse::<TimeSpan>() {
+        assert_eq!(span.to_string().parse::<TimeSpan>().unwrap(), span);
+    }
+});
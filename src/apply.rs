@@ -0,0 +1,350 @@
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::ftag::{self, FtagError};
+use crate::store::{self, TagStore};
+use crate::{git, paths, settings};
+
+/// A single change to make to the database.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Add tags to a path
+    Add { path: Utf8PathBuf, tags: Vec<String> },
+    /// Remove tags from a path
+    Rm { path: Utf8PathBuf, tags: Vec<String> },
+    /// Swap one tag of a path for another
+    Rename { path: Utf8PathBuf, old: String, new: String },
+}
+
+impl Operation {
+    /// Path the operation changes.
+    pub fn path(&self) -> &Utf8PathBuf {
+        match self {
+            Operation::Add { path, .. } | Operation::Rm { path, .. } | Operation::Rename { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    /// Write the operation the way it would appear in a line-based script.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words: Vec<&str> = match self {
+            Operation::Add { path, tags } => ["add", path.as_str()].into_iter().chain(tags.iter().map(String::as_str)).collect(),
+            Operation::Rm { path, tags } => ["rm", path.as_str()].into_iter().chain(tags.iter().map(String::as_str)).collect(),
+            Operation::Rename { path, old, new } => vec!["rename", path.as_str(), old, new],
+        };
+        write!(f, "{}", shell_words::join(words))
+    }
+}
+
+/// Parse one line of a script, like `add "some file.jpg" photo red-rocks`.
+///
+/// # Failure
+///
+/// Returns `Err` if the line is not a valid operation.
+fn parse_line(line: &str) -> Result<Operation, String> {
+    let words = shell_words::split(line).map_err(|err| err.to_string())?;
+    let Some((op, args)) = words.split_first() else {
+        return Err("expected an operation".to_string());
+    };
+
+    match (op.as_str(), args) {
+        ("add", [path, tags @ ..]) if !tags.is_empty() => Ok(Operation::Add { path: path.into(), tags: tags.to_vec() }),
+        ("rm", [path, tags @ ..]) if !tags.is_empty() => Ok(Operation::Rm { path: path.into(), tags: tags.to_vec() }),
+        ("rename", [path, old, new]) => Ok(Operation::Rename { path: path.into(), old: old.clone(), new: new.clone() }),
+        ("add" | "rm", _) => Err(format!("expected {} PATH TAG...", op)),
+        ("rename", _) => Err("expected rename PATH OLD NEW".to_string()),
+        _ => Err(format!("unknown operation {}, expected add, rm, or rename", op)),
+    }
+}
+
+/// Parse a script of operations.
+///
+/// A script is either a JSON array like `[{"op": "add", "path": "a.jpg", "tags": ["photo"]}]`,
+/// or one operation per line like `add a.jpg photo`, quoted like a shell command.
+/// Blank lines and lines starting with `#` are ignored.
+///
+/// # Failure
+///
+/// Returns `Err` naming the first line that could not be parsed.
+pub fn parse_operations(text: &str) -> Result<Vec<Operation>, FtagError> {
+    if text.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(text)?);
+    }
+
+    let mut operations = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let operation = parse_line(line).map_err(|reason| FtagError::ArgumentError(format!("Line {}: {}", number + 1, reason)))?;
+        operations.push(operation);
+    }
+
+    Ok(operations)
+}
+
+/// Carry out a single operation in an open store.
+///
+/// # Failure
+///
+/// Returns `Err` if the operation could not be carried out.
+fn apply_operation(store: &mut dyn TagStore, operation: &Operation) -> Result<(), FtagError> {
+    match operation {
+        Operation::Add { path, tags } => {
+            ftag::add_tags_in(store, path, tags.clone())?;
+        },
+        Operation::Rm { path, tags } => {
+            ftag::remove_tags_in(store, path, tags.clone())?;
+        },
+        Operation::Rename { path, old, new } => {
+            // Compare against the old tag in the same form it would have been stored in
+            let old = ftag::normalize_tags_in(store, vec![old.clone()])?.remove(0);
+            if !store.get_tags(&paths::normalize(path.as_str()))?.unwrap_or_default().contains(&old) {
+                return Err(FtagError::ArgumentError(format!("Tag {} not found", old)));
+            }
+            ftag::remove_tags_in(store, path, vec![old])?;
+            ftag::add_tags_in(store, path, vec![new.clone()])?;
+        },
+    }
+    Ok(())
+}
+
+/// Carry out every operation in one transaction, so either all of them happen or none do.
+///
+/// Returns the paths whose tags changed, sorted, as they are stored in the database.
+///
+/// * `operations` - Operations to carry out, in order
+/// * `dry_run` - Check that every operation would succeed without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` describing the first operation that failed, in which case nothing was changed.
+pub fn apply_operations(operations: &[Operation], dry_run: bool) -> Result<Vec<String>, FtagError> {
+    let mut store = store::open_store()?;
+
+    let changes = |tx: &mut dyn TagStore| {
+        // Operations can undo each other, so compare each path's tags from before the first one with the end result
+        let mut before: BTreeMap<String, HashSet<String>> = BTreeMap::new();
+        for operation in operations {
+            let path = paths::normalize(operation.path().as_str());
+            if let Entry::Vacant(entry) = before.entry(path) {
+                let tags = tx.get_tags(entry.key())?.unwrap_or_default();
+                entry.insert(tags);
+            }
+        }
+
+        for (number, operation) in operations.iter().enumerate() {
+            if let Err(err) = apply_operation(tx, operation) {
+                let reason = match err.is_not_found() {
+                    true => "Filepath does not exist!".to_string(),
+                    false => err.to_string(),
+                };
+                return Err(FtagError::ArgumentError(format!("Operation {} ({}) failed, nothing was changed: {}", number + 1, operation, reason)));
+            }
+        }
+
+        let mut changed = vec![];
+        for (path, tags) in before {
+            if tx.get_tags(&path)?.unwrap_or_default() != tags {
+                changed.push(path);
+            }
+        }
+        Ok(changed)
+    };
+    let changed = match dry_run {
+        true => return store.dry_run(changes),
+        false => store.batch(changes)?,
+    };
+
+    // Remember what each file looked like when it was tagged, once the tags are safely saved
+    if settings::get_bool_setting_in(&*store, settings::GIT_AWARE)? {
+        let mut recorded = HashSet::new();
+        for operation in operations {
+            if let Operation::Add { path, .. } | Operation::Rename { path, .. } = operation {
+                if recorded.insert(path) {
+                    git::record_blob(path.as_str())?;
+                }
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+/// What happened when a mapping of tag names was applied.
+#[derive(Debug, Default)]
+pub struct MappingReport {
+    /// Paths of the files whose tags changed, sorted
+    pub files: Vec<String>,
+    /// Files where renaming left fewer tags than before, with each new tag and the tags merged into it
+    pub merged: Vec<(String, String, Vec<String>)>,
+    /// Old tag names no file had
+    pub unused: Vec<String>,
+}
+
+/// Parse a mapping of tag names with one `OLD<TAB>NEW` pair per line.
+///
+/// Blank lines and lines starting with `#` are ignored.
+///
+/// # Failure
+///
+/// Returns `Err` naming the first line that is not a pair of tag names.
+pub fn parse_mapping(text: &str) -> Result<Vec<(String, String)>, FtagError> {
+    let mut mapping = vec![];
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split('\t').map(str::trim).collect::<Vec<_>>()[..] {
+            [old, new] if !old.is_empty() && !new.is_empty() => mapping.push((old.to_string(), new.to_string())),
+            _ => return Err(FtagError::ArgumentError(format!("Line {}: expected OLD and NEW separated by a tab", number + 1))),
+        }
+    }
+
+    Ok(mapping)
+}
+
+/// Parse the words given to `merge-tags`, like `todo to-do into TODO`, into a mapping from each old tag to the new one.
+///
+/// # Failure
+///
+/// Returns `Err` if the words don't end with `into NEW` after at least one old tag.
+pub fn parse_merge(words: &[String]) -> Result<Vec<(String, String)>, FtagError> {
+    match words {
+        [old @ .., into, new] if !old.is_empty() && into.eq_ignore_ascii_case("into") => {
+            Ok(old.iter().map(|old| (old.clone(), new.clone())).collect())
+        },
+        _ => Err(FtagError::ArgumentError("Expected the tags to merge, then into and the tag to merge them into, like todo to-do into TODO".to_string())),
+    }
+}
+
+/// Rename tags across the whole database in one transaction.
+///
+/// Every file is renamed from its tags as they were before, so a mapping can swap
+/// two names or shift a chain of them without the order of the lines mattering.
+///
+/// * `mapping` - Pairs of old and new tag names
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if one old name is mapped to two different new names, a new name
+/// breaks the naming rules, or errors occur when interacting with the database.
+/// Nothing is changed in that case.
+pub fn apply_mapping(mapping: &[(String, String)], dry_run: bool) -> Result<MappingReport, FtagError> {
+    let mut store = store::open_store()?;
+
+    // Bring every name into the form it is stored in before comparing them
+    let (old, new): (Vec<String>, Vec<String>) = mapping.iter().cloned().unzip();
+    let old = ftag::normalize_tags_in(&*store, old)?;
+    let new = ftag::validate_tags_in(&*store, new)?;
+
+    let mut renames: HashMap<String, String> = HashMap::new();
+    for (old, new) in old.into_iter().zip(new) {
+        match renames.get(&old) {
+            Some(existing) if *existing != new => {
+                return Err(FtagError::ArgumentError(format!("Tag {} is mapped to both {} and {}", old, existing, new)));
+            },
+            _ => {
+                renames.insert(old, new);
+            },
+        }
+    }
+
+    match dry_run {
+        true => store.dry_run(|tx| rename_everywhere(tx, &renames)),
+        false => store.batch(|tx| rename_everywhere(tx, &renames)),
+    }
+}
+
+/// Rename tags in every file of an open store.
+///
+/// # Failure
+///
+/// Returns `Err` if errors occur when interacting with the store.
+fn rename_everywhere(store: &mut dyn TagStore, renames: &HashMap<String, String>) -> Result<MappingReport, FtagError> {
+    let mut report = MappingReport::default();
+    let mut used = HashSet::new();
+    for (path, tags) in store.all_rows()? {
+        used.extend(tags.iter().filter(|tag| renames.contains_key(*tag)).cloned());
+
+        // Gather which old tags end up under each new name
+        let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for tag in &tags {
+            let renamed = renames.get(tag).unwrap_or(tag);
+            sources.entry(renamed.clone()).or_default().push(tag.clone());
+        }
+        let renamed: HashSet<String> = sources.keys().cloned().collect();
+        if renamed == tags {
+            continue;
+        }
+
+        for (new, mut merged) in sources {
+            if merged.len() > 1 {
+                merged.sort();
+                report.merged.push((path.clone(), new, merged));
+            }
+        }
+        store.set_tags(&path, &renamed)?;
+        report.files.push(path);
+    }
+
+    report.files.sort();
+    report.merged.sort();
+    report.unused = renames.keys().filter(|old| !used.contains(*old)).cloned().collect();
+    report.unused.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        let word = any::<String>().prop_filter("words can't be empty", |word| !word.is_empty());
+        prop_oneof![
+            (word.clone(), prop::collection::vec(word.clone(), 1..4))
+                .prop_map(|(path, tags)| Operation::Add { path: path.into(), tags }),
+            (word.clone(), prop::collection::vec(word.clone(), 1..4))
+                .prop_map(|(path, tags)| Operation::Rm { path: path.into(), tags }),
+            (word.clone(), word.clone(), word)
+                .prop_map(|(path, old, new)| Operation::Rename { path: path.into(), old, new }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(script in any::<String>()) {
+            let _ = parse_operations(&script);
+        }
+
+        #[test]
+        fn merge_never_panics(words in prop::collection::vec(any::<String>(), 0..5)) {
+            let _ = parse_merge(&words);
+        }
+
+        #[test]
+        fn lines_round_trip(operation in operation()) {
+            prop_assume!(!operation.to_string().contains(['\n', '\r']));
+            prop_assert_eq!(parse_line(&operation.to_string()).unwrap(), operation);
+        }
+    }
+    #[test]
+    fn parses_merge_examples() {
+        let words = |text: &str| text.split(' ').map(str::to_string).collect::<Vec<_>>();
+        let pair = |old: &str, new: &str| (old.to_string(), new.to_string());
+
+        assert_eq!(parse_merge(&words("todo to-do into TODO")).unwrap(), [pair("todo", "TODO"), pair("to-do", "TODO")]);
+        assert_eq!(parse_merge(&words("a INTO b")).unwrap(), [pair("a", "b")]);
+        assert!(parse_merge(&words("into b")).is_err());
+        assert!(parse_merge(&words("a b c")).is_err());
+        assert!(parse_merge(&[]).is_err());
+    }
+}
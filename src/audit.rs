@@ -0,0 +1,182 @@
+use camino::Utf8Path;
+
+use crate::ftag::FtagError;
+
+/// Permissions and ownership that files are expected to have.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PermPolicy {
+    /// Exact permission bits, like `0o640`
+    pub mode: Option<u32>,
+    /// User id of the owner
+    pub owner: Option<u32>,
+    /// Group id of the group
+    pub group: Option<u32>,
+}
+
+impl PermPolicy {
+    /// Parse a policy like `mode=640,owner=me,group=staff`.
+    ///
+    /// Owners and groups may be names or numeric ids, and `me` stands for the current user or their primary group.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the policy is malformed or names a user or group that doesn't exist.
+    pub fn parse(text: &str) -> Result<Self, FtagError> {
+        let mut policy = PermPolicy::default();
+
+        for item in text.split(',').filter(|item| !item.is_empty()) {
+            let Some((key, value)) = item.split_once('=') else {
+                return Err(FtagError::ArgumentError(format!("Expected key=value in policy, found {}", item)));
+            };
+
+            match key {
+                "mode" => {
+                    let mode = u32::from_str_radix(value, 8)
+                        .map_err(|_| FtagError::ArgumentError(format!("Mode {} is not an octal number", value)))?;
+                    policy.mode = Some(mode);
+                },
+                "owner" => policy.owner = Some(os::lookup_user(value)?),
+                "group" => policy.group = Some(os::lookup_group(value)?),
+                _ => return Err(FtagError::ArgumentError(format!("Unknown policy key {}, expected mode, owner, or group", key))),
+            }
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Check a single file against `policy`, returning a description of every way it deviates.
+///
+/// # Failure
+///
+/// Returns `Err` if the file's metadata can't be read.
+pub fn audit_path(path: &Utf8Path, policy: &PermPolicy) -> Result<Vec<String>, FtagError> {
+    let (mode, owner, group) = os::ownership(path)?;
+    let mut problems = vec![];
+
+    if let Some(expected) = policy.mode {
+        if mode != expected {
+            problems.push(format!("mode is {:o}, expected {:o}", mode, expected));
+        }
+    }
+    if let Some(expected) = policy.owner {
+        if owner != expected {
+            problems.push(format!("owner is {}, expected {}", os::user_name(owner), os::user_name(expected)));
+        }
+    }
+    if let Some(expected) = policy.group {
+        if group != expected {
+            problems.push(format!("group is {}, expected {}", os::group_name(group), os::group_name(expected)));
+        }
+    }
+
+    Ok(problems)
+}
+
+#[cfg(unix)]
+mod os {
+    use camino::Utf8Path;
+    use std::ffi::{CStr, CString};
+    use std::os::unix::fs::MetadataExt;
+
+    use crate::ftag::FtagError;
+
+    /// Return the permission bits, owner, and group of a file.
+    pub fn ownership(path: &Utf8Path) -> Result<(u32, u32, u32), FtagError> {
+        let metadata = path.metadata()?;
+        Ok((metadata.mode() & 0o7777, metadata.uid(), metadata.gid()))
+    }
+
+    /// Turn a user name, numeric id, or `me` into a user id.
+    pub fn lookup_user(name: &str) -> Result<u32, FtagError> {
+        if name == "me" {
+            // SAFETY: geteuid can't fail
+            return Ok(unsafe { libc::geteuid() });
+        }
+        if let Ok(uid) = name.parse() {
+            return Ok(uid);
+        }
+
+        let c_name = CString::new(name).map_err(|_| FtagError::ArgumentError(format!("Unknown user {}", name)))?;
+        // SAFETY: c_name is NUL terminated, and the result is checked for null before reading
+        let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+        if entry.is_null() {
+            return Err(FtagError::ArgumentError(format!("Unknown user {}", name)));
+        }
+        Ok(unsafe { (*entry).pw_uid })
+    }
+
+    /// Turn a group name, numeric id, or `me` into a group id.
+    pub fn lookup_group(name: &str) -> Result<u32, FtagError> {
+        if name == "me" {
+            // SAFETY: getegid can't fail
+            return Ok(unsafe { libc::getegid() });
+        }
+        if let Ok(gid) = name.parse() {
+            return Ok(gid);
+        }
+
+        let c_name = CString::new(name).map_err(|_| FtagError::ArgumentError(format!("Unknown group {}", name)))?;
+        // SAFETY: c_name is NUL terminated, and the result is checked for null before reading
+        let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+        if entry.is_null() {
+            return Err(FtagError::ArgumentError(format!("Unknown group {}", name)));
+        }
+        Ok(unsafe { (*entry).gr_gid })
+    }
+
+    /// Return the name of a user, falling back to their id.
+    pub fn user_name(uid: u32) -> String {
+        // SAFETY: the result is checked for null, and pw_name is a NUL terminated string
+        unsafe {
+            let entry = libc::getpwuid(uid);
+            if entry.is_null() {
+                return uid.to_string();
+            }
+            CStr::from_ptr((*entry).pw_name).to_string_lossy().into_owned()
+        }
+    }
+
+    /// Return the name of a group, falling back to its id.
+    pub fn group_name(gid: u32) -> String {
+        // SAFETY: the result is checked for null, and gr_name is a NUL terminated string
+        unsafe {
+            let entry = libc::getgrgid(gid);
+            if entry.is_null() {
+                return gid.to_string();
+            }
+            CStr::from_ptr((*entry).gr_name).to_string_lossy().into_owned()
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod os {
+    use camino::Utf8Path;
+
+    use crate::ftag::FtagError;
+
+    fn unsupported() -> FtagError {
+        FtagError::ArgumentError("Permission audits are only supported on Unix".to_string())
+    }
+
+    pub fn ownership(_path: &Utf8Path) -> Result<(u32, u32, u32), FtagError> {
+        Err(unsupported())
+    }
+
+    pub fn lookup_user(_name: &str) -> Result<u32, FtagError> {
+        Err(unsupported())
+    }
+
+    pub fn lookup_group(_name: &str) -> Result<u32, FtagError> {
+        Err(unsupported())
+    }
+
+    pub fn user_name(uid: u32) -> String {
+        uid.to_string()
+    }
+
+    pub fn group_name(gid: u32) -> String {
+        gid.to_string()
+    }
+}
@@ -0,0 +1,290 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use globset::{GlobBuilder, GlobMatcher};
+use std::collections::HashSet;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use std::{io, thread};
+
+use crate::ftag::{self, FtagError, TagChange};
+use crate::{migrate, paths, pdf, roots, sandbox};
+
+/// Name of the file holding the auto-tagging rules, next to the database.
+pub const RULES_FILE: &str = ".ftagrules";
+
+/// Directory in ftag's config directory holding tagger plugins.
+pub const TAGGERS_DIR: &str = "taggers";
+
+/// A glob pattern along with the tags every file matching it should have.
+#[derive(Debug)]
+pub struct Rule {
+    /// Tags to add to matching files
+    tags: Vec<String>,
+    matcher: GlobMatcher,
+    /// Patterns without a `/` match the file name in any directory, like `*.pdf`
+    name_only: bool,
+}
+
+impl Rule {
+    /// Check whether a path relative to the database root matches this rule.
+    fn matches(&self, path: &Utf8Path) -> bool {
+        match self.name_only {
+            true => path.file_name().is_some_and(|name| self.matcher.is_match(name)),
+            false => self.matcher.is_match(path.as_str()),
+        }
+    }
+}
+
+/// Parse rules with one `PATTERN -> TAG...` per line, like `screenshots/** -> screenshot`.
+///
+/// `→` can be used in place of `->`. Blank lines and lines starting with `#` are ignored.
+///
+/// # Failure
+///
+/// Returns `Err` naming the first line that is not a rule or has an invalid pattern.
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, FtagError> {
+    let mut rules = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line_error = |reason: String| FtagError::ArgumentError(format!("{} line {}: {}", RULES_FILE, number + 1, reason));
+        let Some((pattern, tags)) = line.split_once("->").or_else(|| line.split_once('→')) else {
+            return Err(line_error("expected PATTERN -> TAG...".to_string()));
+        };
+        let pattern = pattern.trim();
+        let tags: Vec<String> = tags.split_whitespace().map(str::to_string).collect();
+        if pattern.is_empty() || tags.is_empty() {
+            return Err(line_error("expected PATTERN -> TAG...".to_string()));
+        }
+
+        let matcher = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| line_error(format!("invalid pattern: {}", err)))?
+            .compile_matcher();
+
+        rules.push(Rule { tags, matcher, name_only: !pattern.contains('/') });
+    }
+
+    Ok(rules)
+}
+
+/// Read the rules from the rules file at the database root.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no rules file, it can't be read, or it contains an invalid rule.
+pub fn read_rules() -> Result<Vec<Rule>, FtagError> {
+    match std::fs::read_to_string(RULES_FILE) {
+        Ok(text) => parse_rules(&text),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Err(FtagError::ConfigError(format!("No rules found, write them to {} next to the database", RULES_FILE)))
+        },
+        Err(err) => Err(FtagError::IoError(err)),
+    }
+}
+
+/// Something that looks at a file and suggests tags for it, which `autotag` then adds.
+///
+/// The rules file is one, and plugins run as separate programs are another. Code using ftag as a library can
+/// implement it to feed in tags from its own analysis.
+pub trait Tagger {
+    /// Name to mention when the tagger fails.
+    fn name(&self) -> &str;
+
+    /// Suggest tags for a file, which may be none.
+    ///
+    /// * `path` - Path of the file, relative to the database root, which is the current directory
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the file could not be looked at. Other files are still tagged.
+    fn suggest(&self, path: &Utf8Path) -> Result<Vec<String>, FtagError>;
+}
+
+impl Tagger for Vec<Rule> {
+    fn name(&self) -> &str {
+        RULES_FILE
+    }
+
+    fn suggest(&self, path: &Utf8Path) -> Result<Vec<String>, FtagError> {
+        Ok(self.iter().filter(|rule| rule.matches(path)).flat_map(|rule| rule.tags.clone()).collect())
+    }
+}
+
+/// A plugin, which is a program in ftag's `taggers` config directory suggesting tags for one file at a time.
+///
+/// It is run from the database root with the file's path, relative to the root, as its only argument. It prints
+/// each tag it suggests on a line of its own and exits successfully, printing nothing to suggest no tags.
+#[derive(Debug)]
+pub struct PluginTagger {
+    name: String,
+    program: Utf8PathBuf,
+}
+
+impl PluginTagger {
+    /// Find the plugin called `name`, at `$XDG_CONFIG_HOME/ftag/taggers/NAME` or `~/.config/ftag/taggers/NAME`.
+    ///
+    /// Plugins are kept with the user rather than next to the database, for the same reason as hooks.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if there is no such plugin, or ftag's config directory can't be found.
+    pub fn find(name: &str) -> Result<PluginTagger, FtagError> {
+        let dir = roots::user_dir("XDG_CONFIG_HOME", ".config")?.join(TAGGERS_DIR);
+        let program = dir.join(name);
+        if name.contains(['/', '\\']) || !program.is_file() {
+            return Err(FtagError::ConfigError(format!("No tagger called {}, put one in {}", name, dir)));
+        }
+        Ok(PluginTagger { name: name.to_string(), program })
+    }
+}
+
+impl Tagger for PluginTagger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn suggest(&self, path: &Utf8Path) -> Result<Vec<String>, FtagError> {
+        sandbox::check_exec(self.program.as_str())?;
+        let output = Command::new(&self.program)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|err| FtagError::ConfigError(format!("Could not run {}: {}", self.program, err)))?;
+        if !output.status.success() {
+            return Err(FtagError::ConfigError(format!("{} exited unsuccessfully: {}", self.program, output.status)));
+        }
+
+        let text = String::from_utf8(output.stdout).map_err(|_| FtagError::ConfigError(format!("{} printed tags that aren't valid UTF-8", self.program)))?;
+        Ok(text.lines().map(str::trim).filter(|line| !line.is_empty()).map(str::to_string).collect())
+    }
+}
+
+/// Gather the taggers `autotag` asks for tags: the rules file, then each named plugin in order.
+///
+/// The rules file may be left out when plugins are named. `pdf` names the built-in tagger reading PDF metadata,
+/// rather than a plugin.
+///
+/// * `plugins` - Names of the plugins to run
+///
+/// # Failure
+///
+/// Returns `Err` if a plugin doesn't exist or running programs has been forbidden, or the rules file is invalid or
+/// missing with no plugins named.
+pub fn taggers(plugins: &[String]) -> Result<Vec<Box<dyn Tagger>>, FtagError> {
+    let mut taggers: Vec<Box<dyn Tagger>> = vec![];
+    if plugins.is_empty() || Utf8Path::new(RULES_FILE).exists() {
+        taggers.push(Box::new(read_rules()?));
+    }
+    for name in plugins {
+        if name == pdf::TAGGER_NAME {
+            taggers.push(Box::new(pdf::PdfTagger));
+            continue;
+        }
+        let plugin = PluginTagger::find(name)?;
+        sandbox::check_exec(plugin.program.as_str())?;
+        taggers.push(Box::new(plugin));
+    }
+    Ok(taggers)
+}
+
+/// Tag every file beneath `dir` with the tags suggested for it, all in one transaction.
+///
+/// Returns how the tags of each file changed, in path order. A tagger that fails for a file is warned about,
+/// and the file still gets the tags the other taggers suggested.
+///
+/// * `dir` - Directory to look for files in, relative to the database root
+/// * `taggers` - Taggers to ask, such as from `taggers`
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if `dir` is not a directory, a suggested tag is rejected, or errors occur when interacting with the
+/// database. Nothing is changed in that case.
+pub fn autotag(dir: &Utf8Path, taggers: &[Box<dyn Tagger>], dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    if !dir.is_dir() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    ftag::add_tags_to_all(suggested_tags(taggers, files_beneath(dir)?), dry_run)
+}
+
+/// Keep tagging the files that appear beneath `dir` with the tags suggested for them, looking for them every
+/// `interval` until ftag is stopped.
+///
+/// Files already there when watching starts are left alone, so run `autotag` first to tag those. The taggers are
+/// gathered again whenever new files turn up, so the rules can be changed without watching again. Files that
+/// couldn't be tagged, like when the database is busy, are tried again the next time.
+///
+/// * `dir` - Directory to watch, relative to the database root
+/// * `plugins` - Names of the plugins to run along with the rules, as for `taggers`
+/// * `interval` - How long to wait between looking for new files
+/// * `report` - Called with how the tags of each file changed whenever new files are tagged
+///
+/// # Failure
+///
+/// Returns `Err` if `dir` is not a directory or stops being one, or the taggers can't be gathered.
+pub fn watch(dir: &Utf8Path, plugins: &[String], interval: Duration, mut report: impl FnMut(&[TagChange])) -> Result<(), FtagError> {
+    if !dir.is_dir() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    taggers(plugins)?;
+
+    let mut known: HashSet<Utf8PathBuf> = files_beneath(dir)?.into_iter().collect();
+    tracing::debug!("Watching {} files beneath {}", known.len(), dir);
+    loop {
+        thread::sleep(interval);
+        let files = files_beneath(dir)?;
+        let new: Vec<Utf8PathBuf> = files.iter().filter(|file| !known.contains(*file)).cloned().collect();
+        if new.is_empty() {
+            known = files.into_iter().collect();
+            continue;
+        }
+
+        match ftag::add_tags_to_all(suggested_tags(&taggers(plugins)?, new), false) {
+            Ok(changes) => {
+                known = files.into_iter().collect();
+                if !changes.is_empty() {
+                    report(&changes);
+                }
+            },
+            Err(err) => tracing::warn!("Could not tag new files beneath {}, trying again soon: {}", dir, err),
+        }
+    }
+}
+
+/// Collect every file beneath `dir` that could be tagged, as paths relative to the database root.
+///
+/// # Failure
+///
+/// Returns `Err` if a directory can't be read.
+fn files_beneath(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, FtagError> {
+    let mut files = vec![];
+    migrate::walk_files(dir, &mut files)?;
+    Ok(files
+        .into_iter()
+        .map(|file| Utf8PathBuf::from(paths::normalize(file.as_str())))
+        .filter(|file| !ftag::is_database_path(file))
+        .collect())
+}
+
+/// Pair each file any tagger suggests tags for with every tag suggested for it.
+fn suggested_tags(taggers: &[Box<dyn Tagger>], files: Vec<Utf8PathBuf>) -> Vec<(Utf8PathBuf, Vec<String>)> {
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let mut tags = vec![];
+            for tagger in taggers {
+                match tagger.suggest(&file) {
+                    Ok(suggested) => tags.extend(suggested),
+                    Err(err) => tracing::warn!("{} could not suggest tags for {}: {}", tagger.name(), file, err),
+                }
+            }
+            (!tags.is_empty()).then_some((file, tags))
+        })
+        .collect()
+}
@@ -0,0 +1,110 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::Local;
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName};
+
+use crate::ftag::{get_db_path, FtagError};
+use crate::store;
+
+/// Directory next to the database where backups go unless told otherwise.
+pub const DEFAULT_BACKUP_DIR: &str = ".ftag-backups";
+
+/// Start of the name of every backup, so rotation never touches other files.
+const BACKUP_PREFIX: &str = "ftag-";
+
+/// End of the name of every backup.
+const BACKUP_SUFFIX: &str = ".db";
+
+/// Return the backups in a directory, oldest first.
+///
+/// Backups are named after when they were taken, so sorting their names sorts them by age.
+///
+/// # Failure
+///
+/// Returns `Err` if the directory exists but could not be read.
+pub fn list_backups(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, FtagError> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut backups = vec![];
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_name().starts_with(BACKUP_PREFIX) && entry.file_name().ends_with(BACKUP_SUFFIX) {
+            backups.push(entry.into_path());
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// Copy the database into a new backup in a directory, created if needed.
+///
+/// # Failure
+///
+/// Returns `Err` if the directory could not be created or the backup could not be written.
+fn snapshot(conn: &Connection, dir: &Utf8Path) -> Result<Utf8PathBuf, FtagError> {
+    std::fs::create_dir_all(dir)?;
+
+    let name = format!("{}{}{}", BACKUP_PREFIX, Local::now().format("%Y%m%d-%H%M%S%.3f"), BACKUP_SUFFIX);
+    let path = dir.join(name);
+    conn.backup(DatabaseName::Main, &path, None)?;
+    Ok(path)
+}
+
+/// Delete all but the newest `keep` backups in a directory, always keeping at least the newest one.
+///
+/// # Failure
+///
+/// Returns `Err` if the directory could not be read or a backup could not be deleted.
+fn rotate(dir: &Utf8Path, keep: usize) -> Result<(), FtagError> {
+    let backups = list_backups(dir)?;
+    let extra = backups.len().saturating_sub(keep.max(1));
+    for old in &backups[..extra] {
+        tracing::debug!("Removing old backup {}", old);
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Take a snapshot of the database into a directory, then delete all but the newest `keep` backups there.
+///
+/// The snapshot goes through SQLite's backup API, so it is consistent even if another ftag is writing at the time.
+///
+/// * `dir` - Directory to keep backups in, created if needed
+/// * `keep` - Number of backups to keep, counting the new one
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or the backup could not be written.
+pub fn backup(dir: &Utf8Path, keep: usize) -> Result<Utf8PathBuf, FtagError> {
+    let conn = store::open_sqlite("Backups")?;
+    let path = snapshot(&conn, dir)?;
+    rotate(dir, keep)?;
+    Ok(path)
+}
+
+/// Replace the database with a backup, after backing up the current database so this can be undone.
+///
+/// * `source` - Backup to restore
+/// * `dir` - Directory to back up the current database into
+/// * `keep` - Number of backups to keep there
+///
+/// # Failure
+///
+/// Returns `Err` if the backup does not exist, there is no database, it doesn't use the sqlite backend, or the database
+/// could not be backed up or restored. The database is left as it was if the restore fails.
+pub fn restore(source: &Utf8Path, dir: &Utf8Path, keep: usize) -> Result<Utf8PathBuf, FtagError> {
+    if !source.is_file() {
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+
+    let conn = store::open_sqlite("Backups")?;
+    let saved = snapshot(&conn, dir)?;
+    // Restoring needs a connection of its own, which the shared one picks the changes up from afterwards
+    store::open_connection(&get_db_path())?.restore(DatabaseName::Main, source, None::<fn(Progress)>)?;
+
+    // Only rotate once restored, since the backup being restored may be the oldest
+    rotate(dir, keep)?;
+    Ok(saved)
+}
@@ -0,0 +1,111 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+
+use crate::ftag::{self, FtagError};
+use crate::store;
+
+/// Character that marks a bundle where tags are expected, like `@photo2024`.
+pub const BUNDLE_PREFIX: char = '@';
+
+/// Open the database, creating the `bundles` table if this database predates it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or the table could not be created.
+fn open_bundle_db() -> Result<store::Handle, FtagError> {
+    let conn = store::open_sqlite("Tag bundles")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bundles (
+            name    TEXT PRIMARY KEY,
+            tags    TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// Save tags under a bundle name, replacing any bundle already saved with it.
+///
+/// The tags are checked and stored like tags added to a file, so the bundle can always be applied.
+///
+/// # Failure
+///
+/// Returns `Err` if a tag is rejected, there is no database, or errors occur when interacting with the database.
+pub fn create_bundle(name: &str, tags: Vec<String>) -> Result<(), FtagError> {
+    let name = name.strip_prefix(BUNDLE_PREFIX).unwrap_or(name);
+    let tags: HashSet<String> = ftag::validate_tags_in(&*store::open_store()?, tags)?.into_iter().collect();
+    let conn = open_bundle_db()?;
+    conn.execute("INSERT OR REPLACE INTO bundles(name, tags) VALUES (?, ?)", params![name, ftag::serialize_tags(&tags)?])?;
+    Ok(())
+}
+
+/// Return the tags of a bundle, sorted.
+///
+/// # Failure
+///
+/// Returns `Err` if no bundle has that name, there is no database, or errors occur when interacting with the database.
+fn get_bundle(conn: &Connection, name: &str) -> Result<Vec<String>, FtagError> {
+    let json: Option<String> = conn.query_row("SELECT tags FROM bundles WHERE name = ?", params![name], |row| row.get(0)).optional()?;
+    let Some(json) = json else {
+        return Err(FtagError::ArgumentError(format!("No bundle named {}", name)));
+    };
+
+    let mut tags: Vec<String> = ftag::parse_tags(&json)?.into_iter().collect();
+    tags.sort();
+    Ok(tags)
+}
+
+/// Return every bundle with its sorted tags, sorted by name.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn all_bundles() -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let conn = open_bundle_db()?;
+    let names: Vec<String> = conn.prepare("SELECT name FROM bundles ORDER BY name")?.query_map((), |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+    let mut bundles = vec![];
+    for name in names {
+        let tags = get_bundle(&conn, &name)?;
+        bundles.push((name, tags));
+    }
+    Ok(bundles)
+}
+
+/// Forget a bundle. Files already tagged with it keep their tags.
+///
+/// # Failure
+///
+/// Returns `Err` if no bundle has that name, there is no database, or errors occur when interacting with the database.
+pub fn delete_bundle(name: &str) -> Result<(), FtagError> {
+    let name = name.strip_prefix(BUNDLE_PREFIX).unwrap_or(name);
+    let conn = open_bundle_db()?;
+    match conn.execute("DELETE FROM bundles WHERE name = ?", params![name])? {
+        0 => Err(FtagError::ArgumentError(format!("No bundle named {}", name))),
+        _ => Ok(()),
+    }
+}
+
+/// Replace every `@name` among `tags` with the tags of that bundle.
+///
+/// The database is only opened if there is a bundle to look up, so other backends can still add plain tags.
+///
+/// # Failure
+///
+/// Returns `Err` if a bundle doesn't exist, there is no database, or errors occur when interacting with the database.
+pub fn expand_bundles(tags: Vec<String>) -> Result<Vec<String>, FtagError> {
+    if !tags.iter().any(|tag| tag.starts_with(BUNDLE_PREFIX)) {
+        return Ok(tags);
+    }
+
+    let conn = open_bundle_db()?;
+    let mut expanded = vec![];
+    for tag in tags {
+        match tag.strip_prefix(BUNDLE_PREFIX) {
+            Some(name) => expanded.extend(get_bundle(&conn, name)?),
+            None => expanded.push(tag),
+        }
+    }
+    Ok(expanded)
+}
@@ -0,0 +1,226 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::backup;
+use crate::ftag::FtagError;
+use crate::settings;
+use crate::store::{self, Backend, RowLocation};
+
+/// Kinds of problem `check` looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProblemKind {
+    /// The tags of the row can't be read
+    BadJson,
+    /// Another row already holds the same path
+    Duplicate,
+    /// The path no longer exists on disk
+    Missing,
+    /// The row has no tags
+    Empty,
+}
+
+impl ProblemKind {
+    /// Short name of the problem, for scripts to match on.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProblemKind::BadJson => "bad-json",
+            ProblemKind::Duplicate => "duplicate",
+            ProblemKind::Missing => "missing",
+            ProblemKind::Empty => "empty",
+        }
+    }
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Something wrong with one row of the database.
+#[derive(Debug)]
+pub struct Problem {
+    /// What is wrong
+    pub kind: ProblemKind,
+    /// Where the row is kept
+    pub location: RowLocation,
+    /// Path the row is for, if it could be read
+    pub path: Option<String>,
+    /// Explanation of the problem
+    pub detail: String,
+}
+
+/// Results of checking the whole database.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Number of rows checked
+    pub rows: usize,
+    /// Every problem found, in the order the rows are kept
+    pub problems: Vec<Problem>,
+}
+
+/// Check every row of the database for unreadable tags, repeated paths, paths missing from disk, and empty tag sets.
+///
+/// Unlike other commands, a damaged row doesn't stop the check, so every problem is found in one go.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or it could not be read at all.
+pub fn check() -> Result<CheckReport, FtagError> {
+    let rows = store::raw_rows()?;
+    let mut report = CheckReport { rows: rows.len(), problems: vec![] };
+
+    // Remember where each path was first seen, so later rows with it can point back there
+    let mut seen: HashMap<String, RowLocation> = HashMap::new();
+    for row in rows {
+        let mut problem = |kind: ProblemKind, detail: String| {
+            report.problems.push(Problem { kind, location: row.location.clone(), path: row.path.clone(), detail });
+        };
+
+        match &row.tags {
+            Err(err) => problem(ProblemKind::BadJson, format!("the tags can't be read: {}", err)),
+            Ok(tags) if tags.is_empty() => problem(ProblemKind::Empty, "the path has no tags".to_string()),
+            Ok(_) => {},
+        }
+
+        let Some(path) = &row.path else {
+            continue;
+        };
+        match seen.get(path) {
+            Some(first) => problem(ProblemKind::Duplicate, format!("the path is already in {}", first)),
+            None => {
+                if !Utf8Path::new(path).exists() {
+                    problem(ProblemKind::Missing, "the path no longer exists".to_string());
+                }
+                seen.insert(path.clone(), row.location.clone());
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// One change `repair` makes to the database.
+#[derive(Debug)]
+pub struct Fix {
+    /// What was done: `salvaged`, `dropped`, or `merged`
+    pub action: &'static str,
+    /// Where the row was kept
+    pub location: RowLocation,
+    /// Path the row was for, if it could be read
+    pub path: Option<String>,
+    /// Explanation of the change
+    pub detail: String,
+}
+
+/// Results of repairing the database.
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// Every change made, or that would be made in a dry run
+    pub fixes: Vec<Fix>,
+    /// Backup taken before anything was changed, if one was needed
+    pub backup: Option<Utf8PathBuf>,
+}
+
+/// Read whatever path and tags can still be found in the text of a damaged row.
+///
+/// Tags are recovered from a bare list of strings, or from a list under any spelling of a `tags` key.
+fn salvage(text: &str) -> (Option<String>, Option<HashSet<String>>) {
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return (None, None);
+    };
+    let strings = |value: &Value| -> Option<HashSet<String>> {
+        value.as_array()?.iter().map(|tag| tag.as_str().map(str::to_string)).collect()
+    };
+
+    match &value {
+        Value::Array(_) => (None, strings(&value)),
+        Value::Object(object) => {
+            let field = |name: &str| object.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value);
+            (field("path").and_then(Value::as_str).map(str::to_string), field("tags").and_then(strings))
+        },
+        _ => (None, None),
+    }
+}
+
+/// Fix the rows `check` finds unreadable or repeated, taking a backup of a SQLite database first.
+///
+/// Unreadable tags are salvaged where possible and dropped otherwise. Repeated paths are merged into the first row
+/// holding them, keeping the tags of every copy. Deleted files and empty tag sets are left alone, since `prune` and
+/// `rm` already deal with them.
+///
+/// * `dry_run` - Work out what would change without changing anything
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, the backup could not be taken, or errors occur when changing the database.
+pub fn repair(dry_run: bool) -> Result<RepairReport, FtagError> {
+    let mut report = RepairReport::default();
+    let mut dropped = vec![];
+    let mut changes: Vec<(String, Option<HashSet<String>>)> = vec![];
+
+    // Tags of each path so far, along with where its first row is kept
+    let mut known: HashMap<String, (RowLocation, HashSet<String>)> = HashMap::new();
+    for row in store::raw_rows()? {
+        let (salvaged_path, salvaged_tags) = match &row.tags {
+            Ok(_) => (None, None),
+            Err(_) => salvage(row.text.as_deref().unwrap_or_default()),
+        };
+        let readable = row.tags.is_ok();
+        let path = row.path.clone().or(salvaged_path);
+        let tags = row.tags.ok().or(salvaged_tags);
+        let mut fix = |action: &'static str, detail: String| {
+            report.fixes.push(Fix { action, location: row.location.clone(), path: path.clone(), detail });
+        };
+
+        let Some(path) = path.clone() else {
+            fix("dropped", "neither the path nor the tags can be read".to_string());
+            dropped.push(row.location);
+            continue;
+        };
+
+        match known.get_mut(&path) {
+            // Later copies of a path are always dropped, after handing over their tags
+            Some((first, merged)) => {
+                let tags = tags.unwrap_or_default();
+                fix("merged", format!("{} tags merged into {}", tags.len(), first));
+                merged.extend(tags);
+                changes.retain(|(changed, _)| *changed != path);
+                changes.push((path.clone(), Some(merged.clone())));
+                dropped.push(row.location);
+
+                // Files are written again under the name of their path, which may not be the name the first one had
+                if matches!(first, RowLocation::File(_)) && !dropped.contains(first) {
+                    dropped.push(first.clone());
+                }
+            },
+            None => {
+                if !readable {
+                    match &tags {
+                        Some(tags) => fix("salvaged", format!("kept {} tags", tags.len())),
+                        None => fix("dropped", "the tags can't be read".to_string()),
+                    }
+                    changes.push((path.clone(), tags.clone()));
+                    // A file named for some other path would be left behind when the path is written again
+                    if let RowLocation::File(_) = row.location {
+                        dropped.push(row.location.clone());
+                    }
+                }
+                known.insert(path, (row.location, tags.unwrap_or_default()));
+            },
+        }
+    }
+
+    if dry_run || report.fixes.is_empty() {
+        return Ok(report);
+    }
+
+    if store::current_backend()? == Backend::Sqlite {
+        let keep = settings::get_number_setting(settings::BACKUP_KEEP)?;
+        report.backup = Some(backup::backup(Utf8Path::new(backup::DEFAULT_BACKUP_DIR), keep)?);
+    }
+    store::repair_rows(&dropped, &changes)?;
+    Ok(report)
+}
@@ -0,0 +1,191 @@
+use clap::Command;
+
+/// Shell to print a completion script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    /// Bash, loaded with `source <(ftag completions bash)`
+    Bash,
+    /// Zsh, loaded with `source <(ftag completions zsh)` after `compinit`
+    Zsh,
+    /// Fish, loaded with `ftag completions fish | source`
+    Fish,
+}
+
+/// Bash completion. Commands are found by skipping options before them, and tags come from `ftag __complete`.
+const BASH_SCRIPT: &str = r#"_ftag() {
+    local cur=${COMP_WORDS[COMP_CWORD]} command="" command_index=0 i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        if [[ ${COMP_WORDS[i]} != -* ]]; then
+            command=${COMP_WORDS[i]}
+            command_index=$i
+            break
+        fi
+    done
+    if [[ -z $command ]]; then
+        COMPREPLY=($(compgen -W "__COMMANDS__" -- "$cur"))
+        return
+    fi
+    if [[ $cur == -* ]]; then
+        return
+    fi
+
+    local IFS=$'\n'
+    case $command in
+        add|rm|set)
+            if ((COMP_CWORD == command_index + 1)); then
+                COMPREPLY=($(compgen -f -- "$cur"))
+            elif [[ $command == rm ]]; then
+                COMPREPLY=($(ftag __complete --path "${COMP_WORDS[command_index + 1]}" -- "$cur" 2>/dev/null))
+            else
+                COMPREPLY=($(ftag __complete -- "$cur" 2>/dev/null))
+            fi
+            ;;
+        find|cd|merge-tags|tag-matching)
+            COMPREPLY=($(ftag __complete -- "$cur" 2>/dev/null))
+            ;;
+        *)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            ;;
+    esac
+}
+complete -o filenames -F _ftag ftag
+"#;
+
+/// Zsh completion, which describes each command and completes tags from `ftag __complete`.
+const ZSH_SCRIPT: &str = r#"#compdef ftag
+_ftag() {
+    local -a commands tags
+    commands=(
+__COMMANDS__
+    )
+    local command_index=2
+    while [[ $command_index -lt $CURRENT && ${words[command_index]} == -* ]]; do
+        ((command_index++))
+    done
+    if ((CURRENT == command_index)); then
+        _describe 'command' commands
+        return
+    fi
+
+    case ${words[command_index]} in
+        add|rm|set)
+            if ((CURRENT == command_index + 1)); then
+                _files
+                return
+            elif [[ ${words[command_index]} == rm ]]; then
+                tags=(${(f)"$(ftag __complete --path ${(Q)words[command_index + 1]} 2>/dev/null)"})
+            else
+                tags=(${(f)"$(ftag __complete 2>/dev/null)"})
+            fi
+            compadd -a tags
+            ;;
+        find|cd|merge-tags|tag-matching)
+            tags=(${(f)"$(ftag __complete 2>/dev/null)"})
+            compadd -a tags
+            ;;
+        *)
+            _files
+            ;;
+    esac
+}
+compdef _ftag ftag
+"#;
+
+/// Fish completion, which completes tags from `ftag __complete`.
+const FISH_SCRIPT: &str = r#"function __ftag_tags
+    set -l words (commandline -opc)
+    set -l rm_index (contains -i -- rm $words)
+    if test -n "$rm_index"; and test (count $words) -gt $rm_index
+        ftag __complete --path $words[(math $rm_index + 1)] 2>/dev/null
+    else
+        ftag __complete 2>/dev/null
+    end
+end
+
+function __ftag_wants_path
+    set -l words (commandline -opc)
+    test (count $words) -ge 1; and contains -- $words[-1] add rm set
+end
+
+complete -c ftag -f
+__COMMANDS__
+complete -c ftag -n '__fish_seen_subcommand_from add rm set; and __ftag_wants_path' -F
+complete -c ftag -n '__fish_seen_subcommand_from add rm set; and not __ftag_wants_path' -a '(__ftag_tags)'
+complete -c ftag -n '__fish_seen_subcommand_from find cd merge-tags tag-matching' -a '(__ftag_tags)'
+"#;
+
+/// Bash and zsh function that runs `ftag cd` and changes to the directory it prints, passing anything else to ftag.
+const POSIX_INIT: &str = r#"ftag() {
+    if [ "$1" = cd ]; then
+        shift
+        local dir
+        dir=$(command ftag cd "$@") && cd -- "$dir"
+    else
+        command ftag "$@"
+    fi
+}
+"#;
+
+/// Fish function that runs `ftag cd` and changes to the directory it prints, passing anything else to ftag.
+const FISH_INIT: &str = r#"function ftag --wraps ftag
+    if test "$argv[1]" = cd
+        set -l dir (command ftag cd $argv[2..-1]); and cd $dir
+    else
+        command ftag $argv
+    end
+end
+"#;
+
+/// Quote text for a POSIX shell between single quotes.
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Quote text for fish between single quotes, where only backslashes and quotes are escaped.
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Return a completion script for a shell, completing commands and the tags in the database nearest to where
+/// completion happens. Tags are looked up by running the hidden `ftag __complete` command each time.
+///
+/// * `shell` - Shell the script is written for
+/// * `cli` - Every command ftag has, to complete their names
+pub fn script(shell: Shell, cli: &Command) -> String {
+    let commands: Vec<(&str, String)> = cli
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .map(|command| (command.get_name(), command.get_about().map(|about| about.to_string()).unwrap_or_default()))
+        .collect();
+
+    match shell {
+        Shell::Bash => {
+            let names: Vec<&str> = commands.iter().map(|(name, _)| *name).collect();
+            BASH_SCRIPT.replace("__COMMANDS__", &names.join(" "))
+        },
+        Shell::Zsh => {
+            let described: Vec<String> = commands
+                .iter()
+                .map(|(name, about)| format!("        {}", single_quote(&format!("{}:{}", name, about))))
+                .collect();
+            ZSH_SCRIPT.replace("__COMMANDS__", &described.join("\n"))
+        },
+        Shell::Fish => {
+            let described: Vec<String> = commands
+                .iter()
+                .map(|(name, about)| format!("complete -c ftag -n __fish_use_subcommand -a {} -d {}", name, fish_quote(about)))
+                .collect();
+            FISH_SCRIPT.replace("__COMMANDS__", &described.join("\n"))
+        },
+    }
+}
+
+/// Return a shell function wrapping ftag, so `ftag cd TAGS...` changes the shell's directory instead of printing it.
+///
+/// * `shell` - Shell the function is written for
+pub fn init_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash | Shell::Zsh => POSIX_INIT,
+        Shell::Fish => FISH_INIT,
+    }
+}
@@ -0,0 +1,508 @@
+use std::{collections::{HashMap, HashSet}, env, io::{self, IsTerminal}, sync::OnceLock};
+
+use crate::ftag::{TagChange, TAG_SEPARATOR};
+use crate::meta::{TagColor, TagMeta};
+use crate::paths;
+
+/// ANSI escape code that resets all colors and styles.
+const RESET: &str = "\x1b[0m";
+
+/// ANSI escape code for bold text, used to highlight tags that matched a search.
+const BOLD: &str = "\x1b[1m";
+
+/// ANSI escape code for dim text, used for counts.
+const DIM: &str = "\x1b[2m";
+
+/// Color of tags that weren't given one with `ftag tag color`.
+const DEFAULT_TAG_COLOR: TagColor = TagColor::Cyan;
+
+/// Color of error messages.
+const ERROR_COLOR: TagColor = TagColor::Red;
+
+/// Colors given to namespaces that weren't given one with `ftag tag color`, picked by their names.
+const NAMESPACE_COLORS: [TagColor; 5] = [TagColor::Cyan, TagColor::Green, TagColor::Yellow, TagColor::Magenta, TagColor::Blue];
+
+/// Color of the filled stars of a rating.
+#[cfg(feature = "full")]
+const RATING_COLOR: TagColor = TagColor::Yellow;
+
+/// When to color output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// Color output going to a terminal, unless NO_COLOR is set
+    Auto,
+    /// Always color output, even when piped
+    Always,
+    /// Never color output
+    Never,
+}
+
+/// When to color output, set once by `set_color`.
+static COLOR: OnceLock<ColorChoice> = OnceLock::new();
+
+/// Choose when to color output for the rest of this process.
+pub fn set_color(choice: ColorChoice) {
+    let _ = COLOR.set(choice);
+}
+
+/// Whether to color text written to a stream, following `set_color`, NO_COLOR, and `set_plain`.
+fn use_color(stream: &impl IsTerminal) -> bool {
+    if is_plain() {
+        return false;
+    }
+
+    match COLOR.get().copied().unwrap_or(ColorChoice::Auto) {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // Any value of NO_COLOR other than an empty one turns colors off, see https://no-color.org
+        ColorChoice::Auto => env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && stream.is_terminal(),
+    }
+}
+
+/// Whether text written to standard output is colored.
+#[cfg(feature = "full")]
+pub fn stdout_colored() -> bool {
+    use_color(&std::io::stdout())
+}
+
+/// Whether to print plain output, set once by `set_plain`.
+static PLAIN: OnceLock<bool> = OnceLock::new();
+
+/// Print plain output for the rest of this process: no colors, indentation, or symbols standing in for words,
+/// just one labeled line per item, which reads well on screen readers and braille displays.
+pub fn set_plain(plain: bool) {
+    let _ = PLAIN.set(plain);
+}
+
+/// Whether output should be plain, see `set_plain`.
+pub fn is_plain() -> bool {
+    PLAIN.get().copied().unwrap_or(false)
+}
+
+/// Whether to leave out messages about what changed, set once by `set_quiet`.
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Leave out messages about what changed for the rest of this process, so scripts only see results and errors.
+pub fn set_quiet(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+/// Whether messages about what changed should be left out, see `set_quiet`.
+pub fn is_quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
+/// Color `text` the way `tag` is configured to be shown, if standard output is colored.
+///
+/// * `text` - Text to color, usually `tag` itself or one level of it
+/// * `tag` - Full tag name to look up in `meta`
+/// * `meta` - Metadata of all tags
+pub fn paint_tag(text: &str, tag: &str, meta: &HashMap<String, TagMeta>) -> String {
+    if !use_color(&io::stdout()) {
+        return text.to_string();
+    }
+
+    let color = meta.get(tag).and_then(|meta| meta.color).unwrap_or(DEFAULT_TAG_COLOR);
+    format!("{}{}{}", color.ansi_code(), text, RESET)
+}
+
+/// Return the namespace of a hierarchical tag, its first level, or `None` for a tag with one level.
+fn namespace_of(tag: &str) -> Option<&str> {
+    tag.split_once(TAG_SEPARATOR).map(|(namespace, _)| namespace)
+}
+
+/// Return the color of a namespace: the one given to it with `ftag tag color`, or one picked by its name so it
+/// stays the same from one run to the next.
+fn namespace_color(namespace: &str, meta: &HashMap<String, TagMeta>) -> TagColor {
+    meta.get(namespace).and_then(|meta| meta.color).unwrap_or_else(|| {
+        let hash = namespace.bytes().fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte.into()));
+        NAMESPACE_COLORS[hash % NAMESPACE_COLORS.len()]
+    })
+}
+
+/// Color a tag in the color of its namespace, unless it was given a color of its own.
+fn paint_grouped_tag(text: &str, tag: &str, meta: &HashMap<String, TagMeta>) -> String {
+    let color = match (meta.get(tag).and_then(|meta| meta.color), namespace_of(tag)) {
+        (Some(color), _) => color,
+        (None, Some(namespace)) => namespace_color(namespace, meta),
+        (None, None) => return paint_tag(text, tag, meta),
+    };
+    match use_color(&io::stdout()) {
+        true => format!("{}{}{}", color.ansi_code(), text, RESET),
+        false => text.to_string(),
+    }
+}
+
+/// Split tags into the ones without a namespace and a group for each namespace, keeping them in order.
+///
+/// Namespaces are in the order their first tags were seen in.
+fn group_by_namespace<T>(items: Vec<T>, tag: impl Fn(&T) -> &str) -> (Vec<T>, Vec<(String, Vec<T>)>) {
+    let mut loose = vec![];
+    let mut groups: Vec<(String, Vec<T>)> = vec![];
+    for item in items {
+        let Some(namespace) = namespace_of(tag(&item)).map(str::to_string) else {
+            loose.push(item);
+            continue;
+        };
+        match groups.iter_mut().find(|(name, _)| *name == namespace) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((namespace, vec![item])),
+        }
+    }
+    (loose, groups)
+}
+
+/// Print tags with every namespace in a group of its own, like all of `project/*` together under a `project/`
+/// heading saying how many tags it has, each namespace in its own color.
+///
+/// Tags without a namespace come first. Within a group, tags are shown without the namespace.
+///
+/// * `pairs` - Tags in the order to display them, with an optional count for each
+/// * `meta` - Metadata of all tags, used to color and describe them
+pub fn display_tag_groups(pairs: Vec<(String, Option<u32>)>, meta: &HashMap<String, TagMeta>) {
+    let (loose, groups) = group_by_namespace(pairs, |(tag, _)| tag);
+    display_tag_list(loose, meta);
+
+    for (namespace, group) in groups {
+        if is_plain() {
+            println!("namespace: {}; tags: {}", namespace, group.len());
+            display_tag_list(group, meta);
+            continue;
+        }
+
+        let heading = format!("{}{}", namespace, TAG_SEPARATOR);
+        let count = match use_color(&io::stdout()) {
+            true => format!("{}{} tags{}", DIM, group.len(), RESET),
+            false => format!("{} tags", group.len()),
+        };
+        println!("{}  {}", paint_grouped_tag(&heading, &heading, meta), count);
+        for (tag, count) in group {
+            print!("  ");
+            if let Some(count) = count {
+                print!("{}", paint_count(count));
+            }
+            let rest = &tag[namespace.len() + 1..];
+            println!("{}{}", paint_grouped_tag(rest, &tag, meta), annotation(&tag, meta));
+        }
+    }
+}
+
+/// Format the tags of a found file with every namespace on a line of its own, like `project: alpha; beta`, each
+/// namespace in its own color and tags that matched the search standing out.
+///
+/// Tags without a namespace come first, on a line together.
+///
+/// * `tags` - Tags of the file, in order
+/// * `matched` - Check whether a tag matched what was searched for
+/// * `meta` - Metadata of all tags, used to color them
+pub fn paint_grouped_tags(tags: &[String], matched: impl Fn(&str) -> bool, meta: &HashMap<String, TagMeta>) -> Vec<String> {
+    let (loose, groups) = group_by_namespace(tags.iter().collect(), |tag| tag.as_str());
+    let paint = |text: &str, tag: &str| match matched(tag) && use_color(&io::stdout()) {
+        true => format!("{}{}", BOLD, paint_grouped_tag(text, tag, meta)),
+        false => paint_grouped_tag(text, tag, meta),
+    };
+
+    let mut lines = vec![];
+    if !loose.is_empty() {
+        lines.push(loose.iter().map(|tag| paint(tag, tag)).collect::<Vec<_>>().join("; "));
+    }
+    for (namespace, group) in groups {
+        let rests: Vec<String> = group.iter().map(|tag| paint(&tag[namespace.len() + 1..], tag)).collect();
+        lines.push(format!("{}: {}", paint_grouped_tag(&namespace, &format!("{}{}", namespace, TAG_SEPARATOR), meta), rests.join("; ")));
+    }
+    lines
+}
+
+/// Color a tag like `paint_tag`, and make it stand out if it matched what was searched for.
+pub fn paint_found_tag(tag: &str, matched: bool, meta: &HashMap<String, TagMeta>) -> String {
+    match matched && use_color(&io::stdout()) {
+        true => format!("{}{}", BOLD, paint_tag(tag, tag, meta)),
+        false => paint_tag(tag, tag, meta),
+    }
+}
+
+/// Format a count shown before a tag, like `(3) `, dimmed if standard output is colored.
+pub fn paint_count(count: u32) -> String {
+    match use_color(&io::stdout()) {
+        true => format!("{}({}){} ", DIM, count, RESET),
+        false => format!("({}) ", count),
+    }
+}
+
+/// Format a rating as stars, like `★★★★☆`, with the filled ones colored if standard output is colored.
+#[cfg(feature = "full")]
+pub fn paint_rating(rating: u8) -> String {
+    let filled = "★".repeat(rating.into());
+    let empty = "☆".repeat(crate::rating::MAX_RATING.saturating_sub(rating).into());
+    match use_color(&io::stdout()) {
+        true => format!("{}{}{}{}{}{}", RATING_COLOR.ansi_code(), filled, RESET, DIM, empty, RESET),
+        false => format!("{}{}", filled, empty),
+    }
+}
+
+/// Escape a field of a TSV row, so tabs, newlines, and `separator` within it can't be mistaken for the ones between fields.
+fn escape_tsv(field: &str, separator: Option<char>) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if Some(c) == separator => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a file and its tags as a TSV row: the path, a tab, and the tags joined by commas.
+///
+/// This layout is meant for scripts, so it stays the same between versions. Backslashes, tabs, and newlines are
+/// escaped as `\\`, `\t`, and `\n`, and commas within tags as `\,`.
+pub fn tsv_row(path: &str, tags: &[String]) -> String {
+    let tags: Vec<String> = tags.iter().map(|tag| escape_tsv(tag, Some(','))).collect();
+    format!("{}\t{}", escape_tsv(path, None), tags.join(","))
+}
+
+/// Format a file and its tags as one line of JSON, like `{"path":"a.jpg","tags":["red","sky"]}`.
+#[cfg(feature = "full")]
+pub fn jsonl_row(path: &str, tags: &[String]) -> String {
+    serde_json::json!({ "path": path, "tags": tags }).to_string()
+}
+
+/// Color an error message if standard error is colored.
+pub fn paint_error(message: &str) -> String {
+    match use_color(&io::stderr()) {
+        true => format!("{}{}{}", ERROR_COLOR.ansi_code(), message, RESET),
+        false => message.to_string(),
+    }
+}
+
+/// Return the description of `tag` formatted to follow it on a line, or nothing if it has none.
+fn annotation(tag: &str, meta: &HashMap<String, TagMeta>) -> String {
+    match meta.get(tag).and_then(|meta| meta.description.as_ref()) {
+        Some(description) if is_plain() => format!("; description: {}", description),
+        Some(description) => format!("  # {}", description),
+        None => String::new(),
+    }
+}
+
+/// One level of a hierarchical tag, along with everything nested beneath it.
+struct TagNode {
+    name: String,
+    tag: String,
+    count: Option<u32>,
+    children: Vec<TagNode>,
+}
+
+impl TagNode {
+    /// Insert a tag below this node, creating any namespaces along the way.
+    ///
+    /// * `parts` - Remaining levels of the tag, split on `TAG_SEPARATOR`
+    /// * `count` - Count to display next to the last level, if any
+    fn insert(&mut self, parts: &[&str], count: Option<u32>) {
+        let Some((first, rest)) = parts.split_first() else {
+            return;
+        };
+
+        // Reuse the child if it already exists, so siblings keep the order they were first seen in
+        let index = match self.children.iter().position(|child| child.name == *first) {
+            Some(index) => index,
+            None => {
+                // The root has an empty tag, so its children shouldn't start with a separator
+                let tag = match self.tag.is_empty() {
+                    true => first.to_string(),
+                    false => format!("{}{}{}", self.tag, TAG_SEPARATOR, first),
+                };
+                self.children.push(TagNode { name: first.to_string(), tag, count: None, children: vec![] });
+                self.children.len() - 1
+            },
+        };
+
+        let child = &mut self.children[index];
+        if rest.is_empty() {
+            child.count = count;
+        } else {
+            child.insert(rest, count);
+        }
+    }
+
+    /// Print the children of this node, indenting each level by two spaces.
+    fn print_children(&self, depth: usize, meta: &HashMap<String, TagMeta>) {
+        for child in &self.children {
+            print!("{}", "  ".repeat(depth));
+            if let Some(count) = child.count {
+                print!("{}", paint_count(count));
+            }
+            println!("{}{}", paint_tag(&child.name, &child.tag, meta), annotation(&child.tag, meta));
+            child.print_children(depth + 1, meta);
+        }
+    }
+}
+
+/// Print tags as an indented tree, nesting each level of a hierarchical tag under its parent.
+///
+/// * `pairs` - Tags in the order to display them, with an optional count for each
+/// * `meta` - Metadata of all tags, used to color and describe them
+pub fn display_tag_tree(pairs: Vec<(String, Option<u32>)>, meta: &HashMap<String, TagMeta>) {
+    // Indentation is hard to follow without seeing it, so plain output names every tag in full instead
+    if is_plain() {
+        display_tag_list(pairs, meta);
+        return;
+    }
+
+    let mut root = TagNode { name: String::new(), tag: String::new(), count: None, children: vec![] };
+    for (tag, count) in pairs {
+        let parts: Vec<&str> = tag.split(TAG_SEPARATOR).collect();
+        root.insert(&parts, count);
+    }
+    root.print_children(0, meta);
+}
+
+/// One directory or file of a found path, along with everything found beneath it.
+struct PathNode {
+    name: String,
+    /// Whether the path itself was found, rather than only paths beneath it
+    found: bool,
+    /// Line to print beneath the path, like its tags
+    detail: Option<String>,
+    children: Vec<PathNode>,
+}
+
+impl PathNode {
+    /// Insert a found path below this node, creating any directories along the way.
+    ///
+    /// * `parts` - Remaining components of the path
+    /// * `detail` - Line to print beneath the path, if any
+    fn insert(&mut self, parts: &[&str], detail: Option<String>) {
+        let Some((first, rest)) = parts.split_first() else {
+            return;
+        };
+
+        // Reuse the child if it already exists, so siblings keep the order they were first seen in
+        let index = match self.children.iter().position(|child| child.name == *first) {
+            Some(index) => index,
+            None => {
+                self.children.push(PathNode { name: first.to_string(), found: false, detail: None, children: vec![] });
+                self.children.len() - 1
+            },
+        };
+
+        let child = &mut self.children[index];
+        if rest.is_empty() {
+            child.found = true;
+            child.detail = detail;
+        } else {
+            child.insert(rest, detail);
+        }
+    }
+
+    /// Print the children of this node, indenting each level by two spaces and ending directories with a `/`.
+    fn print_children(&self, depth: usize) {
+        for child in &self.children {
+            let indent = "  ".repeat(depth);
+            match (child.children.is_empty(), use_color(&io::stdout())) {
+                (true, _) => println!("{}{}", indent, child.name),
+                (false, true) => println!("{}{}{}/{}", indent, BOLD, child.name, RESET),
+                (false, false) => println!("{}{}/", indent, child.name),
+            }
+            if let Some(detail) = child.detail.as_ref().filter(|_| child.found) {
+                println!("{}  {}", indent, detail);
+            }
+            child.print_children(depth + 1);
+        }
+    }
+}
+
+/// Print found paths as an indented tree, nesting each path under its parent directories.
+///
+/// * `files` - Paths in the order to display them, with an optional line to print beneath each
+pub fn display_file_tree(files: Vec<(String, Option<String>)>) {
+    let mut root = PathNode { name: String::new(), found: false, detail: None, children: vec![] };
+    for (file, detail) in files {
+        let parts: Vec<&str> = file.split('/').filter(|part| !part.is_empty()).collect();
+        root.insert(&parts, detail);
+    }
+    root.print_children(0);
+}
+
+/// Print tags one per line, with an optional count before each.
+///
+/// * `pairs` - Tags in the order to display them, with an optional count for each
+/// * `meta` - Metadata of all tags, used to color and describe them
+pub fn display_tag_list(pairs: Vec<(String, Option<u32>)>, meta: &HashMap<String, TagMeta>) {
+    for (tag, count) in pairs {
+        if is_plain() {
+            let count = count.map(|count| format!("; files: {}", count)).unwrap_or_default();
+            println!("tag: {}{}{}", tag, count, annotation(&tag, meta));
+            continue;
+        }
+
+        // If printing counts, put "(#) " on the same line
+        if let Some(count) = count {
+            print!("{}", paint_count(count));
+        }
+        println!("{}{}", paint_tag(&tag, &tag, meta), annotation(&tag, meta));
+    }
+}
+
+/// Print the tags of a single file in alphabetical order.
+///
+/// * `tags` - Tags to print
+/// * `reverse` - Print in reverse alphabetical order instead
+/// * `tree` - Print hierarchical tags as an indented tree
+/// * `group` - Print the tags of each namespace together, like `display_tag_groups`
+/// * `meta` - Metadata of all tags, used to color and describe them
+pub fn display_tags(tags: HashSet<String>, reverse: bool, tree: bool, group: bool, meta: &HashMap<String, TagMeta>) {
+    // Get the HashSet as a vector and alphabetize it
+    let mut tags: Vec<_> = tags.into_iter().collect();
+    tags.sort(); // alphabetic and case-sensitive
+
+    if reverse {
+        tags.reverse();
+    }
+
+    let pairs = tags.into_iter().map(|tag| (tag, None)).collect();
+    if tree {
+        display_tag_tree(pairs, meta);
+    } else if group {
+        display_tag_groups(pairs, meta);
+    } else {
+        display_tag_list(pairs, meta);
+    }
+}
+
+/// Print how the tags of each file would change, with `-` before lost tags and `+` before gained ones.
+///
+/// * `changes` - Changes to print, in order
+/// * `meta` - Metadata of all tags, used to color them
+pub fn display_changes(changes: &[TagChange], meta: &HashMap<String, TagMeta>) {
+    for change in changes {
+        let path = paths::to_display(&change.path);
+        if is_plain() {
+            let removes = match change.removed.is_empty() {
+                true => String::new(),
+                false => format!("; removes: {}", change.removed.join(", ")),
+            };
+            let adds = match change.added.is_empty() {
+                true => String::new(),
+                false => format!("; adds: {}", change.added.join(", ")),
+            };
+            println!("file: {}{}{}", path, removes, adds);
+            continue;
+        }
+
+        println!("{}", path);
+        if !change.removed.is_empty() {
+            println!("  - {}", change.removed.iter().map(|tag| paint_tag(tag, tag, meta)).collect::<Vec<_>>().join("; "));
+        }
+        if !change.added.is_empty() {
+            println!("  + {}", change.added.iter().map(|tag| paint_tag(tag, tag, meta)).collect::<Vec<_>>().join("; "));
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use std::fmt;
+use std::fs;
+
+use crate::check::{self, ProblemKind};
+use crate::ftag::{get_db_path, get_store_dir_path, get_text_path, FtagError};
+use crate::schema::{self, SCHEMA_VERSION};
+use crate::store::{self, Backend};
+use crate::{paths, units};
+
+/// Size of write-ahead log worth mentioning, since SQLite normally folds it back into the database as it goes.
+const LARGE_WAL: u64 = 64 * 1024 * 1024;
+
+/// How serious something `diagnose` found is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// Nothing wrong
+    Ok,
+    /// Worth a look, but ftag still works
+    Warning,
+    /// Stops ftag from working properly
+    Problem,
+}
+
+impl Status {
+    /// Short name of the status, for scripts to match on.
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Problem => "problem",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One thing `diagnose` looked at, and what it found.
+#[derive(Debug)]
+pub struct Diagnosis {
+    /// What was looked at: `database`, `schema`, `permissions`, `wal`, or `rows`
+    pub topic: &'static str,
+    /// How serious it is
+    pub status: Status,
+    /// What was found
+    pub detail: String,
+    /// What to do about it, if anything
+    pub fix: Option<String>,
+}
+
+impl Diagnosis {
+    fn new(topic: &'static str, status: Status, detail: String) -> Self {
+        Diagnosis { topic, status, detail, fix: None }
+    }
+
+    fn fix(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+}
+
+/// Look for common problems with the database ftag would use from where it was started, with a fix for each.
+///
+/// Covers which database is used and why, whether its schema matches this build, whether it can be read and
+/// written, the state of its write-ahead log, and rows that are damaged or point at missing files. No tags are
+/// changed, and the schema is read without needing to match, so it works even when other commands refuse to.
+///
+/// Must be called after `paths::enter_root`.
+///
+/// # Failure
+///
+/// Returns `Err` if the current directory could not be read.
+pub fn diagnose() -> Result<Vec<Diagnosis>, FtagError> {
+    let started = paths::invocation_dir()?;
+    let root = Utf8PathBuf::try_from(std::env::current_dir()?).map_err(|_| FtagError::ArgumentError("The current directory is not valid UTF-8".to_string()))?;
+    let mut found = vec![];
+
+    let backend = match store::current_backend() {
+        Ok(backend) => backend,
+        Err(FtagError::NoDatabaseError) => {
+            found.push(Diagnosis::new("database", Status::Problem, format!("No database in {} or any directory above it", started)).fix("Run ftag init in the directory that should hold it"));
+            return Ok(found);
+        },
+        Err(err) => {
+            found.push(Diagnosis::new("database", Status::Problem, err.to_string()).fix("Use a full build of ftag"));
+            return Ok(found);
+        },
+    };
+    found.extend(diagnose_location(&started, &root, backend));
+
+    let database = match backend {
+        Backend::Sqlite => get_db_path(),
+        Backend::Json => get_store_dir_path(),
+        Backend::Text => get_text_path(),
+    };
+    found.extend(diagnose_permissions(&root, &database));
+
+    if backend == Backend::Sqlite {
+        let schema = diagnose_sqlite(&mut found);
+        // Reading rows needs the schema this build understands
+        if !schema {
+            return Ok(found);
+        }
+    }
+    found.push(diagnose_rows());
+    Ok(found)
+}
+
+/// Explain which database is used and why, and mention any others it hides.
+fn diagnose_location(started: &Utf8Path, root: &Utf8Path, backend: Backend) -> Vec<Diagnosis> {
+    let mut found = vec![];
+    let why = match started == root {
+        true => "ftag was started there".to_string(),
+        false => format!("it is the nearest one above {}", started),
+    };
+    found.push(Diagnosis::new("database", Status::Ok, format!("Using the {} database in {}, since {}", backend_name(backend), root, why)));
+
+    // Only one backend is used when a directory holds several
+    let present: Vec<Utf8PathBuf> = [get_db_path(), get_store_dir_path(), get_text_path()].into_iter().filter(|path| path.exists()).collect();
+    if present.len() > 1 {
+        let names: Vec<&str> = present.iter().map(|path| path.as_str()).collect();
+        found.push(
+            Diagnosis::new("database", Status::Warning, format!("{} holds {}, but only the {} database is used", root, names.join(", "), backend_name(backend)))
+                .fix("Move the tags you need into one database and delete the others"),
+        );
+    }
+
+    // Databases further up are never reached from beneath this one
+    if let Some(above) = root.ancestors().skip(1).find(|dir| paths::holds_database(dir)) {
+        found.push(Diagnosis::new("database", Status::Ok, format!("The database in {} is hidden by this one beneath it", above)));
+    }
+    found
+}
+
+/// Name a backend the way it is chosen with `ftag init --backend`.
+fn backend_name(backend: Backend) -> &'static str {
+    match backend {
+        Backend::Sqlite => "sqlite",
+        Backend::Json => "json",
+        Backend::Text => "text",
+    }
+}
+
+/// Check that the database and the directory holding it can be read and written.
+fn diagnose_permissions(root: &Utf8Path, database: &Utf8Path) -> Vec<Diagnosis> {
+    let mut found = vec![];
+    match fs::metadata(database) {
+        Err(err) => found.push(Diagnosis::new("permissions", Status::Problem, format!("{} can't be read: {}", database, err)).fix(format!("chmod u+r {}", database))),
+        Ok(meta) if meta.permissions().readonly() => {
+            found.push(Diagnosis::new("permissions", Status::Problem, format!("{} is read-only, so tags can't be changed", database)).fix(format!("chmod u+w {}", database)))
+        },
+        Ok(_) => {},
+    }
+
+    // SQLite keeps its write-ahead log next to the database, and the other backends replace files as they save
+    if fs::metadata(".").is_ok_and(|meta| meta.permissions().readonly()) {
+        found.push(
+            Diagnosis::new("permissions", Status::Problem, format!("{} is read-only, so ftag can't create the files it needs beside the database", root))
+                .fix(format!("chmod u+w {}", root)),
+        );
+    }
+
+    if found.is_empty() {
+        found.push(Diagnosis::new("permissions", Status::Ok, format!("{} can be read and written", database)));
+    }
+    found
+}
+
+/// Check the schema version and write-ahead log of a SQLite database, returning whether the schema is one this
+/// build understands.
+///
+/// The database is opened read-only, so nothing is changed even when it needs migrating.
+fn diagnose_sqlite(found: &mut Vec<Diagnosis>) -> bool {
+    let db = get_db_path();
+    let conn = match Connection::open_with_flags(&db, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX) {
+        Ok(conn) => conn,
+        Err(err) => {
+            found.push(Diagnosis::new("schema", Status::Problem, format!("{} can't be opened: {}", db, err)).fix("Restore it from a backup with ftag restore"));
+            return false;
+        },
+    };
+
+    let schema = match schema::version(&conn) {
+        Err(err) => {
+            found.push(Diagnosis::new("schema", Status::Problem, format!("The schema version can't be read: {}", err)).fix("Restore it from a backup with ftag restore"));
+            false
+        },
+        Ok(version) if version < SCHEMA_VERSION => {
+            found.push(
+                Diagnosis::new("schema", Status::Problem, format!("The database uses schema version {}, older than version {} of ftag {}", version, SCHEMA_VERSION, env!("CARGO_PKG_VERSION")))
+                    .fix("Run ftag migrate"),
+            );
+            false
+        },
+        Ok(version) if version > SCHEMA_VERSION => {
+            found.push(
+                Diagnosis::new("schema", Status::Problem, format!("The database uses schema version {}, newer than version {} of ftag {}", version, SCHEMA_VERSION, env!("CARGO_PKG_VERSION")))
+                    .fix("Update ftag to the version that last wrote the database"),
+            );
+            false
+        },
+        Ok(version) => {
+            found.push(Diagnosis::new("schema", Status::Ok, format!("The database uses schema version {}, the same as ftag {}", version, env!("CARGO_PKG_VERSION"))));
+            true
+        },
+    };
+
+    let mode: String = conn.pragma_query_value(None, "journal_mode", |row| row.get(0)).unwrap_or_default();
+    let wal = fs::metadata(format!("{}-wal", db)).map(|meta| meta.len()).unwrap_or(0);
+    if Utf8Path::new(&format!("{}-journal", db)).exists() {
+        found.push(
+            Diagnosis::new("wal", Status::Warning, format!("{}-journal was left behind, so a change may have been interrupted", db))
+                .fix("Run any ftag command to let SQLite roll the change back, without deleting the journal"),
+        );
+    }
+    if wal > LARGE_WAL {
+        found.push(
+            Diagnosis::new("wal", Status::Warning, format!("{}-wal has grown to {}B, so changes aren't being folded back into the database", db, units::ByteSize(wal)))
+                .fix("Stop any other ftag still running, then run any ftag command to fold them back in"),
+        );
+    } else {
+        found.push(Diagnosis::new("wal", Status::Ok, format!("Journal mode is {}, and the write-ahead log holds {}B of changes", mode, units::ByteSize(wal))));
+    }
+
+    // Another invocation holding the write lock makes changes wait, then fail after --busy-timeout
+    if let Ok(conn) = Connection::open_with_flags(&db, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX) {
+        match conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;") {
+            Err(rusqlite::Error::SqliteFailure(err, _)) if err.code == ErrorCode::DatabaseBusy => found.push(
+                Diagnosis::new("wal", Status::Warning, "Another program is writing to the database right now".to_string())
+                    .fix("Wait for it to finish, or stop it if it is stuck"),
+            ),
+            _ => {},
+        }
+    }
+    schema
+}
+
+/// Count the rows that are damaged or point at files that no longer exist.
+fn diagnose_rows() -> Diagnosis {
+    let checked = match check::check() {
+        Ok(checked) => checked,
+        Err(err) => return Diagnosis::new("rows", Status::Problem, format!("The rows can't be read: {}", err)).fix("Restore the database from a backup with ftag restore"),
+    };
+    let count = |kind: ProblemKind| checked.problems.iter().filter(|problem| problem.kind == kind).count();
+    let (damaged, missing) = (count(ProblemKind::BadJson) + count(ProblemKind::Duplicate), count(ProblemKind::Missing));
+
+    if damaged > 0 {
+        Diagnosis::new("rows", Status::Problem, format!("{} of {} rows are unreadable or repeated, and {} point at missing files", damaged, checked.rows, missing))
+            .fix("Run ftag check to see them, then ftag repair")
+    } else if missing > 0 {
+        Diagnosis::new("rows", Status::Warning, format!("{} of {} rows point at files that no longer exist", missing, checked.rows)).fix("Run ftag prune to remove them")
+    } else {
+        Diagnosis::new("rows", Status::Ok, format!("All {} rows are readable and point at existing files", checked.rows))
+    }
+}
@@ -0,0 +1,208 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Read};
+
+use crate::ftag::{self, FtagError, TagChange};
+use crate::link;
+use crate::store::{self, TagStore};
+
+/// Size of the pieces files are read in when hashing and comparing them.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Tracked files that all have the same contents.
+#[derive(Debug)]
+pub struct DupeGroup {
+    /// Size of each copy, in bytes
+    pub size: u64,
+    /// Path and tags of each copy, sorted by path. The first copy is the one kept when removing the others.
+    pub files: Vec<(String, HashSet<String>)>,
+}
+
+impl DupeGroup {
+    /// Every tag held by any of the copies.
+    fn all_tags(&self) -> HashSet<String> {
+        self.files.iter().flat_map(|(_, tags)| tags.iter().cloned()).collect()
+    }
+}
+
+/// What to do about each group of copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Give every copy the tags of all of them
+    Merge,
+    /// Give the first copy the tags of all of them, then delete the rest
+    Delete,
+    /// Give every copy the tags of all of them, then replace all but the first with symlinks to it
+    Link,
+}
+
+/// Hash the contents of a file. Only files with the same hash need to be compared in full.
+///
+/// # Failure
+///
+/// Returns `Err` if the file could not be read.
+fn hash_file(path: &Utf8Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..read]);
+    }
+}
+
+/// Check whether two files of the same size have exactly the same contents.
+///
+/// # Failure
+///
+/// Returns `Err` if either file could not be read.
+fn same_contents(first: &Utf8Path, second: &Utf8Path) -> io::Result<bool> {
+    let (mut first, mut second) = (File::open(first)?, File::open(second)?);
+    let (mut first_buf, mut second_buf) = (vec![0; CHUNK_SIZE], vec![0; CHUNK_SIZE]);
+    loop {
+        let read = first.read(&mut first_buf)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        second.read_exact(&mut second_buf[..read])?;
+        if first_buf[..read] != second_buf[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Find the tracked files beneath a directory that have the same contents as another tracked file there.
+///
+/// Files are grouped by size first, so only files that could be copies of each other are read. Symlinks and empty
+/// files are left out, since neither takes up space worth reclaiming.
+///
+/// * `dir` - Directory to look in, relative to the database root
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it could not be read, or a tracked file could not be read.
+pub fn find_dupes(dir: &Utf8Path) -> Result<Vec<DupeGroup>, FtagError> {
+    let mut by_size: HashMap<u64, Vec<(String, HashSet<String>)>> = HashMap::new();
+    for (path, tags) in ftag::get_all_rows()? {
+        let file = Utf8Path::new(&path);
+        if dir != "." && !file.starts_with(dir) {
+            continue;
+        }
+        // Deleted files are left to prune, and symlinks are not copies
+        let Ok(metadata) = file.symlink_metadata() else {
+            continue;
+        };
+        if metadata.is_file() && metadata.len() > 0 {
+            by_size.entry(metadata.len()).or_default().push((path, tags));
+        }
+    }
+
+    let mut groups = vec![];
+    for (size, files) in by_size {
+        if files.len() < 2 {
+            continue;
+        }
+
+        let mut by_hash: HashMap<u64, Vec<(String, HashSet<String>)>> = HashMap::new();
+        for (path, tags) in files {
+            by_hash.entry(hash_file(Utf8Path::new(&path))?).or_default().push((path, tags));
+        }
+
+        // Files with the same hash are almost always copies, but are compared in full before anything is deleted
+        for (_, mut files) in by_hash {
+            files.sort_by(|(first, _), (second, _)| first.cmp(second));
+            while files.len() > 1 {
+                let first = files.remove(0);
+                let mut copies = vec![first];
+                let mut others = vec![];
+                for file in files {
+                    match same_contents(Utf8Path::new(&copies[0].0), Utf8Path::new(&file.0))? {
+                        true => copies.push(file),
+                        false => others.push(file),
+                    }
+                }
+                if copies.len() > 1 {
+                    groups.push(DupeGroup { size, files: copies });
+                }
+                files = others;
+            }
+        }
+    }
+
+    groups.sort_by(|first, second| first.files[0].0.cmp(&second.files[0].0));
+    Ok(groups)
+}
+
+/// Replace a file with a symlink to another, leaving the file alone if the link can't be made.
+///
+/// # Failure
+///
+/// Returns `Err` if the link could not be made or moved into place.
+fn replace_with_link(path: &Utf8Path, target: &Utf8Path) -> Result<(), FtagError> {
+    let target: Utf8PathBuf = target.canonicalize_utf8()?;
+    let temp = Utf8PathBuf::from(format!("{}.ftag-link", path));
+    link::symlink(&target, &temp).map_err(|err| FtagError::ArgumentError(format!("Could not link {} to {}: {}", path, target, err)))?;
+    if let Err(err) = std::fs::rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Merge the tags of each group of copies, then delete or link all but the first copy if asked.
+///
+/// Tags are saved in one transaction before any file is touched, so the first copy never loses tags held by a
+/// deleted one. Returns how the tags of each copy changed; deleted copies lose all of theirs.
+///
+/// * `groups` - Groups found by `find_dupes`
+/// * `resolution` - What to do about each group
+/// * `dry_run` - Work out what would change without changing anything
+///
+/// # Failure
+///
+/// Returns `Err` if errors occur when interacting with the database, or a copy could not be deleted or linked.
+pub fn resolve(groups: &[DupeGroup], resolution: Resolution, dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    let mut store = store::open_store()?;
+    let changes = |tx: &mut dyn TagStore| {
+        let mut changed = vec![];
+        for group in groups {
+            let merged = group.all_tags();
+            for (index, (path, tags)) in group.files.iter().enumerate() {
+                let after = match (resolution, index) {
+                    (Resolution::Delete, 1..) => HashSet::new(),
+                    _ => merged.clone(),
+                };
+                if after == *tags {
+                    continue;
+                }
+                match after.is_empty() {
+                    true => tx.remove_path(path)?,
+                    false => tx.set_tags(path, &after)?,
+                }
+                changed.push(TagChange::between(path, tags, &after));
+            }
+        }
+        Ok(changed)
+    };
+    let changed = match dry_run {
+        true => return store.dry_run(changes),
+        false => store.batch(changes)?,
+    };
+
+    for group in groups {
+        let (kept, _) = &group.files[0];
+        for (path, _) in &group.files[1..] {
+            match resolution {
+                Resolution::Merge => {},
+                Resolution::Delete => std::fs::remove_file(path)?,
+                Resolution::Link => replace_with_link(Utf8Path::new(path), Utf8Path::new(kept))?,
+            }
+        }
+    }
+    Ok(changed)
+}
@@ -0,0 +1,40 @@
+use camino::Utf8Path;
+use std::process::Command;
+
+use crate::ftag::FtagError;
+use crate::sandbox;
+
+/// Argument replaced by the path of each file, like in `find -exec`.
+pub const PLACEHOLDER: &str = "{}";
+
+/// Run a command for one file, replacing `{}` in its arguments with the path, or adding the path at the end if
+/// there is no `{}`, and wait for it to finish.
+///
+/// * `command` - Program to run followed by its arguments
+/// * `path` - Path of the file, relative to `dir`
+/// * `dir` - Directory to run the command in
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, or the command could not be run or failed.
+pub fn run_for(command: &[String], path: &str, dir: &Utf8Path) -> Result<(), FtagError> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(FtagError::ArgumentError("No command given to run".to_string()));
+    };
+    sandbox::check_exec(program)?;
+
+    let mut args: Vec<String> = args.iter().map(|arg| arg.replace(PLACEHOLDER, path)).collect();
+    if !command.iter().any(|arg| arg.contains(PLACEHOLDER)) {
+        args.push(path.to_string());
+    }
+
+    let status = Command::new(program)
+        .args(&args)
+        .current_dir(dir)
+        .status()
+        .map_err(|err| FtagError::ConfigError(format!("Could not run {}: {}", program, err)))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(FtagError::ConfigError(format!("{} exited unsuccessfully: {}", program, status))),
+    }
+}
@@ -0,0 +1,232 @@
+use camino::Utf8Path;
+use rusqlite::{params_from_iter, Connection, ToSql};
+
+use crate::ftag::{self, get_store_dir_path, get_text_path, FtagError, TagQuery};
+use crate::store::{self, Backend};
+use crate::journal;
+
+/// A search given to `find`, described well enough to explain how it would be carried out.
+#[derive(Debug)]
+pub struct Search<'a> {
+    /// Tags that matching files must have
+    pub find: &'a [TagQuery],
+    /// Tags that matching files must not have
+    pub exclude: &'a [TagQuery],
+    /// Whether one of the `find` tags is enough, instead of all of them
+    pub any: bool,
+    /// Moment to look back to in seconds since the Unix epoch, if searching the past
+    pub time: Option<i64>,
+    /// Whether files take on the tags of tagged directories
+    pub inherit: bool,
+    /// Glob that paths must match, if any
+    pub path: Option<&'a str>,
+    /// List of paths that files must appear in, if any
+    pub files_from: Option<&'a Utf8Path>,
+    /// Whether any size or age filter was given
+    pub metadata: bool,
+    /// Whether only files tagged within a window of time are wanted
+    pub tagged: bool,
+    /// Whether only files with a certain rating are wanted
+    pub rating: bool,
+    /// Text that the notes of files must contain, if any
+    pub note: Option<&'a str>,
+    /// Whether only files changed since they were tagged are wanted
+    pub dirty: bool,
+}
+
+/// One stage of carrying out a search, in the order they happen.
+#[derive(Debug)]
+pub struct Stage {
+    /// What the stage does
+    pub title: String,
+    /// How it does it, and how many rows are involved
+    pub details: Vec<String>,
+}
+
+impl Stage {
+    fn new(title: &str, details: Vec<String>) -> Self {
+        Stage { title: title.to_string(), details }
+    }
+}
+
+/// Explain how a search would be carried out, without pruning or changing anything.
+///
+/// Row counts come from the database as it is now, so they are what the search would find if run right away.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, the path glob is invalid, or errors occur when reading the database.
+pub fn explain(search: &Search) -> Result<Vec<Stage>, FtagError> {
+    let mut stages = vec![];
+
+    // Pruning only happens when searching the current tags
+    if search.time.is_none() {
+        let detail = match ftag::prune_due()? {
+            true => "Runs first, checking that every tracked path still exists on disk",
+            false => "Skipped, the database is large and was pruned less than an hour ago",
+        };
+        stages.push(Stage::new("Prune removed paths", vec![detail.to_string()]));
+    }
+
+    let rows = match search.time {
+        Some(time) => journal::rows_asof(time)?,
+        None => ftag::get_all_rows()?,
+    };
+    // Directories need every row to pass their tags down, so only a plain search of the current tags is done in SQL
+    let sql_filter = match (search.time, search.inherit) {
+        (None, false) => ftag::sql_filter(search.find, search.exclude, search.any)?,
+        _ => None,
+    };
+    stages.push(match (store::current_backend()?, search.time) {
+        (Backend::Sqlite, Some(time)) => {
+            let conn = store::open_sqlite("Explaining searches")?;
+            let entries: i64 = conn.query_row("SELECT COUNT(*) FROM journal", (), |row| row.get(0))?;
+            let mut stage = query_stage(&conn, "Reconstruct past tags from the journal", journal::ASOF_SQL, &[time])?;
+            stage.details.push(format!("Journal entries: {}", entries));
+            stage.details.push(format!("Rows returned: {}", rows.len()));
+            stage
+        },
+        (Backend::Sqlite, None) => {
+            let conn = store::open_sqlite("Explaining searches")?;
+            match &sql_filter {
+                Some((filter, params)) => {
+                    let sql = format!("SELECT path, tags FROM tags{}", filter);
+                    let returned: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM tags{}", filter), params_from_iter(params), |row| row.get(0))?;
+                    let mut stage = query_stage(&conn, "Read the tags of matching rows", &sql, params)?;
+                    stage.details.push("Each query is matched against every distinct tag once, then the rows holding those tags are looked up in file_tags".to_string());
+                    stage.details.push(format!("Rows in the database: {}", rows.len()));
+                    stage.details.push(format!("Rows returned: {}", returned));
+                    stage
+                },
+                None => {
+                    let mut stage = query_stage(&conn, "Read the current tags", store::ALL_ROWS_SQL, &[] as &[i64])?;
+                    stage.details.push(format!("Rows returned: {}", rows.len()));
+                    stage
+                },
+            }
+        },
+        (Backend::Json, _) => Stage::new(
+            "Read the current tags",
+            vec![
+                format!("Reads one JSON file per path from {}/paths, which has no index", get_store_dir_path()),
+                format!("Rows returned: {}", rows.len()),
+            ],
+        ),
+        (Backend::Text, _) => Stage::new(
+            "Read the current tags",
+            vec![format!("Reads every line of {}, which has no index", get_text_path()), format!("Rows returned: {}", rows.len())],
+        ),
+    });
+
+    let rows = match search.inherit {
+        true => {
+            let dirs = rows.iter().filter(|(path, tags)| !tags.is_empty() && Utf8Path::new(path).is_dir()).count();
+            let rows = ftag::inherit_tags(rows)?;
+            stages.push(Stage::new(
+                "Give files the tags of their directories",
+                vec![format!("Walks {} tagged directories on disk", dirs), format!("Rows afterwards: {}", rows.len())],
+            ));
+            rows
+        },
+        false => rows,
+    };
+
+    let mut details = match sql_filter {
+        Some(_) => vec!["Checks the returned rows again in memory, which also sorts their tags".to_string()],
+        None => vec!["Compares in memory against every tag of every row, no full-text search or index is used".to_string()],
+    };
+    let needed = match search.any {
+        true => {
+            details.push("Needs at least one of the tags it may have".to_string());
+            "May"
+        },
+        false => "Must",
+    };
+    for (query, wanted) in search.find.iter().map(|query| (query, needed)).chain(search.exclude.iter().map(|query| (query, "Must not"))) {
+        details.push(format!("{} have {}", wanted, query.describe()));
+    }
+    let mut files = ftag::filter_rows(rows, search.find, search.exclude, search.any);
+    details.push(format!("Rows matching: {}", files.len()));
+    stages.push(Stage::new("Match tags", details));
+
+    if let Some(pattern) = search.path {
+        files = ftag::filter_paths(files, pattern)?;
+        stages.push(Stage::new(
+            "Match paths",
+            vec![format!("Keeps paths matching {}, without touching the disk", pattern), format!("Rows afterwards: {}", files.len())],
+        ));
+    }
+
+    // The list may be standard input, which can't be read without taking it from the search
+    if let Some(list) = search.files_from {
+        stages.push(Stage::new(
+            "Match the listed paths",
+            vec![format!("Keeps paths listed in {}, at most {} rows", list, files.len())],
+        ));
+    }
+
+    if search.metadata {
+        stages.push(Stage::new(
+            "Check sizes and ages",
+            vec![format!("Reads the metadata of up to {} files on disk", files.len())],
+        ));
+    }
+
+    if search.tagged {
+        stages.push(Stage::new(
+            "Check when tags were added",
+            vec![format!("Reads every row of tag_times and keeps up to {} files tagged in the window", files.len())],
+        ));
+    }
+
+    if search.rating {
+        stages.push(Stage::new(
+            "Check ratings",
+            vec![format!("Reads every row of ratings and keeps up to {} rated files", files.len())],
+        ));
+    }
+
+    if let Some(text) = search.note {
+        stages.push(Stage::new(
+            "Match notes",
+            vec![format!("Reads every row of notes and keeps up to {} files whose note contains {:?}", files.len(), text)],
+        ));
+    }
+
+    if search.dirty {
+        stages.push(Stage::new(
+            "Check for changes since tagging",
+            vec![format!("Hashes up to {} files with git and compares them to the recorded blobs", files.len())],
+        ));
+    }
+
+    Ok(stages)
+}
+
+/// Describe how SQLite runs a query, along with any index it uses.
+///
+/// # Failure
+///
+/// Returns `Err` if the query could not be planned.
+fn query_stage(conn: &Connection, title: &str, sql: &str, params: &[impl ToSql]) -> Result<Stage, FtagError> {
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut statement = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+    let plan: Vec<String> = statement
+        .query_map(params_from_iter(params), |row| row.get(3))?
+        .collect::<Result<_, _>>()?;
+
+    let mut details = vec![format!("SQL: {}", sql)];
+    details.extend(plan.iter().map(|step| format!("Plan: {}", step)));
+    // Scanning the tags of one row with json_each is cheap, unlike scanning a whole table
+    for table in plan.iter().filter_map(|step| step.strip_prefix("SCAN ")).filter(|table| !table.contains("VIRTUAL TABLE")) {
+        details.push(format!("Full scan: every row of {} is read", table));
+    }
+
+    let indexes: Vec<&str> = plan.iter().filter_map(|step| step.split_once(" USING ").map(|(_, index)| index)).collect();
+    details.push(match indexes.is_empty() {
+        true => "Indexes: none".to_string(),
+        false => format!("Indexes: {}", indexes.join(", ")),
+    });
+
+    Ok(Stage::new(title, details))
+}
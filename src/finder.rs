@@ -0,0 +1,381 @@
+use camino::Utf8Path;
+use std::collections::{HashMap, HashSet};
+
+use crate::ftag::{self, FtagError, TagChange};
+use crate::meta::{TagColor, TagMeta};
+
+/// Extended attribute where Finder keeps the tags of a file.
+const TAGS_XATTR: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// Magic bytes every binary property list starts with.
+const BPLIST_MAGIC: &[u8] = b"bplist00";
+
+/// Size of the trailer at the end of a binary property list.
+const TRAILER_SIZE: usize = 32;
+
+/// Read a big-endian unsigned number of 1 to 8 bytes.
+fn read_uint(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |number, byte| (number << 8) | u64::from(*byte)))
+}
+
+/// The parts of a binary property list needed to find its objects.
+struct Bplist<'a> {
+    data: &'a [u8],
+    offset_size: usize,
+    ref_size: usize,
+    objects: usize,
+    table: usize,
+}
+
+impl<'a> Bplist<'a> {
+    /// Read the trailer of a binary property list.
+    fn new(data: &'a [u8]) -> Option<Self> {
+        if !data.starts_with(BPLIST_MAGIC) || data.len() < BPLIST_MAGIC.len() + TRAILER_SIZE {
+            return None;
+        }
+        let trailer = &data[data.len() - TRAILER_SIZE..];
+        Some(Bplist {
+            data,
+            offset_size: usize::from(trailer[6]),
+            ref_size: usize::from(trailer[7]),
+            objects: usize::try_from(read_uint(&trailer[8..16])?).ok()?,
+            table: usize::try_from(read_uint(&trailer[24..32])?).ok()?,
+        })
+        .filter(|plist| plist.top().is_some())
+    }
+
+    /// Return the number of the object at the top of the list.
+    fn top(&self) -> Option<usize> {
+        usize::try_from(read_uint(&self.data[self.data.len() - TRAILER_SIZE + 16..self.data.len() - TRAILER_SIZE + 24])?).ok()
+    }
+
+    /// Read `size` bytes at `start` as a number.
+    fn uint_at(&self, start: usize, size: usize) -> Option<usize> {
+        usize::try_from(read_uint(self.data.get(start..start.checked_add(size)?)?)?).ok()
+    }
+
+    /// Return where an object starts, by looking it up in the offset table.
+    fn offset(&self, object: usize) -> Option<usize> {
+        if object >= self.objects {
+            return None;
+        }
+        self.uint_at(self.table.checked_add(object.checked_mul(self.offset_size)?)?, self.offset_size)
+    }
+
+    /// Return the kind of the object at `offset`, how many items it holds, and where they start.
+    fn header(&self, offset: usize) -> Option<(u8, usize, usize)> {
+        let marker = *self.data.get(offset)?;
+        let (kind, count) = (marker >> 4, marker & 0x0F);
+        if count != 0x0F {
+            return Some((kind, usize::from(count), offset + 1));
+        }
+
+        // Longer counts follow as an integer object of 1, 2, 4, or 8 bytes
+        let int_marker = *self.data.get(offset + 1)?;
+        if int_marker >> 4 != 0x1 || int_marker & 0x0F > 3 {
+            return None;
+        }
+        let size = 1 << (int_marker & 0x0F);
+        Some((kind, self.uint_at(offset + 2, size)?, offset + 2 + size))
+    }
+
+    /// Read the string object numbered `object`, which may be ASCII or UTF-16.
+    fn string(&self, object: usize) -> Option<String> {
+        let (kind, count, start) = self.header(self.offset(object)?)?;
+        match kind {
+            0x5 => String::from_utf8(self.data.get(start..start.checked_add(count)?)?.to_vec()).ok(),
+            0x6 => {
+                let bytes = self.data.get(start..start.checked_add(count.checked_mul(2)?)?)?;
+                let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                String::from_utf16(&units).ok()
+            },
+            _ => None,
+        }
+    }
+
+    /// Read the array object numbered `object`, returning the numbers of the objects it holds.
+    fn array(&self, object: usize) -> Option<Vec<usize>> {
+        let (kind, count, start) = self.header(self.offset(object)?)?;
+        if kind != 0xA {
+            return None;
+        }
+        (0..count).map(|index| self.uint_at(start.checked_add(index.checked_mul(self.ref_size)?)?, self.ref_size)).collect()
+    }
+}
+
+/// Parse the binary property list Finder keeps tags in, which holds a list of names like `Work` or `Red\n6`.
+///
+/// Finder adds the number of the tag's color after a newline, which is left off.
+/// Returns `None` if the data isn't a binary property list holding a list of strings.
+pub fn parse_tags(data: &[u8]) -> Option<Vec<String>> {
+    let plist = Bplist::new(data)?;
+    let mut tags = vec![];
+    for object in plist.array(plist.top()?)? {
+        let tag = plist.string(object)?;
+        let name = tag.split('\n').next().unwrap_or_default().trim();
+        if !name.is_empty() {
+            tags.push(name.to_string());
+        }
+    }
+    Some(tags)
+}
+
+/// Append a big-endian number of `size` bytes.
+fn push_uint(data: &mut Vec<u8>, number: usize, size: usize) {
+    data.extend(&(number as u64).to_be_bytes()[8 - size..]);
+}
+
+/// Return how many bytes are needed to write numbers up to `max`.
+fn uint_size(max: usize) -> usize {
+    match max {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFFFF_FFFF => 4,
+        _ => 8,
+    }
+}
+
+/// Append the marker of an object holding `count` items, like the characters of a string.
+fn push_header(data: &mut Vec<u8>, kind: u8, count: usize) {
+    if count < 0x0F {
+        data.push(kind << 4 | count as u8);
+        return;
+    }
+    let size = uint_size(count);
+    data.push(kind << 4 | 0x0F);
+    data.push(0x10 | size.trailing_zeros() as u8);
+    push_uint(data, count, size);
+}
+
+/// Write tags as the binary property list Finder keeps them in, the reverse of `parse_tags`.
+///
+/// * `tags` - Name of each tag along with the number of its Finder color, where 0 is no color
+pub fn encode_tags(tags: &[(String, u8)]) -> Vec<u8> {
+    let mut data = BPLIST_MAGIC.to_vec();
+    let ref_size = uint_size(tags.len());
+
+    // The list of tags is object 0, and each tag is the object after it
+    let mut offsets = vec![data.len()];
+    push_header(&mut data, 0xA, tags.len());
+    for number in 1..=tags.len() {
+        push_uint(&mut data, number, ref_size);
+    }
+    for (name, color) in tags {
+        offsets.push(data.len());
+        let text = match color {
+            0 => name.clone(),
+            color => format!("{}\n{}", name, color),
+        };
+        match text.is_ascii() {
+            true => {
+                push_header(&mut data, 0x5, text.len());
+                data.extend(text.as_bytes());
+            },
+            false => {
+                let units: Vec<u16> = text.encode_utf16().collect();
+                push_header(&mut data, 0x6, units.len());
+                data.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+            },
+        }
+    }
+
+    let table = data.len();
+    let offset_size = uint_size(table);
+    for offset in &offsets {
+        push_uint(&mut data, *offset, offset_size);
+    }
+    data.extend([0; 6]);
+    data.extend([offset_size as u8, ref_size as u8]);
+    push_uint(&mut data, offsets.len(), 8);
+    push_uint(&mut data, 0, 8);
+    push_uint(&mut data, table, 8);
+    data
+}
+
+/// Number of the Finder color closest to a tag color. Finder has no black, white, or cyan, so gray, no color, and
+/// blue stand in for them.
+fn finder_color(color: TagColor) -> u8 {
+    match color {
+        TagColor::Black => 1,
+        TagColor::Green => 2,
+        TagColor::Magenta => 3,
+        TagColor::Blue | TagColor::Cyan => 4,
+        TagColor::Yellow => 5,
+        TagColor::Red => 6,
+        TagColor::White => 0,
+    }
+}
+
+/// Return the property list holding the Finder tags of a file, or `None` if it has no tags.
+///
+/// # Failure
+///
+/// Returns `Err` if not on macOS, or the tags could not be read.
+pub fn read_raw_tags(path: &Utf8Path) -> Result<Option<Vec<u8>>, FtagError> {
+    os::read_xattr(path, TAGS_XATTR)
+}
+
+/// Write the tags of every tracked file beneath a directory into Finder, replacing the Finder tags they had.
+///
+/// Tags keep the colors given with `ftag tag color`, as near as Finder's colors allow. Files whose Finder tags
+/// already match are left alone, and files that no longer exist are skipped. Returns how the Finder tags of each
+/// file changed, in path order.
+///
+/// * `dir` - Directory to look in, relative to the database root
+/// * `meta` - Colors of the tags
+/// * `dry_run` - Work out what would change without changing anything
+///
+/// # Failure
+///
+/// Returns `Err` if not on macOS, there is no database, or the tags of a file could not be read or written.
+pub fn sync_finder(dir: &Utf8Path, meta: &HashMap<String, TagMeta>, dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    let mut rows = ftag::get_all_rows()?;
+    rows.sort_by(|(first, _), (second, _)| first.cmp(second));
+
+    let mut changes = vec![];
+    for (path, tags) in rows {
+        let file = Utf8Path::new(&path);
+        if (dir != "." && !file.starts_with(dir)) || !file.exists() {
+            continue;
+        }
+
+        let mut wanted: Vec<(String, u8)> = tags
+            .iter()
+            .map(|tag| (tag.clone(), meta.get(tag).and_then(|meta| meta.color).map_or(0, finder_color)))
+            .collect();
+        wanted.sort();
+        let data = match wanted.is_empty() {
+            true => None,
+            false => Some(encode_tags(&wanted)),
+        };
+
+        // Tags Finder can't read are replaced like any others
+        let current = read_raw_tags(file)?;
+        if current == data {
+            continue;
+        }
+        let before: HashSet<String> = current.as_deref().and_then(parse_tags).unwrap_or_default().into_iter().collect();
+        changes.push(TagChange::between(&path, &before, &tags));
+        if !dry_run {
+            os::write_xattr(file, TAGS_XATTR, data.as_deref())?;
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(target_os = "macos")]
+mod os {
+    use camino::Utf8Path;
+    use std::ffi::CString;
+    use std::{io, ptr};
+
+    use crate::ftag::FtagError;
+
+    /// Read an extended attribute of a file, or return `None` if the file doesn't have it.
+    pub fn read_xattr(path: &Utf8Path, name: &str) -> Result<Option<Vec<u8>>, FtagError> {
+        let c_path = CString::new(path.as_str()).map_err(io::Error::from)?;
+        let c_name = CString::new(name).map_err(io::Error::from)?;
+
+        // Ask for the size first, then read it into a buffer that big
+        // SAFETY: both strings are NUL terminated, and a null buffer with size 0 only asks for the size
+        let size = unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), ptr::null_mut(), 0, 0, 0) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENOATTR) => Ok(None),
+                _ => Err(err.into()),
+            };
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        // SAFETY: buf is exactly as long as the size passed
+        let read = unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), buf.as_mut_ptr().cast(), buf.len(), 0, 0) };
+        if read < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        buf.truncate(read as usize);
+        Ok(Some(buf))
+    }
+
+    /// Set an extended attribute of a file, or remove it if `value` is `None`.
+    pub fn write_xattr(path: &Utf8Path, name: &str, value: Option<&[u8]>) -> Result<(), FtagError> {
+        let c_path = CString::new(path.as_str()).map_err(io::Error::from)?;
+        let c_name = CString::new(name).map_err(io::Error::from)?;
+
+        // SAFETY: both strings are NUL terminated, and value is read for exactly its length
+        let result = unsafe {
+            match value {
+                Some(value) => libc::setxattr(c_path.as_ptr(), c_name.as_ptr(), value.as_ptr().cast(), value.len(), 0, 0),
+                None => libc::removexattr(c_path.as_ptr(), c_name.as_ptr(), 0),
+            }
+        };
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            // Removing tags from a file without any is nothing to worry about
+            if value.is_some() || err.raw_os_error() != Some(libc::ENOATTR) {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod os {
+    use camino::Utf8Path;
+
+    use crate::ftag::FtagError;
+
+    fn unsupported() -> FtagError {
+        FtagError::ArgumentError("Finder tags are only supported on macOS".to_string())
+    }
+
+    pub fn read_xattr(_path: &Utf8Path, _name: &str) -> Result<Option<Vec<u8>>, FtagError> {
+        Err(unsupported())
+    }
+
+    pub fn write_xattr(_path: &Utf8Path, _name: &str, _value: Option<&[u8]>) -> Result<(), FtagError> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reading and writing the tags of real files needs macOS, so the property lists are checked here as well
+    #[test]
+    fn reads_what_finder_writes() {
+        // ["Work", "Red\n6", "Ünïcode"] as Finder writes it
+        let mut data = BPLIST_MAGIC.to_vec();
+        let offsets = [data.len(), data.len() + 4];
+        data.extend([0xA3, 1, 2, 3]);
+        data.extend([0x54]);
+        data.extend(b"Work");
+        let red = data.len();
+        data.extend([0x55]);
+        data.extend(b"Red\n6");
+        let unicode = data.len();
+        data.extend([0x67]);
+        data.extend("Ünïcode".encode_utf16().flat_map(u16::to_be_bytes));
+        let table = data.len();
+        data.extend([offsets[0] as u8, offsets[1] as u8, red as u8, unicode as u8]);
+        data.extend([0, 0, 0, 0, 0, 0, 1, 1]);
+        data.extend(4u64.to_be_bytes());
+        data.extend(0u64.to_be_bytes());
+        data.extend((table as u64).to_be_bytes());
+
+        assert_eq!(parse_tags(&data), Some(vec!["Work".to_string(), "Red".to_string(), "Ünïcode".to_string()]));
+        assert_eq!(parse_tags(b"bplist00"), None);
+
+        let many: Vec<(String, u8)> = (0..300).map(|number| (format!("tag {}", number), (number % 8) as u8)).collect();
+        let names: Vec<String> = many.iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(parse_tags(&encode_tags(&many)), Some(names));
+        assert_eq!(parse_tags(&encode_tags(&[("Ünïcode".to_string(), 6)])), Some(vec!["Ünïcode".to_string()]));
+        assert_eq!(parse_tags(&encode_tags(&[])), Some(vec![]));
+        assert_eq!(parse_tags(b"<plist></plist>"), None);
+    }
+}
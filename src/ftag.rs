@@ -1,9 +1,20 @@
-use camino::Utf8PathBuf;
-use itertools::Itertools;
-use rusqlite::{params, Connection};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "full")]
+use rayon::prelude::*;
+#[cfg(feature = "full")]
 use serde::{Deserialize, Serialize};
 use std::{io, collections::{hash_map::HashMap, hash_set::HashSet}};
+#[cfg(feature = "full")]
+use std::ops::ControlFlow;
 
+use crate::store::{self, Backend, TagStore};
+use crate::{journal, paths, settings, validate};
+#[cfg(feature = "full")]
+use crate::{backup, git, keyvalue, migrate};
+#[cfg(feature = "full")]
+use crate::units::{ByteSize, TimeSpan};
+
+#[cfg(feature = "full")]
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 struct Taglist {
@@ -11,166 +22,688 @@ struct Taglist {
 }
 
 /// Errors that can occur when using ftag functions
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum FtagError {
-    IoError(io::ErrorKind),
+    #[error("IO Error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Database error: Database not initialized")]
     NoDatabaseError,
-    DatabaseError(rusqlite::Error),
-    JsonError(serde_json::Error),
+    #[error("Database Error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+    #[cfg(feature = "full")]
+    #[error("JSON Error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(not(feature = "full"))]
+    #[error("JSON Error: {0}")]
+    JsonError(String),
+    #[error("Restricted: {0}")]
+    RestrictedError(String),
+    #[error("Config Error: {0}")]
+    ConfigError(String),
+    #[error("Invalid tag: {0}")]
+    InvalidTagError(String),
+    #[error("{0}")]
+    ArgumentError(String),
+    #[error("Git Error: {0}")]
+    GitError(String),
+    #[cfg(feature = "self-update")]
+    #[error("Update Error: {0}")]
+    UpdateError(String),
 }
-impl From<rusqlite::Error> for FtagError {
-    fn from(err: rusqlite::Error) -> Self {
-        FtagError::DatabaseError(err)
+
+impl From<io::ErrorKind> for FtagError {
+    fn from(kind: io::ErrorKind) -> Self {
+        FtagError::IoError(kind.into())
     }
 }
-impl From<serde_json::Error> for FtagError {
-    fn from(err: serde_json::Error) -> Self {
-        FtagError::JsonError(err)
+
+impl FtagError {
+    /// Check whether this error is about a file that doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, FtagError::IoError(err) if err.kind() == io::ErrorKind::NotFound)
     }
 }
-impl From<io::ErrorKind> for FtagError {
-    fn from(err: io::ErrorKind) -> Self {
-        FtagError::IoError(err)
+
+/// How the tags of one file would change, as shown by `--dry-run`.
+#[derive(Debug, Default)]
+pub struct TagChange {
+    /// Path of the file, as stored in the database
+    pub path: String,
+    /// Tags the file would gain, sorted
+    pub added: Vec<String>,
+    /// Tags the file would lose, sorted
+    pub removed: Vec<String>,
+}
+
+impl TagChange {
+    /// Compare the tags of a file before and after a change.
+    pub fn between(path: &str, before: &HashSet<String>, after: &HashSet<String>) -> Self {
+        let mut added: Vec<String> = after.difference(before).cloned().collect();
+        let mut removed: Vec<String> = before.difference(after).cloned().collect();
+        added.sort();
+        removed.sort();
+        TagChange { path: path.to_string(), added, removed }
     }
 }
-impl ToString for FtagError {
-    fn to_string(&self) -> String {
+
+/// Separator between the levels of a hierarchical tag, like `photo/vacation/2024`.
+pub const TAG_SEPARATOR: char = '/';
+
+/// Separator between the key and value of a tag, like `year=2023`.
+pub const KEY_SEPARATOR: char = '=';
+
+/// How tags being searched for are compared against the tags stored in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum CaseMode {
+    /// Always match case exactly
+    Sensitive,
+    /// Never consider case
+    Insensitive,
+    /// Ignore case unless the searched tag contains an uppercase letter
+    Smart,
+}
+
+impl CaseMode {
+    /// Decide whether `query` should be compared with case taken into account.
+    pub fn is_sensitive(self, query: &str) -> bool {
         match self {
-            FtagError::IoError(err) => format!("IO Error: {}", err.to_string()),
-            FtagError::NoDatabaseError => "Database error: Database not initialized".to_string(),
-            FtagError::DatabaseError(err) => format!("Database Error: {}", err.to_string()),
-            FtagError::JsonError(err) => format!("JSON Error: {}", err.to_string()),
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+            CaseMode::Smart => query.chars().any(char::is_uppercase),
         }
     }
 }
 
-/// Get the path to the database.
-fn get_db_path() -> Utf8PathBuf {
-    Utf8PathBuf::from(".ftag.db")
+/// Orders that found files can be listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SortKey {
+    /// Alphabetically by path, ignoring case
+    Name,
+    /// Most recently modified first
+    Mtime,
+    /// Largest first
+    Size,
+    /// Most tags first
+    TagCount,
+}
+
+/// Alphabetical order of paths, ignoring case in any script. Paths differing only in case are ordered by their bytes,
+/// so every way of sorting files by name, including SQLite's, agrees on the order.
+pub(crate) fn compare_paths(a: &str, b: &str) -> std::cmp::Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b))
 }
 
-/// Update or create a database entry for a path.
+/// Sort found files in place, breaking ties alphabetically.
+/// 
+/// Files whose metadata can't be read sort as if they were the oldest and smallest.
 /// 
-/// * `path` - Path to save in the database row, used to search for existing entry.
-/// * `serialized` - JSON representation of the tags to save
+/// * `files` - Paths along with their tags, such as from `find_tags`
+/// * `key` - What to sort by
+/// * `reverse` - Reverse the sorting order
+pub fn sort_files(files: &mut [(String, Vec<String>)], key: SortKey, reverse: bool) {
+    // Alphabetize first, so the stable sort below leaves ties in alphabetical order
+    files.sort_by(|a, b| compare_paths(&a.0, &b.0));
+
+    match key {
+        SortKey::Name => (),
+        SortKey::Mtime => files.sort_by_cached_key(|file| {
+            let modified = std::fs::metadata(&file.0).and_then(|metadata| metadata.modified()).ok();
+            std::cmp::Reverse(modified)
+        }),
+        SortKey::Size => files.sort_by_cached_key(|file| {
+            std::cmp::Reverse(std::fs::metadata(&file.0).map(|metadata| metadata.len()).unwrap_or(0))
+        }),
+        SortKey::TagCount => files.sort_by_key(|file| std::cmp::Reverse(file.1.len())),
+    }
+
+    if reverse {
+        files.reverse();
+    }
+}
+
+/// Keep only the found files whose paths match a glob pattern, like `docs/**/*.md`.
+/// 
+/// `*` and `?` never match a `/`, but `**` matches any number of directories.
+/// 
+/// * `files` - Paths along with their tags, such as from `find_tags`
+/// * `pattern` - Glob pattern that paths must match
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `pattern` is not a valid glob.
+#[cfg(feature = "full")]
+pub fn filter_paths(files: Vec<(String, Vec<String>)>, pattern: &str) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let matcher = globset::GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|err| FtagError::ArgumentError(format!("Invalid path pattern: {}", err)))?
+        .compile_matcher();
+
+    // Paths may be stored as ./docs/file.md, which should still match docs/**
+    let files = files
+        .into_iter()
+        .filter(|(file, _)| matcher.is_match(file.strip_prefix("./").unwrap_or(file)))
+        .collect();
+
+    Ok(files)
+}
+
+/// Give every file beneath a tagged directory the tags of that directory, along with its own.
+/// 
+/// Files beneath a tagged directory are included even if they were never tagged themselves.
+/// 
+/// * `rows` - Paths along with their tags, such as from `get_all_rows`
 /// 
 /// # Failure
 /// 
-/// Returns `Err` if there is no database in the current directory or if database queries or statements fail.
-fn update_row_into_db(path: &Utf8PathBuf, serialized: String) -> Result<(), FtagError> {
-    if !get_db_path().exists() {
-        return Err(FtagError::NoDatabaseError);
+/// Returns `Err` if a tagged directory could not be read.
+#[cfg(feature = "full")]
+pub fn inherit_tags(rows: Vec<(String, HashSet<String>)>) -> Result<Vec<(String, HashSet<String>)>, FtagError> {
+    let dirs: Vec<(String, HashSet<String>)> = rows
+        .iter()
+        .filter(|(path, tags)| !tags.is_empty() && Utf8Path::new(path).is_dir())
+        .cloned()
+        .collect();
+
+    let mut inherited: HashMap<String, HashSet<String>> = rows.into_iter().collect();
+    for (dir, tags) in dirs {
+        let mut files = vec![];
+        migrate::walk_files(Utf8Path::new(&dir), &mut files)?;
+
+        // The database itself may be beneath a tagged directory, but it isn't one of the tagged files
+        for file in files {
+            let file = paths::normalize(file.as_str());
+            if is_database_path(Utf8Path::new(&file)) {
+                continue;
+            }
+            inherited.entry(file).or_default().extend(tags.iter().cloned());
+        }
     }
-    
-    let conn = Connection::open(get_db_path())?;
-    
-    // Query the database for that path
-    let query = query_db_for_path(path);
-    
-    // Depending on whether a row exists, insert or update
-    match query {
-        Err(_) => {
-            // Err means there was no such row, so we insert
-            let mut stmt = conn.prepare("INSERT INTO tags(path, tags) VALUES (?, ?)")?;
-            stmt.execute(params![path.to_string(), serialized])?;
-        },
-        Ok((id, _)) => {
-            // Ok means there was a row, so we update it
-            let mut stmt = conn.prepare("UPDATE tags SET tags= ? WHERE id = ?")?;
-            stmt.execute(params![serialized, id])?;
-        },
+
+    Ok(inherited.into_iter().collect())
+}
+
+/// Keep only the found files that also appear in a list of paths from another tool.
+/// 
+/// The list holds one path per line, or is separated by NUL bytes if it contains any,
+/// as printed by `fd -0` or `find -print0`. Listed paths are relative to where ftag was started.
+/// 
+/// * `files` - Paths along with their tags, such as from `find_tags`
+/// * `list` - Paths to keep
+#[cfg(feature = "full")]
+pub fn filter_listed(files: Vec<(String, Vec<String>)>, list: &str) -> Vec<(String, Vec<String>)> {
+    let separator = if list.contains('\0') { '\0' } else { '\n' };
+    let listed: HashSet<String> = list
+        .split(separator)
+        .map(|path| path.strip_suffix('\r').unwrap_or(path))
+        .filter(|path| !path.is_empty())
+        .filter_map(|path| paths::to_stored(Utf8Path::new(path)).ok())
+        .map(String::from)
+        .collect();
+
+    files
+        .into_iter()
+        .filter(|(file, _)| listed.contains(&paths::normalize(file)))
+        .collect()
+}
+
+/// Keep only the found files whose size and modification time are within the given limits.
+/// 
+/// Files whose metadata can't be read are dropped whenever a limit is given.
+/// 
+/// * `files` - Paths along with their tags, such as from `find_tags`
+/// * `larger` - Smallest size a file may have
+/// * `smaller` - Size a file must be below
+/// * `newer` - How long ago a file may have last been modified, at most
+/// * `older` - How long ago a file must have last been modified, at least
+#[cfg(feature = "full")]
+pub fn filter_metadata(files: Vec<(String, Vec<String>)>, larger: Option<ByteSize>, smaller: Option<ByteSize>, newer: Option<TimeSpan>, older: Option<TimeSpan>) -> Vec<(String, Vec<String>)> {
+    if larger.is_none() && smaller.is_none() && newer.is_none() && older.is_none() {
+        return files;
     }
 
-    Ok(())
+    let now = std::time::SystemTime::now();
+    files
+        .into_iter()
+        .filter(|(file, _)| {
+            let Ok(metadata) = std::fs::metadata(file) else {
+                return false;
+            };
+            let Ok(modified) = metadata.modified() else {
+                return false;
+            };
+            // Files modified in the future count as brand new
+            let age = now.duration_since(modified).unwrap_or_default();
+
+            larger.is_none_or(|size| metadata.len() >= size.0)
+                && smaller.is_none_or(|size| metadata.len() < size.0)
+                && newer.is_none_or(|span| age <= span.0)
+                && older.is_none_or(|span| age >= span.0)
+        })
+        .collect()
+}
+
+/// Check whether `tag` is `query` itself, one of its descendants in the tag hierarchy, or `query` given a value.
+/// 
+/// * `query` - Tag being searched for, such as `project`
+/// * `tag` - Tag to test, such as `project/ftag`
+/// * `case` - How to treat differences in case between `query` and `tag`
+pub fn tag_matches(query: &str, tag: &str, case: CaseMode) -> bool {
+    if !case.is_sensitive(query) {
+        return tag_matches(&query.to_lowercase(), &tag.to_lowercase(), CaseMode::Sensitive);
+    }
+
+    // A key also matches every value given to it, so `year` matches `year=2023`
+    match tag.strip_prefix(query) {
+        Some(rest) => rest.is_empty() || rest.starts_with(TAG_SEPARATOR) || rest.starts_with(KEY_SEPARATOR),
+        None => false,
+    }
+}
+
+/// A tag being searched for, ready to be compared against the stored tags.
+#[derive(Debug, Clone)]
+pub struct TagQuery {
+    /// The query as it was typed
+    pub text: String,
+    /// Whether case is taken into account
+    sensitive: bool,
+    /// How stored tags are compared against it
+    kind: QueryKind,
+}
+
+/// Ways a `TagQuery` can match stored tags.
+#[derive(Debug, Clone)]
+enum QueryKind {
+    /// The tag itself or one of its descendants, as in `tag_matches`
+    Tag,
+    /// A glob like `proj-*`, matching a tag or one of its ancestors
+    #[cfg(feature = "full")]
+    Glob(globset::GlobMatcher),
+    /// A regular expression found anywhere in the tag
+    #[cfg(feature = "full")]
+    Regex(regex::Regex),
+    /// A comparison like `year>=2020`, matching `key=value` tags
+    #[cfg(feature = "full")]
+    Compare(keyvalue::Comparison),
+}
+
+impl TagQuery {
+    /// Check whether a stored tag matches the query.
+    pub fn matches(&self, tag: &str) -> bool {
+        match &self.kind {
+            QueryKind::Tag => match self.sensitive {
+                true => tag_matches(&self.text, tag, CaseMode::Sensitive),
+                false => tag_matches(&self.text, tag, CaseMode::Insensitive),
+            },
+            // Like plain tags, a glob for a parent also matches everything beneath it
+            #[cfg(feature = "full")]
+            QueryKind::Glob(matcher) => tag
+                .match_indices(TAG_SEPARATOR)
+                .map(|(end, _)| &tag[..end])
+                .chain([tag])
+                .any(|ancestor| matcher.is_match(ancestor)),
+            #[cfg(feature = "full")]
+            QueryKind::Regex(regex) => regex.is_match(tag),
+            #[cfg(feature = "full")]
+            QueryKind::Compare(comparison) => comparison.matches(tag, self.sensitive),
+        }
+    }
+
+    /// Check whether a stored tag starts with the query, or matches it if the query is a glob or regular expression.
+    pub fn matches_prefix(&self, tag: &str) -> bool {
+        match (&self.kind, self.sensitive) {
+            (QueryKind::Tag, true) => tag.starts_with(&self.text),
+            (QueryKind::Tag, false) => tag.to_lowercase().starts_with(&self.text.to_lowercase()),
+            #[cfg(feature = "full")]
+            _ => self.matches(tag),
+        }
+    }
+
+    /// Describe in words which tags match, like `photo, a tag beneath photo/, or photo=VALUE, ignoring case`.
+    pub fn describe(&self) -> String {
+        let text = match &self.kind {
+            QueryKind::Tag => format!("{}, a tag beneath {}{}, or {}{}VALUE", self.text, self.text, TAG_SEPARATOR, self.text, KEY_SEPARATOR),
+            #[cfg(feature = "full")]
+            QueryKind::Glob(_) => format!("a tag matching the glob {}", self.text),
+            #[cfg(feature = "full")]
+            QueryKind::Regex(_) => format!("a tag matching the regular expression {}", self.text),
+            #[cfg(feature = "full")]
+            QueryKind::Compare(comparison) => {
+                format!("a tag {}{}VALUE where VALUE {} {}", comparison.key, KEY_SEPARATOR, comparison.op, comparison.value)
+            },
+        };
+        match self.sensitive {
+            true => format!("{}, matching case", text),
+            false => format!("{}, ignoring case", text),
+        }
+    }
+}
+
+/// Prepare tags typed by the user for searching.
+///
+/// Tags containing `*`, `?`, or `[` are globs, where `*` stays within one level of the hierarchy and `**` crosses them.
+/// Tags like `year>=2020` compare the values of `key=value` tags, as numbers when both values are numbers.
+/// The minimal build has neither, and searches for such tags literally.
+///
+/// * `queries` - Tags as they were typed by the user
+/// * `case` - How to treat differences in case between the given tags and the stored ones
+/// * `regex` - Treat every query as a regular expression instead
+///
+/// # Failure
+///
+/// Returns `Err` if a glob, regular expression, or comparison is invalid, there is no database, or the settings could
+/// not be read.
+pub fn parse_queries(queries: &[String], case: CaseMode, regex: bool) -> Result<Vec<TagQuery>, FtagError> {
+    let case = effective_case(case)?;
+
+    let mut parsed = vec![];
+    for text in queries {
+        let sensitive = case.is_sensitive(text);
+
+        // Comparisons come before globs, so a value may contain glob characters
+        #[cfg(feature = "full")]
+        if !regex {
+            if let Some(comparison) = keyvalue::parse_comparison(text).map_err(FtagError::ArgumentError)? {
+                parsed.push(TagQuery { text: text.clone(), sensitive, kind: QueryKind::Compare(comparison) });
+                continue;
+            }
+        }
+
+        let kind = match (regex, text.contains(['*', '?', '['])) {
+            #[cfg(feature = "full")]
+            (true, _) => QueryKind::Regex(
+                regex::RegexBuilder::new(text)
+                    .case_insensitive(!sensitive)
+                    .build()
+                    .map_err(|err| FtagError::ArgumentError(format!("Invalid regular expression {}: {}", text, err)))?,
+            ),
+            #[cfg(feature = "full")]
+            (false, true) => QueryKind::Glob(
+                globset::GlobBuilder::new(text)
+                    .literal_separator(true)
+                    .case_insensitive(!sensitive)
+                    .build()
+                    .map_err(|err| FtagError::ArgumentError(format!("Invalid tag pattern {}: {}", text, err)))?
+                    .compile_matcher(),
+            ),
+            _ => QueryKind::Tag,
+        };
+        parsed.push(TagQuery { text: text.clone(), sensitive, kind });
+    }
+
+    Ok(parsed)
+}
+
+/// Convert tags into the form they are stored in, according to the database settings.
+/// 
+/// * `tags` - Tags as they were typed by the user
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database or the settings could not be read.
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, FtagError> {
+    normalize_tags_in(&*store::open_store()?, tags)
 }
 
-/// Query the database for a given path, returning the id and tags on a success.
+/// Convert tags into the form they are stored in, according to the settings of an open store.
 /// 
-/// * `path` - Path to query for
+/// * `store` - Store whose settings apply
+/// * `tags` - Tags as they were typed by the user
 /// 
 /// # Failure
 /// 
-/// Returns Err if there is no database in the current directory or if database query fails.
-fn query_db_for_path(path: &Utf8PathBuf) -> Result<(u32, String), FtagError> {
-    if !get_db_path().exists() {
-        return Err(FtagError::NoDatabaseError);
+/// Returns `Err` if the settings could not be read.
+pub fn normalize_tags_in(store: &dyn TagStore, tags: Vec<String>) -> Result<Vec<String>, FtagError> {
+    let case_insensitive = settings::get_bool_setting_in(store, settings::CASE_INSENSITIVE)?;
+
+    let tags = tags
+        .into_iter()
+        .map(|tag| if case_insensitive { tag.to_lowercase() } else { tag })
+        .collect();
+
+    Ok(tags)
+}
+
+/// Check new tags against the naming rules of an open store, then convert them into the form they are stored in.
+/// 
+/// * `store` - Store whose settings apply
+/// * `tags` - New tags as they were typed by the user
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if a tag breaks the naming rules and validation is set to reject it, or the settings could not be read.
+pub fn validate_tags_in(store: &dyn TagStore, tags: Vec<String>) -> Result<Vec<String>, FtagError> {
+    let mode = settings::get_tag_validation_in(store)?;
+
+    let mut checked = vec![];
+    for tag in tags {
+        checked.push(validate::check_tag(&tag, mode)?);
     }
-    
-    // Prepare a query for the correct row of the database
-    let conn = Connection::open(get_db_path())?;
-    let mut stmt = conn.prepare("SELECT id, tags FROM tags WHERE path = ?")?;
-    
-    // Query for a row with matching path
-    let query = stmt.query_row(params![path.to_string()], |row| {
-        let id: u32 = row.get(0)?;
-        let json: String = row.get(1)?;
-        Ok((id, json))
-    })?;
 
-    Ok(query)
+    normalize_tags_in(store, checked)
+}
+
+/// Rewrite the tags of every row in the database into their normalized form, merging any that become equal.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database or errors occur when deserializing data or interacting with the database.
+#[cfg(feature = "full")]
+pub fn canonicalize_all_tags() -> Result<(), FtagError> {
+    let mut store = store::open_store()?;
+
+    // Read everything first, so rows aren't updated while they are being read
+    for (path, tags) in store.all_rows()? {
+        let normalized: HashSet<String> = normalize_tags_in(&*store, tags.iter().cloned().collect())?.into_iter().collect();
+        if normalized != tags {
+            store.set_tags(&path, &normalized)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deserialize the JSON stored in a row into its set of tags.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `json` is not a valid tag list.
+#[cfg(feature = "full")]
+pub(crate) fn parse_tags(json: &str) -> Result<HashSet<String>, FtagError> {
+    let deserialized: Taglist = serde_json::from_str(json)?;
+    Ok(deserialized.tags)
+}
+
+#[cfg(not(feature = "full"))]
+pub(crate) fn parse_tags(json: &str) -> Result<HashSet<String>, FtagError> {
+    crate::minijson::parse_tags(json).map_err(FtagError::JsonError)
+}
+
+/// Serialize a set of tags into the JSON stored in a row.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if the tags could not be serialized.
+#[cfg(feature = "full")]
+pub(crate) fn serialize_tags(tags: &HashSet<String>) -> Result<String, FtagError> {
+    Ok(serde_json::to_string(&Taglist { tags: tags.clone() })?)
+}
+
+#[cfg(not(feature = "full"))]
+pub(crate) fn serialize_tags(tags: &HashSet<String>) -> Result<String, FtagError> {
+    Ok(crate::minijson::serialize_tags(tags))
+}
+
+/// Get the path to the SQLite database.
+pub(crate) fn get_db_path() -> Utf8PathBuf {
+    Utf8PathBuf::from(".ftag.db")
+}
+
+/// Get the path to the directory used by the JSON backend.
+pub(crate) fn get_store_dir_path() -> Utf8PathBuf {
+    Utf8PathBuf::from(".ftag")
+}
+
+/// Get the path to the file used by the text backend.
+pub(crate) fn get_text_path() -> Utf8PathBuf {
+    Utf8PathBuf::from(".ftag.tsv")
+}
+
+/// Check whether a path relative to the database root is part of the database itself, rather than a file to tag.
+#[cfg(feature = "full")]
+pub(crate) fn is_database_path(path: &Utf8Path) -> bool {
+    // SQLite keeps its -wal, -shm, and -journal files next to the database
+    path.as_str().strip_prefix(get_db_path().as_str()).is_some_and(|rest| ["", "-wal", "-shm", "-journal"].contains(&rest))
+        || path.starts_with(get_store_dir_path())
+        || path.as_str().starts_with(get_text_path().as_str())
+        || path.starts_with(backup::DEFAULT_BACKUP_DIR)
 }
 
+/// Internal setting holding when the database was last pruned, in seconds since the Unix epoch.
+const LAST_PRUNE: &str = "last-prune";
+
+/// Internal setting holding how many paths were tracked after the last prune.
+const PRUNE_ENTRIES: &str = "prune-entries";
+
+/// Databases with fewer paths than this are cheap enough to prune every time.
+const PRUNE_ALWAYS_BELOW: usize = 2000;
+
+/// Larger databases are pruned again once the last prune is older than this many seconds.
+const PRUNE_INTERVAL: i64 = 3600;
+
 /// Go through every row in the database, removing entries for paths that no longer exist
 /// 
 /// # Failure
 /// 
 /// Returns `Err` if database does not exist or there are errors when interacting with the database.
-fn prune_db() -> Result<(), FtagError> {
-    if !get_db_path().exists() {
-        return Err(FtagError::NoDatabaseError);
+pub fn prune_db() -> Result<(), FtagError> {
+    let mut store = store::open_store()?;
+
+    // Find all paths that no longer exist
+    let rows = store.all_rows()?;
+    let to_remove = missing(&rows);
+
+    // Don't write to the database when nothing would change but the time of a recent prune
+    tracing::debug!("Pruning {} of {} paths that no longer exist", to_remove.len(), rows.len());
+    let entries = rows.len() - to_remove.len();
+    let (last, recorded) = prune_settings(&*store)?;
+    if to_remove.is_empty() && !is_stale(last) && recorded == Some(entries as i64) {
+        return Ok(());
     }
 
-    // Create an empty vector of paths to remove
-    let mut to_remove: Vec<String> = vec![];
+    // Remove them all in one transaction, which is far quicker than one each
+    store.batch(|store| {
+        for name in &to_remove {
+            store.remove_path(name)?;
+        }
 
-    // Go through the database and add all paths that no longer exist to to_remove
-    let conn = Connection::open(get_db_path())?;
-    let mut stmt = conn.prepare("SELECT path FROM tags;")?;
-    let result = stmt.query_map( params![],
-        |row| {
-            let name: String = row.get(0)?;   
-            let path = Utf8PathBuf::from(name.clone());
+        // Remember how big the database was, so later commands can decide whether pruning is worth it
+        store.set_setting(LAST_PRUNE, &journal::now().to_string())?;
+        store.set_setting(PRUNE_ENTRIES, &entries.to_string())
+    })
+}
 
-            if !path.exists() {
-                to_remove.push(name);
-            }
-            Ok(())
-        },
-    )?;
-    result.for_each(|_| ());
+/// Return the tracked paths that no longer exist, which `prune_db` would remove.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if database does not exist or there are errors when reading it.
+#[cfg(feature = "full")]
+pub fn missing_paths() -> Result<Vec<String>, FtagError> {
+    Ok(missing(&store::open_store()?.all_rows()?))
+}
 
-    // For all the rows that should be removed, remove them
-    let mut stmt = conn.prepare("DELETE FROM tags WHERE path = ?")?;
-    for name in to_remove {
-        stmt.execute(params![name])?;
-    }
+/// Return every path in the database, sorted, including ones that no longer exist.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if database does not exist or there are errors when reading it.
+#[cfg(feature = "full")]
+pub fn tracked_paths() -> Result<Vec<String>, FtagError> {
+    let mut paths: Vec<String> = store::open_store()?.all_rows()?.into_iter().map(|(name, _)| name).collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Pick out the rows whose paths no longer exist.
+///
+/// The full build checks many paths at once, since waiting on each in turn is slow for large databases or network
+/// drives.
+fn missing(rows: &[(String, HashSet<String>)]) -> Vec<String> {
+    #[cfg(feature = "full")]
+    let names = rows.par_iter();
+    #[cfg(not(feature = "full"))]
+    let names = rows.iter();
+
+    let mut missing: Vec<String> = names
+        .map(|(name, _)| name)
+        .filter(|name| !Utf8PathBuf::from(name).exists())
+        .cloned()
+        .collect();
+    missing.sort();
+    missing
+}
 
+/// Prune the database if it is small, or if it has been a while since the last prune.
+/// 
+/// This keeps commands quick on large databases, at the cost of removed files
+/// sometimes showing up until the next prune.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if database does not exist or there are errors when interacting with the database.
+pub fn maybe_prune() -> Result<(), FtagError> {
+    if prune_due()? {
+        prune_db()?;
+    }
     Ok(())
 }
 
+/// Decide whether `maybe_prune` would prune the database now.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if database does not exist or its settings could not be read.
+pub(crate) fn prune_due() -> Result<bool, FtagError> {
+    let (last, entries) = prune_settings(&*store::open_store()?)?;
+    let small = entries.is_none_or(|entries| entries < PRUNE_ALWAYS_BELOW as i64);
+    let stale = is_stale(last);
+    tracing::debug!(
+        "Prune {}: {} paths after the last prune, last pruned {}",
+        if small || stale { "is due" } else { "skipped" },
+        entries.map_or("unknown".to_string(), |entries| entries.to_string()),
+        last.map_or("never".to_string(), |last| format!("{}s ago", journal::now() - last)),
+    );
+    Ok(small || stale)
+}
+
+/// Read when `store` was last pruned and how many paths it tracked afterwards.
+///
+/// # Failure
+///
+/// Returns `Err` if the settings could not be read.
+fn prune_settings(store: &dyn TagStore) -> Result<(Option<i64>, Option<i64>), FtagError> {
+    let read = |key| -> Result<Option<i64>, FtagError> { Ok(store.get_setting(key)?.and_then(|value| value.parse().ok())) };
+    Ok((read(LAST_PRUNE)?, read(PRUNE_ENTRIES)?))
+}
+
+/// Return whether a prune at `last` is older than `PRUNE_INTERVAL`, or never happened.
+fn is_stale(last: Option<i64>) -> bool {
+    last.is_none_or(|last| journal::now() - last >= PRUNE_INTERVAL)
+}
+
 /// Initialize the database if it does not already exist, returning whether it was created.
 /// 
+/// * `backend` - How to store the database on disk
+/// 
 /// # Failure
 /// 
 /// Returns `Err` if a database already exists in the current directory
-pub fn init_db() -> Result<(), FtagError> {
-    // Refuse to init if the database already exists
-    if get_db_path().exists() {
-        return Err(FtagError::IoError(io::ErrorKind::AlreadyExists));
-    }
-
-    // Create a database and a table within it
-    let conn = Connection::open(get_db_path())?;
-    conn.execute(
-        "CREATE TABLE tags (
-            id      INTEGER PRIMARY KEY,
-            path    TEXT NOT NULL,
-            tags    TEXT
-        )",
-        (),
-    )?;
-
-    Ok(())
+pub fn init_db(backend: Backend) -> Result<(), FtagError> {
+    store::init_store(backend)
 }
 
 /// Return the tags belonging to a certain path, or the empty set if there are none.
@@ -182,19 +715,25 @@ pub fn init_db() -> Result<(), FtagError> {
 /// Returns `Err` if `path` does not exist, there is no database, or errors occur when deserializing JSON or querying the database.
 pub fn get_file_tags(path: &Utf8PathBuf) -> Result<HashSet<String>, FtagError> {
     if !path.exists() {
-        return Err(FtagError::IoError(io::ErrorKind::NotFound));
+        return Err(io::ErrorKind::NotFound.into());
     }
 
-    let query: Result<(u32, String), FtagError> = query_db_for_path(path);
-    match query {
-        Ok((_, json)) => {
-            let tags: Taglist = serde_json::from_str(&json)?;
-            Ok(tags.tags)
-        },
-        Err(_) => {
-            Ok(HashSet::new())
-        },
-    }
+    let store = store::open_store()?;
+    Ok(store.get_tags(&paths::normalize(path.as_str()))?.unwrap_or_default())
+}
+
+/// Keep only the tags starting with `pattern`, or matching it if it is a glob like `proj-*`.
+/// 
+/// * `tag_counts` - Tags along with how many paths have them, such as from `get_global_tags`
+/// * `pattern` - Prefix or glob to match, ignoring case unless it contains an uppercase letter
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `pattern` is not a valid glob, there is no database, or the settings could not be read.
+pub fn filter_tag_names(mut tag_counts: HashMap<String, u32>, pattern: &str) -> Result<HashMap<String, u32>, FtagError> {
+    let query = parse_queries(&[pattern.to_string()], CaseMode::Smart, false)?.remove(0);
+    tag_counts.retain(|tag, _| query.matches_prefix(tag));
+    Ok(tag_counts)
 }
 
 /// Return the set of all tags used in the current database.
@@ -203,50 +742,67 @@ pub fn get_file_tags(path: &Utf8PathBuf) -> Result<HashSet<String>, FtagError> {
 /// 
 /// Returns `Err` if there is no database or errors occur when deserializing JSON or querying the database.
 pub fn get_global_tags() -> Result<HashMap<String, u32>, FtagError> {
-    if !get_db_path().exists() {
-        return Err(FtagError::NoDatabaseError);
-    }
-
-    // Before we list the global tags, prune the db
+    // Before we list the global tags, prune the db when it's worth it
     // This makes sure removed paths don't show up
-    // TODO: But it's also probably slow. Can this be fixed or reduced?
-    prune_db()?;
+    maybe_prune()?;
+
+    // The sqlite backend keeps each tag of each path in its own row, so SQLite can count them without reading the tags
+    if store::current_backend()? == Backend::Sqlite {
+        let conn = store::open_sqlite("Counting tags")?;
+        let tag_counts = conn
+            .prepare_cached("SELECT tag, COUNT(*) FROM file_tags GROUP BY tag")?
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        return Ok(tag_counts);
+    }
 
-    // Create a HashSet that will hold the tags
+    // Count how many paths have each tag
     let mut tag_counts: HashMap<String, u32> = HashMap::new();
+    for (_, tags) in get_all_rows()? {
+        for tag in tags {
+            *tag_counts.entry(tag).or_insert(0) += 1;
+        }
+    }
 
-    let conn = Connection::open(get_db_path())?;
-    let mut stmt = conn.prepare("SELECT tags FROM tags;")?;
-    let result = stmt.query_map( params![],
-        |row| {
-            let tags: String = row.get(0)?;            
-            // TODO: Can I avoid unwrapping?
-            let deserialized: Taglist = serde_json::from_str(&tags).unwrap();
-            for tag in deserialized.tags {
-
-                if tag_counts.contains_key(&tag) {
-                    // Get the current count
-                    let count = tag_counts.get(&tag).unwrap() + 1;
-
-                    // Remove and re-add the key-value pair
-                    tag_counts.remove(&tag);
-                    tag_counts.insert(tag, count);
-                } else {
-                    // Add a count of 1
-                    tag_counts.insert(tag, 1);
-                }
-            }
-            Ok(())
-        },
-    )?;
+    Ok(tag_counts)
+}
 
-    // Do nothing for each item in the iterator, to get them to evaluate
-    result.for_each(|_| ());
+/// Return the tags used by a directory and every path beneath it, with how many of those paths have each tag.
+/// 
+/// * `dir` - Directory to look beneath, relative to the database root
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `dir` does not exist, there is no database, or errors occur when deserializing JSON or querying the database.
+pub fn get_tags_beneath(dir: &Utf8PathBuf) -> Result<HashMap<String, u32>, FtagError> {
+    if !dir.exists() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    // Everything is beneath the database root
+    let dir = paths::normalize(dir.as_str());
+    let mut tag_counts: HashMap<String, u32> = HashMap::new();
+    for (path, tags) in get_all_rows()? {
+        if dir != "." && !Utf8Path::new(&path).starts_with(&dir) {
+            continue;
+        }
+        for tag in tags {
+            *tag_counts.entry(tag).or_insert(0) += 1;
+        }
+    }
 
-    // Once we have evaluated the iterator items, we can return the counts
     Ok(tag_counts)
 }
 
+/// Return every path in the database along with its tags, without pruning paths that no longer exist.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database or errors occur when deserializing JSON or querying the database.
+pub fn get_all_rows() -> Result<Vec<(String, HashSet<String>)>, FtagError> {
+    store::open_store()?.all_rows()
+}
+
 /// Add tags to a file's record in the database, returning the set of tags now assigned to that file.
 /// 
 /// * `path` - Path to the file to add tags to
@@ -256,31 +812,46 @@ pub fn get_global_tags() -> Result<HashMap<String, u32>, FtagError> {
 /// 
 /// Returns `Err` if `path` does not exist, there is no database in the current directory, or errors occur when serializing and deserializing data or interacting with the database.
 pub fn add_tags(path: &Utf8PathBuf, add_tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
-    if !path.exists() {
-        return Err(FtagError::IoError(io::ErrorKind::NotFound));
+    let mut store = store::open_store()?;
+    let newtags = add_tags_in(&mut *store, path, add_tags)?;
+
+    // Remember what the file looked like when it was tagged, so changes to it can be found later
+    #[cfg(feature = "full")]
+    if settings::get_bool_setting_in(&*store, settings::GIT_AWARE)? {
+        git::record_blob(&paths::normalize(path.as_str()))?;
     }
-    
-    let query = query_db_for_path(path);
-    
-    // Create an empty list of tags
-    let mut newtags = Taglist { tags: HashSet::new() };
-
-    // Deserialize any existing tags and add them into the existing tags
-    if let Ok((_, json)) = &query {
-        let deserialized: Taglist = serde_json::from_str(&json)?;
-        for tag in deserialized.tags {
-            newtags.tags.insert(tag);
-        }
+
+    Ok(newtags)
+}
+
+/// Add tags to a file's record in an open store, returning the set of tags now assigned to that file.
+/// 
+/// Unlike `add_tags`, this does not record the file's contents in git-aware mode.
+/// 
+/// * `store` - Store to change
+/// * `path` - Path to the file to add tags to
+/// * `add_tags` - Vector containing tags to add. Duplicate tags will be ignored.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `path` does not exist, a tag is rejected, or errors occur when interacting with the store.
+pub fn add_tags_in(store: &mut dyn TagStore, path: &Utf8PathBuf, add_tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
+    if !path.exists() {
+        return Err(io::ErrorKind::NotFound.into());
     }
 
+    // Start from any existing tags
+    let key = paths::normalize(path.as_str());
+    let mut newtags = store.get_tags(&key)?.unwrap_or_default();
+
     // Insert any unique tags to be added
-    for tag in add_tags {
-        newtags.tags.insert(tag);
+    for tag in validate_tags_in(store, add_tags)? {
+        newtags.insert(tag);
     }
 
     // Update that row in the database
-    update_row_into_db(path, serde_json::to_string(&newtags)?)?;    
-    Ok(newtags.tags)
+    store.set_tags(&key, &newtags)?;
+    Ok(newtags)
 }
 
 /// Remove tags from a file's record in the database, returning the set of tags now assigned to that file.
@@ -292,87 +863,501 @@ pub fn add_tags(path: &Utf8PathBuf, add_tags: Vec<String>) -> Result<HashSet<Str
 /// 
 /// Returns `Err` if `path` does not exist, there is no database in the current directory, or errors occur when serializing and deserializing data or interacting with the database.
 pub fn remove_tags(path: &Utf8PathBuf, remove_tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
+    remove_tags_in(&mut *store::open_store()?, path, remove_tags)
+}
+
+/// Remove tags from a file's record in an open store, returning the set of tags now assigned to that file.
+/// 
+/// * `store` - Store to change
+/// * `path` - Path to the file to remove tags from
+/// * `remove_tags` - Vector containing tags to remove. Any tags not belonging to `path` will be ignored.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `path` does not exist or errors occur when interacting with the store.
+pub fn remove_tags_in(store: &mut dyn TagStore, path: &Utf8PathBuf, remove_tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
     if !path.exists() {
-        return Err(FtagError::IoError(io::ErrorKind::NotFound));
+        return Err(io::ErrorKind::NotFound.into());
     }
     
-    let query: Result<(u32, String), FtagError> = query_db_for_path(path);
-    
-    // Create an empty list of tags
-    let mut newtags = Taglist { tags: HashSet::new() };
-
-    // Deserialize any existing tags and append them to the new tags
-    if let Ok((_, json)) = &query {
-        let deserialized: Taglist = serde_json::from_str(&json)?;
-        
-        // Let newtags contain all tags not in remove_tags
-        for tag in deserialized.tags {
-            if !remove_tags.contains(&tag) {
-                newtags.tags.insert(tag);
+    // Remove tags both as typed and in their stored form, so badly formed tags can still be removed
+    let mut remove_tags = remove_tags;
+    remove_tags.extend(normalize_tags_in(store, remove_tags.clone())?);
+
+    // Let the new tags contain all existing tags not in remove_tags
+    let key = paths::normalize(path.as_str());
+    let mut newtags = store.get_tags(&key)?.unwrap_or_default();
+    newtags.retain(|tag| !remove_tags.contains(tag));
+
+    // Update that row in the database
+    store.set_tags(&key, &newtags)?;
+    Ok(newtags)
+}
+
+/// Remove every tag from a file's record, returning the set of tags now assigned to that file, which is empty.
+/// 
+/// * `path` - Path to the file to remove tags from
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `path` does not exist or errors occur when interacting with the database.
+pub fn remove_all_tags(path: &Utf8PathBuf) -> Result<HashSet<String>, FtagError> {
+    remove_all_tags_in(&mut *store::open_store()?, path)
+}
+
+/// Remove every tag from a file's record in an open store, returning the set of tags now assigned to that file.
+/// 
+/// * `store` - Store to change
+/// * `path` - Path to the file to remove tags from
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `path` does not exist or errors occur when interacting with the store.
+pub fn remove_all_tags_in(store: &mut dyn TagStore, path: &Utf8PathBuf) -> Result<HashSet<String>, FtagError> {
+    if !path.exists() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    // Paths that were never tagged are left out of the database rather than added without tags
+    let key = paths::normalize(path.as_str());
+    if store.get_tags(&key)?.is_some() {
+        store.set_tags(&key, &HashSet::new())?;
+    }
+    Ok(HashSet::new())
+}
+
+/// Replace a file's tags with exactly the given ones, returning how its tags changed.
+/// 
+/// * `path` - Path to the file to set the tags of
+/// * `tags` - Tags the file should have. Duplicate tags will be ignored, and no tags removes every tag.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `path` does not exist, a tag is rejected, or errors occur when interacting with the database.
+pub fn set_tags(path: &Utf8PathBuf, tags: Vec<String>) -> Result<TagChange, FtagError> {
+    let mut store = store::open_store()?;
+    let key = paths::normalize(path.as_str());
+    let before = store.get_tags(&key)?.unwrap_or_default();
+    let after = set_tags_in(&mut *store, path, tags)?;
+    let change = TagChange::between(&key, &before, &after);
+
+    #[cfg(feature = "full")]
+    if !change.added.is_empty() && settings::get_bool_setting_in(&*store, settings::GIT_AWARE)? {
+        git::record_blob(&key)?;
+    }
+
+    Ok(change)
+}
+
+/// Replace a file's tags in an open store with exactly the given ones, returning the set of tags now assigned to it.
+/// 
+/// Nothing is written when the file already has exactly those tags.
+/// 
+/// * `store` - Store to change
+/// * `path` - Path to the file to set the tags of
+/// * `tags` - Tags the file should have
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if `path` does not exist, a tag is rejected, or errors occur when interacting with the store.
+pub fn set_tags_in(store: &mut dyn TagStore, path: &Utf8PathBuf, tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
+    if !path.exists() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    // Paths that were never tagged stay out of the database when given no tags, like with `remove_all_tags_in`
+    let key = paths::normalize(path.as_str());
+    let newtags: HashSet<String> = validate_tags_in(store, tags)?.into_iter().collect();
+    if store.get_tags(&key)?.unwrap_or_default() != newtags {
+        store.set_tags(&key, &newtags)?;
+    }
+    Ok(newtags)
+}
+
+/// Add tags to many files in a single transaction, returning how the tags of each file changed in the order given.
+///
+/// Files that already have all of their tags are left out, so doing the same thing again changes nothing.
+///
+/// * `files` - Paths relative to the database root, each with the tags it should have
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if a file does not exist, a tag is rejected, or errors occur when interacting with the database.
+/// Nothing is changed in that case.
+#[cfg(feature = "full")]
+pub fn add_tags_to_all(files: Vec<(Utf8PathBuf, Vec<String>)>, dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    let mut store = store::open_store()?;
+    let changes = |tx: &mut dyn TagStore| {
+        let mut added = vec![];
+        for (file, tags) in files {
+            let current = tx.get_tags(&paths::normalize(file.as_str()))?.unwrap_or_default();
+            let wanted: HashSet<String> = validate_tags_in(tx, tags)?.into_iter().collect();
+            let mut new: Vec<String> = wanted.difference(&current).cloned().collect();
+            if new.is_empty() {
+                continue;
             }
+
+            new.sort();
+            add_tags_in(tx, &file, new.clone())?;
+            added.push(TagChange { path: paths::normalize(file.as_str()), added: new, removed: vec![] });
+        }
+        Ok(added)
+    };
+    let added = match dry_run {
+        true => return store.dry_run(changes),
+        false => store.batch(changes)?,
+    };
+
+    // Remember what each file looked like when it was tagged, once the tags are safely saved
+    if settings::get_bool_setting_in(&*store, settings::GIT_AWARE)? {
+        for change in &added {
+            git::record_blob(&change.path)?;
         }
     }
 
-    // Update that row in the database
-    update_row_into_db(path, serde_json::to_string(&newtags)?)?;    
-    Ok(newtags.tags)
+    Ok(added)
+}
+
+/// Add tags to every file matching a search in a single transaction, returning how the tags of each file changed,
+/// sorted by path.
+///
+/// Files that already have every tag are left out, as are files that no longer exist but haven't been pruned yet.
+///
+/// * `find` - Tags to filter by, from `parse_queries`
+/// * `exclude` - Tags to filter out
+/// * `any` - Match files with at least one of `find` instead of all of them
+/// * `tags` - Tags to add to each matching file
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if a tag is rejected or errors occur when interacting with the database. Nothing is changed in that
+/// case.
+#[cfg(feature = "full")]
+pub fn add_tags_to_matching(find: &[TagQuery], exclude: &[TagQuery], any: bool, tags: Vec<String>, dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    let mut files: Vec<(Utf8PathBuf, Vec<String>)> = find_tags(find, exclude, any)?
+        .into_iter()
+        .filter(|(path, _)| Utf8Path::new(path).exists())
+        .map(|(path, _)| (Utf8PathBuf::from(path), tags.clone()))
+        .collect();
+    files.sort();
+    add_tags_to_all(files, dry_run)
+}
+
+/// Try out a change to the tags of one file without saving it, returning how its tags would change.
+/// 
+/// * `path` - Path to the file being changed
+/// * `change` - Changes to make in the store, returning the tags the file is left with
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database, or `change` fails.
+pub fn preview_change(path: &Utf8PathBuf, change: impl FnOnce(&mut dyn TagStore) -> Result<HashSet<String>, FtagError>) -> Result<TagChange, FtagError> {
+    let mut store = store::open_store()?;
+    store.dry_run(|tx| {
+        let key = paths::normalize(path.as_str());
+        let before = tx.get_tags(&key)?.unwrap_or_default();
+        let after = change(tx)?;
+        Ok(TagChange::between(&key, &before, &after))
+    })
 }
 
 /// Check the entire database for files containg all of `find_tags`, returning their paths.
 /// 
-/// * `find_tags` - Vector of tags to filter by. Any matching files will have all of the tags in `find_tags`.
+/// A file matches a tag if it has that tag or any of its descendants, so `project` matches `project/ftag`.
+/// 
+/// * `find_tags` - Tags to filter by, from `parse_queries`. Any matching files will have all of the tags in `find_tags`.
+/// * `exclude_tags` - Tags to filter out. Any matching files will have none of the tags in `exclude_tags`.
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
 /// 
 /// # Failure
 /// 
 /// Returns `Err` if there is no database, errors occur when deserializing data, or errors occur when querying the database.
-pub fn find_tags(find_tags: &Vec<String>, exclude_tags: &Vec<String>) -> Result<Vec<(String, Vec<String>)>, FtagError> {
-    if !get_db_path().exists() {
-        return Err(FtagError::NoDatabaseError);
+pub fn find_tags(find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    // Before we do anything, prune the db when it's worth it
+    // This makes sure removed paths don't show up
+    maybe_prune()?;
+
+    // Let SQLite skip the rows that can't match, then check the rest the same way as any other rows
+    if let Some((filter, params)) = sql_filter(find_tags, exclude_tags, any)? {
+        let sql = format!("SELECT path, tags FROM tags{}", filter);
+        let found = store::open_sqlite("Searching in SQL").and_then(|conn| {
+            let rows: Vec<(String, String)> = conn
+                .prepare_cached(&sql)?
+                .query_map(rusqlite::params_from_iter(&params), |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            rows.into_iter().map(|(path, json)| Ok((path, parse_tags(&json)?))).collect::<Result<Vec<_>, FtagError>>()
+        });
+        match found {
+            Ok(rows) => return Ok(filter_rows(rows, find_tags, exclude_tags, any)),
+            // Reading every row explains which damaged row needs repairing
+            Err(err) => tracing::debug!("Searching in SQL failed ({}), reading every row instead", err),
+        }
     }
 
-    // Before we do anything, prune the db
-    // This makes sure removed paths don't show up
-    // TODO: But it's also probably slow. Can this be fixed or reduced?
-    prune_db()?;
+    Ok(filter_rows(get_all_rows()?, find_tags, exclude_tags, any))
+}
+
+/// Hand each file matching a search to `visit` as soon as it is read, with its sorted tags, instead of collecting them.
+/// 
+/// Files come in the order the database holds them, and `visit` can stop the search early by returning
+/// `ControlFlow::Break`, like `visit_tags(&find, &[], false, |path, _| Ok(ControlFlow::Break(())))` to find just one.
+/// With the sqlite backend only one row is held at a time, other backends are read whole first.
+/// 
+/// * `find_tags` - Tags to filter by, from `parse_queries`
+/// * `exclude_tags` - Tags to filter out
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
+/// * `visit` - Called with the path and tags of each matching file, saying whether to go on
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database, errors occur when deserializing data or querying the database, or `visit` fails.
+#[cfg(feature = "full")]
+pub fn visit_tags(
+    find_tags: &[TagQuery],
+    exclude_tags: &[TagQuery],
+    any: bool,
+    mut visit: impl FnMut(String, Vec<String>) -> Result<ControlFlow<()>, FtagError>,
+) -> Result<(), FtagError> {
+    maybe_prune()?;
+
+    let mut visit_row = |path: String, tags: HashSet<String>| -> Result<ControlFlow<()>, FtagError> {
+        if !row_matches(&tags, find_tags, exclude_tags, any) {
+            return Ok(ControlFlow::Continue(()));
+        }
+        let mut tags: Vec<String> = tags.into_iter().collect();
+        tags.sort();
+        visit(path, tags)
+    };
+
+    if store::current_backend()? != Backend::Sqlite {
+        for (path, tags) in get_all_rows()? {
+            if visit_row(path, tags)?.is_break() {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let (filter, params) = sql_filter(find_tags, exclude_tags, any)?.unwrap_or_default();
+    let conn = store::open_sqlite("Searching in SQL")?;
+    let mut stmt = conn.prepare_cached(&format!("SELECT path, tags FROM tags{}", filter))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(&params))?;
+    while let Some(row) = rows.next()? {
+        let json: String = row.get(1)?;
+        if visit_row(row.get(0)?, parse_tags(&json)?)?.is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Every distinct tag in `file_tags`, found by jumping from each tag to the next through its index.
+///
+/// `SELECT DISTINCT tag` reads every row instead, which takes tens of milliseconds once there are hundreds of
+/// thousands of them, while there are usually only a few thousand tags.
+const DISTINCT_TAGS_SQL: &str = "
+    WITH RECURSIVE known(tag) AS (
+        SELECT MIN(tag) FROM file_tags
+        UNION ALL
+        SELECT (SELECT MIN(tag) FROM file_tags WHERE tag > known.tag) FROM known WHERE known.tag IS NOT NULL
+    )
+    SELECT tag FROM known WHERE tag IS NOT NULL";
+
+/// Build a `WHERE` clause for the `tags` table keeping the rows `filter_rows` would keep, along with its parameters.
+///
+/// Returns `None` unless the database uses the sqlite backend. Each query is first checked against every distinct tag
+/// in the database, so globs, regular expressions, and comparisons are matched once per tag instead of once per row,
+/// and the rows holding a matching tag are then looked up in `file_tags`. The clause is empty when there is nothing
+/// to filter by.
+///
+/// * `find_tags` - Tags to filter by, from `parse_queries`
+/// * `exclude_tags` - Tags to filter out
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or its tags could not be read.
+pub(crate) fn sql_filter(find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool) -> Result<Option<(String, Vec<String>)>, FtagError> {
+    if store::current_backend()? != Backend::Sqlite {
+        return Ok(None);
+    }
+    if find_tags.is_empty() && exclude_tags.is_empty() {
+        return Ok(Some(Default::default()));
+    }
+
+    let conn = store::open_sqlite("Searching in SQL")?;
+    let known: Vec<String> = conn.prepare_cached(DISTINCT_TAGS_SQL)?.query_map((), |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+    // Each query is one parameter, holding the tags it matches in the same form as a row of `tags`
+    let mut params = vec![];
+    let mut condition = |query: &TagQuery| -> Result<String, FtagError> {
+        let matching: HashSet<String> = known.iter().filter(|tag| query.matches(tag)).cloned().collect();
+        params.push(serialize_tags(&matching)?);
+        Ok(format!("path IN (SELECT path FROM file_tags WHERE tag IN (SELECT value FROM json_each(?{}, '$.Tags')))", params.len()))
+    };
+    let found: Vec<String> = find_tags.iter().map(&mut condition).collect::<Result<_, _>>()?;
+    let excluded: Vec<String> = exclude_tags.iter().map(|query| Ok(format!("NOT {}", condition(query)?))).collect::<Result<_, FtagError>>()?;
+    let mut clauses = match found.is_empty() {
+        true => vec![],
+        false => vec![format!("({})", found.join(if any { " OR " } else { " AND " }))],
+    };
+    clauses.extend(excluded);
+    Ok(Some((format!(" WHERE {}", clauses.join(" AND ")), params)))
+}
+
+/// Count the files `find_tags` would find, without reading every row into memory when it can be avoided.
+///
+/// With the sqlite backend, matching files are counted by the database itself. Other backends count them like
+/// `find_tags` would find them.
+///
+/// * `find_tags` - Tags to filter by, from `parse_queries`
+/// * `exclude_tags` - Tags to filter out
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, errors occur when deserializing data, or errors occur when querying the database.
+pub fn count_tags(find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool) -> Result<usize, FtagError> {
+    maybe_prune()?;
 
-    // Convert find and exclude tags into HashSets, as we'll be checking containment a lot
-    let find_tags: HashSet<String> = HashSet::from_iter(find_tags.iter().cloned());
-    let exclude_tags: HashSet<String> = HashSet::from_iter(exclude_tags.iter().cloned());
+    let Some((filter, params)) = sql_filter(find_tags, exclude_tags, any)? else {
+        return Ok(filter_rows(get_all_rows()?, find_tags, exclude_tags, any).len());
+    };
+    let conn = store::open_sqlite("Counting in SQL")?;
+    let count: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM tags{}", filter), rusqlite::params_from_iter(&params), |row| row.get(0))?;
+    Ok(count as usize)
+}
 
+/// Decide how case is really compared when searching, given how the user asked for it to be compared.
+///
+/// Without a database there is no setting to follow, so case is compared as asked, like when searching other roots.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if the settings could not be read.
+pub fn effective_case(case: CaseMode) -> Result<CaseMode, FtagError> {
+    // Tags are all stored in one case when the database is case-insensitive, so case can't matter when searching
+    match settings::get_bool_setting(settings::CASE_INSENSITIVE) {
+        Ok(true) => Ok(CaseMode::Insensitive),
+        Ok(false) | Err(FtagError::NoDatabaseError) => Ok(case),
+        Err(err) => Err(err),
+    }
+}
+
+/// Keep only the rows containing all of `find_tags` and none of `exclude_tags`, returning their paths and sorted tags.
+/// 
+/// * `rows` - Paths along with their tags, such as from `get_all_rows`
+/// * `find_tags` - Tags to filter by, from `parse_queries`. Any matching files will have all of the tags in `find_tags`.
+/// * `exclude_tags` - Tags to filter out. Any matching files will have none of the tags in `exclude_tags`.
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
+pub fn filter_rows(rows: Vec<(String, HashSet<String>)>, find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool) -> Vec<(String, Vec<String>)> {
     // Store a vector of the files containing those tags
     let mut matching_files: Vec<(String, Vec<String>)> = vec![];
 
-    let conn = Connection::open(get_db_path())?;
-    let mut stmt = conn.prepare("SELECT path, tags FROM tags;")?;
-    let result = stmt.query_map( params![],
-        |row| {
-            // Process each name in the result set
-            let name: String = row.get(0)?;
-            let tags: String = row.get(1)?;
-            // TODO: This unwrap should be avoided
-            let deserialized: Taglist = serde_json::from_str(&tags).unwrap();
-
-            // Are all tags in find_tags contained by deserialized?
-            let find_tags_contained = find_tags
-                .iter()
-                .all(|tag| deserialized.tags.contains(tag));
-
-            // Are all tags in exclude_tags NOT contained by deserialized?
-            let exclude_tags_not_contained = exclude_tags
-                .iter()
-                .all(|tag| !deserialized.tags.contains(tag));
-           
-            // Store the filename if it satisfies both conditions
-            if find_tags_contained && exclude_tags_not_contained {
-                let mut vec_tags = deserialized.tags.into_iter().collect_vec();
-                vec_tags.sort();
-                matching_files.push((name, vec_tags));
+    for (name, tags) in rows {
+        // Store the filename if it satisfies both conditions
+        if row_matches(&tags, find_tags, exclude_tags, any) {
+            let mut vec_tags: Vec<String> = tags.into_iter().collect();
+            vec_tags.sort();
+            matching_files.push((name, vec_tags));
+        }
+    }
+
+    matching_files
+}
+
+/// Rows read from SQLite at a time by `find_page` before checking whether enough files were found.
+const PAGE_BATCH: usize = 256;
+
+/// Check the database for files matching `find_tags` like `find_tags` does, but only return the `limit` files
+/// after skipping `offset` of them, in alphabetical order.
+/// 
+/// With the sqlite backend, the ordering and paging happen in SQL, and rows stop being read once enough files
+/// have been found, so a page near the start of a large database is quick. Other backends are read whole first.
+/// 
+/// * `find_tags` - Tags to filter by, from `parse_queries`. Any matching files will have all of the tags in `find_tags`.
+/// * `exclude_tags` - Tags to filter out. Any matching files will have none of the tags in `exclude_tags`.
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
+/// * `reverse` - Go through files in reverse alphabetical order instead
+/// * `offset` - Number of matching files to skip
+/// * `limit` - Most matching files to return
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database, errors occur when deserializing data, or errors occur when querying the database.
+pub fn find_page(find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool, reverse: bool, offset: usize, limit: usize) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    if store::current_backend()? != Backend::Sqlite {
+        let mut files = self::find_tags(find_tags, exclude_tags, any)?;
+        sort_files(&mut files, SortKey::Name, reverse);
+        return Ok(files.into_iter().skip(offset).take(limit).collect());
+    }
+    maybe_prune()?;
+
+    let order = match reverse {
+        true => "DESC",
+        false => "ASC",
+    };
+    let conn = store::open_sqlite("Paging through files")?;
+
+    // Without any tags to match, every row is a match, so SQLite can skip straight to the page
+    if find_tags.is_empty() && exclude_tags.is_empty() {
+        let sql = format!("SELECT path, tags FROM tags ORDER BY path COLLATE {} {} LIMIT ? OFFSET ?", store::PATH_COLLATION, order);
+        let rows: Vec<(String, String)> = conn
+            .prepare(&sql)?
+            .query_map(rusqlite::params![i64::try_from(limit).unwrap_or(i64::MAX), i64::try_from(offset).unwrap_or(i64::MAX)], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        let rows = rows.into_iter().map(|(path, json)| Ok((path, parse_tags(&json)?))).collect::<Result<Vec<_>, FtagError>>()?;
+        return Ok(filter_rows(rows, find_tags, exclude_tags, any));
+    }
+
+    // Otherwise let SQLite skip the rows that can't match, and check the rest a batch at a time, in order, until the page is full
+    let (filter, params) = sql_filter(find_tags, exclude_tags, any)?.unwrap_or_default();
+    let sql = format!("SELECT path, tags FROM tags{} ORDER BY path COLLATE {} {}", filter, store::PATH_COLLATION, order);
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query_map(rusqlite::params_from_iter(&params), |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    let mut page = vec![];
+    let mut skipped = 0;
+    loop {
+        let batch = rows
+            .by_ref()
+            .take(PAGE_BATCH)
+            .map(|row| {
+                let (path, json) = row?;
+                Ok((path, parse_tags(&json)?))
+            })
+            .collect::<Result<Vec<_>, FtagError>>()?;
+        if batch.is_empty() {
+            return Ok(page);
+        }
+
+        for file in filter_rows(batch, find_tags, exclude_tags, any) {
+            if skipped < offset {
+                skipped += 1;
+            } else if page.len() < limit {
+                page.push(file);
             }
+        }
+        if page.len() >= limit {
+            return Ok(page);
+        }
+    }
+}
+
+/// Check whether a row's tags contain all of `find_tags` and none of `exclude_tags`, the same way as `filter_rows`.
+fn row_matches(tags: &HashSet<String>, find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool) -> bool {
+    // Are all tags in find_tags (or their descendants) contained by tags? Or just one of them, with `any`
+    let has_query = |query: &TagQuery| tags.iter().any(|tag| query.matches(tag));
+    let find_tags_contained = match any {
+        true => find_tags.is_empty() || find_tags.iter().any(has_query),
+        false => find_tags.iter().all(has_query),
+    };
 
-            Ok(())
-        },
-    )?;
+    // Are all tags in exclude_tags (and their descendants) NOT contained by tags?
+    let exclude_tags_not_contained = exclude_tags
+        .iter()
+        .all(|query| !tags.iter().any(|tag| query.matches(tag)));
 
-    result.for_each(|_| ());
-    Ok(matching_files)
+    find_tags_contained && exclude_tags_not_contained
 }
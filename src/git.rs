@@ -0,0 +1,128 @@
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashSet;
+use std::process::Command;
+
+use crate::ftag::FtagError;
+use crate::{sandbox, store};
+
+/// Run git with `args`, returning what it printed.
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, git could not be run, or it exited unsuccessfully.
+fn run_git(args: &[&str]) -> Result<String, FtagError> {
+    sandbox::check_exec("git")?;
+    tracing::debug!("Running git {}", args.join(" "));
+
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|err| FtagError::GitError(format!("Could not run git: {}", err)))?;
+    if !output.status.success() {
+        return Err(FtagError::GitError(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Open the database, creating the `git_blobs` table if this database predates it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or the table could not be created.
+fn open_git_db() -> Result<store::Handle, FtagError> {
+    let conn = store::open_sqlite("Git-aware mode")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS git_blobs (
+            path    TEXT PRIMARY KEY,
+            blob    TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// Hash the current contents of each path the way git would, returning the hashes in the same order.
+///
+/// # Failure
+///
+/// Returns `Err` if git could not hash one of the paths.
+fn hash_objects(paths: &[&str]) -> Result<Vec<String>, FtagError> {
+    if paths.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut args = vec!["hash-object", "--"];
+    args.extend(paths);
+    Ok(run_git(&args)?.lines().map(str::to_string).collect())
+}
+
+/// Remember the git blob hash of the current contents of `path`, so later changes to it can be noticed.
+///
+/// # Failure
+///
+/// Returns `Err` if git could not hash the file or errors occur when interacting with the database.
+pub fn record_blob(path: &str) -> Result<(), FtagError> {
+    let conn = open_git_db()?;
+    let blob = hash_objects(&[path])?.remove(0);
+    conn.execute("INSERT OR REPLACE INTO git_blobs(path, blob) VALUES (?, ?)", params![path, blob])?;
+    Ok(())
+}
+
+/// Return the git blob hash `path` had when it was last tagged, if one was recorded.
+///
+/// # Failure
+///
+/// Returns `Err` if errors occur when interacting with the database.
+pub fn recorded_blob(path: &str) -> Result<Option<String>, FtagError> {
+    let conn = open_git_db()?;
+    Ok(conn.query_row("SELECT blob FROM git_blobs WHERE path = ?", params![path], |row| row.get(0)).optional()?)
+}
+
+/// Return which of `paths` have different contents now than when they were last tagged.
+///
+/// Paths tagged before git-aware mode was turned on have no recorded contents, so they are never reported.
+///
+/// # Failure
+///
+/// Returns `Err` if git could not hash the files or errors occur when interacting with the database.
+pub fn dirty_paths(paths: &[&str]) -> Result<HashSet<String>, FtagError> {
+    let conn = open_git_db()?;
+
+    // Only hash the paths with something to compare against
+    let mut recorded = vec![];
+    for path in paths {
+        let blob: Option<String> = conn
+            .query_row("SELECT blob FROM git_blobs WHERE path = ?", params![path], |row| row.get(0))
+            .optional()?;
+        if let Some(blob) = blob {
+            recorded.push((*path, blob));
+        }
+    }
+
+    let current = hash_objects(&recorded.iter().map(|(path, _)| *path).collect::<Vec<_>>())?;
+    let dirty = recorded
+        .into_iter()
+        .zip(current)
+        .filter(|((_, blob), current)| blob != current)
+        .map(|((path, _), _)| path.to_string())
+        .collect();
+
+    Ok(dirty)
+}
+
+/// Return when the commit `revision` was made, in seconds since the Unix epoch.
+///
+/// * `revision` - Any revision git understands, like `HEAD~5` or a tag name
+///
+/// # Failure
+///
+/// Returns `Err` if git does not know the revision.
+pub fn revision_time(revision: &str) -> Result<i64, FtagError> {
+    let output = run_git(&["log", "-1", "--format=%ct", revision, "--"])?;
+    output
+        .trim()
+        .parse()
+        .map_err(|_| FtagError::GitError(format!("Could not find the time of revision {}", revision)))
+}
@@ -0,0 +1,154 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use regex::Regex;
+use std::io;
+
+use crate::ftag::{self, FtagError, TagChange, KEY_SEPARATOR};
+use crate::{migrate, paths};
+
+/// What a placeholder in a filename pattern turns into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    /// `{key}` tags the file `key=VALUE`
+    Key(String),
+    /// `{}` tags the file with the value itself
+    Plain,
+    /// `{*}` matches anything without tagging the file
+    Ignore,
+}
+
+/// A filename pattern like `{date}-{project}-{*}.md`, which turns the parts of matching names into tags.
+#[derive(Debug)]
+pub struct Pattern {
+    regex: Regex,
+    /// What each capture group of the regex turns into, in order
+    fields: Vec<Field>,
+    /// Patterns without a `/` match the file name, others match the path beneath the harvested directory
+    name_only: bool,
+}
+
+impl Pattern {
+    /// Parse a pattern made of literal text and placeholders.
+    ///
+    /// `{key}` tags the file `key=VALUE`, `{}` tags it `VALUE`, and `{*}` matches anything without tagging.
+    /// A placeholder matches as little as it can, unless it is given its own regex like `{date:\d{4}-\d{2}-\d{2}}`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if a brace is left unclosed, a placeholder's regex is invalid, or there are no placeholders.
+    pub fn parse(pattern: &str) -> Result<Pattern, FtagError> {
+        let invalid = |reason: &str| FtagError::ArgumentError(format!("Invalid pattern {}: {}", pattern, reason));
+
+        let mut regex = String::from("^");
+        let mut fields = vec![];
+        let mut rest = pattern;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(invalid("} without a matching {"));
+            }
+            regex.push_str(&regex::escape(&rest[..start]));
+
+            // Find the closing brace, skipping over any braces inside the placeholder's own regex
+            let mut depth = 0;
+            let end = rest[start..].char_indices().find_map(|(offset, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {},
+                }
+                (depth == 0).then_some(start + offset)
+            });
+            let Some(end) = end else {
+                return Err(invalid("{ without a matching }"));
+            };
+
+            let inside = &rest[start + 1..end];
+            let (name, custom) = match inside.split_once(':') {
+                Some((name, custom)) => (name.trim(), Some(custom)),
+                None => (inside.trim(), None),
+            };
+            fields.push(match name {
+                "" => Field::Plain,
+                "*" => Field::Ignore,
+                name if name.contains(KEY_SEPARATOR) || name.contains(char::is_whitespace) => {
+                    return Err(invalid(&format!("{} can't be a key", name)));
+                },
+                name => Field::Key(name.to_string()),
+            });
+            regex.push_str(&format!("({})", custom.unwrap_or(".+?")));
+            rest = &rest[end + 1..];
+        }
+        regex.push_str(&regex::escape(rest));
+        regex.push('$');
+
+        if fields.is_empty() {
+            return Err(invalid("expected a placeholder like {key}, {}, or {*}"));
+        }
+        let regex = Regex::new(&regex).map_err(|err| invalid(&err.to_string()))?;
+        if regex.captures_len() != fields.len() + 1 {
+            return Err(invalid("use (?:...) for groups inside a placeholder"));
+        }
+
+        Ok(Pattern { regex, fields, name_only: !pattern.contains('/') })
+    }
+
+    /// Return the tags a file gets from its name, or `None` if the pattern doesn't match it.
+    ///
+    /// * `relative` - Path of the file beneath the harvested directory
+    pub fn tags_for(&self, relative: &Utf8Path) -> Option<Vec<String>> {
+        let text = match self.name_only {
+            true => relative.file_name()?,
+            false => relative.as_str(),
+        };
+        let captures = self.regex.captures(text)?;
+
+        let mut tags = vec![];
+        for (field, value) in self.fields.iter().zip(captures.iter().skip(1)) {
+            let Some(value) = value.map(|value| value.as_str().trim()).filter(|value| !value.is_empty()) else {
+                continue;
+            };
+            match field {
+                Field::Key(key) => tags.push(format!("{}{}{}", key, KEY_SEPARATOR, value)),
+                Field::Plain => tags.push(value.to_string()),
+                Field::Ignore => {},
+            }
+        }
+        Some(tags)
+    }
+}
+
+/// Tag every file beneath `dir` whose name matches a pattern with the parts of its name, all in one transaction.
+///
+/// Returns how the tags of each file changed, in path order. Files that don't match are left alone.
+///
+/// * `dir` - Directory to look for files in, relative to the database root
+/// * `pattern` - Pattern the names must match
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if `dir` is not a directory, a derived tag is rejected, or errors occur when interacting with the
+/// database. Nothing is changed in that case.
+pub fn harvest(dir: &Utf8Path, pattern: &Pattern, dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    if !dir.is_dir() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    let mut files = vec![];
+    migrate::walk_files(dir, &mut files)?;
+
+    let mut tagged = vec![];
+    for file in files {
+        let relative = file.strip_prefix(dir).unwrap_or(&file).to_path_buf();
+        let file = Utf8PathBuf::from(paths::normalize(file.as_str()));
+        if ftag::is_database_path(&file) {
+            continue;
+        }
+
+        match pattern.tags_for(&relative) {
+            Some(tags) if !tags.is_empty() => tagged.push((file, tags)),
+            _ => tracing::debug!("{} doesn't match the pattern", file),
+        }
+    }
+
+    ftag::add_tags_to_all(tagged, dry_run)
+}
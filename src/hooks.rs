@@ -0,0 +1,86 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::{env, io};
+
+use crate::ftag::{self, FtagError};
+use crate::{roots, sandbox};
+
+/// Name of the hook run after the tags of a file change, in the hooks directory.
+pub const CHANGE_HOOK: &str = "after-change";
+
+/// What a hook is told about a change on standard input.
+#[derive(Debug, Serialize)]
+struct HookInput<'a> {
+    /// Command that changed the tags, like `add`
+    action: &'a str,
+    /// Absolute path of the database root
+    root: &'a str,
+    /// Path of the file, relative to the database root
+    path: &'a str,
+    /// Tags the file has now, sorted
+    tags: &'a [String],
+}
+
+/// Return where a hook is kept, `$XDG_CONFIG_HOME/ftag/hooks/NAME` or `~/.config/ftag/hooks/NAME`.
+///
+/// Hooks are kept with the user rather than next to the database, so a database shared through git can't run
+/// programs on the machines it is copied to.
+///
+/// # Failure
+///
+/// Returns `Err` if neither `XDG_CONFIG_HOME` nor `HOME` (`APPDATA` on Windows) is set.
+pub fn hook_file(name: &str) -> Result<Utf8PathBuf, FtagError> {
+    Ok(roots::user_dir("XDG_CONFIG_HOME", ".config")?.join("hooks").join(name))
+}
+
+/// Run the `after-change` hook for a file whose tags just changed, if the user has one, and wait for it to finish.
+///
+/// The hook is run from the database root with the action, the path, and the tags the file has now as arguments,
+/// like `after-change add photos/a.jpg beach sunset`. The same is written to its standard input as JSON, along
+/// with the absolute path of the database root.
+///
+/// * `action` - Command that changed the tags, like `add`, `rm`, `set`, `edit`, `rename`, `merge-tags`, `tag-matching`,
+///   or `apply`
+/// * `path` - Path of the file, relative to the database root
+///
+/// # Failure
+///
+/// Returns `Err` if the hook exists but running programs has been forbidden, the file's tags could not be read,
+/// or the hook could not be run or failed.
+pub fn after_change(action: &str, path: &Utf8Path) -> Result<(), FtagError> {
+    let hook = hook_file(CHANGE_HOOK)?;
+    if !hook.is_file() {
+        return Ok(());
+    }
+    sandbox::check_exec(hook.as_str())?;
+
+    let mut tags: Vec<String> = ftag::get_file_tags(&path.to_path_buf())?.into_iter().collect();
+    tags.sort();
+    let root = Utf8PathBuf::try_from(env::current_dir()?).map_err(|_| FtagError::ArgumentError("The current directory is not valid UTF-8".to_string()))?;
+    let input = serde_json::to_string(&HookInput { action, root: root.as_str(), path: path.as_str(), tags: &tags })?;
+
+    tracing::debug!("Running {} for {}", hook, path);
+    let mut child = Command::new(&hook)
+        .arg(action)
+        .arg(path)
+        .args(&tags)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| FtagError::ConfigError(format!("Could not run {}: {}", hook, err)))?;
+
+    // Hooks that only look at their arguments may exit without reading any of it
+    if let Some(mut stdin) = child.stdin.take() {
+        match writeln!(stdin, "{}", input) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => {},
+        }
+    }
+
+    let status = child.wait()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(FtagError::ConfigError(format!("{} exited unsuccessfully: {}", hook, status))),
+    }
+}
@@ -0,0 +1,202 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::ftag::{self, FtagError, TagChange, KEY_SEPARATOR};
+use crate::{finder, migrate, paths};
+
+/// Other tagging tools whose tags can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ImportFormat {
+    /// A TMSU database, usually `.tmsu/db`
+    Tmsu,
+    /// The Finder tags of the files beneath a directory, on macOS
+    Finder,
+    /// The TagSpaces sidecar files beneath a directory, kept in `.ts` next to the files they tag
+    Tagspaces,
+}
+
+/// Directory TagSpaces keeps sidecar files in, next to the files they tag.
+const TAGSPACES_DIR: &str = ".ts";
+
+/// Sidecar TagSpaces keeps the tags of the directory holding it in, rather than the tags of a file.
+const TAGSPACES_DIR_META: &str = "tsm.json";
+
+/// Results of importing tags from another tool.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// How the tags of each file changed, in path order
+    pub changes: Vec<TagChange>,
+    /// Files that could not be imported, each with the reason
+    pub skipped: Vec<(Utf8PathBuf, String)>,
+}
+
+/// Read every tagged file from a TMSU database, each with its tags.
+///
+/// TMSU keeps paths beneath its root directory (the one holding `.tmsu`) relative to it, so they are resolved
+/// against that. Tags with values, like `year=2017`, become `key=value` tags.
+///
+/// # Failure
+///
+/// Returns `Err` if the database could not be opened or isn't a TMSU database.
+fn read_tmsu(source: &Utf8Path) -> Result<BTreeMap<Utf8PathBuf, Vec<String>>, FtagError> {
+    let conn = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let root = match source.parent() {
+        Some(dir) if dir.file_name() == Some(".tmsu") => dir.parent().unwrap_or(dir),
+        Some(dir) => dir,
+        None => Utf8Path::new("."),
+    };
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT file.directory, file.name, tag.name, value.name
+            FROM file_tag
+            JOIN file ON file.id = file_tag.file_id
+            JOIN tag ON tag.id = file_tag.tag_id
+            LEFT JOIN value ON value.id = file_tag.value_id",
+        )
+        .map_err(|err| FtagError::ArgumentError(format!("{} is not a TMSU database: {}", source, err)))?;
+    let rows = stmt.query_map((), |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?))
+    })?;
+
+    let mut files: BTreeMap<Utf8PathBuf, Vec<String>> = BTreeMap::new();
+    for row in rows {
+        let (directory, name, tag, value) = row?;
+        let tag = match value {
+            Some(value) if !value.is_empty() => format!("{}{}{}", tag, KEY_SEPARATOR, value),
+            _ => tag,
+        };
+        let file = paths::normalize(root.join(directory).join(name).as_str());
+        files.entry(Utf8PathBuf::from(file)).or_default().push(tag);
+    }
+    Ok(files)
+}
+
+/// Read the Finder tags of a file, or of every file beneath a directory, skipping files whose tags can't be read.
+///
+/// # Failure
+///
+/// Returns `Err` if not on macOS, the directory could not be read, or a file's extended attributes could not be read.
+fn read_finder(dir: &Utf8Path, report: &mut ImportReport) -> Result<BTreeMap<Utf8PathBuf, Vec<String>>, FtagError> {
+    let mut paths = vec![];
+    match dir.is_dir() {
+        true => migrate::walk_files(dir, &mut paths)?,
+        false => paths.push(dir.to_path_buf()),
+    }
+
+    let mut files = BTreeMap::new();
+    for path in paths {
+        let Some(data) = finder::read_raw_tags(&path)? else {
+            continue;
+        };
+        match finder::parse_tags(&data) {
+            Some(tags) if !tags.is_empty() => {
+                files.insert(path, tags);
+            },
+            Some(_) => {},
+            None => report.skipped.push((path, "its Finder tags can't be read".to_string())),
+        }
+    }
+    Ok(files)
+}
+
+/// Parse a TagSpaces sidecar file, returning the titles of its tags.
+///
+/// Returns `None` if it isn't JSON with a list of tags, each an object with a title.
+pub fn parse_sidecar(text: &str) -> Option<Vec<String>> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    let tags = value.get("tags")?.as_array()?;
+    tags.iter()
+        .map(|tag| tag.get("title")?.as_str().map(|title| title.trim().to_string()))
+        .filter(|title| title.as_ref().is_none_or(|title| !title.is_empty()))
+        .collect()
+}
+
+/// Read the TagSpaces sidecar files beneath a directory, skipping those that can't be read.
+///
+/// The sidecar for `dir/photo.jpg` is `dir/.ts/photo.jpg.json`, and the tags of `dir` itself are in `dir/.ts/tsm.json`.
+///
+/// # Failure
+///
+/// Returns `Err` if the directory could not be read.
+fn read_tagspaces(dir: &Utf8Path, report: &mut ImportReport) -> Result<BTreeMap<Utf8PathBuf, Vec<String>>, FtagError> {
+    let mut paths = vec![];
+    migrate::walk_files(dir, &mut paths)?;
+
+    let mut files = BTreeMap::new();
+    for sidecar in paths {
+        let (Some(ts_dir), Some(name)) = (sidecar.parent(), sidecar.file_name()) else {
+            continue;
+        };
+        let (Some(tagged_dir), Some(tagged_name)) = (ts_dir.parent(), name.strip_suffix(".json")) else {
+            continue;
+        };
+        if ts_dir.file_name() != Some(TAGSPACES_DIR) {
+            continue;
+        }
+        let tagged = match name {
+            TAGSPACES_DIR_META if tagged_dir.as_str().is_empty() => Utf8PathBuf::from("."),
+            TAGSPACES_DIR_META => tagged_dir.to_path_buf(),
+            _ => tagged_dir.join(tagged_name),
+        };
+
+        match std::fs::read_to_string(&sidecar).ok().as_deref().and_then(parse_sidecar) {
+            Some(tags) if !tags.is_empty() => {
+                files.insert(tagged, tags);
+            },
+            Some(_) => {},
+            None => report.skipped.push((tagged, format!("its sidecar {} can't be read", sidecar))),
+        }
+    }
+    Ok(files)
+}
+
+/// Import the tags another tool gave to files beneath the database, adding them to any tags the files already have.
+///
+/// Files that no longer exist or are outside the directory holding the database are skipped. Importing again only
+/// adds tags the files are missing.
+///
+/// * `format` - Tool the tags come from
+/// * `source` - Where that tool keeps its tags
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if `source` does not exist or can't be read, an imported tag is rejected, or errors occur when
+/// interacting with the database. Nothing is changed in that case.
+pub fn import(format: ImportFormat, source: &Utf8Path, dry_run: bool) -> Result<ImportReport, FtagError> {
+    if !source.exists() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    let mut report = ImportReport::default();
+    let files = match format {
+        ImportFormat::Tmsu => read_tmsu(source)?,
+        ImportFormat::Finder => read_finder(source, &mut report)?,
+        ImportFormat::Tagspaces => read_tagspaces(source, &mut report)?,
+    };
+
+    let mut tagged = vec![];
+    for (file, tags) in files {
+        if !file.exists() {
+            report.skipped.push((file, "it no longer exists".to_string()));
+            continue;
+        }
+        // Files found beneath a relative source are already relative to the database root, which is the current directory
+        let stored = match file.is_absolute() {
+            true => paths::to_stored(&file).ok(),
+            false => Some(Utf8PathBuf::from(paths::normalize(file.as_str()))).filter(|stored| !stored.starts_with("..")),
+        };
+        match stored {
+            Some(stored) if ftag::is_database_path(&stored) => {},
+            Some(stored) => tagged.push((stored, tags)),
+            None => report.skipped.push((file, "it is outside the directory holding the database".to_string())),
+        }
+    }
+
+    tagged.sort();
+    report.changes = ftag::add_tags_to_all(tagged, dry_run)?;
+    Ok(report)
+}
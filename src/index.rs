@@ -0,0 +1,97 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use rusqlite::{params, Connection, OpenFlags};
+use std::collections::HashSet;
+use std::fs;
+
+use crate::ftag::{self, parse_tags, serialize_tags, FtagError, TagQuery};
+use crate::{journal, roots};
+
+/// Name of the global index, in ftag's cache directory.
+const INDEX_FILE: &str = "index.db";
+
+/// Return where the global index is kept, `$XDG_CACHE_HOME/ftag/index.db` or `~/.cache/ftag/index.db`.
+///
+/// # Failure
+///
+/// Returns `Err` if neither `XDG_CACHE_HOME` nor `HOME` (`APPDATA` on Windows) is set.
+fn index_file() -> Result<Utf8PathBuf, FtagError> {
+    Ok(roots::user_dir("XDG_CACHE_HOME", ".cache")?.join(INDEX_FILE))
+}
+
+/// Rebuild the global index from every registered root, returning how many files each root added to it.
+///
+/// The new index is written next to the old one and swapped in at the end, so searches never see half of it.
+///
+/// # Failure
+///
+/// Returns `Err` if the roots could not be listed, a root's database could not be read, or the index could not be
+/// written. The old index is left as it was in that case.
+pub fn rebuild() -> Result<Vec<(Utf8PathBuf, usize)>, FtagError> {
+    let rows = roots::in_each_root(ftag::get_all_rows)?;
+
+    let file = index_file()?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = file.with_extension("db.tmp");
+    if temp.exists() {
+        fs::remove_file(&temp)?;
+    }
+
+    let mut conn = Connection::open(&temp)?;
+    conn.execute_batch(
+        "CREATE TABLE roots (
+            root    TEXT PRIMARY KEY,
+            indexed INTEGER NOT NULL
+        );
+        CREATE TABLE files (
+            root    TEXT NOT NULL REFERENCES roots(root),
+            path    TEXT NOT NULL,
+            tags    TEXT NOT NULL
+        );",
+    )?;
+    let tx = conn.transaction()?;
+    let mut counts = vec![];
+    for (root, files) in rows {
+        tx.execute("INSERT INTO roots(root, indexed) VALUES (?, ?)", params![root.as_str(), journal::now()])?;
+        for (path, tags) in &files {
+            tx.execute("INSERT INTO files(root, path, tags) VALUES (?, ?, ?)", params![root.as_str(), path, serialize_tags(tags)?])?;
+        }
+        counts.push((root, files.len()));
+    }
+    tx.commit()?;
+    drop(conn);
+
+    fs::rename(&temp, &file)?;
+    Ok(counts)
+}
+
+/// Search the global index, returning the full path of each matching file with its sorted tags.
+///
+/// The index only knows the tags each root had when it was last rebuilt.
+///
+/// * `find_tags` - Tags to filter by, from `parse_queries`
+/// * `exclude_tags` - Tags to filter out
+/// * `any` - Match files with at least one of `find_tags` instead of all of them
+///
+/// # Failure
+///
+/// Returns `Err` if the index hasn't been built yet or could not be read.
+pub fn search(find_tags: &[TagQuery], exclude_tags: &[TagQuery], any: bool) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let file = index_file()?;
+    if !file.is_file() {
+        return Err(FtagError::ConfigError("There is no index yet, run ftag index rebuild first".to_string()));
+    }
+    let conn = Connection::open_with_flags(&file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let mut stmt = conn.prepare("SELECT root, path, tags FROM files")?;
+    let rows: Vec<(String, String, String)> = stmt
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut all_rows: Vec<(String, HashSet<String>)> = vec![];
+    for (root, path, json) in rows {
+        all_rows.push((Utf8Path::new(&root).join(path).into_string(), parse_tags(&json)?));
+    }
+    Ok(ftag::filter_rows(all_rows, find_tags, exclude_tags, any))
+}
@@ -0,0 +1,307 @@
+#[cfg(feature = "full")]
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+#[cfg(feature = "full")]
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ftag::FtagError;
+#[cfg(feature = "full")]
+use crate::ftag::{parse_tags, TagQuery};
+#[cfg(feature = "full")]
+use crate::store;
+
+/// A change recorded in the journal: when it happened, the path, and the tags it was left with, or `None` if it was
+/// removed from the database.
+#[cfg(feature = "full")]
+pub type Change = (i64, String, Option<HashSet<String>>);
+
+/// Return the current time as seconds since the Unix epoch.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// Make sure the journal table exists, creating it if this database predates it.
+///
+/// A new journal starts with the current state of every row, since earlier history was never recorded.
+///
+/// # Failure
+///
+/// Returns `Err` if the table could not be created or seeded.
+pub(crate) fn ensure_journal(conn: &Connection) -> Result<(), FtagError> {
+    let exists = conn
+        .prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'journal'")?
+        .query_row((), |_| Ok(()))
+        .optional()?
+        .is_some();
+    if exists {
+        return Ok(());
+    }
+
+    conn.execute(
+        "CREATE TABLE journal (
+            id      INTEGER PRIMARY KEY,
+            time    INTEGER NOT NULL,
+            path    TEXT NOT NULL,
+            tags    TEXT
+        )",
+        (),
+    )?;
+    conn.execute(JOURNAL_PATH_INDEX, ())?;
+    conn.execute("INSERT INTO journal(time, path, tags) SELECT ?, path, tags FROM tags", params![now()])?;
+
+    Ok(())
+}
+
+/// Index on the entries of each path in the journal, so finding the latest one doesn't read the whole journal.
+pub(crate) const JOURNAL_PATH_INDEX: &str = "CREATE INDEX IF NOT EXISTS journal_path ON journal(path, id)";
+
+/// Record the new state of a path in the journal.
+///
+/// * `conn` - Connection to the database being changed
+/// * `path` - Path whose tags changed
+/// * `tags` - JSON of the tags the path now has, or `None` if it was removed from the database
+///
+/// # Failure
+///
+/// Returns `Err` if the journal could not be written.
+pub(crate) fn record(conn: &Connection, path: &str, tags: Option<&str>) -> Result<(), FtagError> {
+    ensure_journal(conn)?;
+    conn.prepare_cached("INSERT INTO journal(time, path, tags) VALUES (?, ?, ?)")?.execute(params![now(), path, tags])?;
+    Ok(())
+}
+
+/// Create the `tag_times` table, which keeps when each tag of each path was added.
+///
+/// # Failure
+///
+/// Returns `Err` if the table could not be created.
+pub(crate) fn create_tag_times(conn: &Connection) -> Result<(), FtagError> {
+    conn.execute(
+        "CREATE TABLE tag_times (
+            path    TEXT NOT NULL,
+            tag     TEXT NOT NULL,
+            added   INTEGER NOT NULL,
+            PRIMARY KEY (path, tag)
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Remember when each tag of a path was added, keeping the times of tags it already had.
+///
+/// * `conn` - Connection to the database being changed
+/// * `path` - Path whose tags changed
+/// * `tags` - Tags the path now has, empty if it was removed from the database
+///
+/// # Failure
+///
+/// Returns `Err` if the times could not be written.
+pub(crate) fn record_tag_times(conn: &Connection, path: &str, tags: &HashSet<String>) -> Result<(), FtagError> {
+    let existing: Vec<String> = conn.prepare_cached("SELECT tag FROM tag_times WHERE path = ?")?.query_map(params![path], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    for tag in existing.iter().filter(|tag| !tags.contains(*tag)) {
+        conn.prepare_cached("DELETE FROM tag_times WHERE path = ? AND tag = ?")?.execute(params![path, tag])?;
+    }
+
+    let now = now();
+    for tag in tags {
+        conn.prepare_cached("INSERT OR IGNORE INTO tag_times(path, tag, added) VALUES (?, ?, ?)")?.execute(params![path, tag, now])?;
+    }
+    Ok(())
+}
+
+/// Keep only the files given a tag within a window of time.
+///
+/// When searching for tags, only a tag matching one of them counts, so `find photo --tagged-after` lists files that
+/// became photos recently rather than old photos given some unrelated tag.
+///
+/// * `files` - Paths and tags to narrow down
+/// * `find` - Tags that were searched for, if any
+/// * `after` - Earliest moment a tag may have been added, in seconds since the Unix epoch
+/// * `before` - Latest moment a tag may have been added, in seconds since the Unix epoch
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or errors occur when querying it.
+#[cfg(feature = "full")]
+pub fn filter_tagged(
+    files: Vec<(String, Vec<String>)>,
+    find: &[TagQuery],
+    after: Option<i64>,
+    before: Option<i64>,
+) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let times = tag_times()?;
+    let within = |time: &i64| after.is_none_or(|after| *time >= after) && before.is_none_or(|before| *time <= before);
+
+    Ok(files
+        .into_iter()
+        .filter(|(path, _)| {
+            times.get(path).is_some_and(|tags| {
+                tags.iter().any(|(tag, time)| within(time) && (find.is_empty() || find.iter().any(|query| query.matches(tag))))
+            })
+        })
+        .collect())
+}
+
+/// Return when each tag of every path was added, in seconds since the Unix epoch.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or errors occur when querying it.
+#[cfg(feature = "full")]
+fn tag_times() -> Result<HashMap<String, HashMap<String, i64>>, FtagError> {
+    let conn = store::open_sqlite("Tagging times")?;
+    let mut stmt = conn.prepare("SELECT path, tag, added FROM tag_times")?;
+    let rows = stmt.query_map((), |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?)))?;
+
+    let mut times: HashMap<String, HashMap<String, i64>> = HashMap::new();
+    for row in rows {
+        let (path, tag, added) = row?;
+        times.entry(path).or_default().insert(tag, added);
+    }
+    Ok(times)
+}
+
+/// Return the most recent changes recorded in the journal, newest first.
+///
+/// * `limit` - Maximum number of changes to return
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when deserializing JSON or querying the database.
+#[cfg(feature = "full")]
+pub fn recent_changes(limit: u32) -> Result<Vec<Change>, FtagError> {
+    let conn = store::open_sqlite("The journal")?;
+    ensure_journal(&conn)?;
+
+    let mut stmt = conn.prepare("SELECT time, path, tags FROM journal ORDER BY id DESC LIMIT ?")?;
+    let rows: Vec<(i64, String, Option<String>)> = stmt
+        .query_map(params![limit], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut changes = vec![];
+    for (time, path, json) in rows {
+        let tags = match json {
+            Some(json) => Some(parse_tags(&json)?),
+            None => None,
+        };
+        changes.push((time, path, tags));
+    }
+
+    Ok(changes)
+}
+
+/// Return every tag the journal has seen on any path, with the last time a path was left holding it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when querying the database.
+#[cfg(feature = "full")]
+pub fn last_used_tags() -> Result<HashMap<String, i64>, FtagError> {
+    let conn = store::open_sqlite("The journal")?;
+    ensure_journal(&conn)?;
+
+    let mut stmt = conn.prepare("SELECT tag.value, MAX(journal.time) FROM journal, json_each(journal.tags, '$.Tags') AS tag GROUP BY tag.value")?;
+    let times = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(times)
+}
+
+/// Format a time in seconds since the Unix epoch in the local timezone.
+#[cfg(feature = "full")]
+pub fn format_time(time: i64) -> String {
+    match Local.timestamp_opt(time, 0).earliest() {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        None => time.to_string(),
+    }
+}
+
+/// Query taking the latest journal entry for each path which is no later than a time.
+#[cfg(feature = "full")]
+pub(crate) const ASOF_SQL: &str = "SELECT path, tags FROM journal AS entry
+         WHERE id = (SELECT MAX(id) FROM journal WHERE path = entry.path AND time <= ?)
+         AND tags IS NOT NULL";
+
+/// Reconstruct every path and its tags as they were at `time`, using the journal.
+///
+/// Paths that had been removed from the database by then are left out.
+///
+/// * `time` - Moment to look back to, in seconds since the Unix epoch
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when deserializing JSON or querying the database.
+#[cfg(feature = "full")]
+pub fn rows_asof(time: i64) -> Result<Vec<(String, HashSet<String>)>, FtagError> {
+    let conn = store::open_sqlite("The journal")?;
+    ensure_journal(&conn)?;
+
+    let mut stmt = conn.prepare(ASOF_SQL)?;
+    let rows: Vec<(String, String)> = stmt
+        .query_map(params![time], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut all_rows = vec![];
+    for (path, json) in rows {
+        all_rows.push((path, parse_tags(&json)?));
+    }
+
+    Ok(all_rows)
+}
+
+/// Return when `path` was first tagged and when its tags last changed, or `None` if it isn't in the database.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when querying the database.
+#[cfg(feature = "full")]
+pub fn path_times(path: &str) -> Result<Option<(i64, i64)>, FtagError> {
+    let conn = store::open_sqlite("Tagging times")?;
+    let times = conn
+        .query_row("SELECT created, modified FROM tags WHERE path = ?", params![path], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?;
+    Ok(times)
+}
+
+/// Parse a date like `2024-01-01` or a time like `2024-01-01 13:30` in the local timezone, or an RFC 3339 timestamp.
+///
+/// A bare date means the end of that day, so everything that happened on it is included.
+///
+/// # Failure
+///
+/// Returns `Err` if the text is not in any of the accepted formats.
+#[cfg(feature = "full")]
+pub fn parse_time(text: &str) -> Result<i64, FtagError> {
+    parse_time_at(text, (23, 59, 59))
+}
+
+/// Parse a date or time like `parse_time`, except a bare date means the start of that day.
+///
+/// # Failure
+///
+/// Returns `Err` if the text is not in any of the accepted formats.
+#[cfg(feature = "full")]
+pub fn parse_start_time(text: &str) -> Result<i64, FtagError> {
+    parse_time_at(text, (0, 0, 0))
+}
+
+/// Parse a date or time, taking a bare date to mean the given hour, minute, and second of that day.
+#[cfg(feature = "full")]
+fn parse_time_at(text: &str, (hour, minute, second): (u32, u32, u32)) -> Result<i64, FtagError> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.timestamp());
+    }
+
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(hour, minute, second))
+        });
+
+    naive
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|time| time.timestamp())
+        .ok_or_else(|| FtagError::ArgumentError(format!("Could not understand the date {}, try YYYY-MM-DD", text)))
+}
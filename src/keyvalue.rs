@@ -0,0 +1,129 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::ftag::KEY_SEPARATOR;
+
+/// Ways a search can compare a value, like the value of a `key=value` tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    NotEqual,
+}
+
+impl Operator {
+    /// Every operator as it is written, longest first so `>=` isn't read as `>`.
+    const ALL: &'static [(&'static str, Operator)] = &[
+        (">=", Operator::GreaterOrEqual),
+        ("<=", Operator::LessOrEqual),
+        ("!=", Operator::NotEqual),
+        (">", Operator::Greater),
+        ("<", Operator::Less),
+        ("=", Operator::Equal),
+    ];
+
+    /// Split an operator off the start of some text, like `>=` from `>=4`.
+    pub fn split(text: &str) -> Option<(Operator, &str)> {
+        Operator::ALL.iter().find_map(|(written, op)| text.strip_prefix(written).map(|rest| (*op, rest)))
+    }
+
+    /// Check whether the order of a stored value relative to the searched one satisfies the operator.
+    pub fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Operator::Equal => ordering.is_eq(),
+            Operator::Less => ordering.is_lt(),
+            Operator::LessOrEqual => ordering.is_le(),
+            Operator::Greater => ordering.is_gt(),
+            Operator::GreaterOrEqual => ordering.is_ge(),
+            Operator::NotEqual => ordering.is_ne(),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (text, _) = Operator::ALL.iter().find(|(_, op)| op == self).expect("every operator is listed");
+        write!(f, "{}", text)
+    }
+}
+
+/// A search for `key=value` tags whose value compares a certain way, like `year>=2020`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// Key the tags must have
+    pub key: String,
+    /// How their value must compare
+    pub op: Operator,
+    /// Value to compare against
+    pub value: String,
+}
+
+/// Split a tag like `year=2023` into its key and value, or return `None` if it has no value.
+pub fn split_tag(tag: &str) -> Option<(&str, &str)> {
+    tag.split_once(KEY_SEPARATOR)
+}
+
+/// Parse a search like `year>=2020` or `status!=draft`.
+///
+/// A plain `key=value` is not a comparison, since it is searched for like any other tag.
+///
+/// # Failure
+///
+/// Returns `Err` if the search has an operator but is missing the key or the value.
+pub fn parse_comparison(text: &str) -> Result<Option<Comparison>, String> {
+    // The first operator splits the key from the value, so values may contain anything
+    let Some((start, op, value)) = text.char_indices().find_map(|(start, _)| Operator::split(&text[start..]).map(|(op, value)| (start, op, value)))
+    else {
+        return Ok(None);
+    };
+    // `key=a<b` is a tag with an odd value, not a comparison
+    if op == Operator::Equal {
+        return Ok(None);
+    }
+
+    let key = text[..start].trim();
+    let value = value.trim();
+    if key.is_empty() || value.is_empty() {
+        return Err(format!("Invalid comparison {}, expected something like year>=2020", text));
+    }
+    Ok(Some(Comparison { key: key.to_string(), op, value: value.to_string() }))
+}
+
+/// Order two values, as numbers if both are numbers and as text otherwise.
+///
+/// Dates written like `2024-01-31` sort correctly as text.
+fn compare_values(stored: &str, searched: &str) -> Ordering {
+    match (stored.parse::<f64>(), searched.parse::<f64>()) {
+        (Ok(stored), Ok(searched)) if !stored.is_nan() && !searched.is_nan() => stored.total_cmp(&searched),
+        _ => stored.cmp(searched),
+    }
+}
+
+impl Comparison {
+    /// Check whether a stored tag has the key and a value that compares the right way.
+    ///
+    /// * `tag` - Stored tag, like `year=2023`
+    /// * `sensitive` - Whether case is taken into account when comparing keys and text values
+    pub fn matches(&self, tag: &str, sensitive: bool) -> bool {
+        let Some((key, value)) = split_tag(tag) else {
+            return false;
+        };
+
+        match sensitive {
+            true => key == self.key && self.op.accepts(compare_values(value, &self.value)),
+            false => {
+                key.to_lowercase() == self.key.to_lowercase()
+                    && self.op.accepts(compare_values(&value.to_lowercase(), &self.value.to_lowercase()))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.key, self.op, self.value)
+    }
+}
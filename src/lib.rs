@@ -0,0 +1,91 @@
+//! Tag files and search for them by their tags, keeping the tags in a database next to the files.
+//!
+//! This is everything the `ftag` command does, without the command line. [`ftag`] adds, removes, and searches
+//! for tags in the database of the current directory, and [`store`] reads and writes it. Build without the
+//! default `cli` feature to leave out clap and the other dependencies only the command line needs, and with the
+//! `async` feature for [`nonblocking`] versions of the main calls that don't block a tokio runtime.
+
+#[cfg(feature = "full")]
+pub mod apply;
+#[cfg(feature = "full")]
+pub mod audit;
+#[cfg(feature = "full")]
+pub mod autotag;
+#[cfg(feature = "full")]
+pub mod backup;
+#[cfg(feature = "full")]
+pub mod bundle;
+#[cfg(feature = "full")]
+pub mod check;
+pub mod display;
+#[cfg(feature = "full")]
+pub mod doctor;
+#[cfg(feature = "full")]
+pub mod dupes;
+pub mod ftag;
+#[cfg(feature = "full")]
+pub mod exec;
+#[cfg(feature = "full")]
+pub mod explain;
+#[cfg(feature = "full")]
+pub mod finder;
+#[cfg(feature = "full")]
+pub mod git;
+#[cfg(feature = "full")]
+pub mod harvest;
+#[cfg(feature = "full")]
+pub mod hooks;
+#[cfg(feature = "full")]
+pub mod import;
+#[cfg(feature = "full")]
+pub mod index;
+pub mod journal;
+#[cfg(feature = "full")]
+pub mod keyvalue;
+#[cfg(feature = "full")]
+pub mod link;
+pub mod meta;
+#[cfg(feature = "full")]
+pub mod migrate;
+#[cfg(feature = "full")]
+pub mod mirror;
+#[cfg(feature = "full")]
+pub mod query;
+#[cfg(feature = "full")]
+pub mod rating;
+#[cfg(feature = "full")]
+pub mod remote;
+#[cfg(feature = "full")]
+pub mod report;
+#[cfg(feature = "full")]
+pub mod roots;
+#[cfg(any(test, not(feature = "full")))]
+mod minijson;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+#[cfg(feature = "full")]
+pub mod note;
+#[cfg(feature = "full")]
+pub mod open;
+pub mod paths;
+#[cfg(feature = "full")]
+pub mod pdf;
+#[cfg(feature = "full")]
+pub mod pick;
+pub mod sandbox;
+pub mod schema;
+pub mod settings;
+#[cfg(feature = "full")]
+pub mod show;
+#[cfg(feature = "full")]
+pub mod stats;
+pub mod store;
+#[cfg(feature = "full")]
+pub mod sync;
+#[cfg(feature = "full")]
+pub mod template;
+#[cfg(feature = "full")]
+pub mod units;
+#[cfg(feature = "self-update")]
+pub mod update;
+pub mod validate;
@@ -0,0 +1,82 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::HashSet;
+use std::{fs, io};
+
+use crate::ftag::FtagError;
+
+/// Make a symlink at `link` pointing to `target`.
+///
+/// # Failure
+///
+/// Returns `Err` if the link could not be made, such as when something already exists at `link`.
+pub fn symlink(target: &Utf8Path, link: &Utf8Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    return match target.is_dir() {
+        true => std::os::windows::fs::symlink_dir(target, link),
+        false => std::os::windows::fs::symlink_file(target, link),
+    };
+}
+
+/// Choose a name in the directory of links for `file`, adding a number like `photo (2).jpg` if the name is taken.
+fn link_name(file: &Utf8Path, taken: &mut HashSet<String>) -> String {
+    let name = file.file_name().unwrap_or(file.as_str());
+    let mut candidate = name.to_string();
+    let mut number = 1;
+    while !taken.insert(candidate.clone()) {
+        number += 1;
+        candidate = match (file.file_stem(), file.extension()) {
+            (Some(stem), Some(extension)) => format!("{} ({}).{}", stem, number, extension),
+            _ => format!("{} ({})", name, number),
+        };
+    }
+    candidate
+}
+
+/// Fill `dir` with symlinks to `files`, so programs that only understand directories can see the result of a search.
+///
+/// Links point to absolute paths, so they keep working if the directory is moved.
+///
+/// * `files` - Files to link to
+/// * `dir` - Directory to put the links in, created if needed
+/// * `clean` - Remove the symlinks already in `dir` first, leaving anything else alone
+///
+/// Files that already have a link in `dir` are skipped, so running it again only adds the new ones.
+/// Returns how many links were made.
+///
+/// # Failure
+///
+/// Returns `Err` if `dir` could not be created or read, a file could not be found, or a link could not be made,
+/// such as when a file that isn't a link already has its name.
+pub fn link_files(files: &[String], dir: &Utf8Path, clean: bool) -> Result<usize, FtagError> {
+    fs::create_dir_all(dir)?;
+
+    let mut taken = HashSet::new();
+    let mut linked = HashSet::new();
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        let is_link = entry.file_type()?.is_symlink();
+        if clean && is_link {
+            fs::remove_file(entry.path())?;
+            continue;
+        }
+        if is_link {
+            linked.insert(fs::read_link(entry.path())?);
+        }
+        taken.insert(entry.file_name().to_string());
+    }
+
+    let mut made = 0;
+    for file in files {
+        let target: Utf8PathBuf = Utf8Path::new(file).canonicalize_utf8()?;
+        if linked.contains(target.as_std_path()) {
+            continue;
+        }
+        let link = dir.join(link_name(Utf8Path::new(file), &mut taken));
+        symlink(&target, &link).map_err(|err| FtagError::ArgumentError(format!("Could not link {} to {}: {}", link, file, err)))?;
+        made += 1;
+    }
+
+    Ok(made)
+}
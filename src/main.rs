@@ -1,11 +1,39 @@
-use std::{io::ErrorKind, collections::HashSet};
+use std::{collections::HashMap, fmt::Display, io::ErrorKind, process::ExitCode, sync::OnceLock};
+use tracing::Level;
 use camino::Utf8PathBuf;
 use clap::{Parser, Subcommand};
+#[cfg(feature = "full")]
+use clap::CommandFactory;
+#[cfg(feature = "full")]
 use rand::seq::SliceRandom;
 
-mod ftag;
-use ftag::{FtagError, get_file_tags};
-use itertools::Itertools;
+#[cfg(feature = "full")]
+mod completions;
+
+use ::ftag::{display, ftag, meta, paths, sandbox, schema, store};
+#[cfg(feature = "full")]
+use ::ftag::{
+    apply, audit, autotag, backup, bundle, check, doctor, dupes, exec, explain, finder, git, harvest, hooks, import, index, journal, link, migrate,
+    mirror, note, open, pdf, pick, query, rating, remote, report, roots, settings, show, stats, sync, template, units,
+};
+#[cfg(feature = "self-update")]
+use ::ftag::update;
+use display::{display_changes, display_file_tree, display_tag_groups, display_tag_list, display_tag_tree, display_tags, is_plain, is_quiet, paint_found_tag, paint_grouped_tags, tsv_row, ColorChoice};
+#[cfg(feature = "full")]
+use display::{jsonl_row, paint_count, paint_rating, paint_tag};
+use ftag::{CaseMode, FtagError, SortKey};
+#[cfg(feature = "full")]
+use ftag::{get_file_tags, TagQuery};
+#[cfg(feature = "full")]
+use meta::TagColor;
+#[cfg(feature = "full")]
+use rating::RatingFilter;
+use meta::TagMeta;
+use store::Backend;
+#[cfg(feature = "full")]
+use template::{Field, Record, Template};
+#[cfg(feature = "full")]
+use units::{ByteSize, TimeSpan};
 
 /// Utility to tag files for easy access
 #[derive(Debug, Parser)]
@@ -13,12 +41,70 @@ use itertools::Itertools;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Refuse anything that would access the network, such as --remote and self-update
+    #[arg(long, global = true)]
+    no_network: bool,
+
+    /// Refuse to run external programs, such as hooks and file openers
+    #[arg(long, global = true)]
+    no_exec: bool,
+
+    /// Have the operating system enforce --no-network and --no-exec (seccomp on Linux, pledge on OpenBSD).
+    /// Given without either, it implies both
+    #[arg(long, global = true)]
+    sandbox: bool,
+
+    /// Go ahead with commands that change many files without asking first
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    /// Only print results and errors, leaving out messages about what changed
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log what ftag is doing to standard error. Repeat for more detail, like -vv to log every SQL statement
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Print one labeled line per item without colors or indentation, for screen readers and braille displays
+    #[arg(long, global = true, env = "FTAG_PLAIN", value_parser = clap::builder::FalseyValueParser::new())]
+    plain: bool,
+
+    /// When to color output. NO_COLOR turns colors off unless this is `always`
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Milliseconds to wait for another ftag to finish writing before giving up
+    #[arg(long, global = true, value_name = "MS", env = "FTAG_BUSY_TIMEOUT", default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Run the command over SSH against the database in a directory on another machine, like user@nas:/srv/photos
+    #[cfg(feature = "full")]
+    #[arg(long, global = true, value_name = "HOST:DIR")]
+    remote: Option<remote::Remote>,
 }
 
 #[derive(Debug, Subcommand)]
+#[allow(clippy::large_enum_variant)] // Only one command is ever parsed, so boxing find's many options gains nothing
 enum Commands {
     /// Initialize the database
-    Init,
+    Init {
+        /// How to store the database
+        #[arg(short, long, value_enum, default_value_t = Backend::Sqlite)]
+        backend: Backend,
+    },
+
+    /// Upgrade a database made by an older version of ftag
+    Migrate,
+
+    /// Download and install the latest release of ftag, after checking its signature
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only check whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
 
     /// List tags of a path or globally
     List {
@@ -29,13 +115,45 @@ enum Commands {
         #[arg(short, long)]
         reverse: bool,
 
-        /// Display tag counts on global list (only on global list)
+        /// Display tag counts on global list (only on global or recursive list)
         #[arg(short, long)]
         count: bool,
 
-        /// Sort by descending count, instead of alphabetically (only on global list)
+        /// Sort by descending count, instead of alphabetically (only on global or recursive list)
         #[arg(short, long)]
         sortcount: bool,
+
+        /// Display hierarchical tags like `photo/vacation` as an indented tree
+        #[arg(short, long)]
+        tree: bool,
+
+        /// Group tags by namespace, like all of `project/*` together, with a color and count for each
+        #[arg(short, long, conflicts_with = "tree")]
+        group: bool,
+
+        /// Only list tags starting with a prefix or matching a glob like 'proj-*' (only on global or recursive list)
+        #[arg(short, long, value_name = "PREFIX_OR_GLOB")]
+        filter: Option<String>,
+
+        /// List every tag used beneath the directory at the path, and how many paths have each
+        #[arg(short = 'R', long, requires = "path")]
+        recursive: bool,
+
+        /// Print each tag with a template like '{tag}\t{count}', or '{path}\t{tags:,}' when listing a path
+        #[cfg(feature = "full")]
+        #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["tree", "group"])]
+        format: Option<String>,
+    },
+
+    /// Look at tags as they were at a past date
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Asof {
+        /// Date to look back to, like 2024-01-01 or "2024-01-01 13:30"
+        date: String,
+
+        #[command(subcommand)]
+        command: AsofCommands,
     },
 
     /// Add tags to a path
@@ -56,8 +174,47 @@ enum Commands {
         path: Utf8PathBuf,
 
         /// Tags to remove
-        #[arg(required = true)]
+        #[arg(required = false)]
+        tags: Vec<String>,
+
+        /// Remove every tag the path has, instead of naming them
+        #[arg(long, conflicts_with = "tags")]
+        all: bool,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Replace every tag of a path with the ones given, showing which were added and removed
+    #[command(arg_required_else_help = true)]
+    Set {
+        /// Target path
+        path: Utf8PathBuf,
+
+        /// Tags the path should have. If unspecified, removes every tag
+        #[arg(required = false)]
         tags: Vec<String>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Change the tags of a path by ticking them in a checklist, or by editing them as text
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Edit {
+        /// Target path
+        path: Utf8PathBuf,
+
+        /// Tick and untick every known tag with fzf, or the picker in FTAG_PICKER, instead of using $EDITOR
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
     },
 
     /// Find files with particular tags
@@ -71,194 +228,2813 @@ enum Commands {
         #[arg(short, long)]
         tags: bool,
 
+        /// Only list this many matching files
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+
+        /// Skip this many matching files before listing any
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset: usize,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
+        /// Match files with at least one of the tags, instead of all of them
+        #[arg(long)]
+        any: bool,
+
+        #[cfg(feature = "full")]
+        #[command(flatten)]
+        filters: FindFilters,
+
+        /// Order to list matching files in
+        #[arg(long, value_enum, default_value_t = SortKey::Name)]
+        sort: SortKey,
+
+        /// Reverse sorting order
+        #[arg(short, long)]
+        reverse: bool,
+
+        /// Exit with status 5 when no files are found, like grep
+        #[arg(long)]
+        exit_code: bool,
+
+        /// Print only how many files were found
+        #[arg(short, long, conflicts_with_all = ["tags", "print0", "tree", "limit", "offset"])]
+        count: bool,
+
+        /// Separate files with NUL instead of newlines, for use with `xargs -0`
+        #[arg(short = '0', long, conflicts_with = "tags")]
+        print0: bool,
+
+        /// Group files under their parent directories as an indented tree
+        #[arg(long, conflicts_with = "print0")]
+        tree: bool,
+
+        /// Show the tags of each file grouped by namespace, a line and color for each (with --tags)
+        #[arg(short, long, requires = "tags", conflicts_with = "tree")]
+        group: bool,
+
+        /// Print one tab-separated row per file, its path and then its tags joined by commas
+        #[arg(long, conflicts_with_all = ["tags", "count", "print0", "tree"])]
+        tsv: bool,
+
+        /// Print one JSON object per file as soon as it is found, like {"path":"a.jpg","tags":["red"]}, in no particular order
+        #[cfg(feature = "full")]
+        #[arg(long, conflicts_with_all = ["tags", "count", "print0", "tree", "tsv", "format", "pick", "sort", "reverse"])]
+        jsonl: bool,
+
+        /// Optional tags which matching files must not have
+        #[arg(required=false, last=true)]
+        exclude: Vec<String>,
+    },
+
+    /// Open files with specific tags in their default applications
+    #[cfg(feature = "full")]
+    Open {
+        /// Tags that matching files must have
+        #[arg(required=false)]
+        find: Vec<String>,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
+        /// Program to open every file at once with, like "mpv --shuffle", instead of xdg-open or open
+        #[arg(long, value_name = "PROGRAM")]
+        with: Option<String>,
+
+        /// Choose which files to open with fzf, or the picker in FTAG_PICKER
+        #[arg(long)]
+        pick: bool,
+
+        /// Optional tags which matching files must not have
+        #[arg(required=false, last=true)]
+        exclude: Vec<String>,
+    },
+
+    /// Run a command for each file with specific tags, replacing {} with its path
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Exec {
+        /// Tags that matching files must have
+        #[arg(required=false)]
+        find: Vec<String>,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
+        /// Tag which matching files must not have, can be given more than once
+        #[arg(short = 'x', long = "exclude", value_name = "TAG")]
+        exclude: Vec<String>,
+
+        /// Command to run after --, like `-- cp {} backup/`. The path is added at the end if there is no {}
+        #[arg(required = true, last = true)]
+        command: Vec<String>,
+    },
+
+    /// Make a directory of symlinks to the files with specific tags
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Link {
+        /// Tags that matching files must have
+        #[arg(required=false)]
+        find: Vec<String>,
+
+        /// Directory to put the links in, created if needed
+        #[arg(long, value_name = "DIR")]
+        into: Utf8PathBuf,
+
+        /// Remove the links already in the directory first, so it only holds this search
+        #[arg(long)]
+        clean: bool,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
         /// Optional tags which matching files must not have
         #[arg(required=false, last=true)]
         exclude: Vec<String>,
     },
 
     /// Select a random file with specific tags
+    #[cfg(feature = "full")]
     #[command(arg_required_else_help = false)]
     Rand {
         /// Tags that matching files must have
         #[arg(required=false)]
         find: Vec<String>,
 
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
         /// Optional tags which matching files must not have
         #[arg(required=false, last=true)]
         exclude: Vec<String>,
     },
 
-    /// Rename a single tag for a path
+    /// Print the directory of a file with specific tags, choosing among several with fzf, for `ftag shell-init`
+    #[cfg(feature = "full")]
     #[command(arg_required_else_help = true)]
-    Rename {
-        /// Target path
+    Cd {
+        /// Tags that matching files must have
+        #[arg(required=false)]
+        find: Vec<String>,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
+        /// Optional tags which matching files must not have
+        #[arg(required=false, last=true)]
+        exclude: Vec<String>,
+    },
+
+    /// Report tagged files whose permissions or ownership break a policy
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    AuditPerms {
+        /// Tags that audited files must have
+        #[arg(required=false)]
+        find: Vec<String>,
+
+        /// Expected permissions, like `mode=640,owner=me,group=staff`
+        #[arg(short, long, required=true)]
+        expect: String,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
+        /// Optional tags which audited files must not have
+        #[arg(required=false, last=true)]
+        exclude: Vec<String>,
+    },
+
+    /// Tag files by the directories they are in, such as `Genre/Artist/Album`
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    MigrateDirs {
+        /// Root of the directory tree to convert
+        dir: Utf8PathBuf,
+
+        /// Number of directory levels beneath DIR that make up each tag, at least 1
+        #[arg(short, long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        levels: usize,
+
+        /// Namespace to put every derived tag under
+        #[arg(short, long)]
+        prefix: Option<String>,
+
+        /// Move every file directly into DIR after tagging it
+        #[arg(short, long)]
+        flatten: bool,
+
+        /// Show what would happen to each file without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Tag the files beneath a directory by the glob rules in .ftagrules, PDF metadata, or tagger plugins
+    #[cfg(feature = "full")]
+    Autotag {
+        /// Directory to look for files in. If unspecified, uses the current directory
+        dir: Option<Utf8PathBuf>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Keep running, tagging new files as they appear instead of the files already there
+        #[arg(short, long, conflicts_with = "dry_run")]
+        watch: bool,
+
+        /// How often to look for new files while watching, like 2s or 1m
+        #[arg(long, value_name = "DURATION", default_value = "2s", requires = "watch")]
+        interval: TimeSpan,
+
+        /// Also add the tags suggested by a plugin in ~/.config/ftag/taggers, can be given more than once
+        #[arg(long = "tagger", value_name = "NAME")]
+        taggers: Vec<String>,
+
+        /// Also tag PDFs with the keywords, authors, and title in their metadata, the same as --tagger pdf
+        #[arg(long)]
+        pdf: bool,
+    },
+
+    /// Tag the files beneath a directory with parts of their names, like `{date}-{project}-{*}.md`
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Harvest {
+        /// Directory to look for files in. If unspecified, uses the current directory
+        dir: Option<Utf8PathBuf>,
+
+        /// Pattern the names must match. `{key}` tags a file key=VALUE, `{}` tags it VALUE, and `{*}` matches anything
+        #[arg(short, long, required = true)]
+        pattern: String,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Copy the tags of files beneath one directory to the files with the same paths beneath another
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Mirror {
+        /// Directory whose files' tags are copied
+        src: Utf8PathBuf,
+
+        /// Directory whose files are given the same tags
+        dst: Utf8PathBuf,
+
+        /// Match files by their path without the extension, like raw/a.cr2 and jpg/a.jpg
+        #[arg(long)]
+        ignore_extension: bool,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Copy the tags another tagging tool gave to files beneath the database
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Import {
+        /// Tool the tags come from
+        #[arg(long, value_enum)]
+        from: import::ImportFormat,
+
+        /// Where that tool keeps its tags, like .tmsu/db for TMSU or a directory of tagged files for Finder
+        source: Utf8PathBuf,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Copy the tags of the files beneath a directory into Finder on macOS, replacing the Finder tags they had
+    #[cfg(feature = "full")]
+    SyncFinder {
+        /// Directory to look in. If unspecified, uses every tracked file
+        dir: Option<Utf8PathBuf>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Write an HTML report on the health of the database
+    #[cfg(feature = "full")]
+    Report {
+        /// File to write the report to. If unspecified, prints the report
+        #[arg(short, long)]
+        output: Option<Utf8PathBuf>,
+    },
+
+    /// Show the tags of a path, whether it still exists, and when it was tagged
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Show {
+        /// Path to show, which may have been deleted since it was tagged
         path: Utf8PathBuf,
+    },
 
-        /// Old tag name
-        #[arg(name="OLD")]
-        old_tag: String,
+    /// Rate a file from 1 to 5 stars, kept apart from its tags
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Rate {
+        /// Path to rate
+        path: Utf8PathBuf,
 
-        /// New tag name
-        #[arg(name="NEW")]
-        new_tag: String,
-    }
-}
+        /// Number of stars, or 0 to remove the rating
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=rating::MAX_RATING as i64))]
+        rating: u8,
+    },
 
-fn display_tags(tags: HashSet<String>, reverse: bool) {
-    // Get the HashSet as a vector and alphabetize it
-    let mut tags: Vec<_> = tags.into_iter().collect();
-    tags.sort(); // alphabetic and case-sensitive
+    /// Attach a sentence of context to a file, or show the one it has
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Note {
+        /// Path to attach the note to
+        path: Utf8PathBuf,
 
-    if reverse {
-        tags.reverse();
-    }
+        /// Text of the note, replacing any note the file had. If unspecified, shows the current note
+        text: Option<String>,
 
-    // Print them out with a little header
-    for tag in tags {
-        println!("{}", tag);
-    }
-}
+        /// Remove the note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
 
-fn main() {
-    let args = Cli::parse();
+    /// Show statistics about the tags in the database
+    #[cfg(feature = "full")]
+    Stats {
+        /// Number of most used tags and tag pairs to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
 
-    // Handle whichever command the user chose
-    match args.command {
-        Commands::Init => {
-            match ftag::init_db() {
-                Ok(_) => println!("Initialized database."),
-                Err(err) => match err {
-                    FtagError::IoError(ErrorKind::AlreadyExists) => eprintln!("Database already exists!"),
-                    _ => eprintln!("{}", err.to_string())
-                },
-            }
-        }
+    /// Find tracked files with the same contents, and merge their tags or remove the extra copies
+    #[cfg(feature = "full")]
+    Dupes {
+        /// Directory to look in. If unspecified, looks through every tracked file
+        dir: Option<Utf8PathBuf>,
 
-        Commands::List { path, reverse, count, sortcount} => match path {
-            Some(path) => {
-                match ftag::get_file_tags(&path) {
-                    Err(err) => match err {
-                        FtagError::IoError(ErrorKind::NotFound) => eprintln!("Filepath {} does not exist!", path),
-                        _ => eprintln!("{}", err.to_string())
-                    }
-                    Ok(tags) => display_tags(tags, reverse),
-                }
-            },
-            None => {
-                match ftag::get_global_tags() {
-                    Err(err) => eprintln!("{}", err.to_string()),
-                    Ok(tag_counts) => {
-                        // Collect the keys and value into a vector of tuples
-                        let mut pairs: Vec<(String, u32)> = tag_counts.into_iter().collect();
-
-                        // Sort either by counts or alphabetically
-                        if sortcount {
-                            // Sort by count, descending order
-                            pairs.sort_by(|a, b| b.1.cmp(&a.1));
-                        } else {
-                            // Sort alphabetically, ascending order
-                            pairs.sort_by(|a, b| a.0.cmp(&b.0));
-                        }
+        /// Give every copy of a file the tags of all of them
+        #[arg(long)]
+        merge: bool,
 
-                        // Reverse the list if that was specified
-                        if reverse {
-                            pairs.reverse();
-                        }
+        /// Give the first copy of each file the tags of all of them, then delete the other copies
+        #[arg(long, conflicts_with_all = ["merge", "link"])]
+        delete: bool,
 
-                        for pair in pairs {
-                            // If printing counts, put "(#) " on the same line
-                            if count {
-                                print!("({}) ", pair.1);
-                            }
+        /// Give every copy of a file the tags of all of them, then replace all but the first with symlinks to it
+        #[arg(long, conflicts_with = "merge")]
+        link: bool,
 
-                            // Always print path
-                            println!("{}", pair.0);
-                        }
-                    },
-                }
-            },
-        },
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
 
-        Commands::Add { path, tags } => {
-            match ftag::add_tags(&path, tags) {
-                Err(err) => match err {
-                    FtagError::IoError(ErrorKind::NotFound) => eprintln!("Filepath {} does not exist!", path),
-                    _ => eprintln!("{}", err.to_string()),
-                },
-                Ok(new_tags) => display_tags(new_tags, false),
-            }
-        },
+    /// Diagnose common problems: which database is used and why, its schema, permissions, and write-ahead log
+    #[cfg(feature = "full")]
+    Doctor,
 
-        Commands::Rm { path, tags } => {
-            match ftag::remove_tags(&path, tags) {
-                Err(err) => match err {
-                    FtagError::IoError(ErrorKind::NotFound) => eprintln!("Filepath {} does not exist!", path),
-                    _ => eprintln!("{}", err.to_string()),
-                },
-                Ok(new_tags) => display_tags(new_tags, false),
-            }
-        },
+    /// Check the database for unreadable rows, repeated paths, deleted files, and paths without tags
+    #[cfg(feature = "full")]
+    Check,
 
-        Commands::Find { find, exclude , tags } => {
-            match ftag::find_tags(&find, &exclude) {
-                Err(err) => eprintln!("{}", err.to_string()),
-                Ok(mut files) => {
-                    // Alphabetize the vector returned
-                    files.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+    /// Salvage or drop unreadable rows and merge repeated paths, backing up the database first
+    #[cfg(feature = "full")]
+    Repair {
+        /// Show what would be fixed without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
 
-                    // Print them out with a little header
-                    for (file, file_tags) in files {
-                        println!("{}", file);
-                        if tags {
-                            println!("  {}", file_tags.iter().format("; "));
-                        }
-                    }
-                },
-            }
-        },
+    /// Save a snapshot of the database, keeping only the newest few (see the backup-keep setting)
+    #[cfg(feature = "full")]
+    Backup {
+        /// Directory to keep backups in. If unspecified, uses .ftag-backups next to the database
+        dir: Option<Utf8PathBuf>,
 
-        Commands::Rand { find, exclude } => {
-            match ftag::find_tags(&find, &exclude) {
-                Err(err) => eprintln!("{}", err.to_string()),
-                Ok(files) => {
-                    match files.choose(&mut rand::thread_rng()) {
-                        Some((name, _)) => println!("{}", name),
-                        None => println!(),
-                    }
-                },
-            }
-        },
+        /// Number of backups to keep, instead of the backup-keep setting
+        #[arg(long, value_name = "COUNT")]
+        keep: Option<usize>,
+    },
 
-        Commands::Rename { path, old_tag, new_tag} => {
-            // Determine whether the path contains old_tag
-            let current_tags = get_file_tags(&path);
-            match current_tags {
-                Err(err) => match err {
-                    FtagError::IoError(ErrorKind::NotFound) => eprintln!("Filepath {} does not exist!", path),
-                    _ => eprintln!("{}", err.to_string()),
-                },
-                Ok(tags) => {
-                    if !tags.contains(&old_tag) {
-                        eprintln!("Tag {} not found.", old_tag);
-                        return;
-                    }
+    /// Roll the database back to a backup, backing up the current database first
+    #[cfg(feature = "full")]
+    Restore {
+        /// Backup to restore. If unspecified, restores the newest one
+        backup: Option<Utf8PathBuf>,
 
-                    // Remove the old tag and swap in the new one
-                    if let Err(err) = ftag::remove_tags(&path, vec![old_tag]) {
-                        eprintln!("{}", err.to_string());
-                    }
-                    if let Err(err) = ftag::add_tags(&path, vec![new_tag]) {
-                        eprintln!("{}", err.to_string());
-                    }
+        /// Directory holding the backups. If unspecified, uses .ftag-backups next to the database
+        #[arg(long, value_name = "DIR")]
+        dir: Option<Utf8PathBuf>,
 
-                    // Print out the properly updated tags
-                    match ftag::get_file_tags(&path) {
-                        Err(err) => eprintln!("{}", err.to_string()),
-                        Ok(tags) => display_tags(tags, false),
-                    }
-                }
-            }
+        /// List the backups, oldest first, instead of restoring one
+        #[arg(long, conflicts_with = "backup")]
+        list: bool,
+    },
 
-        }
+    /// Bring the database and another copy of it up to date with each other, keeping whichever tags changed last
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Sync {
+        /// The other database, or the directory holding it
+        other: Utf8PathBuf,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Remove paths that no longer exist from the database
+    #[cfg(feature = "full")]
+    Prune {
+        /// Show which paths would be removed without removing them
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// List every path in the database, whatever its tags
+    #[cfg(feature = "full")]
+    Paths {
+        /// Only list paths that no longer exist, which prune would remove
+        #[arg(long, conflicts_with = "existing")]
+        missing: bool,
+
+        /// Only list paths that still exist
+        #[arg(long)]
+        existing: bool,
+    },
+
+    /// Show or change database settings
+    #[cfg(feature = "full")]
+    Config {
+        /// Setting to show or change. If unspecified, shows all settings
+        key: Option<String>,
+
+        /// New value for the setting. If unspecified, shows the current value
+        value: Option<String>,
+    },
+
+    /// Describe or color a tag
+    #[cfg(feature = "full")]
+    #[command(subcommand)]
+    Tag(TagCommands),
+
+    /// Save tags you often add together under a name, then add them all with @NAME
+    #[cfg(feature = "full")]
+    #[command(subcommand)]
+    Bundle(BundleCommands),
+
+    /// Save searches like "todo and not done" under a name and run them later
+    #[cfg(feature = "full")]
+    #[command(subcommand)]
+    Query(QueryCommands),
+
+    /// Register databases in other directories, so `find --all-roots` searches them all at once
+    #[cfg(feature = "full")]
+    #[command(subcommand)]
+    Roots(RootsCommands),
+
+    /// Keep a merged index of every registered root, so `find --index` searches them all without opening each one
+    #[cfg(feature = "full")]
+    #[command(subcommand)]
+    Index(IndexCommands),
+
+    /// Apply many add, rm, and rename operations from a script, all at once
+    #[cfg(feature = "full")]
+    Apply {
+        /// Script to read, with one operation per line or a JSON list. If unspecified or -, reads standard input
+        file: Option<Utf8PathBuf>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Rename a single tag for a path, or many tags across the whole database
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Rename {
+        /// Target path
+        #[arg(required_unless_present = "map")]
+        path: Option<Utf8PathBuf>,
+
+        /// Old tag name
+        #[arg(name="OLD", required_unless_present = "map")]
+        old_tag: Option<String>,
+
+        /// New tag name
+        #[arg(name="NEW", required_unless_present = "map")]
+        new_tag: Option<String>,
+
+        /// Rename tags in every file, from a file with one OLD<TAB>NEW pair per line ("-" reads stdin)
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["path", "OLD", "NEW"])]
+        map: Option<Utf8PathBuf>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Replace one or more tags with another in every file, like `ftag merge-tags todo to-do into TODO`
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true, override_usage = "ftag merge-tags [OPTIONS] <OLD>... into <NEW>")]
+    MergeTags {
+        /// Tags to merge, then `into` and the tag to merge them into
+        #[arg(value_name = "WORDS", required = true)]
+        words: Vec<String>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Add tags to every file matching a search, like `ftag tag-matching 2019 --add archived`
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true, override_usage = "ftag tag-matching [OPTIONS] <FIND>... --add <TAG>... [-- <EXCLUDE>...]")]
+    TagMatching {
+        /// Tags that matching files must have
+        #[arg(required = true)]
+        find: Vec<String>,
+
+        /// Tags to add to every matching file
+        #[arg(short, long = "add", value_name = "TAG", num_args = 1.., required = true)]
+        add: Vec<String>,
+
+        /// Match files with at least one of the tags instead of all of them
+        #[arg(long)]
+        any: bool,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Optional tags which matching files must not have
+        #[arg(required = false, last = true)]
+        exclude: Vec<String>,
+    },
+
+    /// Print a script that completes commands and tag names, like `source <(ftag completions bash)`
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    Completions {
+        /// Shell to complete in
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+
+    /// Print a shell function that lets `ftag cd TAGS...` change directory, like `eval "$(ftag shell-init bash)"`
+    #[cfg(feature = "full")]
+    #[command(arg_required_else_help = true)]
+    ShellInit {
+        /// Shell to define the function in
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+
+    /// List the tags starting with a prefix, one per line, for completion scripts
+    #[cfg(feature = "full")]
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// Only list the tags this path has
+        #[arg(long)]
+        path: Option<Utf8PathBuf>,
+
+        /// Start of the tag being completed
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+/// Options of `find` that look further back in time or narrow down the results.
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, Default, PartialEq, clap::Args)]
+struct FindFilters {
+    /// Search the tags as they were at a past date, like 2024-01-01
+    #[arg(long, value_name = "DATE")]
+    asof: Option<String>,
+
+    /// Search the tags as they were when a git revision was committed, like HEAD~5
+    #[arg(long, value_name = "REVISION", conflicts_with = "asof")]
+    at: Option<String>,
+
+    /// Only list files whose paths match a glob, like 'docs/**/*.md'
+    #[arg(long, value_name = "GLOB")]
+    path: Option<String>,
+
+    /// Treat files beneath a tagged directory as having that directory's tags too
+    #[arg(long)]
+    inherit: bool,
+
+    /// Only list files also named in a list of paths, one per line or NUL-separated ("-" reads stdin)
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<Utf8PathBuf>,
+
+    /// Only list files whose contents changed since they were tagged (needs the git-aware setting)
+    #[arg(long)]
+    dirty: bool,
+
+    /// Only list files of at least this size, like 10M or 1.5G
+    #[arg(long, value_name = "SIZE")]
+    larger: Option<ByteSize>,
+
+    /// Only list files smaller than this size, like 10M or 1.5G
+    #[arg(long, value_name = "SIZE")]
+    smaller: Option<ByteSize>,
+
+    /// Treat the tags searched for as regular expressions, like '^20\d\d$'
+    #[arg(long)]
+    regex: bool,
+
+    /// Choose among the found files with fzf, or the picker in FTAG_PICKER, and list only the chosen ones
+    #[arg(long, conflicts_with_all = ["tags", "count"])]
+    pick: bool,
+
+    /// Show how the search would be carried out and how many rows each step handles, instead of running it
+    #[arg(long)]
+    explain: bool,
+
+    /// Only list files modified within this long, like 90d or 2w
+    #[arg(long, value_name = "DURATION")]
+    newer: Option<TimeSpan>,
+
+    /// Only list files last modified at least this long ago, like 90d or 2w
+    #[arg(long, value_name = "DURATION")]
+    older: Option<TimeSpan>,
+
+    /// Only list files given a searched tag on or after a date, like 2024-01-01
+    #[arg(long, value_name = "DATE")]
+    tagged_after: Option<String>,
+
+    /// Only list files given a searched tag on or before a date, like 2024-01-01
+    #[arg(long, value_name = "DATE")]
+    tagged_before: Option<String>,
+
+    /// Only list files with a rating, like 4 or '>=4'
+    #[arg(long, value_name = "RATING")]
+    rating: Option<RatingFilter>,
+
+    /// Only list files whose note contains some text, ignoring case
+    #[arg(long, value_name = "TEXT")]
+    note_contains: Option<String>,
+
+    /// Search every registered root instead of this database, listing files by their full paths
+    #[arg(long, conflicts_with_all = ["explain", "files_from"])]
+    all_roots: bool,
+
+    /// Search the index of every registered root made by `ftag index rebuild`, which is faster than --all-roots
+    #[arg(
+        long,
+        conflicts_with_all = ["all_roots", "asof", "at", "inherit", "files_from", "dirty", "tagged_after", "tagged_before", "rating", "note_contains", "explain"]
+    )]
+    index: bool,
+
+    /// Print each file with a template like '{path}\t{tags:,}', using {path}, {tags}, {count}, and {mtime}
+    #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["tags", "count", "print0", "tree", "tsv"])]
+    format: Option<String>,
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Subcommand)]
+enum AsofCommands {
+    /// List tags of a path or globally
+    List {
+        /// Target path for list. If unspecified, will list tags globally
+        path: Option<Utf8PathBuf>,
+
+        /// Reverse sorting order
+        #[arg(short, long)]
+        reverse: bool,
+
+        /// Display tag counts on global list (only on global list)
+        #[arg(short, long)]
+        count: bool,
+
+        /// Sort by descending count, instead of alphabetically (only on global list)
+        #[arg(short, long)]
+        sortcount: bool,
+
+        /// Display hierarchical tags like `photo/vacation` as an indented tree
+        #[arg(short, long)]
+        tree: bool,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Subcommand)]
+enum BundleCommands {
+    /// Save tags under a bundle name, replacing any bundle already saved with it
+    #[command(arg_required_else_help = true)]
+    Create {
+        /// Name of the bundle, used as @NAME when adding or removing tags
+        name: String,
+
+        /// Tags in the bundle
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// List the bundles and their tags
+    List,
+
+    /// Forget a bundle, leaving files already tagged with it alone
+    #[command(arg_required_else_help = true)]
+    Delete {
+        /// Name of the bundle
+        name: String,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Subcommand)]
+enum QueryCommands {
+    /// Save a search under a name, replacing any search already saved with it
+    #[command(arg_required_else_help = true)]
+    Save {
+        /// Name to save the search under
+        name: String,
+
+        /// Tags combined with and, or, not, and parentheses, like "todo and not (done or archived)"
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+
+    /// Run a saved search, listing the files it finds
+    #[command(arg_required_else_help = true)]
+    Run {
+        /// Name of the search
+        name: String,
+
+        /// Display tags of each file
+        #[arg(short, long)]
+        tags: bool,
+
+        /// How to compare case when matching tags
+        #[arg(long, value_enum, default_value_t = CaseMode::Smart)]
+        case: CaseMode,
+    },
+
+    /// List the saved searches
+    List,
+
+    /// Forget a saved search
+    #[command(arg_required_else_help = true)]
+    Delete {
+        /// Name of the search
+        name: String,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Subcommand)]
+enum IndexCommands {
+    /// Read the tags of every registered root into the index, replacing what it held before
+    Rebuild,
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Subcommand)]
+enum RootsCommands {
+    /// List the registered roots
+    List,
+
+    /// Register the database in a directory as a root
+    Add {
+        /// Directory holding the database. If unspecified, uses the current database
+        dir: Option<Utf8PathBuf>,
+    },
+
+    /// Forget a registered root, leaving its database alone
+    #[command(arg_required_else_help = true)]
+    Rm {
+        /// Directory of the root
+        dir: Utf8PathBuf,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Debug, Subcommand)]
+enum TagCommands {
+    /// Attach a description to a tag, shown when listing tags
+    #[command(arg_required_else_help = true)]
+    Describe {
+        /// Tag to describe
+        name: String,
+
+        /// Description of the tag. If unspecified, removes the current description
+        text: Option<String>,
+    },
+
+    /// Set the color a tag is displayed in
+    #[command(arg_required_else_help = true)]
+    Color {
+        /// Tag to color
+        name: String,
+
+        /// Color to use. If unspecified, goes back to the default color
+        #[arg(id = "tag_color", value_name = "COLOR", value_enum)]
+        color: Option<TagColor>,
+    },
+
+    /// List tags with a description or color, or used in the past, that no existing file has anymore
+    Orphans {
+        /// Remove the descriptions and colors of the orphaned tags. Their history is kept for `asof`
+        #[arg(long)]
+        clean: bool,
+    },
+}
+
+/// Print the tags used across the database, sorted and formatted as requested.
+/// 
+/// * `tag_counts` - Every tag along with how many paths have it
+/// * `reverse` - Reverse the sorting order
+/// * `count` - Print the count before each tag
+/// * `sortcount` - Sort by descending count instead of alphabetically
+/// * `tree` - Print hierarchical tags as an indented tree
+/// * `tag_meta` - Metadata of all tags, used to color and describe them
+fn display_global_tags(tag_counts: HashMap<String, u32>, reverse: bool, count: bool, sortcount: bool, tree: bool, group: bool, tag_meta: &HashMap<String, TagMeta>) {
+    let pairs = sort_tag_counts(tag_counts, reverse, sortcount);
+
+    // Only keep the counts around if they should be printed
+    let pairs = pairs.into_iter().map(|(tag, n)| (tag, count.then_some(n))).collect();
+    if tree {
+        display_tag_tree(pairs, tag_meta);
+    } else if group {
+        display_tag_groups(pairs, tag_meta);
+    } else {
+        display_tag_list(pairs, tag_meta);
+    }
+}
+
+/// Sort the tags used across the database alphabetically, or by descending count.
+///
+/// * `tag_counts` - Every tag along with how many paths have it
+/// * `reverse` - Reverse the sorting order
+/// * `sortcount` - Sort by descending count instead of alphabetically
+fn sort_tag_counts(tag_counts: HashMap<String, u32>, reverse: bool, sortcount: bool) -> Vec<(String, u32)> {
+    // Collect the keys and value into a vector of tuples
+    let mut pairs: Vec<(String, u32)> = tag_counts.into_iter().collect();
+
+    // Sort either by counts or alphabetically
+    if sortcount {
+        // Sort by count, descending order
+        pairs.sort_by_key(|pair| std::cmp::Reverse(pair.1));
+    } else {
+        // Sort alphabetically, ascending order
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    // Reverse the list if that was specified
+    if reverse {
+        pairs.reverse();
+    }
+    pairs
+}
+
+/// Find files with all of `find` and none of `exclude`, applying the extra options given to `find`.
+/// 
+/// * `find` - Tags that matching files must have, from `ftag::parse_queries`
+/// * `exclude` - Tags that matching files must not have
+/// * `any` - Match files with at least one of `find` instead of all of them
+/// * `filters` - When to look back to, and how to narrow down the results
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database, an option is invalid, or errors occur when reading the database or running git.
+#[cfg(feature = "full")]
+fn find_filtered(find: &[TagQuery], exclude: &[TagQuery], any: bool, filters: FindFilters) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let time = search_time(&filters)?;
+
+    // Look back through the journal if a time was given, otherwise search the current tags
+    let mut files = match (time, filters.inherit) {
+        (Some(time), false) => ftag::filter_rows(journal::rows_asof(time)?, find, exclude, any),
+        (None, false) => ftag::find_tags(find, exclude, any)?,
+        (Some(time), true) => ftag::filter_rows(ftag::inherit_tags(journal::rows_asof(time)?)?, find, exclude, any),
+        (None, true) => {
+            ftag::maybe_prune()?;
+            ftag::filter_rows(ftag::inherit_tags(ftag::get_all_rows()?)?, find, exclude, any)
+        },
+    };
+
+    // Narrow down to the files in the right place
+    if let Some(pattern) = filters.path {
+        files = ftag::filter_paths(files, &pattern)?;
+    }
+
+    // Narrow down to the files another tool listed
+    if let Some(list) = filters.files_from {
+        let listed = match list.as_str() {
+            "-" => std::io::read_to_string(std::io::stdin()),
+            _ => std::fs::read_to_string(&list),
+        };
+        let listed = listed.map_err(|err| FtagError::ArgumentError(format!("Could not read {}: {}", list, err)))?;
+        files = ftag::filter_listed(files, &listed);
+    }
+
+    // Narrow down to files of the right size and age
+    files = ftag::filter_metadata(files, filters.larger, filters.smaller, filters.newer, filters.older);
+
+    // Narrow down to the files tagged at the right time
+    if filters.tagged_after.is_some() || filters.tagged_before.is_some() {
+        let after = filters.tagged_after.as_deref().map(journal::parse_start_time).transpose()?;
+        let before = filters.tagged_before.as_deref().map(journal::parse_time).transpose()?;
+        files = journal::filter_tagged(files, find, after, before)?;
+    }
+
+    // Narrow down to the files rated highly enough
+    if let Some(filter) = filters.rating {
+        files = rating::filter_rated(files, filter)?;
+    }
+
+    // Narrow down to the files with the right notes
+    if let Some(text) = filters.note_contains {
+        files = note::filter_noted(files, &text)?;
+    }
+
+    // Narrow down to the files that changed since they were tagged
+    if filters.dirty {
+        let changed = git::dirty_paths(&files.iter().map(|(file, _)| file.as_str()).collect::<Vec<_>>())?;
+        files.retain(|(file, _)| changed.contains(file));
+    }
+
+    Ok(files)
+}
+
+/// Explain how `find_filtered` would search, without running the search.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if there is no database, an option is invalid, or errors occur when reading the database or running git.
+#[cfg(feature = "full")]
+fn explain_filtered(find: &[TagQuery], exclude: &[TagQuery], any: bool, filters: &FindFilters) -> Result<Vec<explain::Stage>, FtagError> {
+    explain::explain(&explain::Search {
+        find,
+        exclude,
+        any,
+        time: search_time(filters)?,
+        inherit: filters.inherit,
+        path: filters.path.as_deref(),
+        files_from: filters.files_from.as_deref(),
+        metadata: filters.larger.is_some() || filters.smaller.is_some() || filters.newer.is_some() || filters.older.is_some(),
+        tagged: filters.tagged_after.is_some() || filters.tagged_before.is_some(),
+        rating: filters.rating.is_some(),
+        note: filters.note_contains.as_deref(),
+        dirty: filters.dirty,
+    })
+}
+
+/// Work out when `find` should look back to, if a date or revision was given.
+/// 
+/// # Failure
+/// 
+/// Returns `Err` if the date could not be understood or the revision could not be found.
+#[cfg(feature = "full")]
+fn search_time(filters: &FindFilters) -> Result<Option<i64>, FtagError> {
+    match (&filters.asof, &filters.at) {
+        (Some(date), _) => Ok(Some(journal::parse_time(date)?)),
+        (_, Some(revision)) => Ok(Some(git::revision_time(revision)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Convert the paths typed on the command line so they mean the same files from the database root.
+///
+/// # Failure
+///
+/// Returns `Err` if a path to tag or look up is outside the directory holding the database.
+fn resolve_paths(command: &mut Commands) -> Result<(), FtagError> {
+    match command {
+        Commands::List { path: Some(path), .. } | Commands::Add { path, .. } | Commands::Rm { path, .. } | Commands::Set { path, .. } => {
+            *path = paths::to_stored(path)?;
+        },
+        #[cfg(feature = "full")]
+        Commands::Asof { command: AsofCommands::List { path: Some(path), .. }, .. }
+        | Commands::MigrateDirs { dir: path, .. }
+        | Commands::Show { path }
+        | Commands::Edit { path, .. }
+        | Commands::Rate { path, .. }
+        | Commands::Note { path, .. }
+        | Commands::Autotag { dir: Some(path), .. }
+        | Commands::Dupes { dir: Some(path), .. }
+        | Commands::Harvest { dir: Some(path), .. }
+        | Commands::SyncFinder { dir: Some(path), .. }
+        | Commands::Rename { path: Some(path), .. }
+        | Commands::Complete { path: Some(path), .. } => {
+            *path = paths::to_stored(path)?;
+        },
+        #[cfg(feature = "full")]
+        Commands::Mirror { src, dst, .. } => {
+            *src = paths::to_stored(src)?;
+            *dst = paths::to_stored(dst)?;
+        },
+        #[cfg(feature = "full")]
+        Commands::Autotag { dir: dir @ None, .. } | Commands::Harvest { dir: dir @ None, .. } => {
+            *dir = Some(paths::to_stored(camino::Utf8Path::new("."))?);
+        },
+        #[cfg(feature = "full")]
+        Commands::Find { filters: FindFilters { files_from: Some(file), .. }, .. }
+        | Commands::Report { output: Some(file) }
+        | Commands::Apply { file: Some(file), .. }
+        | Commands::Rename { map: Some(file), .. } if file.as_str() != "-" => {
+            // Reading from stdin stays as it is, other files are found from where ftag was started
+            *file = paths::from_invocation(file);
+        },
+        #[cfg(feature = "full")]
+        Commands::Link { into: dir, .. }
+        | Commands::Import { source: dir, .. }
+        | Commands::Sync { other: dir, .. }
+        | Commands::Roots(RootsCommands::Add { dir: Some(dir) } | RootsCommands::Rm { dir })
+        | Commands::Backup { dir: Some(dir), .. }
+        | Commands::Restore { dir: Some(dir), .. }
+        | Commands::Restore { backup: Some(dir), .. } => {
+            *dir = paths::from_invocation(dir);
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+/// Replace the bundles named like `@photo2024` among the tags to add or remove with the tags they hold.
+///
+/// # Failure
+///
+/// Returns `Err` if a bundle doesn't exist or the database could not be read.
+#[cfg(feature = "full")]
+fn expand_bundles(command: &mut Commands) -> Result<(), FtagError> {
+    if let Commands::Add { tags, .. } | Commands::Rm { tags, .. } | Commands::Set { tags, .. } | Commands::TagMatching { add: tags, .. } = command {
+        *tags = bundle::expand_bundles(std::mem::take(tags))?;
+    }
+    Ok(())
+}
+
+/// Run the user's `after-change` hook for a path whose tags were just changed by `action`.
+///
+/// The tags are already saved, so a hook that can't run or fails is only warned about.
+#[cfg(feature = "full")]
+fn run_change_hook(action: &str, path: &camino::Utf8Path) {
+    if let Err(err) = hooks::after_change(action, path) {
+        tracing::warn!("The {} hook failed for {}: {}", hooks::CHANGE_HOOK, paths::to_display(path.as_str()), err);
+    }
+}
+
+/// Convert a path relative to the database root into one to print, leaving absolute paths as they are.
+#[cfg(feature = "full")]
+fn shown_path(path: &camino::Utf8Path) -> String {
+    match path.is_absolute() {
+        true => path.to_string(),
+        false => paths::to_display(path.as_str()),
+    }
+}
+
+/// Exit status for failures without a more specific status. Clap uses 2 for invalid arguments.
+const EXIT_FAILURE: u8 = 1;
+
+/// Exit status for invalid arguments that clap can't catch itself, the same status clap uses.
+const EXIT_USAGE: u8 = 2;
+
+/// Exit status when there is no database in the current directory or above it.
+const EXIT_NO_DATABASE: u8 = 3;
+
+/// Exit status when a path given on the command line does not exist.
+const EXIT_NOT_FOUND: u8 = 4;
+
+/// Exit status of `find --exit-code` when nothing was found.
+const EXIT_NO_MATCHES: u8 = 5;
+
+/// Exit status of this invocation, decided by the first failure.
+static EXIT_STATUS: OnceLock<u8> = OnceLock::new();
+
+/// Print a failure and remember to exit with `status`, unless an earlier failure already chose one.
+fn fail(status: u8, message: impl Display) {
+    eprintln!("{}", display::paint_error(&message.to_string()));
+    let _ = EXIT_STATUS.set(status);
+}
+
+/// Print an error and remember to exit with the status matching it.
+fn report(err: FtagError) {
+    fail(status_of(&err), err);
+}
+
+/// Pick the exit status for an error.
+fn status_of(err: &FtagError) -> u8 {
+    match err {
+        FtagError::NoDatabaseError => EXIT_NO_DATABASE,
+        _ if err.is_not_found() => EXIT_NOT_FOUND,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Send log messages to standard error, showing more of them with each -v and fewer with -q.
+fn init_logging(quiet: bool, verbose: u8) {
+    let level = match (quiet, verbose) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::WARN,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+fn main() -> ExitCode {
+    run();
+    store::close_connections();
+    ExitCode::from(EXIT_STATUS.get().copied().unwrap_or(0))
+}
+
+/// Ask before changing more files than the `confirm-above` setting allows, unless `--yes` was given.
+///
+/// * `yes` - Whether `--yes` was given
+/// * `count` - Work out how many files would change, only called if it matters
+///
+/// # Failure
+///
+/// Returns `Err` if the user declines, there is no terminal to ask on, or the count or setting can't be found.
+#[cfg(feature = "full")]
+fn confirm_bulk(yes: bool, count: impl FnOnce() -> Result<usize, FtagError>) -> Result<(), FtagError> {
+    use std::io::{stdin, IsTerminal};
+
+    if yes {
+        return Ok(());
+    }
+    let files = count()?;
+    if files <= settings::get_number_setting(settings::CONFIRM_ABOVE)? {
+        return Ok(());
+    }
+
+    // Standard input may be a script or mapping, so only ask when someone is there to answer
+    if !stdin().is_terminal() {
+        return Err(FtagError::ArgumentError(format!("This would change {} files, pass --yes to go ahead", files)));
+    }
+    eprint!("This will change {} files. Continue? [y/N] ", files);
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(FtagError::ArgumentError("Cancelled, nothing was changed.".to_string())),
+    }
+}
+
+/// Carry out the command the user chose, reporting any failure with `fail` or `report`.
+fn run() {
+    let mut args = Cli::parse();
+
+    // Lock down what this invocation is allowed to do before doing anything else. A bare --sandbox would otherwise
+    // enforce nothing at all, so it forbids both
+    let (no_network, no_exec) = match (args.no_network, args.no_exec) {
+        (false, false) if args.sandbox => (true, true),
+        given => given,
+    };
+    if let Err(err) = sandbox::restrict(no_network, no_exec, args.sandbox) {
+        report(err);
+        return;
+    }
+
+    init_logging(args.quiet, args.verbose);
+    display::set_quiet(args.quiet);
+    store::set_busy_timeout(std::time::Duration::from_millis(args.busy_timeout));
+    display::set_plain(args.plain);
+    display::set_color(args.color);
+
+    // Everything else happens on the other machine, including finding its database
+    #[cfg(feature = "full")]
+    if let Some(remote) = &args.remote {
+        match remote::run_remote(remote, display::stdout_colored()) {
+            Err(err) => report(err),
+            Ok(status) => {
+                let _ = EXIT_STATUS.set(status);
+            },
+        }
+        return;
+    }
+
+    // Work from the directory holding the database, unless making a new one here
+    if !matches!(args.command, Commands::Init { .. }) {
+        if let Err(err) = paths::enter_root().and_then(|_| resolve_paths(&mut args.command)) {
+            report(err);
+            return;
+        }
+    }
+
+    #[cfg(feature = "full")]
+    if let Err(err) = expand_bundles(&mut args.command) {
+        report(err);
+        return;
+    }
+
+    // Tag metadata only decorates output, so carry on without it if it can't be read
+    let tag_meta = meta::get_all_tag_meta().unwrap_or_default();
+
+    // Handle whichever command the user chose
+    match args.command {
+        Commands::Init { backend } => {
+            match ftag::init_db(backend) {
+                Ok(_) if is_quiet() => {},
+                Ok(_) => println!("Initialized database."),
+                Err(FtagError::IoError(err)) if err.kind() == ErrorKind::AlreadyExists => fail(EXIT_FAILURE, "Database already exists!"),
+                Err(err) => report(err),
+            }
+        }
+
+        #[cfg(feature = "self-update")]
+        Commands::SelfUpdate { check } => match update::self_update(check) {
+            Err(err) => report(err),
+            Ok(update::UpdateStatus::UpToDate(version)) => println!("ftag {} is up to date.", version),
+            Ok(update::UpdateStatus::Available(version)) => println!("ftag {} is available.", version),
+            Ok(update::UpdateStatus::Installed(_)) if is_quiet() => {},
+            Ok(update::UpdateStatus::Installed(version)) => println!("Updated to ftag {}.", version),
+        },
+
+        Commands::Migrate => match schema::migrate() {
+            Err(err) => report(err),
+            Ok(_) if is_quiet() => {},
+            Ok(version) if version == schema::SCHEMA_VERSION => println!("Database is already up to date."),
+            Ok(version) => println!("Upgraded database from schema version {} to {}.", version, schema::SCHEMA_VERSION),
+        },
+
+        Commands::List { path, reverse, count, sortcount, tree, group, filter, recursive, #[cfg(feature = "full")] format } => {
+            // A path has one file to fill in, while the global and recursive lists have a line per tag
+            #[cfg(feature = "full")]
+            let fields: &[Field] = match path {
+                Some(_) if !recursive => &[Field::Path, Field::Tags, Field::Count, Field::Mtime],
+                _ => &[Field::Tag, Field::Count],
+            };
+            #[cfg(feature = "full")]
+            let template = match format.map(|text| Template::parse(&text, fields)).transpose() {
+                Ok(template) => template,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            match path {
+                Some(path) if !recursive => {
+                    match ftag::get_file_tags(&path) {
+                        Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                        Err(err) => report(err),
+                        Ok(tags) => {
+                            #[cfg(feature = "full")]
+                            if let Some(template) = &template {
+                                let mut tags: Vec<String> = tags.into_iter().collect();
+                                tags.sort();
+                                let shown = paths::to_display(path.as_str());
+                                let record = Record { path: Some(&shown), file: Some(path.as_str()), tags: Some(&tags), count: Some(tags.len()), ..Default::default() };
+                                println!("{}", template.render(&record));
+                                return;
+                            }
+                            display_tags(tags, reverse, tree, group, &tag_meta)
+                        },
+                    }
+                },
+                dir => {
+                    let tag_counts = match &dir {
+                        Some(dir) => ftag::get_tags_beneath(dir),
+                        None => ftag::get_global_tags(),
+                    };
+                    let tag_counts = match filter {
+                        Some(pattern) => tag_counts.and_then(|tag_counts| ftag::filter_tag_names(tag_counts, &pattern)),
+                        None => tag_counts,
+                    };
+                    match tag_counts {
+                        Err(err) if dir.is_some() && err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", dir.unwrap_or_default())),
+                        Err(err) => report(err),
+                        Ok(tag_counts) => {
+                            #[cfg(feature = "full")]
+                            if let Some(template) = &template {
+                                for (tag, n) in sort_tag_counts(tag_counts, reverse, sortcount) {
+                                    let record = Record { tag: Some(&tag), count: Some(n as usize), ..Default::default() };
+                                    println!("{}", template.render(&record));
+                                }
+                                return;
+                            }
+                            display_global_tags(tag_counts, reverse, count, sortcount, tree, group, &tag_meta)
+                        },
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Asof { date, command: AsofCommands::List { path, reverse, count, sortcount, tree } } => {
+            let rows = match journal::parse_time(&date).and_then(journal::rows_asof) {
+                Ok(rows) => rows,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            match path {
+                // The path may not exist anymore, so only look for it in the reconstructed rows
+                Some(path) => {
+                    let tags = rows.into_iter().find(|(name, _)| name == path.as_str()).map(|(_, tags)| tags);
+                    display_tags(tags.unwrap_or_default(), reverse, tree, false, &tag_meta);
+                },
+                None => {
+                    let mut tag_counts = HashMap::new();
+                    for tag in rows.into_iter().flat_map(|(_, tags)| tags) {
+                        *tag_counts.entry(tag).or_insert(0) += 1;
+                    }
+                    display_global_tags(tag_counts, reverse, count, sortcount, tree, false, &tag_meta);
+                },
+            }
+        },
+
+        Commands::Add { path, tags } => {
+            match ftag::add_tags(&path, tags) {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                Err(err) => report(err),
+                Ok(new_tags) => {
+                    #[cfg(feature = "full")]
+                    run_change_hook("add", &path);
+                    if !is_quiet() {
+                        display_tags(new_tags, false, false, false, &tag_meta);
+                    }
+                },
+            }
+        },
+
+        // Removing nothing would do nothing, and guessing it meant every tag would be too surprising
+        Commands::Rm { path, tags, all: false, .. } if tags.is_empty() => {
+            fail(EXIT_USAGE, format!("No tags to remove from {} were given, name them or pass --all to remove every tag", paths::to_display(path.as_str())));
+        },
+
+        Commands::Rm { path, tags, all, dry_run: true } => {
+            let change = ftag::preview_change(&path, |tx| match all {
+                true => ftag::remove_all_tags_in(tx, &path),
+                false => ftag::remove_tags_in(tx, &path, tags),
+            });
+            match change {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                Err(err) => report(err),
+                Ok(change) => display_changes(&[change], &tag_meta),
+            }
+        },
+
+        Commands::Rm { path, tags, all, dry_run: false } => {
+            let removed = match all {
+                true => ftag::remove_all_tags(&path),
+                false => ftag::remove_tags(&path, tags),
+            };
+            match removed {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                Err(err) => report(err),
+                Ok(new_tags) => {
+                    #[cfg(feature = "full")]
+                    run_change_hook("rm", &path);
+                    if !is_quiet() {
+                        display_tags(new_tags, false, false, false, &tag_meta);
+                    }
+                },
+            }
+        },
+
+        Commands::Set { path, tags, dry_run } => {
+            let change = match dry_run {
+                true => ftag::preview_change(&path, |tx| ftag::set_tags_in(tx, &path, tags)),
+                false => ftag::set_tags(&path, tags),
+            };
+            match change {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                Err(err) => report(err),
+                Ok(change) => {
+                    #[cfg(feature = "full")]
+                    if !dry_run && (!change.added.is_empty() || !change.removed.is_empty()) {
+                        run_change_hook("set", &path);
+                    }
+                    if dry_run || !is_quiet() {
+                        display_changes(&[change], &tag_meta);
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Edit { path, interactive, dry_run } => {
+            let change = ftag::get_file_tags(&path).and_then(|before| {
+                let mut current: Vec<String> = before.iter().cloned().collect();
+                current.sort();
+                let tags = match interactive {
+                    true => {
+                        let mut known: Vec<String> = ftag::get_global_tags()?.into_keys().filter(|tag| !before.contains(tag)).chain(current).collect();
+                        known.sort();
+                        pick::toggle(&known, &before)?.into_iter().collect()
+                    },
+                    false => pick::edit_lines(&format!("# Tags of {}, one per line", paths::to_display(path.as_str())), &current)?,
+                };
+                match dry_run {
+                    true => ftag::preview_change(&path, |tx| ftag::set_tags_in(tx, &path, tags)),
+                    false => ftag::set_tags(&path, tags),
+                }
+            });
+            match change {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                Err(err) => report(err),
+                Ok(change) if change.added.is_empty() && change.removed.is_empty() => {
+                    if !is_quiet() {
+                        println!("No changes.");
+                    }
+                },
+                Ok(change) => {
+                    if !dry_run {
+                        run_change_hook("edit", &path);
+                    }
+                    if dry_run || !is_quiet() {
+                        display_changes(&[change], &tag_meta);
+                    }
+                },
+            }
+        },
+
+        Commands::Find { find, exclude, tags, limit, offset, case, any, sort, reverse, exit_code, count, print0, tree, group, tsv, #[cfg(feature = "full")] jsonl, #[cfg(feature = "full")] filters } => {
+            #[cfg(feature = "full")]
+            let regex = filters.regex;
+            #[cfg(not(feature = "full"))]
+            let regex = false;
+            let (find_text, exclude_text) = (find, exclude);
+            let (find, exclude) = match ftag::parse_queries(&find_text, case, regex).and_then(|find| Ok((find, ftag::parse_queries(&exclude_text, case, regex)?))) {
+                Ok(queries) => queries,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            #[cfg(feature = "full")]
+            let template = match filters.format.as_deref().map(|text| Template::parse(text, &[Field::Path, Field::Tags, Field::Count, Field::Mtime])).transpose() {
+                Ok(template) => template,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            #[cfg(feature = "full")]
+            if filters.explain {
+                match explain_filtered(&find, &exclude, any, &filters) {
+                    Err(err) => report(err),
+                    Ok(stages) => {
+                        for (number, stage) in stages.iter().enumerate() {
+                            if is_plain() {
+                                for detail in &stage.details {
+                                    println!("step {}: {}; {}", number + 1, stage.title, detail);
+                                }
+                                continue;
+                            }
+
+                            println!("{}. {}", number + 1, stage.title);
+                            for detail in &stage.details {
+                                println!("   {}", detail);
+                            }
+                        }
+                    },
+                }
+                return;
+            }
+
+            // Without any filters the database can count the files itself, instead of listing them all
+            #[cfg(feature = "full")]
+            let unfiltered = filters == FindFilters::default();
+            #[cfg(not(feature = "full"))]
+            let unfiltered = true;
+            if count && unfiltered {
+                match ftag::count_tags(&find, &exclude, any) {
+                    Err(err) => report(err),
+                    Ok(found) => {
+                        if exit_code && found == 0 {
+                            let _ = EXIT_STATUS.set(EXIT_NO_MATCHES);
+                        }
+                        println!("{}", found);
+                    },
+                }
+                return;
+            }
+
+            // Without any filters each file can be printed as it is read, so huge results never pile up in memory
+            #[cfg(feature = "full")]
+            if jsonl && unfiltered && limit.is_none() && offset == 0 {
+                use std::io::Write;
+                use std::ops::ControlFlow;
+
+                let mut found_any = false;
+                let mut out = std::io::stdout().lock();
+                let streamed = ftag::visit_tags(&find, &exclude, any, |file, file_tags| {
+                    found_any = true;
+                    match writeln!(out, "{}", jsonl_row(&paths::to_display(&file), &file_tags)) {
+                        // Whatever reads the output may stop early, like `head`, and then there's no point going on
+                        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(ControlFlow::Break(())),
+                        Err(err) => Err(err.into()),
+                        Ok(()) => Ok(ControlFlow::Continue(())),
+                    }
+                });
+                match streamed {
+                    Err(err) => report(err),
+                    Ok(()) if exit_code && !found_any => {
+                        let _ = EXIT_STATUS.set(EXIT_NO_MATCHES);
+                    },
+                    Ok(()) => {},
+                }
+                return;
+            }
+
+            #[cfg(feature = "full")]
+            let pick = filters.pick;
+
+            // Alphabetical pages of the current tags can be cut out in SQL, anything else is cut after sorting
+            let paged = (limit.is_some() || offset > 0) && unfiltered && sort == SortKey::Name;
+
+            #[cfg(feature = "full")]
+            let found = match (filters.index, filters.all_roots) {
+                // The index only holds tags, so it only allows the filters that need nothing else from a database
+                (true, _) => index::search(&find, &exclude, any).and_then(|files| {
+                    let files = ftag::filter_metadata(files, filters.larger, filters.smaller, filters.newer, filters.older);
+                    match &filters.path {
+                        Some(pattern) => ftag::filter_paths(files, pattern),
+                        None => Ok(files),
+                    }
+                }),
+                // Each root may compare case differently, so the search is parsed again in each of them
+                (false, true) => roots::search_roots(|| {
+                    let find = ftag::parse_queries(&find_text, case, regex)?;
+                    find_filtered(&find, &ftag::parse_queries(&exclude_text, case, regex)?, any, filters.clone())
+                }),
+                (false, false) if paged => ftag::find_page(&find, &exclude, any, reverse, offset, limit.unwrap_or(usize::MAX)),
+                (false, false) => find_filtered(&find, &exclude, any, filters),
+            };
+            #[cfg(not(feature = "full"))]
+            let found = match paged {
+                true => ftag::find_page(&find, &exclude, any, reverse, offset, limit.unwrap_or(usize::MAX)),
+                false => ftag::find_tags(&find, &exclude, any),
+            };
+
+            match found {
+                Err(err) => report(err),
+                Ok(mut files) => {
+                    ftag::sort_files(&mut files, sort, reverse);
+                    if !paged {
+                        files = files.into_iter().skip(offset).take(limit.unwrap_or(usize::MAX)).collect();
+                    }
+
+                    // Let the user narrow the results down by hand, keeping them in order
+                    #[cfg(feature = "full")]
+                    if pick {
+                        let shown: Vec<String> = files.iter().map(|(file, _)| paths::to_display(file)).collect();
+                        match pick::pick(&shown) {
+                            Ok(chosen) => files.retain(|(file, _)| chosen.contains(&paths::to_display(file))),
+                            Err(err) => {
+                                report(err);
+                                return;
+                            },
+                        }
+                    }
+
+                    // Scripts can ask to be told when nothing was found, without any message
+                    if exit_code && files.is_empty() {
+                        let _ = EXIT_STATUS.set(EXIT_NO_MATCHES);
+                    }
+                    if count {
+                        println!("{}", files.len());
+                        return;
+                    }
+
+                    #[cfg(feature = "full")]
+                    if jsonl {
+                        for (file, file_tags) in &files {
+                            println!("{}", jsonl_row(&paths::to_display(file), file_tags));
+                        }
+                        return;
+                    }
+
+                    // Templates decide the whole line themselves
+                    #[cfg(feature = "full")]
+                    if let Some(template) = &template {
+                        for (file, file_tags) in &files {
+                            let shown = paths::to_display(file);
+                            let record = Record { path: Some(&shown), file: Some(file), tags: Some(file_tags), count: Some(file_tags.len()), ..Default::default() };
+                            println!("{}", template.render(&record));
+                        }
+                        return;
+                    }
+
+                    // Ratings are shown next to the tags, so they are only needed along with them
+                    #[cfg(feature = "full")]
+                    let ratings = match tags {
+                        true => rating::all_ratings().unwrap_or_default(),
+                        false => HashMap::new(),
+                    };
+
+                    // Highlight the tags that made the file match, followed by the rating if the file has one
+                    let paint_tags = |#[cfg_attr(not(feature = "full"), allow(unused_variables))] file: &str, file_tags: &[String]| {
+                        let painted = match group {
+                            true => paint_grouped_tags(file_tags, |tag| find.iter().any(|query| query.matches(tag)), &tag_meta).join("\n  "),
+                            false => file_tags
+                                .iter()
+                                .map(|tag| paint_found_tag(tag, find.iter().any(|query| query.matches(tag)), &tag_meta))
+                                .collect::<Vec<_>>()
+                                .join("; "),
+                        };
+                        #[cfg(feature = "full")]
+                        if let Some(rating) = ratings.get(file) {
+                            return format!("{}  {}", painted, paint_rating(*rating));
+                        }
+                        painted
+                    };
+
+                    // Indentation is hard to follow without seeing it, so plain output keeps one file per line
+                    if tree && !is_plain() {
+                        let files = files
+                            .iter()
+                            .map(|(file, file_tags)| (paths::to_display(file), tags.then(|| paint_tags(file, file_tags))))
+                            .collect();
+                        display_file_tree(files);
+                        return;
+                    }
+
+                    // Print them out with a little header
+                    for (file, file_tags) in files {
+                        let shown = paths::to_display(&file);
+                        if print0 {
+                            print!("{}\0", shown);
+                            continue;
+                        }
+                        if tsv {
+                            println!("{}", tsv_row(&shown, &file_tags));
+                            continue;
+                        }
+
+                        if tags && is_plain() {
+                            #[cfg(feature = "full")]
+                            if let Some(rating) = ratings.get(&file) {
+                                println!("file: {}; tags: {}; rating: {}", shown, file_tags.join(", "), rating);
+                                continue;
+                            }
+                            println!("file: {}; tags: {}", shown, file_tags.join(", "));
+                            continue;
+                        }
+
+                        println!("{}", shown);
+                        if tags {
+                            println!("  {}", paint_tags(&file, &file_tags));
+                        }
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Open { find, exclude, case, with, pick } => {
+            let found = ftag::parse_queries(&find, case, false).and_then(|find| ftag::find_tags(&find, &ftag::parse_queries(&exclude, case, false)?, false));
+            let mut files = match found {
+                Ok(files) => files,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+            ftag::sort_files(&mut files, SortKey::Name, false);
+
+            let mut files: Vec<String> = files.into_iter().map(|(file, _)| file).collect();
+            if pick {
+                let shown: Vec<String> = files.iter().map(|file| paths::to_display(file)).collect();
+                match pick::pick(&shown) {
+                    Ok(chosen) => files.retain(|file| chosen.contains(&paths::to_display(file))),
+                    Err(err) => {
+                        report(err);
+                        return;
+                    },
+                }
+            }
+
+            if files.is_empty() {
+                fail(EXIT_FAILURE, "No files to open");
+                return;
+            }
+            if let Err(err) = open::open_files(&files, with.as_deref()) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Exec { find, exclude, case, command } => {
+            let found = ftag::parse_queries(&find, case, false).and_then(|find| ftag::find_tags(&find, &ftag::parse_queries(&exclude, case, false)?, false));
+            let (mut files, dir) = match found.and_then(|files| Ok((files, paths::invocation_dir()?))) {
+                Ok(found) => found,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+            ftag::sort_files(&mut files, SortKey::Name, false);
+
+            // Keep going after a failure like find -exec does, so one bad file doesn't stop the rest
+            for (file, _) in files {
+                let shown = paths::to_display(&file);
+                if let Err(err) = exec::run_for(&command, &shown, &dir) {
+                    fail(status_of(&err), format!("{}: {}", shown, err));
+                }
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Link { find, into, clean, case, exclude } => {
+            let found = ftag::parse_queries(&find, case, false).and_then(|find| ftag::find_tags(&find, &ftag::parse_queries(&exclude, case, false)?, false));
+            let files: Vec<String> = match found {
+                Ok(mut files) => {
+                    ftag::sort_files(&mut files, SortKey::Name, false);
+                    files.into_iter().map(|(file, _)| file).collect()
+                },
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            match link::link_files(&files, &into, clean) {
+                Err(err) => report(err),
+                Ok(count) if !is_quiet() => println!("Made {} new links.", count),
+                Ok(_) => {},
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Rand { find, exclude, case } => {
+            match ftag::parse_queries(&find, case, false).and_then(|find| ftag::find_tags(&find, &ftag::parse_queries(&exclude, case, false)?, false)) {
+                Err(err) => report(err),
+                Ok(files) => {
+                    match files.choose(&mut rand::thread_rng()) {
+                        Some((name, _)) => println!("{}", paths::to_display(name)),
+                        None => println!(),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Cd { find, exclude, case } => {
+            let found = ftag::parse_queries(&find, case, false).and_then(|find| ftag::find_tags(&find, &ftag::parse_queries(&exclude, case, false)?, false));
+            let mut files = match found {
+                Ok(files) => files,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+            ftag::sort_files(&mut files, SortKey::Name, false);
+
+            let mut files: Vec<String> = files.into_iter().map(|(file, _)| file).collect();
+            if files.len() > 1 {
+                let shown: Vec<String> = files.iter().map(|file| paths::to_display(file)).collect();
+                match pick::pick(&shown) {
+                    Ok(chosen) => files.retain(|file| chosen.contains(&paths::to_display(file))),
+                    Err(err) => {
+                        report(err);
+                        return;
+                    },
+                }
+            }
+
+            // A tagged directory is gone into itself, a file goes to the directory holding it
+            let Some(file) = files.first().map(camino::Utf8Path::new) else {
+                fail(EXIT_FAILURE, "No file to go to");
+                return;
+            };
+            let dir = match file.is_dir() {
+                true => file,
+                false => file.parent().unwrap_or(file),
+            };
+            match paths::to_display(dir.as_str()) {
+                dir if dir.is_empty() => println!("."),
+                dir => println!("{}", dir),
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::AuditPerms { find, expect, case, exclude } => {
+            let policy = match audit::PermPolicy::parse(&expect) {
+                Ok(policy) => policy,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            match ftag::parse_queries(&find, case, false).and_then(|find| ftag::find_tags(&find, &ftag::parse_queries(&exclude, case, false)?, false)) {
+                Err(err) => report(err),
+                Ok(mut files) => {
+                    files.sort_by_key(|file| file.0.to_lowercase());
+                    for (file, _) in files {
+                        let shown = paths::to_display(&file);
+                        match audit::audit_path(&Utf8PathBuf::from(&file), &policy) {
+                            Err(err) => fail(status_of(&err), format!("{}: {}", shown, err)),
+                            Ok(problems) => {
+                                for problem in problems {
+                                    println!("{}: {}", shown, problem);
+                                }
+                            },
+                        }
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::MigrateDirs { dir, levels, prefix, flatten, dry_run } => {
+            let steps = match migrate::plan_migration(&dir, levels, prefix.as_deref(), flatten) {
+                Ok(steps) => steps,
+                Err(err) => {
+                    match err.is_not_found() {
+                        true => fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", dir)),
+                        false => report(err),
+                    }
+                    return;
+                },
+            };
+
+            if dry_run {
+                for step in steps {
+                    if is_plain() {
+                        println!("file: {}; moves to: {}; tag: {}", paths::to_display(step.from.as_str()), paths::to_display(step.to.as_str()), step.tag);
+                        continue;
+                    }
+
+                    if step.to != step.from {
+                        println!("{} -> {}", paths::to_display(step.from.as_str()), paths::to_display(step.to.as_str()));
+                    } else {
+                        println!("{}", paths::to_display(step.from.as_str()));
+                    }
+                    println!("  {}", step.tag);
+                }
+                return;
+            }
+
+            if let Err(err) = confirm_bulk(args.yes, || Ok(steps.len())) {
+                report(err);
+                return;
+            }
+
+            match migrate::apply_migration(steps) {
+                Err(err) => report(err),
+                Ok(not_moved) => {
+                    for (step, reason) in not_moved {
+                        fail(EXIT_FAILURE, format!("Tagged {} without moving it: {}", step.from, reason));
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Autotag { dir, watch: true, interval, mut taggers, pdf, .. } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from("."));
+            if pdf {
+                taggers.push(pdf::TAGGER_NAME.to_string());
+            }
+            if !is_quiet() {
+                eprintln!("Watching {} for new files, press Ctrl-C to stop.", paths::to_display(dir.as_str()));
+            }
+            let watched = autotag::watch(&dir, &taggers, interval.0, |added| {
+                if !is_quiet() {
+                    display_changes(added, &tag_meta);
+                }
+            });
+            match watched {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", dir)),
+                Err(err) => report(err),
+                Ok(()) => {},
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Autotag { dir, dry_run, mut taggers, pdf, .. } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from("."));
+            if pdf {
+                taggers.push(pdf::TAGGER_NAME.to_string());
+            }
+            let added = autotag::taggers(&taggers).and_then(|taggers| {
+                if !dry_run {
+                    confirm_bulk(args.yes, || Ok(autotag::autotag(&dir, &taggers, true)?.len()))?;
+                }
+                autotag::autotag(&dir, &taggers, dry_run)
+            });
+            match added {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", dir)),
+                Err(err) => report(err),
+                Ok(_) if is_quiet() => {},
+                Ok(added) => {
+                    display_changes(&added, &tag_meta);
+                    match dry_run {
+                        true => println!("Would tag {} files.", added.len()),
+                        false => println!("Tagged {} files.", added.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Harvest { dir, pattern, dry_run } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from("."));
+            let pattern = match harvest::Pattern::parse(&pattern) {
+                Ok(pattern) => pattern,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || Ok(harvest::harvest(&dir, &pattern, true)?.len())),
+            };
+            match confirmed.and_then(|_| harvest::harvest(&dir, &pattern, dry_run)) {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", dir)),
+                Err(err) => report(err),
+                Ok(_) if is_quiet() => {},
+                Ok(added) => {
+                    display_changes(&added, &tag_meta);
+                    match dry_run {
+                        true => println!("Would tag {} files.", added.len()),
+                        false => println!("Tagged {} files.", added.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Mirror { src, dst, ignore_extension, dry_run } => {
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || Ok(mirror::mirror(&src, &dst, ignore_extension, true)?.len())),
+            };
+            match confirmed.and_then(|_| mirror::mirror(&src, &dst, ignore_extension, dry_run)) {
+                Err(err) if err.is_not_found() => {
+                    let missing = if src.is_dir() { &dst } else { &src };
+                    fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", paths::to_display(missing.as_str())));
+                },
+                Err(err) => report(err),
+                Ok(_) if is_quiet() => {},
+                Ok(added) => {
+                    display_changes(&added, &tag_meta);
+                    match dry_run {
+                        true => println!("Would tag {} files.", added.len()),
+                        false => println!("Tagged {} files.", added.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Import { from, source, dry_run } => {
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || Ok(import::import(from, &source, true)?.changes.len())),
+            };
+            match confirmed.and_then(|_| import::import(from, &source, dry_run)) {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", shown_path(&source))),
+                Err(err) => report(err),
+                Ok(imported) => {
+                    for (file, reason) in &imported.skipped {
+                        tracing::warn!("Skipped {} because {}", shown_path(file), reason);
+                    }
+                    if is_quiet() {
+                        return;
+                    }
+                    display_changes(&imported.changes, &tag_meta);
+                    match dry_run {
+                        true => println!("Would tag {} files.", imported.changes.len()),
+                        false => println!("Tagged {} files.", imported.changes.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::SyncFinder { dir, dry_run } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from("."));
+            if !dir.is_dir() {
+                fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", dir));
+                return;
+            }
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || Ok(finder::sync_finder(&dir, &tag_meta, true)?.len())),
+            };
+            match confirmed.and_then(|_| finder::sync_finder(&dir, &tag_meta, dry_run)) {
+                Err(err) => report(err),
+                Ok(_) if is_quiet() => {},
+                Ok(changed) => {
+                    display_changes(&changed, &tag_meta);
+                    match dry_run {
+                        true => println!("Would update the Finder tags of {} files.", changed.len()),
+                        false => println!("Updated the Finder tags of {} files.", changed.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Report { output } => match report::build_report() {
+            Err(err) => report(err),
+            Ok(html) => match output {
+                Some(output) => {
+                    if let Err(err) = std::fs::write(&output, html) {
+                        fail(EXIT_FAILURE, format!("Could not write {}: {}", output, err));
+                    }
+                },
+                None => print!("{}", html),
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Show { path } => match show::details(&path) {
+            Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} is not tagged and does not exist!", path)),
+            Err(err) => report(err),
+            Ok(details) => {
+                let mut tags: Vec<String> = details.tags.iter().flatten().cloned().collect();
+                tags.sort();
+                let tags = match (&details.tags, is_plain()) {
+                    (None, _) => "not in the database".to_string(),
+                    (Some(_), true) => tags.join(", "),
+                    (Some(_), false) => tags.iter().map(|tag| paint_tag(tag, tag, &tag_meta)).collect::<Vec<_>>().join("; "),
+                };
+                let time = |time: Option<i64>| time.map_or("unknown".to_string(), journal::format_time);
+                let content = match (&details.blob, details.changed) {
+                    (None, _) => "not recorded, see the git-aware setting".to_string(),
+                    (Some(blob), None) => format!("git blob {}", blob),
+                    (Some(blob), Some(true)) => format!("git blob {}, changed since it was tagged", blob),
+                    (Some(blob), Some(false)) => format!("git blob {}, unchanged since it was tagged", blob),
+                };
+
+                let fields = [
+                    ("Path", paths::to_display(path.as_str())),
+                    ("Tags", tags),
+                    ("Rating", details.rating.map_or("not rated".to_string(), |rating| match is_plain() {
+                        true => rating.to_string(),
+                        false => paint_rating(rating),
+                    })),
+                    ("Note", details.note.clone().unwrap_or_else(|| "none".to_string())),
+                    ("On disk", if details.exists { "yes" } else { "no, deleted since it was tagged" }.to_string()),
+                    ("First tagged", time(details.first_tagged)),
+                    ("Last changed", time(details.last_changed)),
+                    ("Contents", content),
+                ];
+                for (label, value) in fields {
+                    match is_plain() {
+                        true => println!("{}: {}", label.to_lowercase(), value),
+                        false => println!("{:<14}{}", format!("{}:", label), value),
+                    }
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Rate { path, rating } => {
+            // A rating can still be taken off a file that was deleted
+            if rating > 0 && !path.exists() {
+                fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path));
+                return;
+            }
+            if let Err(err) = rating::set_rating(path.as_str(), rating) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Note { path, text: None, clear: false } => match note::get_note(path.as_str()) {
+            Err(err) => report(err),
+            Ok(None) => fail(EXIT_FAILURE, format!("Filepath {} has no note", path)),
+            Ok(Some(note)) => println!("{}", note),
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Note { path, text, .. } => {
+            // A note can still be taken off a file that was deleted
+            let text = text.unwrap_or_default();
+            if !text.trim().is_empty() && !path.exists() {
+                fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path));
+                return;
+            }
+            if let Err(err) = note::set_note(path.as_str(), &text) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Stats { top } => match stats::get_stats(top) {
+            Err(err) => report(err),
+            Ok(stats) => {
+                println!("Tracked files: {}", stats.files);
+                println!("Distinct tags: {}", stats.distinct_tags);
+                println!("Average tags per file: {:.2}", stats.average_tags);
+
+                if is_plain() {
+                    for (tag, count) in stats.top_tags {
+                        println!("Most used tag: {}; files: {}", tag, count);
+                    }
+                    for ((first, second), count) in stats.top_pairs {
+                        println!("Common tag pair: {} and {}; files: {}", first, second, count);
+                    }
+                    return;
+                }
+
+                println!();
+                println!("Most used tags:");
+                for (tag, count) in stats.top_tags {
+                    println!("  {}{}", paint_count(count), paint_tag(&tag, &tag, &tag_meta));
+                }
+
+                println!();
+                println!("Most common tag pairs:");
+                for ((first, second), count) in stats.top_pairs {
+                    println!("  {}{} + {}", paint_count(count), paint_tag(&first, &first, &tag_meta), paint_tag(&second, &second, &tag_meta));
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Dupes { dir, merge, delete, link, dry_run } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from("."));
+            if !dir.is_dir() {
+                fail(EXIT_NOT_FOUND, format!("Directory {} does not exist!", dir));
+                return;
+            }
+            let groups = match dupes::find_dupes(&dir) {
+                Ok(groups) => groups,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            let resolution = match (merge, delete, link) {
+                (true, _, _) => dupes::Resolution::Merge,
+                (_, true, _) => dupes::Resolution::Delete,
+                (_, _, true) => dupes::Resolution::Link,
+                _ => {
+                    for (number, group) in groups.iter().enumerate() {
+                        if is_plain() {
+                            for (file, file_tags) in &group.files {
+                                let mut file_tags: Vec<&String> = file_tags.iter().collect();
+                                file_tags.sort();
+                                let file_tags: Vec<&str> = file_tags.into_iter().map(String::as_str).collect();
+                                println!("group: {}; file: {}; size: {}; tags: {}", number + 1, paths::to_display(file), group.size, file_tags.join(", "));
+                            }
+                            continue;
+                        }
+
+                        println!("{} copies, size {}:", group.files.len(), ByteSize(group.size));
+                        for (file, file_tags) in &group.files {
+                            let mut file_tags: Vec<&String> = file_tags.iter().collect();
+                            file_tags.sort();
+                            let painted: Vec<String> = file_tags.into_iter().map(|tag| paint_tag(tag, tag, &tag_meta)).collect();
+                            println!("  {}  {}", paths::to_display(file), painted.join("; "));
+                        }
+                    }
+                    if !is_quiet() && !is_plain() {
+                        println!("Found {} files with copies.", groups.len());
+                    }
+                    return;
+                },
+            };
+
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || Ok(groups.iter().map(|group| group.files.len()).sum())),
+            };
+            match confirmed.and_then(|_| dupes::resolve(&groups, resolution, dry_run)) {
+                Err(err) => report(err),
+                Ok(_) if is_quiet() => {},
+                Ok(changed) => {
+                    display_changes(&changed, &tag_meta);
+                    let copies: usize = groups.iter().map(|group| group.files.len() - 1).sum();
+                    match (resolution, dry_run) {
+                        (dupes::Resolution::Merge, true) => println!("Would merge the tags of {} files with copies.", groups.len()),
+                        (dupes::Resolution::Merge, false) => println!("Merged the tags of {} files with copies.", groups.len()),
+                        (dupes::Resolution::Delete, true) => println!("Would delete {} copies.", copies),
+                        (dupes::Resolution::Delete, false) => println!("Deleted {} copies.", copies),
+                        (dupes::Resolution::Link, true) => println!("Would replace {} copies with links.", copies),
+                        (dupes::Resolution::Link, false) => println!("Replaced {} copies with links.", copies),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Doctor => match doctor::diagnose() {
+            Err(err) => report(err),
+            Ok(found) => {
+                for diagnosis in &found {
+                    if is_plain() {
+                        let fix = diagnosis.fix.as_ref().map(|fix| format!("; fix: {}", fix)).unwrap_or_default();
+                        println!("check: {}; status: {}; detail: {}{}", diagnosis.topic, diagnosis.status, diagnosis.detail, fix);
+                        continue;
+                    }
+                    println!("{} {}: {}", diagnosis.status, diagnosis.topic, diagnosis.detail);
+                    if let Some(fix) = &diagnosis.fix {
+                        println!("  fix: {}", fix);
+                    }
+                }
+
+                // Scripts can tell from the status alone whether anything needs fixing
+                if found.iter().any(|diagnosis| diagnosis.status == doctor::Status::Problem) {
+                    let _ = EXIT_STATUS.set(EXIT_FAILURE);
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Check => match check::check() {
+            Err(err) => report(err),
+            Ok(checked) => {
+                for problem in &checked.problems {
+                    let path = problem.path.as_deref().map(paths::to_display);
+                    match (is_plain(), path) {
+                        (true, path) => println!(
+                            "problem: {}; location: {}; path: {}; detail: {}",
+                            problem.kind,
+                            problem.location,
+                            path.unwrap_or_default(),
+                            problem.detail
+                        ),
+                        (false, Some(path)) => println!("{}: {} ({}): {}", problem.kind, path, problem.location, problem.detail),
+                        (false, None) => println!("{}: {}: {}", problem.kind, problem.location, problem.detail),
+                    }
+                }
+
+                // Scripts can tell from the status alone whether anything is wrong
+                if !checked.problems.is_empty() {
+                    let _ = EXIT_STATUS.set(EXIT_FAILURE);
+                }
+                if !is_quiet() && !is_plain() {
+                    println!("Checked {} rows, found {} problems.", checked.rows, checked.problems.len());
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Repair { dry_run } => match check::repair(dry_run) {
+            Err(err) => report(err),
+            Ok(repaired) => {
+                for fix in &repaired.fixes {
+                    let path = fix.path.as_deref().map(paths::to_display);
+                    match (is_plain(), path) {
+                        (true, path) => println!(
+                            "action: {}; location: {}; path: {}; detail: {}",
+                            fix.action,
+                            fix.location,
+                            path.unwrap_or_default(),
+                            fix.detail
+                        ),
+                        (false, Some(path)) => println!("{}: {} ({}): {}", fix.action, path, fix.location, fix.detail),
+                        (false, None) => println!("{}: {}: {}", fix.action, fix.location, fix.detail),
+                    }
+                }
+
+                if is_quiet() || is_plain() {
+                    return;
+                }
+                match (dry_run, &repaired.backup) {
+                    (true, _) => println!("Would make {} fixes.", repaired.fixes.len()),
+                    (false, Some(saved)) => println!("Made {} fixes. The database as it was is saved in {}.", repaired.fixes.len(), shown_path(saved)),
+                    (false, None) => println!("Made {} fixes.", repaired.fixes.len()),
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Backup { dir, keep } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from(backup::DEFAULT_BACKUP_DIR));
+            match keep.map_or_else(|| settings::get_number_setting(settings::BACKUP_KEEP), Ok).and_then(|keep| backup::backup(&dir, keep)) {
+                Err(err) => report(err),
+                Ok(path) if !is_quiet() => println!("Backed up the database to {}.", shown_path(&path)),
+                Ok(_) => {},
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Restore { list: true, dir, .. } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from(backup::DEFAULT_BACKUP_DIR));
+            match backup::list_backups(&dir) {
+                Err(err) => report(err),
+                Ok(backups) => {
+                    for path in backups {
+                        println!("{}", shown_path(&path));
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Restore { backup: source, dir, .. } => {
+            let dir = dir.unwrap_or_else(|| Utf8PathBuf::from(backup::DEFAULT_BACKUP_DIR));
+            let source = match source {
+                Some(source) => Ok(source),
+                None => backup::list_backups(&dir).and_then(|backups| {
+                    backups.into_iter().last().ok_or_else(|| FtagError::ArgumentError(format!("No backups in {}", shown_path(&dir))))
+                }),
+            };
+            let restored = source.and_then(|source| {
+                let keep = settings::get_number_setting(settings::BACKUP_KEEP)?;
+                Ok((backup::restore(&source, &dir, keep)?, source))
+            });
+            match restored {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, "That backup does not exist!"),
+                Err(err) => report(err),
+                Ok((saved, source)) if !is_quiet() => {
+                    println!("Restored {}. The database as it was is saved in {}.", shown_path(&source), shown_path(&saved));
+                },
+                Ok(_) => {},
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Sync { other, dry_run } => {
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || sync::sync(&other, true).map(|synced| synced.pulled.len() + synced.pushed.len())),
+            };
+            match confirmed.and_then(|_| sync::sync(&other, dry_run)) {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Database {} does not exist!", shown_path(&other))),
+                Err(err) => report(err),
+                Ok(synced) => {
+                    for (path, file) in &synced.skipped {
+                        tracing::warn!("Skipped {} because {} doesn't exist", paths::to_display(path), shown_path(file));
+                    }
+                    if is_quiet() {
+                        return;
+                    }
+                    for (heading, changes) in [("Here:", &synced.pulled), (&*format!("In {}:", shown_path(&other)), &synced.pushed)] {
+                        if !changes.is_empty() && !is_plain() {
+                            println!("{}", heading);
+                        }
+                        display_changes(changes, &tag_meta);
+                    }
+                    match dry_run {
+                        true => println!("Would copy {} changes here and {} to the other database.", synced.pulled.len(), synced.pushed.len()),
+                        false => println!("Copied {} changes here and {} to the other database.", synced.pulled.len(), synced.pushed.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Prune { dry_run: true } => match ftag::missing_paths() {
+            Err(err) => report(err),
+            Ok(missing) => {
+                for path in &missing {
+                    println!("{}", paths::to_display(path));
+                }
+                println!("Would remove {} paths.", missing.len());
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Prune { dry_run: false } => {
+            if let Err(err) = confirm_bulk(args.yes, || Ok(ftag::missing_paths()?.len())).and_then(|_| ftag::prune_db()) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Paths { missing, existing } => match ftag::tracked_paths() {
+            Err(err) => report(err),
+            Ok(tracked) => {
+                for path in tracked {
+                    // Only look at the disk when asked to, since that is slow for a large database
+                    if (missing || existing) && camino::Utf8Path::new(&path).exists() != existing {
+                        continue;
+                    }
+                    println!("{}", paths::to_display(&path));
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Config { key, value } => match (key, value) {
+            (Some(key), Some(value)) => {
+                if let Err(err) = settings::set_setting(&key, &value) {
+                    report(err);
+                }
+            },
+            (Some(key), None) => match settings::get_setting(&key) {
+                Err(err) => report(err),
+                Ok(value) => println!("{}", value),
+            },
+            (None, _) => {
+                for (key, _, description) in settings::SETTINGS {
+                    match settings::get_setting(key) {
+                        Err(err) => report(err),
+                        Ok(value) => println!("{} = {}  # {}", key, value, description),
+                    }
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Tag(TagCommands::Describe { name, text }) => {
+            if let Err(err) = meta::set_tag_description(&name, text.as_deref()) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Tag(TagCommands::Color { name, color }) => {
+            if let Err(err) = meta::set_tag_color(&name, color) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Tag(TagCommands::Orphans { clean }) => {
+            let orphans = match meta::orphan_tags() {
+                Ok(orphans) => orphans,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+
+            if clean {
+                let described: Vec<String> = orphans.into_iter().filter(|orphan| orphan.has_meta).map(|orphan| orphan.tag).collect();
+                match meta::remove_tag_meta(&described) {
+                    Err(err) => report(err),
+                    Ok(_) if is_quiet() => {},
+                    Ok(removed) => println!("Removed the descriptions and colors of {} tags.", removed),
+                }
+                return;
+            }
+
+            for orphan in orphans {
+                let used = orphan.last_used.map(journal::format_time);
+                match is_plain() {
+                    true => println!("tag: {}; last used: {}; described or colored: {}", orphan.tag, used.as_deref().unwrap_or("never"), if orphan.has_meta { "yes" } else { "no" }),
+                    false => {
+                        let used = used.map_or("never used".to_string(), |time| format!("last used {}", time));
+                        println!("{}  {}", paint_tag(&orphan.tag, &orphan.tag, &tag_meta), used);
+                    },
+                }
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Bundle(BundleCommands::Create { name, tags }) => {
+            if let Err(err) = bundle::create_bundle(&name, tags) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Bundle(BundleCommands::List) => match bundle::all_bundles() {
+            Err(err) => report(err),
+            Ok(bundles) => {
+                for (name, tags) in bundles {
+                    match is_plain() {
+                        true => println!("bundle: {}; tags: {}", name, tags.join(", ")),
+                        false => {
+                            let painted: Vec<String> = tags.iter().map(|tag| paint_tag(tag, tag, &tag_meta)).collect();
+                            println!("@{}: {}", name, painted.join("; "));
+                        },
+                    }
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Bundle(BundleCommands::Delete { name }) => {
+            if let Err(err) = bundle::delete_bundle(&name) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Query(QueryCommands::Save { name, query }) => {
+            if let Err(err) = query::save_query(&name, &query.join(" ")) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Query(QueryCommands::Run { name, tags, case }) => {
+            let found = query::get_query(&name).and_then(|text| query::find_matching(&query::parse(&text)?, case));
+            match found {
+                Err(err) => report(err),
+                Ok(mut files) => {
+                    ftag::sort_files(&mut files, SortKey::Name, false);
+                    for (file, file_tags) in files {
+                        let file = paths::to_display(&file);
+                        match (tags, is_plain()) {
+                            (false, _) => println!("{}", file),
+                            (true, true) => println!("file: {}; tags: {}", file, file_tags.join(", ")),
+                            (true, false) => {
+                                let painted: Vec<String> = file_tags.iter().map(|tag| paint_tag(tag, tag, &tag_meta)).collect();
+                                println!("{}\n  {}", file, painted.join("; "));
+                            },
+                        }
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Query(QueryCommands::List) => match query::all_queries() {
+            Err(err) => report(err),
+            Ok(queries) => {
+                for (name, text) in queries {
+                    match is_plain() {
+                        true => println!("query: {}; search: {}", name, text),
+                        false => println!("{}: {}", name, text),
+                    }
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Query(QueryCommands::Delete { name }) => {
+            if let Err(err) = query::delete_query(&name) {
+                report(err);
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Index(IndexCommands::Rebuild) => match index::rebuild() {
+            Err(err) => report(err),
+            Ok(_) if is_quiet() => {},
+            Ok(counts) => {
+                for (root, files) in &counts {
+                    match is_plain() {
+                        true => println!("root: {}; files: {}", root, files),
+                        false => println!("{}: {} files", root, files),
+                    }
+                }
+                println!("Indexed {} files from {} roots.", counts.iter().map(|(_, files)| files).sum::<usize>(), counts.len());
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Roots(RootsCommands::List) => match roots::list_roots() {
+            Err(err) => report(err),
+            Ok(roots) => {
+                for root in roots {
+                    match is_plain() {
+                        true => println!("root: {}", root),
+                        false => println!("{}", root),
+                    }
+                }
+            },
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Roots(RootsCommands::Add { dir }) => match roots::add_root(dir.as_deref().unwrap_or(camino::Utf8Path::new("."))) {
+            Err(err) => report(err),
+            Ok(_) if is_quiet() => {},
+            Ok((root, true)) => println!("Added {} to the roots.", root),
+            Ok((root, false)) => println!("{} is already a root.", root),
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Roots(RootsCommands::Rm { dir }) => match roots::remove_root(&dir) {
+            Err(err) => report(err),
+            Ok(_) if is_quiet() => {},
+            Ok(root) => println!("Removed {} from the roots.", root),
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Apply { file, dry_run } => {
+            // Read the whole script before changing anything
+            let script = match file.as_ref().filter(|file| file.as_str() != "-") {
+                Some(file) => std::fs::read_to_string(file),
+                None => std::io::read_to_string(std::io::stdin()),
+            };
+            let script = match script {
+                Ok(script) => script,
+                Err(err) => {
+                    fail(EXIT_FAILURE, format!("Could not read script: {}", err));
+                    return;
+                },
+            };
+
+            match apply::parse_operations(&script).and_then(|mut operations| {
+                // Paths in the script are relative to where ftag was started
+                for operation in &mut operations {
+                    let (apply::Operation::Add { path, .. } | apply::Operation::Rm { path, .. } | apply::Operation::Rename { path, .. }) = operation;
+                    *path = paths::to_stored(path)?;
+                }
+                if !dry_run {
+                    confirm_bulk(args.yes, || Ok(operations.iter().map(apply::Operation::path).collect::<std::collections::HashSet<_>>().len()))?;
+                }
+                let changed = apply::apply_operations(&operations, dry_run)?;
+                if !dry_run {
+                    for path in &changed {
+                        run_change_hook("apply", camino::Utf8Path::new(path));
+                    }
+                }
+                Ok(operations.len())
+            }) {
+                Err(err) => report(err),
+                Ok(_) if is_quiet() => {},
+                Ok(count) if dry_run => println!("Would apply {} operations.", count),
+                Ok(count) => println!("Applied {} operations.", count),
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Rename { map: Some(map), dry_run, .. } => {
+            let mapping = match map.as_str() {
+                "-" => std::io::read_to_string(std::io::stdin()),
+                _ => std::fs::read_to_string(&map),
+            };
+            let mapping = match mapping {
+                Ok(mapping) => mapping,
+                Err(err) => {
+                    fail(EXIT_FAILURE, format!("Could not read mapping: {}", err));
+                    return;
+                },
+            };
+
+            let renamed = apply::parse_mapping(&mapping).and_then(|mapping| {
+                if !dry_run {
+                    confirm_bulk(args.yes, || Ok(apply::apply_mapping(&mapping, true)?.files.len()))?;
+                }
+                apply::apply_mapping(&mapping, dry_run)
+            });
+            match renamed {
+                Err(err) => report(err),
+                Ok(report) => {
+                    for (path, new, merged) in &report.merged {
+                        tracing::warn!("{}: {} were merged into {}", paths::to_display(path), merged.join(", "), new);
+                    }
+                    for old in &report.unused {
+                        tracing::warn!("Tag {} not found.", old);
+                    }
+                    if dry_run {
+                        println!("Would rename tags in {} files.", report.files.len());
+                        return;
+                    }
+                    for path in &report.files {
+                        run_change_hook("rename", camino::Utf8Path::new(path));
+                    }
+                    if !is_quiet() {
+                        println!("Renamed tags in {} files.", report.files.len());
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::MergeTags { words, dry_run } => {
+            let merged = apply::parse_merge(&words).and_then(|mapping| {
+                if !dry_run {
+                    confirm_bulk(args.yes, || Ok(apply::apply_mapping(&mapping, true)?.files.len()))?;
+                }
+                apply::apply_mapping(&mapping, dry_run)
+            });
+            match merged {
+                Err(err) => report(err),
+                Ok(report) => {
+                    for old in &report.unused {
+                        tracing::warn!("Tag {} not found.", old);
+                    }
+                    if dry_run {
+                        println!("Would merge tags in {} files.", report.files.len());
+                        return;
+                    }
+                    for path in &report.files {
+                        run_change_hook("merge-tags", camino::Utf8Path::new(path));
+                    }
+                    if !is_quiet() {
+                        println!("Merged tags in {} files.", report.files.len());
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::TagMatching { find, add, any, case, dry_run, exclude } => {
+            let queries = ftag::parse_queries(&find, case, false).and_then(|find| Ok((find, ftag::parse_queries(&exclude, case, false)?)));
+            let (find, exclude) = match queries {
+                Ok(queries) => queries,
+                Err(err) => {
+                    report(err);
+                    return;
+                },
+            };
+            let confirmed = match dry_run {
+                true => Ok(()),
+                false => confirm_bulk(args.yes, || Ok(ftag::add_tags_to_matching(&find, &exclude, any, add.clone(), true)?.len())),
+            };
+            match confirmed.and_then(|_| ftag::add_tags_to_matching(&find, &exclude, any, add, dry_run)) {
+                Err(err) => report(err),
+                Ok(added) => {
+                    if !dry_run {
+                        for change in &added {
+                            run_change_hook("tag-matching", camino::Utf8Path::new(&change.path));
+                        }
+                    }
+                    if is_quiet() {
+                        return;
+                    }
+                    display_changes(&added, &tag_meta);
+                    match dry_run {
+                        true => println!("Would tag {} files.", added.len()),
+                        false => println!("Tagged {} files.", added.len()),
+                    }
+                },
+            }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Rename { path, old_tag, new_tag, map: None, dry_run } => {
+            // Without a mapping file clap requires all three
+            let (Some(path), Some(old_tag), Some(new_tag)) = (path, old_tag, new_tag) else {
+                return;
+            };
+
+            // Determine whether the path contains old_tag
+            let current_tags = get_file_tags(&path);
+            match current_tags {
+                Err(err) if err.is_not_found() => fail(EXIT_NOT_FOUND, format!("Filepath {} does not exist!", path)),
+                Err(err) => report(err),
+                Ok(tags) => {
+                    // Compare against the old tag in the same form it would have been stored in
+                    let old_tag = match ftag::normalize_tags(vec![old_tag]) {
+                        Ok(mut normalized) => normalized.remove(0),
+                        Err(err) => {
+                            report(err);
+                            return;
+                        },
+                    };
+
+                    if !tags.contains(&old_tag) {
+                        fail(EXIT_FAILURE, format!("Tag {} not found.", old_tag));
+                        return;
+                    }
+
+                    if dry_run {
+                        let change = ftag::preview_change(&path, |tx| {
+                            ftag::remove_tags_in(tx, &path, vec![old_tag])?;
+                            ftag::add_tags_in(tx, &path, vec![new_tag])
+                        });
+                        match change {
+                            Err(err) => report(err),
+                            Ok(change) => display_changes(&[change], &tag_meta),
+                        }
+                        return;
+                    }
+
+                    // Remove the old tag and swap in the new one, only running the hook once both are saved
+                    let renamed = ftag::remove_tags(&path, vec![old_tag]).and_then(|_| ftag::add_tags(&path, vec![new_tag]));
+                    match renamed {
+                        Err(err) => report(err),
+                        Ok(_) => run_change_hook("rename", &path),
+                    }
+
+                    // Print out the properly updated tags
+                    match ftag::get_file_tags(&path) {
+                        Err(err) => report(err),
+                        Ok(_) if is_quiet() => {},
+                        Ok(tags) => display_tags(tags, false, false, false, &tag_meta),
+                    }
+                }
+            }
+
+        }
+
+        #[cfg(feature = "full")]
+        Commands::Completions { shell } => print!("{}", completions::script(shell, &Cli::command())),
+
+        #[cfg(feature = "full")]
+        Commands::ShellInit { shell } => print!("{}", completions::init_script(shell)),
+
+        #[cfg(feature = "full")]
+        Commands::Complete { path, prefix } => {
+            // Completion should stay quiet, so anything that goes wrong just completes nothing
+            let tags: Vec<String> = match path {
+                Some(path) => ftag::get_file_tags(&path).map(|tags| tags.into_iter().collect()),
+                None => ftag::get_global_tags().map(|tag_counts| tag_counts.into_keys().collect()),
+            }
+            .unwrap_or_default();
+            let mut tags: Vec<String> = tags.into_iter().filter(|tag| tag.starts_with(&prefix)).collect();
+            tags.sort();
+            for tag in tags {
+                println!("{}", tag);
+            }
+        },
     }
 }
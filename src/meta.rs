@@ -0,0 +1,213 @@
+#[cfg(feature = "full")]
+use camino::Utf8Path;
+#[cfg(feature = "full")]
+use rusqlite::params;
+use std::collections::HashMap;
+#[cfg(feature = "full")]
+use std::collections::{BTreeSet, HashSet};
+
+use crate::ftag::FtagError;
+#[cfg(feature = "full")]
+use crate::{ftag, journal};
+use crate::{schema, store};
+
+/// Colors a tag can be displayed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TagColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl TagColor {
+    /// Every color, in the order they are listed.
+    const ALL: [TagColor; 8] = [
+        TagColor::Black,
+        TagColor::Red,
+        TagColor::Green,
+        TagColor::Yellow,
+        TagColor::Blue,
+        TagColor::Magenta,
+        TagColor::Cyan,
+        TagColor::White,
+    ];
+
+    /// Name used to store the color in the database, the same as it is typed on the command line.
+    fn name(self) -> &'static str {
+        match self {
+            TagColor::Black => "black",
+            TagColor::Red => "red",
+            TagColor::Green => "green",
+            TagColor::Yellow => "yellow",
+            TagColor::Blue => "blue",
+            TagColor::Magenta => "magenta",
+            TagColor::Cyan => "cyan",
+            TagColor::White => "white",
+        }
+    }
+
+    /// Parse a color stored with `name`, ignoring anything unrecognized.
+    fn from_name(name: &str) -> Option<Self> {
+        TagColor::ALL.into_iter().find(|color| color.name().eq_ignore_ascii_case(name))
+    }
+
+    /// ANSI escape code that switches the terminal foreground to this color.
+    pub fn ansi_code(self) -> &'static str {
+        match self {
+            TagColor::Black => "\x1b[30m",
+            TagColor::Red => "\x1b[31m",
+            TagColor::Green => "\x1b[32m",
+            TagColor::Yellow => "\x1b[33m",
+            TagColor::Blue => "\x1b[34m",
+            TagColor::Magenta => "\x1b[35m",
+            TagColor::Cyan => "\x1b[36m",
+            TagColor::White => "\x1b[37m",
+        }
+    }
+}
+
+/// Extra information attached to a tag name, independent of the files it is on.
+#[derive(Debug, Default, Clone)]
+pub struct TagMeta {
+    pub description: Option<String>,
+    pub color: Option<TagColor>,
+}
+
+/// Set one column of a tag's metadata, creating its row if needed and dropping it once it is empty.
+///
+/// * `tag` - Tag to update
+/// * `column` - Column of `tag_meta` to set
+/// * `value` - New value, or `None` to clear it
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+#[cfg(feature = "full")]
+fn set_meta_column(tag: &str, column: &str, value: Option<&str>) -> Result<(), FtagError> {
+    let conn = store::open_sqlite("Describing and coloring tags")?;
+
+    // Make sure the row exists, then update just the one column
+    conn.execute("INSERT OR IGNORE INTO tag_meta(tag) VALUES (?)", params![tag])?;
+    conn.execute(&format!("UPDATE tag_meta SET {} = ? WHERE tag = ?", column), params![value, tag])?;
+
+    // Don't leave rows around that no longer say anything
+    conn.execute("DELETE FROM tag_meta WHERE description IS NULL AND color IS NULL", ())?;
+
+    Ok(())
+}
+
+/// Attach a description to a tag, or remove it.
+///
+/// * `tag` - Tag to describe
+/// * `description` - Text to attach, or `None` to remove the current description
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+#[cfg(feature = "full")]
+pub fn set_tag_description(tag: &str, description: Option<&str>) -> Result<(), FtagError> {
+    set_meta_column(tag, "description", description)
+}
+
+/// Set the color a tag is displayed in, or remove it.
+///
+/// * `tag` - Tag to color
+/// * `color` - Color to use, or `None` to go back to the default
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+#[cfg(feature = "full")]
+pub fn set_tag_color(tag: &str, color: Option<TagColor>) -> Result<(), FtagError> {
+    set_meta_column(tag, "color", color.map(TagColor::name))
+}
+
+/// Return the metadata of every tag that has any.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn get_all_tag_meta() -> Result<HashMap<String, TagMeta>, FtagError> {
+    let conn = store::open_sqlite("Describing and coloring tags")?;
+
+    // Reading is no reason to change the database, so a table that was never made just means no metadata
+    if !schema::has_table(&conn, "tag_meta")? {
+        return Ok(HashMap::new());
+    }
+    let mut stmt = conn.prepare("SELECT tag, description, color FROM tag_meta")?;
+    let rows = stmt.query_map((), |row| {
+        let tag: String = row.get(0)?;
+        let color: Option<String> = row.get(2)?;
+        Ok((tag, TagMeta { description: row.get(1)?, color: color.as_deref().and_then(TagColor::from_name) }))
+    })?;
+
+    let mut all_meta = HashMap::new();
+    for row in rows {
+        let (tag, meta) = row?;
+        all_meta.insert(tag, meta);
+    }
+
+    Ok(all_meta)
+}
+
+/// A tag that no existing file has, though the database still knows it.
+#[cfg(feature = "full")]
+#[derive(Debug)]
+pub struct OrphanTag {
+    pub tag: String,
+    /// Whether the tag still has a description or color
+    pub has_meta: bool,
+    /// When a path last had the tag, in seconds since the Unix epoch, or `None` if no path ever did
+    pub last_used: Option<i64>,
+}
+
+/// Return the tags that have metadata or were used before, but aren't on any file that still exists, sorted by name.
+///
+/// Tags only on deleted files count as orphans, since pruning the database would drop them.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or errors occur when reading it.
+#[cfg(feature = "full")]
+pub fn orphan_tags() -> Result<Vec<OrphanTag>, FtagError> {
+    let all_meta = get_all_tag_meta()?;
+    let last_used = journal::last_used_tags()?;
+
+    let mut attached = HashSet::new();
+    for (path, tags) in ftag::get_all_rows()? {
+        if Utf8Path::new(&path).exists() {
+            attached.extend(tags);
+        }
+    }
+
+    let known: BTreeSet<&String> = all_meta.keys().chain(last_used.keys()).filter(|tag| !attached.contains(*tag)).collect();
+    Ok(known
+        .into_iter()
+        .map(|tag| OrphanTag { tag: tag.clone(), has_meta: all_meta.contains_key(tag), last_used: last_used.get(tag).copied() })
+        .collect())
+}
+
+/// Remove the descriptions and colors of tags, returning how many tags had any.
+///
+/// * `tags` - Tags whose metadata should go
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+#[cfg(feature = "full")]
+pub fn remove_tag_meta(tags: &[String]) -> Result<usize, FtagError> {
+    let conn = store::open_sqlite("Describing and coloring tags")?;
+    let tx = conn.unchecked_transaction()?;
+    let mut removed = 0;
+    for tag in tags {
+        removed += tx.execute("DELETE FROM tag_meta WHERE tag = ?", params![tag])?;
+    }
+    tx.commit()?;
+    Ok(removed)
+}
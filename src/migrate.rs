@@ -0,0 +1,228 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use std::collections::HashSet;
+use std::{fs, io};
+
+use crate::ftag::{self, FtagError, TAG_SEPARATOR};
+use crate::{autotag, paths, settings, store, validate};
+
+/// What will happen to a single file when migrating a directory tree to tags.
+#[derive(Debug)]
+pub struct MigrationStep {
+    /// Where the file is now
+    pub from: Utf8PathBuf,
+    /// Where the file will be afterwards, which is `from` unless flattening
+    pub to: Utf8PathBuf,
+    /// Tag derived from the directories the file was in
+    pub tag: String,
+}
+
+/// Name of the file listing paths for ftag to skip when looking through directories, next to the database.
+pub const IGNORE_FILE: &str = ".ftagignore";
+
+/// Read the patterns in the ignore file at the database root, written like a `.gitignore`.
+///
+/// There is nothing to ignore when the file doesn't exist.
+///
+/// # Failure
+///
+/// Returns `Err` naming the first line with an invalid pattern, or if the file exists but can't be read.
+pub fn read_ignore() -> Result<Gitignore, FtagError> {
+    let text = match fs::read_to_string(IGNORE_FILE) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Gitignore::empty()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut builder = GitignoreBuilder::new(".");
+    for (number, line) in text.lines().enumerate() {
+        builder
+            .add_line(None, line)
+            .map_err(|err| FtagError::ArgumentError(format!("{} line {}: {}", IGNORE_FILE, number + 1, err)))?;
+    }
+    builder.build().map_err(|err| FtagError::ArgumentError(format!("{}: {}", IGNORE_FILE, err)))
+}
+
+/// Collect every file beneath `dir`, in a stable order.
+///
+/// Files and directories matching the ignore file at the database root are skipped, along with everything beneath
+/// them, as are those a `.gitignore` ignores if the `respect-gitignore` setting is on and `dir` is in a git
+/// repository. `.git` directories and ftag's own rules and ignore files are always skipped. ftag can only store
+/// UTF-8 paths, so names that aren't UTF-8 are skipped with a warning naming them.
+///
+/// # Failure
+///
+/// Returns `Err` if the ignore file is invalid, the settings could not be read, or a directory can't be read.
+pub(crate) fn walk_files(dir: &Utf8Path, files: &mut Vec<Utf8PathBuf>) -> Result<(), FtagError> {
+    let ignore = read_ignore()?;
+    let gitignore = match settings::get_bool_setting(settings::RESPECT_GITIGNORE) {
+        Ok(respect) => respect,
+        Err(FtagError::NoDatabaseError) => false,
+        Err(err) => return Err(err),
+    };
+
+    // Don't follow symlinked directories, they could loop forever
+    let walker = WalkBuilder::new(dir)
+        .standard_filters(false)
+        .git_ignore(gitignore)
+        .git_exclude(gitignore)
+        .git_global(gitignore)
+        .follow_links(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            let Some(path) = Utf8Path::from_path(entry.path()) else {
+                tracing::warn!("Skipped {} because its name isn't valid UTF-8", entry.path().display());
+                return false;
+            };
+
+            // Git's own files are never worth tagging, whatever the settings say
+            if entry.depth() > 0 && path.file_name() == Some(".git") {
+                return false;
+            }
+
+            // The ignore file only speaks for paths beneath the database root
+            let relative = Utf8PathBuf::from(paths::normalize(path.as_str()));
+            let inside = entry.depth() > 0 && !relative.starts_with("..") && !relative.is_absolute();
+            if inside && [IGNORE_FILE, autotag::RULES_FILE].contains(&relative.as_str()) {
+                return false;
+            }
+            let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+            if inside && ignore.matched_path_or_any_parents(&relative, is_dir).is_ignore() {
+                tracing::debug!("Skipped {} because {} ignores it", relative, IGNORE_FILE);
+                return false;
+            }
+            true
+        })
+        .build();
+
+    for entry in walker {
+        let entry = entry.map_err(|err| match err.into_io_error() {
+            Some(err) => FtagError::IoError(err),
+            None => FtagError::IoError(io::Error::other(format!("Could not look through {}", dir))),
+        })?;
+        if entry.depth() == 0 {
+            continue;
+        }
+
+        // filter_entry already skipped names that aren't UTF-8
+        let Some(path) = Utf8Path::from_path(entry.path()) else {
+            continue;
+        };
+        let is_file = match entry.path_is_symlink() {
+            true => path.is_file(),
+            false => entry.file_type().is_some_and(|kind| !kind.is_dir()),
+        };
+        if is_file {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Work out how each file beneath `dir` would be tagged, and moved if flattening.
+///
+/// A file in `dir/Rock/Beatles/Abbey Road/` with `levels` of 2 gets the tag `Rock/Beatles`.
+/// Files directly inside `dir` have no directories to derive a tag from, so they are left out.
+///
+/// * `dir` - Root of the directory tree to migrate
+/// * `levels` - How many directories beneath `dir` make up the tag
+/// * `prefix` - Namespace to put every derived tag under, if any
+/// * `flatten` - Move every file directly into `dir`
+///
+/// # Failure
+///
+/// Returns `Err` if `dir` is not a directory or it can't be read, `levels` is 0, or a derived tag breaks the
+/// naming rules and validation is set to reject it.
+pub fn plan_migration(dir: &Utf8Path, levels: usize, prefix: Option<&str>, flatten: bool) -> Result<Vec<MigrationStep>, FtagError> {
+    if !dir.is_dir() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    if levels == 0 {
+        return Err(FtagError::ArgumentError("At least one directory level is needed to make a tag".to_string()));
+    }
+
+    let mut files = vec![];
+    walk_files(dir, &mut files)?;
+
+    let mut steps = vec![];
+    for from in files {
+        // Take the directory names between dir and the file itself
+        let relative = from.strip_prefix(dir).expect("walked files are inside dir");
+        let directories: Vec<&str> = match relative.parent() {
+            Some(parent) => parent.components().map(|component| component.as_str()).collect(),
+            None => vec![],
+        };
+        if directories.is_empty() {
+            continue;
+        }
+
+        let mut parts: Vec<&str> = prefix.into_iter().collect();
+        parts.extend(directories.iter().take(levels));
+        let tag = parts.join(&TAG_SEPARATOR.to_string());
+
+        let to = match flatten {
+            true => dir.join(from.file_name().expect("walked files have names")),
+            false => from.clone(),
+        };
+
+        steps.push(MigrationStep { from, to, tag });
+    }
+
+    check_steps(&steps)?;
+    Ok(steps)
+}
+
+/// Check the tag of every step against the naming rules, so a bad one is found before anything is changed.
+///
+/// # Failure
+///
+/// Returns `Err` if a tag breaks the naming rules and validation is set to reject it, or the settings could not be read.
+fn check_steps(steps: &[MigrationStep]) -> Result<(), FtagError> {
+    let mode = settings::get_tag_validation_in(&*store::open_store()?)?;
+    for step in steps {
+        validate::check_tag(&step.tag, mode).map_err(|err| FtagError::InvalidTagError(format!("{} for {}", err, step.from)))?;
+    }
+    Ok(())
+}
+
+/// Carry out a migration planned by `plan_migration`, returning the files that could not be moved and why.
+///
+/// When flattening, a file is tagged where it is rather than overwriting another file with the same name.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, a tag breaks the naming rules, or errors occur when interacting with the
+/// database. Every tag is checked first, so a bad one leaves every file as it was.
+pub fn apply_migration(steps: Vec<MigrationStep>) -> Result<Vec<(MigrationStep, String)>, FtagError> {
+    check_steps(&steps)?;
+
+    let mut not_moved = vec![];
+    let mut claimed: HashSet<Utf8PathBuf> = HashSet::new();
+
+    for step in steps {
+        // Bring along any tags the file already had, since its old path will be pruned
+        let mut tags: Vec<String> = ftag::get_file_tags(&step.from)?.into_iter().collect();
+        tags.push(step.tag.clone());
+
+        if step.to != step.from {
+            // Never clobber an existing file, or another file flattened to the same name
+            let problem = if step.to.exists() || !claimed.insert(step.to.clone()) {
+                Some("a file with that name is already there".to_string())
+            } else {
+                std::fs::rename(&step.from, &step.to).err().map(|err| err.to_string())
+            };
+
+            if let Some(problem) = problem {
+                ftag::add_tags(&step.from, tags)?;
+                not_moved.push((step, problem));
+                continue;
+            }
+        }
+
+        ftag::add_tags(&step.to, tags)?;
+    }
+
+    Ok(not_moved)
+}
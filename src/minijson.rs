@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Serialize tags the same way serde_json would serialize the tag list.
+pub fn serialize_tags(tags: &HashSet<String>) -> String {
+    let mut json = String::from("{\"Tags\":[");
+    for (i, tag) in tags.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        push_string(&mut json, tag);
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Parse a tag list like `{"Tags":["a","b"]}`.
+///
+/// # Failure
+///
+/// Returns `Err` describing the problem if `json` is not a tag list.
+pub fn parse_tags(json: &str) -> Result<HashSet<String>, String> {
+    let mut chars = json.chars().peekable();
+    let mut tags = HashSet::new();
+
+    expect(&mut chars, '{')?;
+    if parse_string(&mut chars)? != "Tags" {
+        return Err("expected the key Tags".to_string());
+    }
+    expect(&mut chars, ':')?;
+    expect(&mut chars, '[')?;
+
+    // An empty list closes straight away, otherwise read strings separated by commas
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&']') {
+        chars.next();
+    } else {
+        loop {
+            tags.insert(parse_string(&mut chars)?);
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some(']') => break,
+                _ => return Err("expected , or ] in the tag list".to_string()),
+            }
+        }
+    }
+
+    expect(&mut chars, '}')?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(tags),
+        Some(c) => Err(format!("unexpected {} after the tag list", c)),
+    }
+}
+
+/// Append `text` as a quoted JSON string.
+fn push_string(json: &mut String, text: &str) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Advance past any whitespace.
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+        chars.next();
+    }
+}
+
+/// Skip whitespace, then consume `expected`.
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        _ => Err(format!("expected {}", expected)),
+    }
+}
+
+/// Read four hex digits of a `\u` escape.
+fn parse_hex(chars: &mut Peekable<Chars>) -> Result<u32, String> {
+    let digits: String = chars.take(4).collect();
+    u32::from_str_radix(&digits, 16).map_err(|_| format!("invalid escape \\u{}", digits))
+}
+
+/// Skip whitespace, then read a quoted string, resolving escapes.
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".to_string()),
+            Some('"') => return Ok(text),
+            Some('\\') => match chars.next() {
+                Some('"') => text.push('"'),
+                Some('\\') => text.push('\\'),
+                Some('/') => text.push('/'),
+                Some('b') => text.push('\u{8}'),
+                Some('f') => text.push('\u{c}'),
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some('u') => {
+                    let mut code = parse_hex(chars)?;
+
+                    // Characters outside the basic plane are written as a pair of surrogates
+                    if (0xD800..0xDC00).contains(&code) {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err("unpaired surrogate".to_string());
+                        }
+                        let low = parse_hex(chars)?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                    }
+
+                    text.push(char::from_u32(code).ok_or_else(|| format!("invalid character \\u{:04x}", code))?);
+                },
+                _ => return Err("invalid escape".to_string()),
+            },
+            Some(c) => text.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn never_panics(json in any::<String>()) {
+            let _ = parse_tags(&json);
+        }
+
+        #[test]
+        fn round_trips(tags in prop::collection::hash_set(any::<String>(), 0..8)) {
+            prop_assert_eq!(parse_tags(&serialize_tags(&tags)).unwrap(), tags);
+        }
+
+        // Both builds read and write the same files, so compare against serde_json whenever it's around
+        #[test]
+        #[cfg(feature = "full")]
+        fn reads_serde_json(tags in prop::collection::hash_set(any::<String>(), 0..8)) {
+            let json = serde_json::json!({ "Tags": tags }).to_string();
+            prop_assert_eq!(parse_tags(&json).unwrap(), tags);
+        }
+
+        #[test]
+        #[cfg(feature = "full")]
+        fn serde_json_reads_it(tags in prop::collection::hash_set(any::<String>(), 0..8)) {
+            let value: serde_json::Value = serde_json::from_str(&serialize_tags(&tags)).unwrap();
+            let read: HashSet<String> = serde_json::from_value(value["Tags"].clone()).unwrap();
+            prop_assert_eq!(read, tags);
+        }
+    }
+
+    #[test]
+    fn reads_escapes() {
+        let tags = parse_tags(r#" { "Tags" : [ "a\"b", "\u00e9", "\ud83d\ude00" ] } "#).unwrap();
+        assert_eq!(tags, HashSet::from(["a\"b".to_string(), "é".to_string(), "😀".to_string()]));
+    }
+}
@@ -0,0 +1,79 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+
+use crate::ftag::{self, FtagError, TagChange};
+use crate::{migrate, paths};
+
+/// Return what a file is matched by in the other tree, its path beneath the tree's directory, without its
+/// extension if extensions don't have to match.
+fn match_key(relative: &Utf8Path, ignore_extension: bool) -> String {
+    match ignore_extension {
+        true => relative.with_extension("").into_string(),
+        false => relative.to_string(),
+    }
+}
+
+/// Copy the tags of each file beneath `src` to the file with the same path beneath `dst`, all in one transaction.
+///
+/// Tags are only added, so files beneath `dst` keep any tags they already had. Returns how the tags of each file
+/// changed, in path order. Tagged files with no counterpart beneath `dst` are left alone.
+///
+/// * `src` - Directory whose files' tags are copied, relative to the database root
+/// * `dst` - Directory whose files are tagged, relative to the database root
+/// * `ignore_extension` - Match `raw/a.cr2` with `jpg/a.jpg`, so every file with the same name gets the tags
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if either directory does not exist, they are the same directory, or errors occur when interacting
+/// with the database. Nothing is changed in that case.
+pub fn mirror(src: &Utf8Path, dst: &Utf8Path, ignore_extension: bool, dry_run: bool) -> Result<Vec<TagChange>, FtagError> {
+    if !src.is_dir() || !dst.is_dir() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    let (src, dst) = (paths::normalize(src.as_str()), paths::normalize(dst.as_str()));
+    if src == dst {
+        return Err(FtagError::ArgumentError(format!("Can't mirror {} into itself", paths::to_display(&src))));
+    }
+
+    // Everything is beneath the database root
+    let beneath = |dir: &str, path: &str| -> Option<Utf8PathBuf> {
+        match dir {
+            "." => Some(Utf8PathBuf::from(path)),
+            _ => Utf8Path::new(path).strip_prefix(dir).ok().map(Utf8Path::to_path_buf),
+        }
+    };
+
+    // Files beneath the destination, by what a source file must match to be copied to them
+    let mut files = vec![];
+    migrate::walk_files(Utf8Path::new(&dst), &mut files)?;
+    let mut targets: HashMap<String, Vec<Utf8PathBuf>> = HashMap::new();
+    for file in files {
+        let file = Utf8PathBuf::from(paths::normalize(file.as_str()));
+        if ftag::is_database_path(&file) {
+            continue;
+        }
+        if let Some(relative) = beneath(&dst, file.as_str()) {
+            targets.entry(match_key(&relative, ignore_extension)).or_default().push(file);
+        }
+    }
+
+    // Several sources can share a destination when extensions are ignored, which then gets all of their tags
+    let mut tagged: BTreeMap<Utf8PathBuf, BTreeSet<String>> = BTreeMap::new();
+    for (path, tags) in ftag::get_all_rows()? {
+        let Some(relative) = beneath(&src, &path).filter(|_| !tags.is_empty()) else {
+            continue;
+        };
+        match targets.get(&match_key(&relative, ignore_extension)) {
+            Some(files) => {
+                for file in files {
+                    tagged.entry(file.clone()).or_default().extend(tags.iter().cloned());
+                }
+            },
+            None => tracing::debug!("{} has no counterpart beneath {}", path, dst),
+        }
+    }
+
+    ftag::add_tags_to_all(tagged.into_iter().map(|(file, tags)| (file, tags.into_iter().collect())).collect(), dry_run)
+}
@@ -0,0 +1,93 @@
+//! Async versions of the main library calls, for programs that can't block the thread they run on.
+//!
+//! Each call runs its blocking counterpart in [`ftag`](crate::ftag) on tokio's blocking threads, so it must be
+//! awaited inside a tokio runtime. Like the rest of the library, they use the database of the current directory.
+//! The connection a call opens is closed again before it returns, so idle blocking threads don't hold the database
+//! open.
+
+use camino::Utf8PathBuf;
+use std::collections::HashSet;
+use std::io;
+use std::ops::ControlFlow;
+use tokio::sync::mpsc;
+use tokio::task;
+
+use crate::ftag::{self, FtagError, TagChange, TagQuery};
+use crate::store;
+
+/// Files `visit_tags` reads ahead of the receiver before waiting for it to catch up.
+const VISIT_BUFFER: usize = 256;
+
+/// Run `work` on a blocking thread and wait for its result, passing on any panic.
+async fn unblock<T: Send + 'static>(work: impl FnOnce() -> Result<T, FtagError> + Send + 'static) -> Result<T, FtagError> {
+    let blocking = task::spawn_blocking(move || {
+        let result = work();
+        // Connections are kept per thread, and tokio may keep this one around for a while doing nothing
+        store::close_connections();
+        result
+    });
+    match blocking.await {
+        Ok(result) => result,
+        Err(err) => match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            // Only happens when the runtime shuts down first
+            Err(err) => Err(FtagError::IoError(io::Error::other(err))),
+        },
+    }
+}
+
+/// Check the database for files matching a search, like `ftag::find_tags`.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, errors occur when deserializing data, or errors occur when querying the database.
+pub async fn find_tags(find_tags: Vec<TagQuery>, exclude_tags: Vec<TagQuery>, any: bool) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    unblock(move || ftag::find_tags(&find_tags, &exclude_tags, any)).await
+}
+
+/// Receive each file matching a search as soon as it is read, like `ftag::visit_tags`.
+///
+/// The search starts right away and stops early once the receiver is dropped. A failed search ends with an `Err`.
+pub fn visit_tags(find_tags: Vec<TagQuery>, exclude_tags: Vec<TagQuery>, any: bool) -> mpsc::Receiver<Result<(String, Vec<String>), FtagError>> {
+    let (sender, receiver) = mpsc::channel(VISIT_BUFFER);
+    task::spawn_blocking(move || {
+        let visited = ftag::visit_tags(&find_tags, &exclude_tags, any, |path, tags| {
+            Ok(match sender.blocking_send(Ok((path, tags))) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            })
+        });
+        store::close_connections();
+        if let Err(err) = visited {
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+    receiver
+}
+
+/// Add tags to a file, returning the tags it has now, like `ftag::add_tags`.
+///
+/// # Failure
+///
+/// Returns `Err` if `path` does not exist, there is no database, or errors occur when interacting with the database.
+pub async fn add_tags(path: Utf8PathBuf, tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
+    unblock(move || ftag::add_tags(&path, tags)).await
+}
+
+/// Remove tags from a file, returning the tags it has now, like `ftag::remove_tags`.
+///
+/// # Failure
+///
+/// Returns `Err` if `path` does not exist, there is no database, or errors occur when interacting with the database.
+pub async fn remove_tags(path: Utf8PathBuf, tags: Vec<String>) -> Result<HashSet<String>, FtagError> {
+    unblock(move || ftag::remove_tags(&path, tags)).await
+}
+
+/// Replace the tags of a file with exactly `tags`, returning how they changed, like `ftag::set_tags`.
+///
+/// # Failure
+///
+/// Returns `Err` if `path` does not exist, there is no database, or errors occur when interacting with the database.
+pub async fn set_tags(path: Utf8PathBuf, tags: Vec<String>) -> Result<TagChange, FtagError> {
+    unblock(move || ftag::set_tags(&path, tags)).await
+}
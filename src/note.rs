@@ -0,0 +1,61 @@
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+
+use crate::ftag::FtagError;
+use crate::store;
+
+/// Open the database, creating the `notes` table if this database predates it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or the table could not be created.
+fn open_note_db() -> Result<store::Handle, FtagError> {
+    let conn = store::open_sqlite("Notes")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notes (
+            path    TEXT PRIMARY KEY,
+            note    TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// Attach a note to a path, replacing any note it had. An empty note removes it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn set_note(path: &str, note: &str) -> Result<(), FtagError> {
+    let conn = open_note_db()?;
+    match note.trim() {
+        "" => conn.execute("DELETE FROM notes WHERE path = ?", params![path])?,
+        note => conn.execute("INSERT OR REPLACE INTO notes(path, note) VALUES (?, ?)", params![path, note])?,
+    };
+    Ok(())
+}
+
+/// Return the note attached to a path, if it has one.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn get_note(path: &str) -> Result<Option<String>, FtagError> {
+    let conn = open_note_db()?;
+    Ok(conn.query_row("SELECT note FROM notes WHERE path = ?", params![path], |row| row.get(0)).optional()?)
+}
+
+/// Keep only the files whose note contains some text, ignoring case.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn filter_noted(files: Vec<(String, Vec<String>)>, text: &str) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let conn = open_note_db()?;
+    let mut stmt = conn.prepare("SELECT path, note FROM notes")?;
+    let notes: HashMap<String, String> = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+
+    let text = text.to_lowercase();
+    Ok(files.into_iter().filter(|(path, _)| notes.get(path).is_some_and(|note| note.to_lowercase().contains(&text))).collect())
+}
@@ -0,0 +1,63 @@
+use std::process::Command;
+
+use crate::ftag::FtagError;
+use crate::sandbox;
+
+/// Program that opens a file with its default application on this platform.
+#[cfg(target_os = "macos")]
+const DEFAULT_OPENER: &[&str] = &["open"];
+#[cfg(windows)]
+const DEFAULT_OPENER: &[&str] = &["cmd", "/C", "start", ""];
+#[cfg(not(any(target_os = "macos", windows)))]
+const DEFAULT_OPENER: &[&str] = &["xdg-open"];
+
+/// Run a program and wait for it to finish.
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, or the program could not be run or failed.
+fn run(program: &str, args: &[&str]) -> Result<(), FtagError> {
+    sandbox::check_exec(program)?;
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|err| FtagError::ConfigError(format!("Could not run {}: {}", program, err)))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(FtagError::ConfigError(format!("{} exited unsuccessfully: {}", program, status))),
+    }
+}
+
+/// Open files with their default applications, or all at once with another program.
+///
+/// The default opener only takes one file, so it is run once for each. A program given with `with`
+/// is run once with every file, so `vim` or `mpv` get them all together.
+///
+/// * `files` - Files to open
+/// * `with` - Program to open them with, along with any arguments, like `mpv --shuffle`
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, `with` could not be parsed, or the opener could not be run or failed.
+pub fn open_files(files: &[String], with: Option<&str>) -> Result<(), FtagError> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let Some(with) = with else {
+        let (program, args) = DEFAULT_OPENER.split_first().expect("the default opener names a program");
+        for file in files {
+            let mut args = args.to_vec();
+            args.push(file);
+            run(program, &args)?;
+        }
+        return Ok(());
+    };
+
+    let words = shell_words::split(with).map_err(|err| FtagError::ArgumentError(format!("Could not parse {}: {}", with, err)))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(FtagError::ArgumentError("No program given to open files with".to_string()));
+    };
+    let args: Vec<&str> = args.iter().chain(files).map(String::as_str).collect();
+    run(program, &args)
+}
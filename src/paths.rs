@@ -0,0 +1,147 @@
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use std::env;
+use std::sync::OnceLock;
+
+use crate::ftag::{get_db_path, get_store_dir_path, get_text_path, FtagError};
+
+/// Where ftag was started, relative to the directory holding the database. Empty when started in that directory.
+static PREFIX: OnceLock<Utf8PathBuf> = OnceLock::new();
+
+/// The directory holding the database, as an absolute path.
+static ROOT: OnceLock<Utf8PathBuf> = OnceLock::new();
+
+/// Move into the nearest directory at or above the current one that holds a database.
+///
+/// Every path in the database is relative to that directory, so this lets ftag work from any subdirectory.
+/// Paths given on the command line should be passed through `to_stored` or `from_invocation` afterwards.
+/// Nothing happens if there is no database, so commands can report that themselves.
+///
+/// # Failure
+///
+/// Returns `Err` if the current directory could not be read or changed.
+pub fn enter_root() -> Result<(), FtagError> {
+    let cwd = env::current_dir()?;
+    let cwd = Utf8PathBuf::from_path_buf(cwd).map_err(|_| FtagError::ArgumentError("The current directory is not valid UTF-8".to_string()))?;
+
+    let Some(root) = cwd.ancestors().find(|dir| holds_database(dir)) else {
+        tracing::debug!("No database in {} or above it", cwd);
+        return Ok(());
+    };
+    tracing::debug!("Using the database in {}", root);
+    env::set_current_dir(root)?;
+
+    let _ = PREFIX.set(cwd.strip_prefix(root).unwrap_or(Utf8Path::new("")).to_path_buf());
+    let _ = ROOT.set(root.to_path_buf());
+    Ok(())
+}
+
+/// Check whether a directory holds a database of any backend.
+pub fn holds_database(dir: &Utf8Path) -> bool {
+    dir.join(get_db_path()).exists() || dir.join(get_store_dir_path()).is_dir() || dir.join(get_text_path()).is_file()
+}
+
+/// Tidy up a relative path without looking at the filesystem, so `./a`, `a`, and `b/../a` are all `a`.
+///
+/// `..` is only kept at the start, where there is nothing left to cancel it out.
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = vec![];
+    for component in Utf8Path::new(path).components() {
+        match component {
+            Utf8Component::CurDir => {},
+            Utf8Component::ParentDir => match parts.last() {
+                // Nothing is above the root of an absolute path
+                Some(&"") => {},
+                Some(&"..") | None => parts.push(".."),
+                Some(_) => {
+                    parts.pop();
+                },
+            },
+            Utf8Component::Normal(part) => parts.push(part),
+            // Absolute paths keep their root
+            Utf8Component::RootDir => parts.push(""),
+            Utf8Component::Prefix(prefix) => parts.push(prefix.as_str()),
+        }
+    }
+
+    match parts.as_slice() {
+        [] => ".".to_string(),
+        [""] => "/".to_string(),
+        _ => parts.join("/"),
+    }
+}
+
+/// Convert a path typed by the user into the form it is stored in the database, relative to the database root.
+///
+/// # Failure
+///
+/// Returns `Err` if the path is outside the directory holding the database.
+pub fn to_stored(path: &Utf8Path) -> Result<Utf8PathBuf, FtagError> {
+    let prefix = PREFIX.get().map_or(Utf8Path::new(""), |prefix| prefix.as_path());
+    let root = ROOT.get();
+
+    let relative = match (path.is_absolute(), root) {
+        (true, Some(root)) => {
+            // The root comes from the working directory, which has its symlinks resolved, so try that too
+            let resolved = path.canonicalize_utf8().unwrap_or_else(|_| path.to_path_buf());
+            match (path.strip_prefix(root), resolved.strip_prefix(root)) {
+                (Ok(inside), _) | (_, Ok(inside)) => normalize(inside.as_str()),
+                _ => return Err(outside(path)),
+            }
+        },
+        (true, None) => return Ok(path.to_path_buf()),
+        (false, _) => normalize(prefix.join(path).as_str()),
+    };
+
+    if relative == ".." || relative.starts_with("../") {
+        return Err(outside(path));
+    }
+    tracing::debug!("Resolved {} to {}", path, relative);
+    Ok(Utf8PathBuf::from(relative))
+}
+
+/// Error for a path that can't be stored because it isn't beneath the database.
+fn outside(path: &Utf8Path) -> FtagError {
+    FtagError::ArgumentError(format!("{} is outside the directory holding the database", path))
+}
+
+/// Convert a path to some other file typed by the user, like a script or an output file,
+/// so it can still be found after `enter_root` changed directory.
+pub fn from_invocation(path: &Utf8Path) -> Utf8PathBuf {
+    match PREFIX.get() {
+        Some(prefix) if path.is_relative() => prefix.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Return the directory ftag was started in, so programs it runs can be started there too.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database and the current directory could not be read.
+pub fn invocation_dir() -> Result<Utf8PathBuf, FtagError> {
+    match (ROOT.get(), PREFIX.get()) {
+        (Some(root), Some(prefix)) => Ok(root.join(prefix)),
+        _ => Ok(Utf8PathBuf::try_from(env::current_dir()?).map_err(|_| FtagError::ArgumentError("The current directory is not valid UTF-8".to_string()))?),
+    }
+}
+
+/// Convert a path stored in the database into one relative to where ftag was started, for printing.
+///
+/// Absolute paths, like those found in other roots, are left as they are.
+pub fn to_display(stored: &str) -> String {
+    let Some(prefix) = PREFIX.get().filter(|prefix| !prefix.as_str().is_empty() && Utf8Path::new(stored).is_relative()) else {
+        return stored.to_string();
+    };
+
+    // Climb out of the starting directory as far as it differs from the stored path
+    let from: Vec<&str> = prefix.iter().collect();
+    let to: Vec<&str> = Utf8Path::new(stored).iter().collect();
+    let shared = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts = vec![".."; from.len() - shared];
+    parts.extend(&to[shared..]);
+    match parts.is_empty() {
+        true => ".".to_string(),
+        false => parts.join("/"),
+    }
+}
@@ -0,0 +1,463 @@
+use camino::Utf8Path;
+use flate2::read::ZlibDecoder;
+use regex::bytes::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+
+use crate::autotag::Tagger;
+use crate::ftag::{FtagError, KEY_SEPARATOR, TAG_SEPARATOR};
+
+/// Name of the built-in tagger reading PDF metadata, as given to `autotag --tagger`.
+pub const TAGGER_NAME: &str = "pdf";
+
+/// Deepest nesting of arrays and dictionaries read before giving up on a file.
+const MAX_DEPTH: usize = 32;
+
+/// What a PDF says about itself in its document information dictionary.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PdfInfo {
+    /// Title of the document
+    pub title: Option<String>,
+    /// Each author, split from a list like `Ada Lovelace, Charles Babbage`
+    pub authors: Vec<String>,
+    /// Each keyword, split from a list like `graphs; algorithms`
+    pub keywords: Vec<String>,
+}
+
+impl PdfInfo {
+    /// Return the tags a PDF gets from its metadata: each keyword as it is, `author=NAME` for each author, and
+    /// `title=TITLE`.
+    ///
+    /// A `/` in a value would start a new level of the tag, so it's replaced with a space.
+    pub fn tags(&self) -> Vec<String> {
+        let clean = |text: &str| text.replace(TAG_SEPARATOR, " ").split_whitespace().collect::<Vec<_>>().join(" ");
+        let keyed = |key: &str, value: &str| format!("{}{}{}", key, KEY_SEPARATOR, clean(value));
+
+        let mut tags: Vec<String> = self.keywords.iter().map(|keyword| clean(keyword)).collect();
+        tags.extend(self.authors.iter().map(|author| keyed("author", author)));
+        tags.extend(self.title.iter().map(|title| keyed("title", title)));
+        tags.retain(|tag| !tag.is_empty());
+        tags
+    }
+}
+
+/// An object in a PDF, keeping only what's needed to read the information dictionary.
+#[derive(Debug)]
+enum Object {
+    Dict(Vec<(Vec<u8>, Object)>),
+    Array,
+    String(Vec<u8>),
+    Name(Vec<u8>),
+    /// A reference to another object by number, like `12 0 R`
+    Ref(u32),
+    /// A number, `true`, `null`, or anything else that is a single word
+    Word(Vec<u8>),
+}
+
+impl Object {
+    /// Look up a key of a dictionary.
+    fn get(&self, key: &[u8]) -> Option<&Object> {
+        match self {
+            Object::Dict(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Check whether a byte separates words in a PDF.
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+/// Check whether a byte ends a word in a PDF without being whitespace.
+fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+/// Reads objects from PDF syntax, starting at some position in a file.
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Skip whitespace and comments.
+    fn skip_space(&mut self) {
+        while let Some(&byte) = self.data.get(self.pos) {
+            if byte == b'%' {
+                while self.data.get(self.pos).is_some_and(|&byte| byte != b'\n' && byte != b'\r') {
+                    self.pos += 1;
+                }
+            } else if is_space(byte) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Read the bytes up to the next whitespace or delimiter.
+    fn word(&mut self) -> &[u8] {
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(|&byte| !is_space(byte) && !is_delimiter(byte)) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    /// Read the object starting at the current position, or `None` if there isn't a valid one.
+    fn object(&mut self, depth: usize) -> Option<Object> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.skip_space();
+        let rest = &self.data[self.pos..];
+        match *rest.first()? {
+            b'<' if rest.get(1) == Some(&b'<') => {
+                self.pos += 2;
+                let mut entries = vec![];
+                loop {
+                    self.skip_space();
+                    if self.data[self.pos..].starts_with(b">>") {
+                        self.pos += 2;
+                        return Some(Object::Dict(entries));
+                    }
+                    let Object::Name(key) = self.object(depth + 1)? else {
+                        return None;
+                    };
+                    entries.push((key, self.object(depth + 1)?));
+                }
+            },
+            b'[' => {
+                self.pos += 1;
+                loop {
+                    self.skip_space();
+                    if self.data.get(self.pos) == Some(&b']') {
+                        self.pos += 1;
+                        return Some(Object::Array);
+                    }
+                    self.object(depth + 1)?;
+                }
+            },
+            b'(' => self.literal_string().map(Object::String),
+            b'<' => self.hex_string().map(Object::String),
+            b'/' => {
+                self.pos += 1;
+                Some(Object::Name(unescape_name(self.word())))
+            },
+            _ => {
+                let word = self.word().to_vec();
+                if word.is_empty() {
+                    return None;
+                }
+                // `12 0 R` is a reference, anything else is a lone word
+                let after = self.pos;
+                if let Ok(number) = std::str::from_utf8(&word).unwrap_or_default().parse::<u32>() {
+                    self.skip_space();
+                    let generation = self.word().to_vec();
+                    self.skip_space();
+                    if !generation.is_empty() && generation.iter().all(u8::is_ascii_digit) && self.word() == b"R" {
+                        return Some(Object::Ref(number));
+                    }
+                }
+                self.pos = after;
+                Some(Object::Word(word))
+            },
+        }
+    }
+
+    /// Read a string like `(Graph \(theory\))`, where balanced parentheses need no escape.
+    fn literal_string(&mut self) -> Option<Vec<u8>> {
+        self.pos += 1;
+        let mut text = vec![];
+        let mut depth = 0;
+        loop {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            match byte {
+                b'(' => depth += 1,
+                b')' if depth == 0 => return Some(text),
+                b')' => depth -= 1,
+                b'\\' => {
+                    let escaped = *self.data.get(self.pos)?;
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => text.push(b'\n'),
+                        b'r' => text.push(b'\r'),
+                        b't' => text.push(b'\t'),
+                        b'b' => text.push(b'\x08'),
+                        b'f' => text.push(b'\x0c'),
+                        b'0'..=b'7' => {
+                            let mut code = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.data.get(self.pos) {
+                                    Some(&digit @ b'0'..=b'7') => {
+                                        code = code * 8 + u32::from(digit - b'0');
+                                        self.pos += 1;
+                                    },
+                                    _ => break,
+                                }
+                            }
+                            text.push(code as u8);
+                        },
+                        // A backslash at the end of a line continues the string on the next one
+                        b'\r' => {
+                            if self.data.get(self.pos) == Some(&b'\n') {
+                                self.pos += 1;
+                            }
+                        },
+                        b'\n' => {},
+                        other => text.push(other),
+                    }
+                    continue;
+                },
+                _ => {},
+            }
+            text.push(byte);
+        }
+    }
+
+    /// Read a string written in hexadecimal like `<4164610>`, where a missing last digit is 0.
+    fn hex_string(&mut self) -> Option<Vec<u8>> {
+        self.pos += 1;
+        let end = self.pos + self.data[self.pos..].iter().position(|&byte| byte == b'>')?;
+        let digits: Vec<u8> = self.data[self.pos..end].iter().copied().filter(|&byte| !is_space(byte)).collect();
+        self.pos = end + 1;
+
+        let mut text = vec![];
+        for pair in digits.chunks(2) {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = pair.get(1).map_or(Some(0), |&digit| (digit as char).to_digit(16))?;
+            text.push((high * 16 + low) as u8);
+        }
+        Some(text)
+    }
+}
+
+/// Undo the `#xx` escapes allowed in names.
+fn unescape_name(name: &[u8]) -> Vec<u8> {
+    let mut unescaped = vec![];
+    let mut i = 0;
+    while i < name.len() {
+        let escaped = name.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (name[i], escaped) {
+            (b'#', Some(byte)) => {
+                unescaped.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                unescaped.push(byte);
+                i += 1;
+            },
+        }
+    }
+    unescaped
+}
+
+/// Decode a text string, which is UTF-16 when it starts with a byte order mark and PDFDocEncoding otherwise.
+///
+/// PDFDocEncoding is treated as Latin-1, which it matches for letters.
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(b"\xfe\xff") {
+        let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        bytes.iter().map(|&byte| byte as char).collect()
+    }
+}
+
+/// Every object a PDF has, found by their `12 0 obj` headers and inside compressed object streams.
+struct Objects<'a> {
+    data: &'a [u8],
+    /// Where each object starts in the file, the last one winning since updates are appended
+    offsets: HashMap<u32, usize>,
+}
+
+impl<'a> Objects<'a> {
+    /// Find the objects of a PDF.
+    fn find(data: &'a [u8]) -> Objects<'a> {
+        let header = Regex::new(r"(?-u)(?:^|[^0-9])([0-9]+)[ \t\r\n\x0c\x00]+[0-9]+[ \t\r\n\x0c\x00]+obj").unwrap();
+        let offsets = header
+            .captures_iter(data)
+            .filter_map(|captures| {
+                let number = std::str::from_utf8(&captures[1]).ok()?.parse().ok()?;
+                Some((number, captures.get(0)?.end()))
+            })
+            .collect();
+        Objects { data, offsets }
+    }
+
+    /// Decompress the object stream starting at `offset`, if that's what the object there is.
+    fn object_stream(&self, offset: usize) -> Option<(Vec<u8>, HashMap<u32, usize>)> {
+        let mut parser = Parser { data: self.data, pos: offset };
+        let dict = parser.object(0)?;
+        if !matches!(dict.get(b"Type"), Some(Object::Name(name)) if name == b"ObjStm") {
+            return None;
+        }
+        if !matches!(dict.get(b"Filter"), Some(Object::Name(name)) if name == b"FlateDecode") {
+            return None;
+        }
+
+        // The data starts after the line break following `stream`
+        parser.skip_space();
+        let rest = self.data[parser.pos..].strip_prefix(b"stream")?;
+        let line_break = match rest {
+            [b'\r', b'\n', ..] => 2,
+            [b'\r' | b'\n', ..] => 1,
+            _ => 0,
+        };
+        let start = parser.pos + b"stream".len() + line_break;
+        let end = match dict.get(b"Length") {
+            Some(Object::Word(length)) => start + std::str::from_utf8(length).ok()?.parse::<usize>().ok()?,
+            _ => start + self.data[start..].windows(9).position(|window| window == b"endstream")?,
+        };
+
+        // Keep whatever decompressed if the stream is cut short
+        let mut stream = vec![];
+        let _ = ZlibDecoder::new(self.data.get(start..end.min(self.data.len()))?).read_to_end(&mut stream);
+
+        let word = |object: Option<&Object>| match object {
+            Some(Object::Word(word)) => std::str::from_utf8(word).ok()?.parse::<usize>().ok(),
+            _ => None,
+        };
+        let count = word(dict.get(b"N"))?;
+        let first = word(dict.get(b"First"))?;
+
+        // The stream starts with the number and offset of each object in it
+        let mut header = Parser { data: &stream, pos: 0 };
+        let mut offsets = HashMap::new();
+        for _ in 0..count {
+            let number = word(header.object(0).as_ref())?;
+            let offset = word(header.object(0).as_ref())?;
+            offsets.insert(u32::try_from(number).ok()?, first.checked_add(offset)?);
+        }
+        Some((stream, offsets))
+    }
+
+    /// Read the object with a number.
+    fn get(&self, number: u32) -> Option<Object> {
+        if let Some(&offset) = self.offsets.get(&number) {
+            return Parser { data: self.data, pos: offset }.object(0);
+        }
+        // Only objects that aren't in the file as they are need decompressing
+        self.offsets.values().find_map(|&offset| {
+            let (stream, offsets) = self.object_stream(offset)?;
+            Parser { data: &stream, pos: *offsets.get(&number)? }.object(0)
+        })
+    }
+
+    /// Read an object, looking up the object it refers to if it's a reference.
+    fn resolve(&self, object: &Object) -> Option<Object> {
+        match object {
+            Object::Ref(number) => self.get(*number),
+            Object::String(text) => Some(Object::String(text.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Read the title, authors, and keywords from the contents of a PDF.
+///
+/// Returns `None` if it has no document information dictionary, or is encrypted so the dictionary can't be read.
+pub fn parse_info(data: &[u8]) -> Option<PdfInfo> {
+    // The trailer, or the cross-reference stream standing in for it, is near the end
+    let trailer = Regex::new(r"(?-u)/Info[ \t\r\n\x0c\x00]*([0-9]+)[ \t\r\n\x0c\x00]+[0-9]+[ \t\r\n\x0c\x00]+R").unwrap();
+    let info = trailer.captures_iter(data).last()?;
+    if data.windows(8).any(|window| window == b"/Encrypt") {
+        return None;
+    }
+    let objects = Objects::find(data);
+    let dict = objects.get(std::str::from_utf8(&info[1]).ok()?.parse().ok()?)?;
+
+    let text = |key: &[u8]| match dict.get(key).and_then(|value| objects.resolve(value)) {
+        Some(Object::String(text)) => Some(decode_text(&text).trim().to_string()).filter(|text| !text.is_empty()),
+        _ => None,
+    };
+    let split = |text: Option<String>, separators: &[char]| -> Vec<String> {
+        text.iter()
+            .flat_map(|text| text.split(separators))
+            .flat_map(|part| part.split(" and "))
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    Some(PdfInfo {
+        title: text(b"Title"),
+        authors: split(text(b"Author"), &[',', ';', '&']),
+        keywords: split(text(b"Keywords"), &[',', ';']),
+    })
+}
+
+/// Read the title, authors, and keywords of a PDF file.
+///
+/// # Failure
+///
+/// Returns `Err` if the file can't be read.
+pub fn read_info(path: &Utf8Path) -> Result<Option<PdfInfo>, FtagError> {
+    Ok(parse_info(&fs::read(path)?))
+}
+
+/// The built-in tagger suggesting the tags from `PdfInfo::tags` for every file ending in `.pdf`.
+#[derive(Debug, Default)]
+pub struct PdfTagger;
+
+impl Tagger for PdfTagger {
+    fn name(&self) -> &str {
+        TAGGER_NAME
+    }
+
+    fn suggest(&self, path: &Utf8Path) -> Result<Vec<String>, FtagError> {
+        if !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pdf")) {
+            return Ok(vec![]);
+        }
+        Ok(read_info(path)?.map(|info| info.tags()).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use proptest::prelude::*;
+    use std::io::Write;
+
+    proptest! {
+        #[test]
+        fn never_panics(data in any::<Vec<u8>>(), text in "[0-9 <>()\\[\\]/RobjInfoTypeObjStm\\\\]*") {
+            let _ = parse_info(&data);
+            let _ = parse_info(format!("/Info 1 0 R 1 0 obj {}", text).as_bytes());
+        }
+    }
+
+    #[test]
+    fn parses_examples() {
+        let plain = b"%PDF-1.4\n1 0 obj\n<< /Title (Graph \\(theory\\)) /Author (Ada Lovelace and Charles Babbage)\n\
+            /Keywords <FEFF0067007200610070006800730020003B00200074007200650065> /Producer 2 0 R >>\nendobj\n\
+            trailer\n<< /Size 2 /Info 1 0 R >>\n%%EOF";
+        let info = parse_info(plain).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Graph (theory)"));
+        assert_eq!(info.authors, ["Ada Lovelace", "Charles Babbage"]);
+        assert_eq!(info.keywords, ["graphs", "tree"]);
+        assert_eq!(info.tags(), ["graphs", "tree", "author=Ada Lovelace", "author=Charles Babbage", "title=Graph (theory)"]);
+
+        // The information dictionary can be compressed in an object stream, with its values elsewhere
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"5 0 << /Title 6 0 R /Keywords (a/b, c) >>").unwrap();
+        let stream = encoder.finish().unwrap();
+        let mut compressed = b"%PDF-1.5\n4 0 obj\n<< /Type /ObjStm /N 1 /First 4 /Filter /FlateDecode /Length ".to_vec();
+        compressed.extend(format!("{} >>\nstream\n", stream.len()).as_bytes());
+        compressed.extend(&stream);
+        compressed.extend(b"\nendstream\nendobj\n6 0 obj (Paper) endobj\n7 0 obj << /Type /XRef /Info 5 0 R >> endobj");
+        let info = parse_info(&compressed).unwrap();
+        assert_eq!(info.tags(), ["a b", "c", "title=Paper"]);
+
+        assert_eq!(parse_info(b"%PDF-1.4\n1 0 obj << /Title (x) >> endobj"), None);
+        assert_eq!(parse_info(b"1 0 obj << /Title (x) >> endobj trailer << /Info 1 0 R /Encrypt 2 0 R >>"), None);
+    }
+}
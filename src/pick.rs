@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::{env, fs, process};
+
+use crate::ftag::FtagError;
+use crate::sandbox;
+
+/// Environment variable naming the picker to run instead of fzf, like `sk --multi`.
+pub const PICKER_VAR: &str = "FTAG_PICKER";
+
+/// Picker run when `FTAG_PICKER` isn't set.
+const DEFAULT_PICKER: &str = "fzf --multi";
+
+/// Marks put before tags in `toggle` to show whether they're chosen.
+const CHECKED: &str = "[x] ";
+const UNCHECKED: &str = "[ ] ";
+
+/// Editor run when neither `VISUAL` nor `EDITOR` is set.
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Let the user choose among `items` with an interactive fuzzy finder, returning the chosen ones.
+///
+/// The items are given to the picker one per line on its standard input, and it prints each chosen line.
+/// Choosing nothing or cancelling the picker returns no items rather than an error.
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, or the picker could not be run or failed.
+pub fn pick(items: &[String]) -> Result<HashSet<String>, FtagError> {
+    let picker = env::var(PICKER_VAR).unwrap_or_else(|_| DEFAULT_PICKER.to_string());
+    let words = shell_words::split(&picker).map_err(|err| FtagError::ConfigError(format!("Could not parse {}: {}", PICKER_VAR, err)))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(FtagError::ConfigError(format!("{} is empty", PICKER_VAR)));
+    };
+    sandbox::check_exec(program)?;
+
+    // The picker draws on the terminal itself, so only its input and output go through ftag
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| FtagError::ConfigError(format!("Could not run {}: {}, install it or set {}", program, err, PICKER_VAR)))?;
+
+    let mut input = child.stdin.take().expect("stdin is piped");
+    for item in items {
+        // A picker may stop reading once it has what it needs
+        match writeln!(input, "{}", item) {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+            result => result?,
+        }
+    }
+    drop(input);
+
+    let output = child.wait_with_output()?;
+    match output.status.code() {
+        // fzf and skim exit with 1 when nothing matched and 130 when cancelled
+        Some(0) => Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect()),
+        Some(1 | 130) => Ok(HashSet::new()),
+        _ => Err(FtagError::ConfigError(format!("{} exited unsuccessfully: {}", program, output.status))),
+    }
+}
+
+/// Let the user tick and untick `items` like a checklist, returning the ones ticked afterwards.
+///
+/// Every item is shown in the picker with `[x] ` before it if it is in `checked` and `[ ] ` otherwise, so they
+/// can be filtered by whether they're ticked. Each item chosen in the picker is toggled. Choosing nothing or
+/// cancelling the picker leaves every item as it was.
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, or the picker could not be run or failed.
+pub fn toggle(items: &[String], checked: &HashSet<String>) -> Result<HashSet<String>, FtagError> {
+    let shown: Vec<String> = items
+        .iter()
+        .map(|item| format!("{}{}", if checked.contains(item) { CHECKED } else { UNCHECKED }, item))
+        .collect();
+
+    let mut ticked = checked.clone();
+    for line in pick(&shown)? {
+        let item = line.strip_prefix(CHECKED).or_else(|| line.strip_prefix(UNCHECKED)).unwrap_or(&line);
+        if !ticked.remove(item) {
+            ticked.insert(item.to_string());
+        }
+    }
+    Ok(ticked)
+}
+
+/// Let the user edit lines of text in their editor, `$VISUAL`, `$EDITOR`, or vi, returning the lines they saved.
+///
+/// Blank lines and lines starting with `#` are left out, so `header` can explain what to do in comments.
+///
+/// * `header` - Comment lines put before the text, each starting with `#`
+/// * `lines` - Lines to start with
+///
+/// # Failure
+///
+/// Returns `Err` if running programs has been forbidden, or the editor could not be run or failed.
+pub fn edit_lines(header: &str, lines: &[String]) -> Result<Vec<String>, FtagError> {
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let words = shell_words::split(&editor).map_err(|err| FtagError::ConfigError(format!("Could not parse editor {}: {}", editor, err)))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(FtagError::ConfigError("The editor is empty, set VISUAL or EDITOR".to_string()));
+    };
+    sandbox::check_exec(program)?;
+
+    let file = env::temp_dir().join(format!("ftag-edit-{}.txt", process::id()));
+    fs::write(&file, format!("{}\n{}\n", header, lines.join("\n")))?;
+    let status = Command::new(program).args(args).arg(&file).status();
+    let edited = fs::read_to_string(&file);
+    let _ = fs::remove_file(&file);
+
+    let status = status.map_err(|err| FtagError::ConfigError(format!("Could not run {}: {}", program, err)))?;
+    if !status.success() {
+        return Err(FtagError::ConfigError(format!("{} exited unsuccessfully: {}", program, status)));
+    }
+    Ok(edited?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
@@ -0,0 +1,251 @@
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::ftag::{self, CaseMode, FtagError, TagQuery};
+use crate::store;
+
+/// A search combining tags with `and`, `or`, `not`, and parentheses, like `todo and not (done or archived)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// Files with a tag, or one of its descendants
+    Tag(String),
+    /// Files not matching the inner search
+    Not(Box<Expr>),
+    /// Files matching both searches
+    And(Box<Expr>, Box<Expr>),
+    /// Files matching either search
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A piece of a search as it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A bare word, which may be `and`, `or`, or `not`
+    Word(String),
+    /// A quoted tag, which is never a keyword
+    Quoted(String),
+    Open,
+    Close,
+}
+
+/// Split a search into words, quoted tags, and parentheses.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut tag = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => tag.push(c),
+                        None => return Err("unclosed quote".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(tag));
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse `a or b or ...`, which binds loosest.
+fn parse_or(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Expr, String> {
+    let mut expr = parse_and(tokens)?;
+    while tokens.next_if(|token| matches!(token, Token::Word(word) if word == "or")).is_some() {
+        expr = Expr::Or(Box::new(expr), Box::new(parse_and(tokens)?));
+    }
+    Ok(expr)
+}
+
+/// Parse `a and b ...`, where the `and` may be left out.
+fn parse_and(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Expr, String> {
+    let mut expr = parse_not(tokens)?;
+    loop {
+        match tokens.peek() {
+            Some(Token::Word(word)) if word == "and" => {
+                tokens.next();
+            },
+            Some(Token::Word(word)) if word == "or" => break,
+            Some(Token::Close) | None => break,
+            Some(_) => {},
+        }
+        expr = Expr::And(Box::new(expr), Box::new(parse_not(tokens)?));
+    }
+    Ok(expr)
+}
+
+/// Parse a tag, a search in parentheses, or `not` followed by either.
+fn parse_not(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Expr, String> {
+    match tokens.next() {
+        Some(Token::Word(word)) if word == "not" => Ok(Expr::Not(Box::new(parse_not(tokens)?))),
+        Some(Token::Word(word)) if word == "and" || word == "or" => Err(format!("expected a tag before {}", word)),
+        Some(Token::Word(tag) | Token::Quoted(tag)) => Ok(Expr::Tag(tag)),
+        Some(Token::Open) => {
+            let expr = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(expr),
+                _ => Err("expected )".to_string()),
+            }
+        },
+        Some(Token::Close) => Err("unexpected )".to_string()),
+        None => Err("expected a tag".to_string()),
+    }
+}
+
+/// Parse a search like `todo and not (done or archived)`.
+///
+/// Tags next to each other must both match, so `todo urgent` is `todo and urgent`.
+/// Quote a tag named `and`, `or`, or `not` to search for it, like `"not"`.
+///
+/// # Failure
+///
+/// Returns `Err` if the search is empty or not written correctly.
+pub fn parse(text: &str) -> Result<Expr, FtagError> {
+    let invalid = |reason: String| FtagError::ArgumentError(format!("Invalid query \"{}\": {}", text, reason));
+
+    let mut tokens = tokenize(text).map_err(invalid)?.into_iter().peekable();
+    let expr = parse_or(&mut tokens).map_err(invalid)?;
+    // Anything else would have been taken as another tag, so only a stray parenthesis can be left over
+    match tokens.next() {
+        Some(_) => Err(invalid("unexpected )".to_string())),
+        None => Ok(expr),
+    }
+}
+
+impl Expr {
+    /// Add every tag the search mentions to `tags`.
+    fn collect_tags(&self, tags: &mut Vec<String>) {
+        match self {
+            Expr::Tag(tag) => tags.push(tag.clone()),
+            Expr::Not(inner) => inner.collect_tags(tags),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.collect_tags(tags);
+                right.collect_tags(tags);
+            },
+        }
+    }
+
+    /// Check whether a file matches the search, given a way to tell if it has each tag.
+    fn matches(&self, has: &impl Fn(&str) -> bool) -> bool {
+        match self {
+            Expr::Tag(tag) => has(tag),
+            Expr::Not(inner) => !inner.matches(has),
+            Expr::And(left, right) => left.matches(has) && right.matches(has),
+            Expr::Or(left, right) => left.matches(has) || right.matches(has),
+        }
+    }
+}
+
+/// Find every file matching a search, returning their paths and sorted tags.
+///
+/// Tags are matched like `find` matches them, so globs work and `project` matches `project/ftag`.
+///
+/// * `expr` - Search to run, from `parse`
+/// * `case` - How to treat differences in case between the searched tags and the stored ones
+///
+/// # Failure
+///
+/// Returns `Err` if a tag is an invalid glob, there is no database, or errors occur when reading the database.
+pub fn find_matching(expr: &Expr, case: CaseMode) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let mut tags = vec![];
+    expr.collect_tags(&mut tags);
+    let queries: HashMap<String, TagQuery> = tags.iter().cloned().zip(ftag::parse_queries(&tags, case, false)?).collect();
+
+    ftag::maybe_prune()?;
+    let mut files = vec![];
+    for (path, file_tags) in ftag::get_all_rows()? {
+        if expr.matches(&|tag| file_tags.iter().any(|file_tag| queries[tag].matches(file_tag))) {
+            let mut file_tags: Vec<String> = file_tags.into_iter().collect();
+            file_tags.sort();
+            files.push((path, file_tags));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Open the database, creating the `saved_queries` table if this database predates it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or the table could not be created.
+fn open_query_db() -> Result<store::Handle, FtagError> {
+    let conn = store::open_sqlite("Saving queries")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS saved_queries (
+            name    TEXT PRIMARY KEY,
+            query   TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// Save a search under a name, replacing any search already saved with it.
+///
+/// # Failure
+///
+/// Returns `Err` if the search is not written correctly, there is no database, or errors occur when interacting with
+/// the database.
+pub fn save_query(name: &str, query: &str) -> Result<(), FtagError> {
+    parse(query)?;
+    let conn = open_query_db()?;
+    conn.execute("INSERT OR REPLACE INTO saved_queries(name, query) VALUES (?, ?)", params![name, query])?;
+    Ok(())
+}
+
+/// Return the search saved under a name.
+///
+/// # Failure
+///
+/// Returns `Err` if no search has that name, there is no database, or errors occur when interacting with the database.
+pub fn get_query(name: &str) -> Result<String, FtagError> {
+    let conn = open_query_db()?;
+    conn.query_row("SELECT query FROM saved_queries WHERE name = ?", params![name], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| FtagError::ArgumentError(format!("No query named {}", name)))
+}
+
+/// Return every saved search with its name, sorted by name.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn all_queries() -> Result<Vec<(String, String)>, FtagError> {
+    let conn = open_query_db()?;
+    let mut stmt = conn.prepare("SELECT name, query FROM saved_queries ORDER BY name")?;
+    let queries = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(queries)
+}
+
+/// Forget the search saved under a name.
+///
+/// # Failure
+///
+/// Returns `Err` if no search has that name, there is no database, or errors occur when interacting with the database.
+pub fn delete_query(name: &str) -> Result<(), FtagError> {
+    let conn = open_query_db()?;
+    match conn.execute("DELETE FROM saved_queries WHERE name = ?", params![name])? {
+        0 => Err(FtagError::ArgumentError(format!("No query named {}", name))),
+        _ => Ok(()),
+    }
+}
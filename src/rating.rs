@@ -0,0 +1,112 @@
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::ftag::FtagError;
+use crate::keyvalue::Operator;
+use crate::store;
+
+/// Highest rating a file can be given.
+pub const MAX_RATING: u8 = 5;
+
+/// A condition on the rating of a file, written like `4`, `>=4`, or `!=1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RatingFilter {
+    /// How the rating of a file must compare
+    pub op: Operator,
+    /// Rating to compare against
+    pub rating: u8,
+}
+
+impl FromStr for RatingFilter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rating {}, expected something like 4 or '>=4' with ratings from 1 to {}", text, MAX_RATING);
+
+        let text = text.trim();
+        let (op, rating) = Operator::split(text).unwrap_or((Operator::Equal, text));
+        match rating.trim().parse() {
+            Ok(rating) if (1..=MAX_RATING).contains(&rating) => Ok(RatingFilter { op, rating }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl RatingFilter {
+    /// Check whether a rating satisfies the condition.
+    pub fn accepts(&self, rating: u8) -> bool {
+        self.op.accepts(rating.cmp(&self.rating))
+    }
+}
+
+/// Open the database, creating the `ratings` table if this database predates it.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, or the table could not be created.
+fn open_rating_db() -> Result<store::Handle, FtagError> {
+    let conn = store::open_sqlite("Rating files")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ratings (
+            path    TEXT PRIMARY KEY,
+            rating  INTEGER NOT NULL
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// Rate a path, replacing any rating it had. A rating of 0 removes it.
+///
+/// Ratings are kept apart from tags, so a path can be rated without being tagged.
+///
+/// # Failure
+///
+/// Returns `Err` if the rating is above `MAX_RATING`, there is no database, or errors occur when interacting with the
+/// database.
+pub fn set_rating(path: &str, rating: u8) -> Result<(), FtagError> {
+    if rating > MAX_RATING {
+        return Err(FtagError::ArgumentError(format!("Ratings go from 1 to {}, or 0 to remove one", MAX_RATING)));
+    }
+
+    let conn = open_rating_db()?;
+    match rating {
+        0 => conn.execute("DELETE FROM ratings WHERE path = ?", params![path])?,
+        _ => conn.execute("INSERT OR REPLACE INTO ratings(path, rating) VALUES (?, ?)", params![path, rating])?,
+    };
+    Ok(())
+}
+
+/// Return the rating of a path, if it has one.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn get_rating(path: &str) -> Result<Option<u8>, FtagError> {
+    let conn = open_rating_db()?;
+    Ok(conn.query_row("SELECT rating FROM ratings WHERE path = ?", params![path], |row| row.get(0)).optional()?)
+}
+
+/// Return the rating of every rated path.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn all_ratings() -> Result<HashMap<String, u8>, FtagError> {
+    let conn = open_rating_db()?;
+    let mut stmt = conn.prepare("SELECT path, rating FROM ratings")?;
+    let ratings = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(ratings)
+}
+
+/// Keep only the files whose rating satisfies a condition. Files without a rating never do.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when interacting with the database.
+pub fn filter_rated(files: Vec<(String, Vec<String>)>, filter: RatingFilter) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let ratings = all_ratings()?;
+    Ok(files.into_iter().filter(|(path, _)| ratings.get(path).is_some_and(|rating| filter.accepts(*rating))).collect())
+}
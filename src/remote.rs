@@ -0,0 +1,99 @@
+use std::env;
+use std::ffi::OsString;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::ftag::FtagError;
+use crate::sandbox;
+
+/// Environment variable naming the ftag to run on the other machine, in case it isn't on the PATH there.
+pub const REMOTE_COMMAND_VAR: &str = "FTAG_REMOTE_COMMAND";
+
+/// A directory on another machine, reached over SSH, like `user@nas:/srv/photos`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// Host to connect to, in any form `ssh` accepts
+    pub host: String,
+    /// Directory on the host to run ftag in, which may be relative to the home directory there
+    pub dir: String,
+}
+
+impl FromStr for Remote {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.split_once(':') {
+            Some((host, dir)) if !host.is_empty() && !host.starts_with('-') => {
+                Ok(Remote { host: host.to_string(), dir: if dir.is_empty() { ".".to_string() } else { dir.to_string() } })
+            },
+            _ => Err("expected HOST:DIR, like user@nas:/srv/photos".to_string()),
+        }
+    }
+}
+
+/// Quote an argument so a POSIX shell passes it along unchanged.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Take out the options that only make sense here, leaving the ones to pass along to the other machine.
+///
+/// Removes `--remote` itself and `--color`, since whether to color depends on the terminal here.
+/// Anything after `--` is left alone.
+fn forwarded_args(args: impl IntoIterator<Item = OsString>) -> Result<Vec<String>, FtagError> {
+    let mut args = args
+        .into_iter()
+        .map(|arg| arg.into_string().map_err(|arg| FtagError::ArgumentError(format!("{} is not valid UTF-8", arg.to_string_lossy()))));
+    let mut forwarded = vec![];
+    while let Some(arg) = args.next() {
+        let arg = arg?;
+        match arg.as_str() {
+            "--" => {
+                forwarded.push(arg);
+                for arg in args.by_ref() {
+                    forwarded.push(arg?);
+                }
+            },
+            "--remote" | "--color" => {
+                args.next().transpose()?;
+            },
+            arg if arg.starts_with("--remote=") || arg.starts_with("--color=") => {},
+            _ => forwarded.push(arg),
+        }
+    }
+    Ok(forwarded)
+}
+
+/// Run this same ftag command on another machine with `ssh`, against the database in a directory there, and wait for
+/// it to finish. Its output goes straight to this terminal.
+///
+/// Returns the exit status of the command, or of `ssh` if it couldn't connect.
+///
+/// * `remote` - Where to run the command
+/// * `color` - Whether the output should be colored, since the other machine can't see this terminal
+///
+/// # Failure
+///
+/// Returns `Err` if running programs or accessing the network has been forbidden, an argument isn't valid UTF-8, or
+/// `ssh` could not be run.
+pub fn run_remote(remote: &Remote, color: bool) -> Result<u8, FtagError> {
+    sandbox::check_exec("ssh")?;
+    sandbox::check_network(&remote.host)?;
+
+    let program = env::var(REMOTE_COMMAND_VAR).unwrap_or_else(|_| "ftag".to_string());
+    let mut command = format!("cd {} && {} --color={}", shell_quote(&remote.dir), program, if color { "always" } else { "never" });
+    for arg in forwarded_args(env::args_os().skip(1))? {
+        command.push(' ');
+        command.push_str(&shell_quote(&arg));
+    }
+
+    tracing::debug!("Running on {}: {}", remote.host, command);
+    let status = Command::new("ssh")
+        .arg("--")
+        .arg(&remote.host)
+        .arg(command)
+        .status()
+        .map_err(|err| FtagError::ConfigError(format!("Could not run ssh: {}", err)))?;
+    // A command killed by a signal has no status, so count it as a failure
+    Ok(status.code().map_or(1, |code| code as u8))
+}
@@ -0,0 +1,183 @@
+use camino::Utf8PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::ftag::{self, FtagError};
+use crate::journal;
+use crate::store::{self, Backend};
+
+/// How many of the most used tags to include in a report.
+const LARGEST_TAGS: usize = 20;
+
+/// How many of the latest changes to include in a report.
+const RECENT_CHANGES: u32 = 50;
+
+/// Escape text so it can be placed inside HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append a section to `html` with a heading, containing a list of items or a note that there are none.
+fn list_section(html: &mut String, heading: &str, items: &[String]) {
+    let _ = writeln!(html, "<h2>{} ({})</h2>", escape(heading), items.len());
+    if items.is_empty() {
+        html.push_str("<p class=\"none\">Nothing to report.</p>\n");
+        return;
+    }
+
+    html.push_str("<ul>\n");
+    for item in items {
+        let _ = writeln!(html, "<li>{}</li>", escape(item));
+    }
+    html.push_str("</ul>\n");
+}
+
+/// Find problems with the tags in the database which are worth a look, such as near-duplicate tags.
+///
+/// * `rows` - Every path in the database along with its tags
+/// * `tag_counts` - How many paths use each tag
+fn lint(rows: &[(String, HashSet<String>)], tag_counts: &HashMap<String, u32>) -> Vec<String> {
+    let mut findings = vec![];
+
+    // Paths that are tracked but have nothing on them
+    for (path, tags) in rows {
+        if tags.is_empty() {
+            findings.push(format!("{} has no tags", path));
+        }
+    }
+
+    // Paths with more than one row
+    let mut seen_paths = HashSet::new();
+    for (path, _) in rows {
+        if !seen_paths.insert(path) {
+            findings.push(format!("{} is stored more than once", path));
+        }
+    }
+
+    // Tags that are probably typos of each other
+    let mut by_lowercase: HashMap<String, Vec<&String>> = HashMap::new();
+    for tag in tag_counts.keys() {
+        by_lowercase.entry(tag.to_lowercase()).or_default().push(tag);
+    }
+    for variants in by_lowercase.values_mut().filter(|variants| variants.len() > 1) {
+        variants.sort();
+        findings.push(format!("Tags differ only in case: {}", variants.iter().map(|tag| tag.as_str()).collect::<Vec<_>>().join(", ")));
+    }
+
+    // Tags that are hard to type or read correctly
+    for tag in tag_counts.keys() {
+        if tag.trim() != tag || tag.chars().any(char::is_control) {
+            findings.push(format!("Tag {:?} contains unusual whitespace or control characters", tag));
+        }
+    }
+
+    findings.sort();
+    findings
+}
+
+/// Build a single, self-contained HTML page describing the health of the database.
+///
+/// The report covers overall statistics, problems found in the tags, paths which no longer exist, the most used tags,
+/// and the latest changes.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when reading it.
+pub fn build_report() -> Result<String, FtagError> {
+    let rows = ftag::get_all_rows()?;
+
+    // Count every tag, including ones only used by paths that no longer exist
+    let mut tag_counts: HashMap<String, u32> = HashMap::new();
+    for (_, tags) in &rows {
+        for tag in tags {
+            *tag_counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+    }
+
+    let total_tags: usize = rows.iter().map(|(_, tags)| tags.len()).sum();
+    let average = match rows.len() {
+        0 => 0.0,
+        files => total_tags as f64 / files as f64,
+    };
+
+    let mut dead_paths: Vec<String> = rows
+        .iter()
+        .filter(|(path, _)| !Utf8PathBuf::from(path).exists())
+        .map(|(path, _)| path.clone())
+        .collect();
+    dead_paths.sort();
+
+    let mut largest: Vec<(&String, &u32)> = tag_counts.iter().collect();
+    largest.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    largest.truncate(LARGEST_TAGS);
+
+    let mut html = String::new();
+    html.push_str(concat!(
+        "<!DOCTYPE html>\n",
+        "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>ftag report</title>\n",
+        "<style>\n",
+        "body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; }\n",
+        "table { border-collapse: collapse; }\n",
+        "td, th { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n",
+        "td.count { text-align: right; }\n",
+        ".none { color: #888; }\n",
+        "</style>\n</head>\n<body>\n<h1>ftag report</h1>\n",
+    ));
+
+    html.push_str("<h2>Statistics</h2>\n<table>\n");
+    let _ = writeln!(html, "<tr><th>Tracked paths</th><td class=\"count\">{}</td></tr>", rows.len());
+    let _ = writeln!(html, "<tr><th>Distinct tags</th><td class=\"count\">{}</td></tr>", tag_counts.len());
+    let _ = writeln!(html, "<tr><th>Average tags per path</th><td class=\"count\">{:.2}</td></tr>", average);
+    let _ = writeln!(html, "<tr><th>Paths that no longer exist</th><td class=\"count\">{}</td></tr>", dead_paths.len());
+    html.push_str("</table>\n");
+
+    list_section(&mut html, "Lint findings", &lint(&rows, &tag_counts));
+    list_section(&mut html, "Paths that no longer exist", &dead_paths);
+
+    let _ = writeln!(html, "<h2>Largest tags</h2>");
+    if largest.is_empty() {
+        html.push_str("<p class=\"none\">Nothing to report.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Tag</th><th>Paths</th></tr>\n");
+        for (tag, count) in largest {
+            let _ = writeln!(html, "<tr><td>{}</td><td class=\"count\">{}</td></tr>", escape(tag), count);
+        }
+        html.push_str("</table>\n");
+    }
+
+    // Only the sqlite backend keeps a journal of changes
+    let changes = match store::current_backend()? {
+        Backend::Sqlite => journal::recent_changes(RECENT_CHANGES)?,
+        Backend::Json | Backend::Text => vec![],
+    };
+    let recent: Vec<String> = changes
+        .into_iter()
+        .map(|(time, path, tags)| {
+            let change = match tags {
+                Some(tags) if tags.is_empty() => "left with no tags".to_string(),
+                Some(tags) => {
+                    let mut tags: Vec<String> = tags.into_iter().collect();
+                    tags.sort();
+                    format!("tagged {}", tags.join("; "))
+                },
+                None => "removed from the database".to_string(),
+            };
+            format!("{} {} {}", journal::format_time(time), path, change)
+        })
+        .collect();
+    list_section(&mut html, "Recent activity", &recent);
+
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
@@ -0,0 +1,150 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{env, fs, io};
+
+use crate::ftag::FtagError;
+use crate::paths;
+
+/// Name of the file listing the registered roots, in ftag's config directory.
+const ROOTS_FILE: &str = "roots";
+
+/// Return the ftag directory within one of the user's directories, like `$XDG_CONFIG_HOME/ftag` or `~/.config/ftag`.
+///
+/// * `var` - Variable that may name the user's directory, like `XDG_CONFIG_HOME`
+/// * `fallback` - Directory in the home directory to use when `var` isn't set, like `.config`
+///
+/// # Failure
+///
+/// Returns `Err` if neither `var` nor `HOME` (`APPDATA` on Windows) is set.
+pub(crate) fn user_dir(var: &str, fallback: &str) -> Result<Utf8PathBuf, FtagError> {
+    let dir = env::var(var)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(Utf8PathBuf::from)
+        .or_else(|| env::var("HOME").ok().filter(|dir| !dir.is_empty()).map(|home| Utf8PathBuf::from(home).join(fallback)))
+        .or_else(|| env::var("APPDATA").ok().map(Utf8PathBuf::from));
+    match dir {
+        Some(dir) => Ok(dir.join("ftag")),
+        None => Err(FtagError::ConfigError(format!("Can't tell where ftag should keep its files, set {} or HOME", var))),
+    }
+}
+
+/// Return where the registered roots are kept, `$XDG_CONFIG_HOME/ftag/roots` or `~/.config/ftag/roots`.
+///
+/// # Failure
+///
+/// Returns `Err` if neither `XDG_CONFIG_HOME` nor `HOME` (`APPDATA` on Windows) is set.
+fn roots_file() -> Result<Utf8PathBuf, FtagError> {
+    Ok(user_dir("XDG_CONFIG_HOME", ".config")?.join(ROOTS_FILE))
+}
+
+/// Return the registered roots, sorted. There are none until one is added.
+///
+/// # Failure
+///
+/// Returns `Err` if the list of roots exists but could not be read.
+pub fn list_roots() -> Result<Vec<Utf8PathBuf>, FtagError> {
+    let text = match fs::read_to_string(roots_file()?) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(text.lines().filter(|line| !line.trim().is_empty()).map(Utf8PathBuf::from).collect())
+}
+
+/// Replace the registered roots, keeping them sorted with no repeats.
+fn save_roots(mut roots: Vec<Utf8PathBuf>) -> Result<(), FtagError> {
+    roots.sort();
+    roots.dedup();
+    let file = roots_file()?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text: String = roots.iter().map(|root| format!("{}\n", root)).collect();
+    fs::write(file, text)?;
+    Ok(())
+}
+
+/// Turn a directory typed by the user into the absolute path a root is registered under, without needing it to exist.
+fn absolute(dir: &Utf8Path) -> Result<Utf8PathBuf, FtagError> {
+    if let Ok(resolved) = dir.canonicalize_utf8() {
+        return Ok(resolved);
+    }
+    let cwd = Utf8PathBuf::try_from(env::current_dir()?).map_err(|_| FtagError::ArgumentError("The current directory is not valid UTF-8".to_string()))?;
+    Ok(Utf8PathBuf::from(paths::normalize(cwd.join(dir).as_str())))
+}
+
+/// Register the database in `dir` as a root, returning its absolute path and whether it is new.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database in `dir` or the list of roots could not be read or written.
+pub fn add_root(dir: &Utf8Path) -> Result<(Utf8PathBuf, bool), FtagError> {
+    let root = absolute(dir)?;
+    if !paths::holds_database(&root) {
+        return Err(FtagError::ArgumentError(format!("There is no database in {}", dir)));
+    }
+
+    let mut roots = list_roots()?;
+    if roots.contains(&root) {
+        return Ok((root, false));
+    }
+    roots.push(root.clone());
+    save_roots(roots)?;
+    Ok((root, true))
+}
+
+/// Forget a registered root, which may no longer exist, returning its absolute path.
+///
+/// # Failure
+///
+/// Returns `Err` if `dir` isn't a registered root or the list of roots could not be read or written.
+pub fn remove_root(dir: &Utf8Path) -> Result<Utf8PathBuf, FtagError> {
+    let root = absolute(dir)?;
+    let mut roots = list_roots()?;
+    let count = roots.len();
+    roots.retain(|registered| *registered != root);
+    if roots.len() == count {
+        return Err(FtagError::ArgumentError(format!("{} is not a registered root", dir)));
+    }
+    save_roots(roots)?;
+    Ok(root)
+}
+
+/// Run something from inside every registered root in turn, returning what it returned for each of them.
+///
+/// Roots that no longer hold a database are skipped with a warning, so one missing drive doesn't stop the rest.
+///
+/// # Failure
+///
+/// Returns `Err` if the list of roots could not be read, a root could not be entered, or `run` fails in any root.
+pub fn in_each_root<T>(mut run: impl FnMut() -> Result<T, FtagError>) -> Result<Vec<(Utf8PathBuf, T)>, FtagError> {
+    let start = env::current_dir()?;
+    let mut results = vec![];
+    for root in list_roots()? {
+        if !paths::holds_database(&root) {
+            tracing::warn!("Skipped {} because there is no database there", root);
+            continue;
+        }
+        tracing::debug!("Working in {}", root);
+        env::set_current_dir(&root)?;
+        let result = run();
+        env::set_current_dir(&start)?;
+        results.push((root, result?));
+    }
+    Ok(results)
+}
+
+/// Run a search in every registered root, returning what each found with its root in front of the paths.
+///
+/// * `search` - Search to run, from inside the root being searched
+///
+/// # Failure
+///
+/// Returns `Err` if the list of roots could not be read, or `search` fails in any root.
+pub fn search_roots(search: impl FnMut() -> Result<Vec<(String, Vec<String>)>, FtagError>) -> Result<Vec<(String, Vec<String>)>, FtagError> {
+    let mut found = vec![];
+    for (root, files) in in_each_root(search)? {
+        found.extend(files.into_iter().map(|(file, tags)| (root.join(file).into_string(), tags)));
+    }
+    Ok(found)
+}
@@ -0,0 +1,169 @@
+use std::sync::OnceLock;
+
+use crate::ftag::FtagError;
+
+/// Things ftag has been told it may not do during this invocation.
+#[derive(Debug, Default, Clone, Copy)]
+struct Restrictions {
+    no_network: bool,
+    no_exec: bool,
+}
+
+static RESTRICTIONS: OnceLock<Restrictions> = OnceLock::new();
+
+/// Forbid ftag from touching the network or running other programs for the rest of this process.
+///
+/// Features that would do either (hooks, openers, --remote, self-update) must ask `check_exec` or `check_network` first.
+///
+/// * `no_network` - Refuse anything that would access the network
+/// * `no_exec` - Refuse anything that would execute another program
+/// * `enforce` - Also ask the operating system to enforce the restrictions, so they hold even if a check is missed
+///
+/// # Failure
+///
+/// Returns `Err` if `enforce` is set and the operating system restriction could not be applied.
+pub fn restrict(no_network: bool, no_exec: bool, enforce: bool) -> Result<(), FtagError> {
+    // Only the first call counts, restrictions are never loosened
+    let _ = RESTRICTIONS.set(Restrictions { no_network, no_exec });
+
+    if enforce && (no_network || no_exec) {
+        os::enforce(no_network, no_exec)?;
+    }
+
+    Ok(())
+}
+
+/// Make sure running `what` as an external program has not been forbidden.
+///
+/// # Failure
+///
+/// Returns `Err` if `--no-exec` was given.
+pub fn check_exec(what: &str) -> Result<(), FtagError> {
+    match RESTRICTIONS.get() {
+        Some(restrictions) if restrictions.no_exec => {
+            Err(FtagError::RestrictedError(format!("Refusing to run {} because of --no-exec", what)))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Make sure accessing the network for `what` has not been forbidden.
+///
+/// # Failure
+///
+/// Returns `Err` if `--no-network` was given.
+#[cfg_attr(not(any(feature = "full", feature = "self-update")), allow(dead_code))] // Only --remote and self-update access the network so far
+pub fn check_network(what: &str) -> Result<(), FtagError> {
+    match RESTRICTIONS.get() {
+        Some(restrictions) if restrictions.no_network => {
+            Err(FtagError::RestrictedError(format!("Refusing to contact {} because of --no-network", what)))
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod os {
+    use super::FtagError;
+    use libc::{sock_filter, sock_fprog};
+
+    // Values from linux/audit.h, which libc does not export
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    // Offsets into struct seccomp_data
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+
+    // x32 system calls share the x86_64 architecture but have this bit set in their number
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    fn statement(code: u32, k: u32) -> sock_filter {
+        sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter { code: code as u16, jt, jf, k }
+    }
+
+    /// Install a seccomp filter that makes the forbidden system calls fail with `EPERM`.
+    pub fn enforce(no_network: bool, no_exec: bool) -> Result<(), FtagError> {
+        let mut blocked: Vec<libc::c_long> = vec![];
+        if no_exec {
+            blocked.extend([libc::SYS_execve, libc::SYS_execveat]);
+        }
+        if no_network {
+            blocked.extend([libc::SYS_socket, libc::SYS_socketpair]);
+        }
+
+        let deny = libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA);
+        let mut filter = vec![
+            // Kill anything calling in through an architecture we did not write the filter for
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARCH_OFFSET),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, NR_OFFSET),
+            jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, X32_SYSCALL_BIT, 0, 1),
+            statement(libc::BPF_RET | libc::BPF_K, deny),
+        ];
+        for nr in blocked {
+            filter.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr as u32, 0, 1));
+            filter.push(statement(libc::BPF_RET | libc::BPF_K, deny));
+        }
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+
+        let program = sock_fprog { len: filter.len() as u16, filter: filter.as_mut_ptr() };
+
+        // SAFETY: program points at a valid filter which outlives both calls, and the kernel copies it
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                || libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program as *const sock_fprog) != 0
+            {
+                return Err(FtagError::IoError(std::io::Error::last_os_error()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "openbsd")]
+mod os {
+    use super::FtagError;
+    use std::ffi::CString;
+
+    /// Pledge away the promises ftag no longer needs.
+    pub fn enforce(no_network: bool, no_exec: bool) -> Result<(), FtagError> {
+        let mut promises = vec!["stdio", "rpath", "wpath", "cpath", "flock", "fattr"];
+        if !no_exec {
+            promises.extend(["proc", "exec"]);
+        }
+        if !no_network {
+            promises.extend(["inet", "dns"]);
+        }
+
+        let promises = CString::new(promises.join(" ")).expect("promises contain no NUL bytes");
+
+        // SAFETY: promises is a valid NUL terminated string, and a null execpromises leaves them unchanged
+        if unsafe { libc::pledge(promises.as_ptr(), std::ptr::null()) } != 0 {
+            return Err(FtagError::IoError(std::io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")),
+    target_os = "openbsd",
+)))]
+mod os {
+    use super::FtagError;
+
+    /// No operating system restriction is available here, so refuse rather than pretend.
+    pub fn enforce(_no_network: bool, _no_exec: bool) -> Result<(), FtagError> {
+        Err(FtagError::RestrictedError("--sandbox is not supported on this platform".to_string()))
+    }
+}
@@ -0,0 +1,294 @@
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::ftag::{get_db_path, parse_tags, serialize_tags, FtagError};
+use crate::store::{self, Backend};
+use crate::{journal, paths};
+
+/// Version of the SQLite schema this build of ftag reads and writes, kept in the `user_version` pragma.
+pub const SCHEMA_VERSION: i64 = 7;
+
+/// A step upgrading the schema by one version.
+type Migration = fn(&Connection) -> Result<(), FtagError>;
+
+/// Every upgrade in order, where the step at index `i` upgrades a database from version `i` to `i + 1`.
+const MIGRATIONS: &[Migration] = &[to_v1, to_v2, to_v3, to_v4, to_v5, to_v6, to_v7];
+
+/// Version 1 starts keeping a version. Databases before it may be missing the tables added since `init` first
+/// existed, which are created here instead of on first use.
+fn to_v1(conn: &Connection) -> Result<(), FtagError> {
+    store::ensure_settings(conn)?;
+    journal::ensure_journal(conn)?;
+    Ok(())
+}
+
+/// Version 2 stores paths in a tidy form relative to the database root, so `./a`, `a`, and `b/../a` are one file.
+/// Rows that turn out to be the same file are merged, keeping every tag from each.
+fn to_v2(conn: &Connection) -> Result<(), FtagError> {
+    let mut rows: BTreeMap<String, Vec<(String, HashSet<String>)>> = BTreeMap::new();
+    {
+        let mut statement = conn.prepare("SELECT path, tags FROM tags")?;
+        let mut query = statement.query(())?;
+        while let Some(row) = query.next()? {
+            let path: String = row.get(0)?;
+            let tags: Option<String> = row.get(1)?;
+            let tags = match tags {
+                Some(json) => parse_tags(&json)?,
+                None => HashSet::new(),
+            };
+            rows.entry(paths::normalize(&path)).or_default().push((path, tags));
+        }
+    }
+
+    for (normalized, originals) in rows {
+        if originals.len() == 1 && originals[0].0 == normalized {
+            continue;
+        }
+
+        let merged: HashSet<String> = originals.iter().flat_map(|(_, tags)| tags.iter().cloned()).collect();
+        let serialized = serialize_tags(&merged)?;
+        for (path, _) in &originals {
+            conn.execute("DELETE FROM tags WHERE path = ?", params![path])?;
+            conn.execute("UPDATE journal SET path = ? WHERE path = ?", params![normalized, path])?;
+        }
+        conn.execute("INSERT INTO tags(path, tags) VALUES (?, ?)", params![normalized, serialized])?;
+        journal::record(conn, &normalized, Some(&serialized))?;
+    }
+
+    // Recorded git blobs follow their paths, if git-aware mode was ever used
+    if has_table(conn, "git_blobs")? {
+        let blobs: Vec<String> = conn.prepare("SELECT path FROM git_blobs")?.query_map((), |row| row.get(0))?.collect::<Result<_, _>>()?;
+        for path in blobs {
+            let normalized = paths::normalize(&path);
+            if normalized != path {
+                conn.execute("UPDATE OR REPLACE git_blobs SET path = ? WHERE path = ?", params![normalized, path])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 3 keeps when each path was first tagged and last changed, and when each of its tags was added.
+/// Existing rows get their times by replaying the journal.
+fn to_v3(conn: &Connection) -> Result<(), FtagError> {
+    // Looking up the journal of every path is slow without the index version 7 adds, so make it early
+    conn.execute(journal::JOURNAL_PATH_INDEX, ())?;
+    conn.execute("ALTER TABLE tags ADD COLUMN created INTEGER", ())?;
+    conn.execute("ALTER TABLE tags ADD COLUMN modified INTEGER", ())?;
+    conn.execute(
+        "UPDATE tags SET
+            created = (SELECT MIN(time) FROM journal WHERE journal.path = tags.path),
+            modified = (SELECT MAX(time) FROM journal WHERE journal.path = tags.path)",
+        (),
+    )?;
+    journal::create_tag_times(conn)?;
+
+    // A tag was added at the latest entry where it appeared without being there just before
+    let mut added: BTreeMap<String, BTreeMap<String, i64>> = BTreeMap::new();
+    {
+        let mut statement = conn.prepare("SELECT time, path, tags FROM journal ORDER BY id")?;
+        let mut query = statement.query(())?;
+        while let Some(row) = query.next()? {
+            let time: i64 = row.get(0)?;
+            let path: String = row.get(1)?;
+            let tags: Option<String> = row.get(2)?;
+            let tags = match tags {
+                Some(json) => parse_tags(&json)?,
+                None => HashSet::new(),
+            };
+
+            let times = added.entry(path).or_default();
+            times.retain(|tag, _| tags.contains(tag));
+            for tag in tags {
+                times.entry(tag).or_insert(time);
+            }
+        }
+    }
+
+    for (path, times) in added {
+        for (tag, time) in times {
+            conn.execute(
+                "INSERT INTO tag_times(path, tag, added) SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM tags WHERE path = ?1)",
+                params![path, tag, time],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 4 allows only one row per path, with an index so looking up a path doesn't read every row.
+/// Rows left with the same path by writers racing each other are merged first, keeping every tag from each.
+fn to_v4(conn: &Connection) -> Result<(), FtagError> {
+    let mut duplicates: BTreeMap<String, HashSet<String>> = BTreeMap::new();
+    {
+        let mut statement = conn.prepare("SELECT path, tags FROM tags WHERE path IN (SELECT path FROM tags GROUP BY path HAVING COUNT(*) > 1)")?;
+        let mut query = statement.query(())?;
+        while let Some(row) = query.next()? {
+            let path: String = row.get(0)?;
+            let tags: Option<String> = row.get(1)?;
+            let merged = duplicates.entry(path).or_default();
+            if let Some(json) = tags {
+                merged.extend(parse_tags(&json)?);
+            }
+        }
+    }
+
+    for (path, tags) in duplicates {
+        let serialized = serialize_tags(&tags)?;
+        let (created, modified): (Option<i64>, Option<i64>) =
+            conn.query_row("SELECT MIN(created), MAX(modified) FROM tags WHERE path = ?", params![path], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        conn.execute("DELETE FROM tags WHERE path = ?", params![path])?;
+        conn.execute("INSERT INTO tags(path, tags, created, modified) VALUES (?, ?, ?, ?)", params![path, serialized, created, modified])?;
+        journal::record(conn, &path, Some(&serialized))?;
+    }
+
+    conn.execute(TAGS_PATH_INDEX, ())?;
+    Ok(())
+}
+
+/// Version 5 keeps every tag of every path in the `file_tags` table as well, so a search for a tag looks it up
+/// instead of reading the tags of every row.
+fn to_v5(conn: &Connection) -> Result<(), FtagError> {
+    conn.execute_batch(FILE_TAGS_SQL)?;
+    conn.execute(
+        "INSERT OR IGNORE INTO file_tags(tag, path)
+        SELECT tag.value, tags.path FROM tags, json_each(CASE WHEN json_valid(tags.tags) THEN tags.tags ELSE '{}' END, '$.Tags') AS tag
+        WHERE tag.type = 'text'",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Version 6 creates the `tag_meta` table up front, rather than on first use by whatever command ran next.
+/// Databases that already described or colored a tag have it already.
+fn to_v6(conn: &Connection) -> Result<(), FtagError> {
+    if !has_table(conn, "tag_meta")? {
+        conn.execute_batch(TAG_META_SQL)?;
+    }
+    Ok(())
+}
+
+/// Version 7 indexes the journal by path, since looking back at the tags of each path otherwise reads every entry
+/// once per path.
+fn to_v7(conn: &Connection) -> Result<(), FtagError> {
+    conn.execute(journal::JOURNAL_PATH_INDEX, ())?;
+    Ok(())
+}
+
+/// Index allowing only one row per path in the `tags` table.
+pub(crate) const TAGS_PATH_INDEX: &str = "CREATE UNIQUE INDEX tags_path ON tags(path)";
+
+/// The `file_tags` table, holding one row for each tag of each path in the `tags` table, and the triggers keeping
+/// it up to date whenever a row of `tags` changes. Rows whose tags can't be read have no tags here until repaired.
+pub(crate) const FILE_TAGS_SQL: &str = "
+    CREATE TABLE file_tags (
+        tag     TEXT NOT NULL,
+        path    TEXT NOT NULL,
+        PRIMARY KEY (tag, path)
+    ) WITHOUT ROWID;
+    CREATE INDEX file_tags_path ON file_tags(path);
+    CREATE TRIGGER file_tags_insert AFTER INSERT ON tags BEGIN
+        INSERT OR IGNORE INTO file_tags(tag, path)
+        SELECT value, new.path FROM json_each(CASE WHEN json_valid(new.tags) THEN new.tags ELSE '{}' END, '$.Tags')
+        WHERE type = 'text';
+    END;
+    CREATE TRIGGER file_tags_update AFTER UPDATE OF path, tags ON tags BEGIN
+        DELETE FROM file_tags WHERE path = old.path;
+        INSERT OR IGNORE INTO file_tags(tag, path)
+        SELECT value, new.path FROM json_each(CASE WHEN json_valid(new.tags) THEN new.tags ELSE '{}' END, '$.Tags')
+        WHERE type = 'text';
+    END;
+    CREATE TRIGGER file_tags_delete AFTER DELETE ON tags BEGIN
+        DELETE FROM file_tags WHERE path = old.path;
+    END;";
+
+/// The `tag_meta` table, holding the description and color of each tag that has either.
+pub(crate) const TAG_META_SQL: &str = "
+    CREATE TABLE tag_meta (
+        tag         TEXT PRIMARY KEY,
+        description TEXT,
+        color       TEXT
+    );";
+
+/// Return whether a database has a table named `name`.
+///
+/// # Failure
+///
+/// Returns `Err` if the schema could not be read.
+pub(crate) fn has_table(conn: &Connection, name: &str) -> Result<bool, FtagError> {
+    Ok(conn.prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")?.exists(params![name])?)
+}
+
+/// Return the schema version of a database. Databases from before versioning are version 0.
+///
+/// # Failure
+///
+/// Returns `Err` if the version could not be read.
+pub fn version(conn: &Connection) -> Result<i64, FtagError> {
+    Ok(conn.query_row("PRAGMA user_version", (), |row| row.get(0))?)
+}
+
+/// Record the schema version of a database.
+///
+/// # Failure
+///
+/// Returns `Err` if the version could not be written.
+pub fn set_version(conn: &Connection, version: i64) -> Result<(), FtagError> {
+    conn.pragma_update(None, "user_version", version)?;
+    Ok(())
+}
+
+/// Make sure a database uses the schema this build of ftag understands.
+///
+/// # Failure
+///
+/// Returns `Err` if the database is older and needs `ftag migrate`, is newer than this build, or the version could
+/// not be read.
+pub fn check_version(conn: &Connection) -> Result<(), FtagError> {
+    let found = version(conn)?;
+    if found < SCHEMA_VERSION {
+        return Err(FtagError::ConfigError(format!(
+            "Database uses schema version {}, run ftag migrate to upgrade it to version {}",
+            found, SCHEMA_VERSION
+        )));
+    }
+    if found > SCHEMA_VERSION {
+        return Err(FtagError::ConfigError(format!(
+            "Database uses schema version {}, but this ftag only understands up to version {}",
+            found, SCHEMA_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Upgrade the database in the current directory to the latest schema in place, returning the version it started at.
+///
+/// Every step runs in one transaction, so a failed upgrade leaves the database as it was.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, it doesn't use the sqlite backend, it is newer than this build,
+/// or an upgrade step fails.
+pub fn migrate() -> Result<i64, FtagError> {
+    if store::current_backend()? != Backend::Sqlite {
+        return Err(FtagError::ConfigError("Schema migration is only needed by the sqlite backend".to_string()));
+    }
+
+    let conn = store::connect(&get_db_path())?;
+    let found = version(&conn)?;
+    if found > SCHEMA_VERSION {
+        check_version(&conn)?;
+    }
+
+    // Other parts of ftag may share this connection, so it can't be borrowed mutably for a checked transaction
+    let tx = conn.unchecked_transaction()?;
+    for step in &MIGRATIONS[found.max(0) as usize..] {
+        step(&tx)?;
+    }
+    set_version(&tx, SCHEMA_VERSION)?;
+    tx.commit()?;
+
+    Ok(found)
+}
@@ -0,0 +1,167 @@
+use crate::ftag::FtagError;
+#[cfg(feature = "full")]
+use crate::ftag;
+use crate::store::{self, TagStore};
+#[cfg(feature = "full")]
+use crate::store::Backend;
+use crate::validate::TagValidation;
+
+/// Treat tags differing only in case as the same tag, storing them in lowercase.
+pub const CASE_INSENSITIVE: &str = "case-insensitive";
+
+/// How to handle new tags with surrounding whitespace, control characters, or leading dashes.
+pub const TAG_VALIDATION: &str = "tag-validation";
+
+/// Remember the git blob hash of files when they are tagged, so `find --dirty` can tell when they change.
+#[cfg(feature = "full")]
+pub const GIT_AWARE: &str = "git-aware";
+
+/// Skip files a `.gitignore` ignores when looking through directories inside a git repository.
+#[cfg(feature = "full")]
+pub const RESPECT_GITIGNORE: &str = "respect-gitignore";
+
+/// Ask for confirmation before a single command changes more than this many files.
+#[cfg(feature = "full")]
+pub const CONFIRM_ABOVE: &str = "confirm-above";
+
+/// Number of backups `ftag backup` keeps before deleting the oldest.
+#[cfg(feature = "full")]
+pub const BACKUP_KEEP: &str = "backup-keep";
+
+/// Every setting that can be changed with `ftag config`, along with its default value and a description.
+pub const SETTINGS: &[(&str, &str, &str)] = &[
+    (CASE_INSENSITIVE, "false", "Treat tags differing only in case as the same tag"),
+    (TAG_VALIDATION, "normalize", "Fix (normalize), refuse (reject), or allow (off) badly formed new tags"),
+    #[cfg(feature = "full")]
+    (GIT_AWARE, "false", "Record the git blob hash of files when tagging them"),
+    #[cfg(feature = "full")]
+    (RESPECT_GITIGNORE, "false", "Skip files a .gitignore ignores when looking through directories"),
+    #[cfg(feature = "full")]
+    (CONFIRM_ABOVE, "20", "Ask before one command changes more than this many files"),
+    #[cfg(feature = "full")]
+    (BACKUP_KEEP, "5", "Number of backups to keep, deleting the oldest"),
+];
+
+/// Look up the default value of a setting.
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting.
+fn default_value(key: &str) -> Result<&'static str, FtagError> {
+    match SETTINGS.iter().find(|(name, _, _)| *name == key) {
+        Some((_, default, _)) => Ok(default),
+        None => Err(FtagError::ConfigError(format!("Unknown setting {}", key))),
+    }
+}
+
+/// Parse the value of a true or false setting.
+///
+/// # Failure
+///
+/// Returns `Err` if `value` is neither true nor false.
+fn parse_bool(key: &str, value: &str) -> Result<bool, FtagError> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(FtagError::ConfigError(format!("Setting {} must be true or false, not {}", key, value))),
+    }
+}
+
+/// Return the current value of a setting, or its default if it was never changed.
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting, there is no database, or the database query fails.
+pub fn get_setting(key: &str) -> Result<String, FtagError> {
+    get_setting_in(&*store::open_store()?, key)
+}
+
+/// Return the current value of a setting in an open store, or its default if it was never changed.
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting or the store could not be read.
+pub fn get_setting_in(store: &dyn TagStore, key: &str) -> Result<String, FtagError> {
+    let default = default_value(key)?;
+    let value = store.get_setting(key)?;
+    Ok(value.unwrap_or_else(|| default.to_string()))
+}
+
+/// Return the current value of a true or false setting.
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting, its value is not true or false, there is no database, or the database query fails.
+pub fn get_bool_setting(key: &str) -> Result<bool, FtagError> {
+    get_bool_setting_in(&*store::open_store()?, key)
+}
+
+/// Return the current value of a true or false setting in an open store.
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting, its value is not true or false, or the store could not be read.
+pub fn get_bool_setting_in(store: &dyn TagStore, key: &str) -> Result<bool, FtagError> {
+    parse_bool(key, &get_setting_in(store, key)?)
+}
+
+/// Return the current value of a setting holding a number.
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting, its value is not a whole number, there is no database, or the database query fails.
+#[cfg(feature = "full")]
+pub fn get_number_setting(key: &str) -> Result<usize, FtagError> {
+    let value = get_setting(key)?;
+    value.parse().map_err(|_| FtagError::ConfigError(format!("Setting {} must be a whole number, not {}", key, value)))
+}
+
+/// Return how new tags should be validated in an open store.
+///
+/// # Failure
+///
+/// Returns `Err` if the stored value is invalid or the store could not be read.
+pub fn get_tag_validation_in(store: &dyn TagStore) -> Result<TagValidation, FtagError> {
+    let value = get_setting_in(store, TAG_VALIDATION)?;
+    TagValidation::from_name(&value)
+        .ok_or_else(|| FtagError::ConfigError(format!("Setting {} has unknown value {}", TAG_VALIDATION, value)))
+}
+
+/// Change the value of a setting, updating any stored data that depends on it.
+///
+/// * `key` - Name of the setting
+/// * `value` - New value for the setting
+///
+/// # Failure
+///
+/// Returns `Err` if `key` is not a known setting, `value` is invalid for it, there is no database, or errors occur when interacting with the database.
+#[cfg(feature = "full")]
+pub fn set_setting(key: &str, value: &str) -> Result<(), FtagError> {
+    default_value(key)?;
+
+    // Validate the value before storing it, and note whether existing tags need to be rewritten
+    let mut canonicalize = false;
+    if key == CASE_INSENSITIVE {
+        canonicalize = parse_bool(key, value)?;
+    }
+    if key == GIT_AWARE && parse_bool(key, value)? && store::current_backend()? != Backend::Sqlite {
+        return Err(FtagError::ConfigError(format!("Setting {} is only supported by the sqlite backend", key)));
+    }
+    if key == RESPECT_GITIGNORE {
+        parse_bool(key, value)?;
+    }
+    if (key == CONFIRM_ABOVE || key == BACKUP_KEEP) && value.parse::<usize>().is_err() {
+        return Err(FtagError::ConfigError(format!("Setting {} must be a whole number, not {}", key, value)));
+    }
+    if key == TAG_VALIDATION && TagValidation::from_name(value).is_none() {
+        return Err(FtagError::ConfigError(format!("Setting {} must be one of {}", key, TagValidation::NAMES.join(", "))));
+    }
+
+    store::open_store()?.set_setting(key, value)?;
+
+    if canonicalize {
+        ftag::canonicalize_all_tags()?;
+    }
+
+    Ok(())
+}
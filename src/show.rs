@@ -0,0 +1,62 @@
+use camino::Utf8Path;
+use std::collections::HashSet;
+
+use crate::ftag::FtagError;
+use crate::store::{self, Backend};
+use crate::{git, journal, note, paths, rating, settings};
+
+/// Everything ftag knows about a single path.
+#[derive(Debug, Default)]
+pub struct Details {
+    /// Tags of the path, or `None` if it isn't in the database
+    pub tags: Option<HashSet<String>>,
+    /// Rating of the path, if it has one
+    pub rating: Option<u8>,
+    /// Note attached to the path, if it has one
+    pub note: Option<String>,
+    /// Whether the path exists on disk
+    pub exists: bool,
+    /// When the path was first tagged, in seconds since the Unix epoch
+    pub first_tagged: Option<i64>,
+    /// When the tags of the path last changed, in seconds since the Unix epoch
+    pub last_changed: Option<i64>,
+    /// Git blob hash of the contents when the path was last tagged, if git-aware mode recorded one
+    pub blob: Option<String>,
+    /// Whether the contents differ from `blob` now, if that could be checked
+    pub changed: Option<bool>,
+}
+
+/// Gather everything known about `path`, which may have been deleted from disk since it was tagged.
+///
+/// Ratings, notes, times, and content hashes are only kept by the sqlite backend, and are left out for others.
+///
+/// # Failure
+///
+/// Returns `Err` if the path is neither on disk nor in the database, there is no database,
+/// or errors occur when interacting with the database or running git.
+pub fn details(path: &Utf8Path) -> Result<Details, FtagError> {
+    let stored = paths::normalize(path.as_str());
+    let store = store::open_store()?;
+    let mut details = Details { tags: store.get_tags(&stored)?, exists: path.exists(), ..Default::default() };
+    if details.tags.is_none() && !details.exists {
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+
+    if store::current_backend()? != Backend::Sqlite {
+        return Ok(details);
+    }
+
+    details.rating = rating::get_rating(&stored)?;
+    details.note = note::get_note(&stored)?;
+    if let Some((first, last)) = journal::path_times(&stored)? {
+        details.first_tagged = Some(first);
+        details.last_changed = Some(last);
+    }
+
+    details.blob = git::recorded_blob(&stored)?;
+    if details.blob.is_some() && details.exists && settings::get_bool_setting_in(&*store, settings::GIT_AWARE)? {
+        details.changed = Some(git::dirty_paths(&[&stored])?.contains(&stored));
+    }
+
+    Ok(details)
+}
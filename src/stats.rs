@@ -0,0 +1,75 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ftag::{self, FtagError};
+
+/// Summary of how tags are used across the database.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Number of tracked paths
+    pub files: usize,
+    /// Number of distinct tags
+    pub distinct_tags: usize,
+    /// Average number of tags on each path
+    pub average_tags: f64,
+    /// The most used tags with how many paths have them, most used first
+    pub top_tags: Vec<(String, u32)>,
+    /// The pairs of tags that most often appear on the same path with how many paths have both, most common first
+    pub top_pairs: Vec<((String, String), u32)>,
+}
+
+/// Sort counted items by descending count, breaking ties by the item itself, and keep the first `top`.
+fn most_common<T: Ord>(counts: HashMap<T, u32>, top: usize) -> Vec<(T, u32)> {
+    let mut sorted: Vec<(T, u32)> = counts.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    sorted.truncate(top);
+    sorted
+}
+
+/// Count how tags are used in `rows`.
+///
+/// * `rows` - Paths along with their tags, such as from `ftag::get_all_rows`
+/// * `top` - How many of the most used tags and tag pairs to keep
+pub fn compute_stats(rows: &[(String, HashSet<String>)], top: usize) -> Stats {
+    let mut tag_counts: HashMap<String, u32> = HashMap::new();
+    let mut pair_counts: HashMap<(String, String), u32> = HashMap::new();
+
+    for (_, tags) in rows {
+        // Sort the tags so each pair is only counted one way around
+        let mut tags: Vec<&String> = tags.iter().collect();
+        tags.sort();
+
+        for (i, tag) in tags.iter().enumerate() {
+            *tag_counts.entry((*tag).clone()).or_insert(0) += 1;
+            for other in &tags[i + 1..] {
+                *pair_counts.entry(((*tag).clone(), (*other).clone())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let total_tags: usize = rows.iter().map(|(_, tags)| tags.len()).sum();
+    let average_tags = match rows.len() {
+        0 => 0.0,
+        files => total_tags as f64 / files as f64,
+    };
+
+    Stats {
+        files: rows.len(),
+        distinct_tags: tag_counts.len(),
+        average_tags,
+        top_tags: most_common(tag_counts, top),
+        top_pairs: most_common(pair_counts, top),
+    }
+}
+
+/// Count how tags are used across the database, after removing paths that no longer exist.
+///
+/// * `top` - How many of the most used tags and tag pairs to keep
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when deserializing JSON or querying the database.
+pub fn get_stats(top: usize) -> Result<Stats, FtagError> {
+    // Prune first so removed paths don't count, the same as when listing tags
+    ftag::maybe_prune()?;
+    Ok(compute_stats(&ftag::get_all_rows()?, top))
+}
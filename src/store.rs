@@ -0,0 +1,1136 @@
+use camino::Utf8Path;
+#[cfg(feature = "full")]
+use camino::Utf8PathBuf;
+use rusqlite::{params, Connection, OptionalExtension};
+#[cfg(feature = "full")]
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashSet;
+#[cfg(feature = "full")]
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+#[cfg(feature = "full")]
+use std::fs;
+use std::io;
+use std::path::{self, PathBuf};
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::ftag::{compare_paths, get_db_path, get_store_dir_path, get_text_path, parse_tags, serialize_tags, FtagError};
+use crate::{journal, schema};
+
+/// Storage for the tags of each path, along with the database settings.
+///
+/// Paths are stored exactly as they were given, and a path may be tracked with an empty set of tags.
+pub trait TagStore {
+    /// Return the tags of `path`, or `None` if it isn't tracked.
+    fn get_tags(&self, path: &str) -> Result<Option<HashSet<String>>, FtagError>;
+
+    /// Replace the tags of `path`, starting to track it if needed.
+    fn set_tags(&mut self, path: &str, tags: &HashSet<String>) -> Result<(), FtagError>;
+
+    /// Stop tracking `path`. Does nothing if it isn't tracked.
+    fn remove_path(&mut self, path: &str) -> Result<(), FtagError>;
+
+    /// Return every tracked path along with its tags.
+    fn all_rows(&self) -> Result<Vec<(String, HashSet<String>)>, FtagError>;
+
+    /// Return the stored value of a setting, or `None` if it was never changed.
+    fn get_setting(&self, key: &str) -> Result<Option<String>, FtagError>;
+
+    /// Store the value of a setting.
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), FtagError>;
+
+    /// Start a transaction, so no changes to tags are saved until `commit` is called.
+    fn begin(&mut self) -> Result<(), FtagError>;
+
+    /// Save every change to tags made since `begin`, all at once.
+    fn commit(&mut self) -> Result<(), FtagError>;
+
+    /// Throw away every change to tags made since `begin`.
+    fn rollback(&mut self) -> Result<(), FtagError>;
+}
+
+impl dyn TagStore + '_ {
+    /// Make many changes in a single transaction, like `store.batch(|tx| ftag::add_tags_in(tx, &path, tags))`.
+    ///
+    /// Everything `changes` does is saved at once if it returns `Ok`, and thrown away if it returns `Err`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if `changes` does, or the transaction could not be started or saved.
+    pub fn batch<T>(&mut self, changes: impl FnOnce(&mut dyn TagStore) -> Result<T, FtagError>) -> Result<T, FtagError> {
+        self.begin()?;
+        match changes(self) {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            },
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            },
+        }
+    }
+
+    /// Try out changes in a transaction and throw them away, returning whatever `changes` returned.
+    ///
+    /// This lets `--dry-run` report exactly what a command would do, without saving any of it.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if `changes` does, or the transaction could not be started or thrown away.
+    pub fn dry_run<T>(&mut self, changes: impl FnOnce(&mut dyn TagStore) -> Result<T, FtagError>) -> Result<T, FtagError> {
+        self.begin()?;
+        let result = changes(self);
+        self.rollback()?;
+        result
+    }
+}
+
+/// Ways a database can be stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Backend {
+    /// A single SQLite file, `.ftag.db`
+    Sqlite,
+    /// A `.ftag` directory of small JSON files, which is easy to diff and track with git
+    #[cfg(feature = "full")]
+    Json,
+    /// A single `.ftag.tsv` text file with one line per path, sorted so git can merge it
+    #[cfg(feature = "full")]
+    Text,
+}
+
+/// Create an empty database using `backend` in the current directory.
+///
+/// # Failure
+///
+/// Returns `Err` if a database of either kind already exists in the current directory, or it could not be created.
+pub fn init_store(backend: Backend) -> Result<(), FtagError> {
+    // Refuse to init if a database already exists
+    if get_db_path().exists() || get_store_dir_path().exists() || get_text_path().exists() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    match backend {
+        Backend::Sqlite => SqliteStore::create(&get_db_path()),
+        #[cfg(feature = "full")]
+        Backend::Json => JsonDirStore::create(&get_store_dir_path()),
+        #[cfg(feature = "full")]
+        Backend::Text => TextStore::create(&get_text_path()),
+    }
+}
+
+/// Return which backend the database in the current directory uses.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database in the current directory.
+pub fn current_backend() -> Result<Backend, FtagError> {
+    if get_db_path().exists() {
+        Ok(Backend::Sqlite)
+    } else if get_store_dir_path().is_dir() {
+        #[cfg(feature = "full")]
+        return Ok(Backend::Json);
+        #[cfg(not(feature = "full"))]
+        return Err(FtagError::ConfigError("The json backend is not supported by this build".to_string()));
+    } else if get_text_path().is_file() {
+        #[cfg(feature = "full")]
+        return Ok(Backend::Text);
+        #[cfg(not(feature = "full"))]
+        return Err(FtagError::ConfigError("The text backend is not supported by this build".to_string()));
+    } else {
+        Err(FtagError::NoDatabaseError)
+    }
+}
+
+/// Open the database in the current directory, whichever backend it uses.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database in the current directory or it could not be opened.
+pub fn open_store() -> Result<Box<dyn TagStore>, FtagError> {
+    match current_backend()? {
+        Backend::Sqlite => Ok(Box::new(SqliteStore::open(&get_db_path())?)),
+        #[cfg(feature = "full")]
+        Backend::Json => Ok(Box::new(JsonDirStore::open(&get_store_dir_path())?)),
+        #[cfg(feature = "full")]
+        Backend::Text => Ok(Box::new(TextStore::open(&get_text_path())?)),
+    }
+}
+
+/// How long to wait for another invocation to finish writing before giving up, set once by `set_busy_timeout`.
+static BUSY_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// How long to wait for another invocation when `set_busy_timeout` was never called.
+const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Set how long every connection opened from now on waits when the database is locked by another invocation.
+pub fn set_busy_timeout(timeout: Duration) {
+    // Only the first call counts, the same as the sandbox restrictions
+    let _ = BUSY_TIMEOUT.set(timeout);
+}
+
+/// How many prepared statements each connection keeps around for `prepare_cached`.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// A connection to a SQLite database, shared by everything using that database until `close_connections`.
+///
+/// Statements prepared with `prepare_cached` stay compiled along with it, so a statement run once per path is only
+/// compiled once per invocation. Every user of a database shares its transactions as well, so changes made
+/// during `TagStore::batch` are all saved or thrown away together.
+pub type Handle = Rc<Connection>;
+
+thread_local! {
+    /// Connections opened so far, by the absolute path of their database.
+    static CONNECTIONS: RefCell<Vec<(PathBuf, Handle)>> = const { RefCell::new(vec![]) };
+}
+
+/// Name of the collation ordering paths like `sort_files` does, which unlike `NOCASE` ignores case outside ASCII too.
+pub(crate) const PATH_COLLATION: &str = "path_order";
+
+/// Open a new connection to a SQLite database file, waiting out other invocations instead of failing right away.
+///
+/// The database is switched to WAL journaling, so reading it never blocks on a writer.
+/// The mode is saved in the file, so this only has to write anything the first time.
+///
+/// Most code should use `connect`, which reuses the connection already open. A separate one is only needed for
+/// things SQLite won't do while the database is shared, like restoring a backup into it.
+///
+/// # Failure
+///
+/// Returns `Err` if the database could not be opened or configured.
+pub(crate) fn open_connection(path: &Utf8Path) -> Result<Connection, FtagError> {
+    tracing::debug!("Opening {}", path);
+    let mut conn = Connection::open(path)?;
+    if tracing::enabled!(tracing::Level::TRACE) {
+        conn.trace(Some(log_sql));
+    }
+    conn.busy_timeout(*BUSY_TIMEOUT.get().unwrap_or(&DEFAULT_BUSY_TIMEOUT))?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    conn.create_collation(PATH_COLLATION, compare_paths)?;
+
+    let mode: String = conn.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+    if !mode.eq_ignore_ascii_case("wal") {
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    }
+
+    Ok(conn)
+}
+
+/// Return the connection to a SQLite database file, opening it the first time it is needed.
+///
+/// # Failure
+///
+/// Returns `Err` if the database could not be opened or configured.
+pub(crate) fn connect(path: &Utf8Path) -> Result<Handle, FtagError> {
+    // Commands like `index rebuild` move between databases, so a relative path isn't enough to tell them apart
+    let key = path::absolute(path)?;
+    if let Some(conn) = CONNECTIONS.with_borrow(|open| open.iter().find(|(open_path, _)| *open_path == key).map(|(_, conn)| conn.clone())) {
+        return Ok(conn);
+    }
+
+    let conn = Rc::new(open_connection(path)?);
+    CONNECTIONS.with_borrow_mut(|open| open.push((key, conn.clone())));
+    Ok(conn)
+}
+
+/// Close every connection opened by `connect`, so SQLite can tidy up its write-ahead log before ftag exits.
+pub fn close_connections() {
+    let open = CONNECTIONS.with_borrow_mut(std::mem::take);
+    for (path, conn) in open {
+        tracing::debug!("Closing {}", path.display());
+        // Anything still using the connection keeps it open, which SQLite cleans up after anyway
+        if let Ok(conn) = Rc::try_unwrap(conn) {
+            if let Err((_, err)) = conn.close() {
+                tracing::warn!("Could not close {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Log each SQL statement as it runs, with its parameters filled in.
+fn log_sql(sql: &str) {
+    tracing::trace!("SQL: {}", sql);
+}
+
+/// Open a connection to the SQLite database, for features that only the SQLite backend supports.
+///
+/// * `feature` - Name of the feature, used to explain why other backends are refused
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database, the database uses a different backend, it could not be opened,
+/// or it needs to be migrated first.
+#[cfg_attr(not(feature = "full"), allow(unused_variables))]
+pub(crate) fn open_sqlite(feature: &str) -> Result<Handle, FtagError> {
+    match current_backend()? {
+        Backend::Sqlite => {
+            let conn = connect(&get_db_path())?;
+            schema::check_version(&conn)?;
+            Ok(conn)
+        },
+        #[cfg(feature = "full")]
+        _ => Err(FtagError::ConfigError(format!("{} is only supported by the sqlite backend", feature))),
+    }
+}
+
+/// Query reading every row of the `tags` table, which `find` and most other commands start from.
+pub(crate) const ALL_ROWS_SQL: &str = "SELECT path, tags FROM tags;";
+
+/// Tags stored in the `tags` table of a SQLite database, with every change recorded in the journal.
+pub struct SqliteStore {
+    conn: Handle,
+}
+
+impl SqliteStore {
+    /// Create a new database file at `path`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the database or its tables could not be created.
+    fn create(path: &Utf8Path) -> Result<(), FtagError> {
+        let conn = connect(path)?;
+        conn.execute(
+            "CREATE TABLE tags (
+                id          INTEGER PRIMARY KEY,
+                path        TEXT NOT NULL,
+                tags        TEXT,
+                created     INTEGER,
+                modified    INTEGER
+            )",
+            (),
+        )?;
+        conn.execute(schema::TAGS_PATH_INDEX, ())?;
+        conn.execute_batch(schema::FILE_TAGS_SQL)?;
+        conn.execute_batch(schema::TAG_META_SQL)?;
+        journal::ensure_journal(&conn)?;
+        journal::create_tag_times(&conn)?;
+        ensure_settings(&conn)?;
+        schema::set_version(&conn, schema::SCHEMA_VERSION)?;
+
+        Ok(())
+    }
+
+    /// Open the existing database file at `path`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the database could not be opened or needs to be migrated first.
+    pub fn open(path: &Utf8Path) -> Result<Self, FtagError> {
+        let conn = connect(path)?;
+        schema::check_version(&conn)?;
+        Ok(SqliteStore { conn })
+    }
+
+    /// Return the last change to every path the database knows of, which may be removing it from the database.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if errors occur when deserializing JSON or querying the database.
+    #[cfg(feature = "full")]
+    pub fn last_changes(&self) -> Result<Vec<journal::Change>, FtagError> {
+        journal::ensure_journal(&self.conn)?;
+        let mut changes = BTreeMap::new();
+
+        // Paths that are gone are only left in the journal, while the rest keep their own times
+        let mut stmt = self.conn.prepare("SELECT path, MAX(time) FROM journal WHERE tags IS NULL GROUP BY path")?;
+        for row in stmt.query_map((), |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))? {
+            let (path, time) = row?;
+            changes.insert(path, (time, None));
+        }
+
+        let mut stmt = self.conn.prepare("SELECT path, tags, modified FROM tags")?;
+        let rows: Vec<(String, String, Option<i64>)> = stmt
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        for (path, json, modified) in rows {
+            changes.insert(path, (modified.unwrap_or(0), Some(parse_tags(&json)?)));
+        }
+
+        Ok(changes.into_iter().map(|(path, (time, tags))| (time, path, tags)).collect())
+    }
+}
+
+/// Create the `settings` table if this database predates it.
+pub(crate) fn ensure_settings(conn: &Connection) -> Result<(), FtagError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key     TEXT PRIMARY KEY,
+            value   TEXT NOT NULL
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+impl TagStore for SqliteStore {
+    fn get_tags(&self, path: &str) -> Result<Option<HashSet<String>>, FtagError> {
+        let json: Option<String> = self
+            .conn
+            .prepare_cached("SELECT tags FROM tags WHERE path = ?")?
+            .query_row(params![path], |row| row.get(0))
+            .optional()?;
+
+        match json {
+            Some(json) => Ok(Some(parse_tags(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_tags(&mut self, path: &str, tags: &HashSet<String>) -> Result<(), FtagError> {
+        let serialized = serialize_tags(tags)?;
+
+        // Insert a new row, or update the one the path already has
+        self.conn
+            .prepare_cached(
+                "INSERT INTO tags(path, tags, created, modified) VALUES (?1, ?2, ?3, ?3)
+                ON CONFLICT(path) DO UPDATE SET tags = excluded.tags, modified = excluded.modified",
+            )?
+            .execute(params![path, serialized, journal::now()])?;
+
+        // Remember the change, so the tags can be looked up as they were at this moment
+        journal::record_tag_times(&self.conn, path, tags)?;
+        journal::record(&self.conn, path, Some(&serialized))
+    }
+
+    fn remove_path(&mut self, path: &str) -> Result<(), FtagError> {
+        if self.conn.prepare_cached("DELETE FROM tags WHERE path = ?")?.execute(params![path])? > 0 {
+            journal::record_tag_times(&self.conn, path, &HashSet::new())?;
+            journal::record(&self.conn, path, None)?;
+        }
+        Ok(())
+    }
+
+    fn all_rows(&self) -> Result<Vec<(String, HashSet<String>)>, FtagError> {
+        let mut stmt = self.conn.prepare_cached(ALL_ROWS_SQL)?;
+        let rows: Vec<(String, String)> = stmt
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        // Deserialize outside of the query, so bad JSON is an error instead of a panic
+        let mut all_rows = vec![];
+        for (path, json) in rows {
+            let tags = parse_tags(&json);
+            #[cfg(feature = "full")]
+            let tags = tags.map_err(|err| FtagError::ConfigError(format!("The tags of {} can't be read ({}), run ftag repair to fix them", path, err)));
+            all_rows.push((path, tags?));
+        }
+
+        Ok(all_rows)
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>, FtagError> {
+        ensure_settings(&self.conn)?;
+        let value = self
+            .conn
+            .prepare_cached("SELECT value FROM settings WHERE key = ?")?
+            .query_row(params![key], |row| row.get(0))
+            .optional()?;
+        Ok(value)
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), FtagError> {
+        ensure_settings(&self.conn)?;
+        self.conn.prepare_cached("INSERT OR REPLACE INTO settings(key, value) VALUES (?, ?)")?.execute(params![key, value])?;
+        Ok(())
+    }
+
+    fn begin(&mut self) -> Result<(), FtagError> {
+        // Make sure every table exists first, so nothing needs creating partway through
+        ensure_settings(&self.conn)?;
+        journal::ensure_journal(&self.conn)?;
+
+        // Take the write lock straight away, so the transaction can't fail later because another writer got in first
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), FtagError> {
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), FtagError> {
+        self.conn.execute_batch("ROLLBACK")?;
+        Ok(())
+    }
+}
+
+/// Longest name of an entry in a [`JsonDirStore`] before `.json`, leaving room under the usual limit of 255 bytes for
+/// the extension of temporary files.
+#[cfg(feature = "full")]
+const MAX_ENTRY_NAME: usize = 200;
+
+/// 64-bit FNV-1a hash of `bytes`, which unlike the hashers in std is the same on every platform and release.
+#[cfg(feature = "full")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Contents of one file in a [`JsonDirStore`].
+#[cfg(feature = "full")]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PathEntry {
+    path: String,
+    tags: BTreeSet<String>,
+}
+
+/// Tags stored as a directory of pretty-printed JSON files, one per tracked path, so changes show up clearly in diffs.
+///
+/// ```text
+/// .ftag/
+///   settings.json
+///   paths/
+///     photos%2Fexample.jpg.json
+/// ```
+///
+/// During a transaction, changes are kept in memory and only written out by `commit`.
+#[cfg(feature = "full")]
+pub struct JsonDirStore {
+    dir: Utf8PathBuf,
+    /// Changes made during a transaction, with `None` for removed paths
+    pending: Option<HashMap<String, Option<HashSet<String>>>>,
+}
+
+#[cfg(feature = "full")]
+impl JsonDirStore {
+    /// Create a new, empty store directory at `dir`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the directory could not be created.
+    fn create(dir: &Utf8Path) -> Result<(), FtagError> {
+        fs::create_dir_all(dir.join("paths"))?;
+        Ok(())
+    }
+
+    /// Open the existing store directory at `dir`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if `dir` is not a store directory.
+    pub fn open(dir: &Utf8Path) -> Result<Self, FtagError> {
+        if !dir.join("paths").is_dir() {
+            return Err(FtagError::NoDatabaseError);
+        }
+        Ok(JsonDirStore { dir: dir.to_path_buf(), pending: None })
+    }
+
+    /// Name of the file holding the tags of `path`, with anything but plain characters percent-encoded.
+    ///
+    /// Names too long for the filesystem are cut short and end with `~` and a hash of the whole path instead, which
+    /// can't clash with an encoded name since `~` is always encoded. The full path is still kept inside the file.
+    fn entry_path(&self, path: &str) -> Utf8PathBuf {
+        let mut name = String::with_capacity(path.len() + 5);
+        for byte in path.bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => name.push(byte as char),
+                // Dots are only escaped at the start, so names can't be `.` or `..` or hidden
+                b'.' if !name.is_empty() => name.push('.'),
+                _ => name.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        if name.len() > MAX_ENTRY_NAME {
+            // Don't leave half of an escape behind, so the start of the name still reads as the path
+            let mut end = MAX_ENTRY_NAME - 17;
+            while name[..end].ends_with('%') || name[..end - 1].ends_with('%') {
+                end -= 1;
+            }
+            name = format!("{}~{:016x}", &name[..end], fnv1a(path.as_bytes()));
+        }
+        name.push_str(".json");
+        self.dir.join("paths").join(name)
+    }
+
+    /// Read a JSON file, returning `None` if it doesn't exist.
+    fn read_json<T: for<'de> Deserialize<'de>>(path: &Utf8Path) -> Result<Option<T>, FtagError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(FtagError::IoError(err)),
+        }
+    }
+
+    /// Write a value as pretty-printed JSON, replacing the file all at once so it is never left half written.
+    fn write_json<T: Serialize>(path: &Utf8Path, value: &T) -> Result<(), FtagError> {
+        let mut text = serde_json::to_string_pretty(value)?;
+        text.push('\n');
+
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, text)?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    /// Write the tags of `path` to its file.
+    fn write_entry(&self, path: &str, tags: &HashSet<String>) -> Result<(), FtagError> {
+        // Sort the tags so the file only changes when the tags do
+        let entry = PathEntry { path: path.to_string(), tags: tags.iter().cloned().collect() };
+        Self::write_json(&self.entry_path(path), &entry)
+    }
+
+    /// Delete the file of `path`, if there is one.
+    fn remove_entry(&self, path: &str) -> Result<(), FtagError> {
+        match fs::remove_file(self.entry_path(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(FtagError::IoError(err)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read every stored setting.
+    fn settings(&self) -> Result<BTreeMap<String, String>, FtagError> {
+        Ok(Self::read_json(&self.dir.join("settings.json"))?.unwrap_or_default())
+    }
+}
+
+#[cfg(feature = "full")]
+impl TagStore for JsonDirStore {
+    fn get_tags(&self, path: &str) -> Result<Option<HashSet<String>>, FtagError> {
+        if let Some(change) = self.pending.as_ref().and_then(|pending| pending.get(path)) {
+            return Ok(change.clone());
+        }
+
+        // A shortened name could in theory belong to another path with the same hash
+        let entry: Option<PathEntry> = Self::read_json(&self.entry_path(path))?;
+        Ok(entry.filter(|entry| entry.path == path).map(|entry| entry.tags.into_iter().collect()))
+    }
+
+    fn set_tags(&mut self, path: &str, tags: &HashSet<String>) -> Result<(), FtagError> {
+        match &mut self.pending {
+            Some(pending) => {
+                pending.insert(path.to_string(), Some(tags.clone()));
+                Ok(())
+            },
+            None => self.write_entry(path, tags),
+        }
+    }
+
+    fn remove_path(&mut self, path: &str) -> Result<(), FtagError> {
+        match &mut self.pending {
+            Some(pending) => {
+                pending.insert(path.to_string(), None);
+                Ok(())
+            },
+            None => self.remove_entry(path),
+        }
+    }
+
+    fn all_rows(&self) -> Result<Vec<(String, HashSet<String>)>, FtagError> {
+        let mut all_rows = vec![];
+        for file in fs::read_dir(self.dir.join("paths"))? {
+            let file = file?;
+
+            // Skip anything that isn't an entry, like leftover temporary files
+            if file.path().extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            let text = fs::read_to_string(file.path())?;
+            let entry: PathEntry = serde_json::from_str(&text).map_err(|err| {
+                FtagError::ConfigError(format!("{} can't be read ({}), run ftag repair to fix it", file.path().display(), err))
+            })?;
+            all_rows.push((entry.path, entry.tags.into_iter().collect()));
+        }
+
+        // Swap in anything changed during a transaction
+        if let Some(pending) = &self.pending {
+            all_rows.retain(|(path, _)| !pending.contains_key(path));
+            for (path, change) in pending {
+                if let Some(tags) = change {
+                    all_rows.push((path.clone(), tags.clone()));
+                }
+            }
+        }
+
+        Ok(all_rows)
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>, FtagError> {
+        Ok(self.settings()?.remove(key))
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), FtagError> {
+        let mut settings = self.settings()?;
+        settings.insert(key.to_string(), value.to_string());
+        Self::write_json(&self.dir.join("settings.json"), &settings)
+    }
+
+    fn begin(&mut self) -> Result<(), FtagError> {
+        self.pending = Some(HashMap::new());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), FtagError> {
+        for (path, change) in self.pending.take().unwrap_or_default() {
+            match change {
+                Some(tags) => self.write_entry(&path, &tags)?,
+                None => self.remove_entry(&path)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), FtagError> {
+        self.pending = None;
+        Ok(())
+    }
+}
+
+/// First line of every text store, so anyone opening it knows what it is.
+#[cfg(feature = "full")]
+const TEXT_HEADER: &str = "# ftag tags: each line is a path followed by its tags, separated by tabs";
+
+/// Start of the lines holding settings in a text store.
+#[cfg(feature = "full")]
+const TEXT_SETTING: &str = "#set\t";
+
+/// One line of a text store.
+#[cfg(feature = "full")]
+#[derive(Debug, PartialEq, Eq)]
+enum TextLine {
+    /// A path along with its tags
+    Row(String, BTreeSet<String>),
+    /// A setting along with its value
+    Setting(String, String),
+}
+
+/// Escape a field of a text store, so tabs and newlines inside it can't split the line.
+#[cfg(feature = "full")]
+fn escape_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    // A path starting with # would look like a comment
+    if escaped.starts_with('#') {
+        escaped.insert(0, '\\');
+    }
+    escaped
+}
+
+/// Undo `escape_field`.
+///
+/// # Failure
+///
+/// Returns `Err` if a backslash is followed by anything that isn't an escape.
+#[cfg(feature = "full")]
+fn unescape_field(field: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('#') => unescaped.push('#'),
+            Some(other) => return Err(format!("unknown escape \\{}", other)),
+            None => return Err("a backslash ends the line".to_string()),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Parse one line of a text store, returning `None` for blank lines and comments.
+///
+/// # Failure
+///
+/// Returns `Err` if the line has a bad escape, an empty path or tag, or is a setting without a value.
+#[cfg(feature = "full")]
+fn parse_text_line(line: &str) -> Result<Option<TextLine>, String> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if let Some(setting) = line.strip_prefix(TEXT_SETTING) {
+        let (key, value) = setting.split_once('\t').ok_or("the setting has no value")?;
+        return Ok(Some(TextLine::Setting(unescape_field(key)?, unescape_field(value)?)));
+    }
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut fields = line.split('\t');
+    let path = unescape_field(fields.next().unwrap_or_default())?;
+    let tags = fields.map(unescape_field).collect::<Result<BTreeSet<_>, _>>()?;
+    if path.is_empty() || tags.contains("") {
+        return Err("the path or a tag is empty".to_string());
+    }
+    Ok(Some(TextLine::Row(path, tags)))
+}
+
+/// Write the line of a text store holding a path and its tags, the reverse of `parse_text_line`.
+#[cfg(feature = "full")]
+fn format_text_row(path: &str, tags: &BTreeSet<String>) -> String {
+    let mut line = escape_field(path);
+    for tag in tags {
+        line.push('\t');
+        line.push_str(&escape_field(tag));
+    }
+    line
+}
+
+/// Tags stored in a single text file with one line per path, sorted by path with sorted tags.
+///
+/// ```text
+/// # ftag tags: each line is a path followed by its tags, separated by tabs
+/// #set    case-insensitive    true
+/// photos/example.jpg    holiday    year=2024
+/// ```
+///
+/// Since the same tags always give the same file, changes made on two machines usually merge cleanly in git.
+/// The whole file is read when opened, and written out again after every change or transaction.
+#[cfg(feature = "full")]
+pub struct TextStore {
+    file: Utf8PathBuf,
+    rows: BTreeMap<String, BTreeSet<String>>,
+    settings: BTreeMap<String, String>,
+    /// Rows as they were when a transaction began, to go back to if it is thrown away
+    saved: Option<BTreeMap<String, BTreeSet<String>>>,
+}
+
+#[cfg(feature = "full")]
+impl TextStore {
+    /// Create a new, empty text store at `file`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the file could not be written.
+    fn create(file: &Utf8Path) -> Result<(), FtagError> {
+        TextStore { file: file.to_path_buf(), rows: BTreeMap::new(), settings: BTreeMap::new(), saved: None }.write()
+    }
+
+    /// Read the existing text store at `file`.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if the file could not be read or has a line that can't be parsed.
+    pub fn open(file: &Utf8Path) -> Result<Self, FtagError> {
+        let mut store = TextStore { file: file.to_path_buf(), rows: BTreeMap::new(), settings: BTreeMap::new(), saved: None };
+        for (number, line) in fs::read_to_string(file)?.lines().enumerate() {
+            match parse_text_line(line) {
+                Ok(Some(TextLine::Row(path, tags))) => {
+                    store.rows.insert(path, tags);
+                },
+                Ok(Some(TextLine::Setting(key, value))) => {
+                    store.settings.insert(key, value);
+                },
+                Ok(None) => {},
+                Err(err) => {
+                    return Err(FtagError::ConfigError(format!("{} line {} can't be read ({}), run ftag repair to fix it", file, number + 1, err)));
+                },
+            }
+        }
+        Ok(store)
+    }
+
+    /// Write the whole store out, replacing the file all at once so it is never left half written.
+    ///
+    /// Rows changed during a transaction are left out until it is committed.
+    fn write(&self) -> Result<(), FtagError> {
+        let mut text = format!("{}\n", TEXT_HEADER);
+        for (key, value) in &self.settings {
+            text.push_str(&format!("{}{}\t{}\n", TEXT_SETTING, escape_field(key), escape_field(value)));
+        }
+        for (path, tags) in self.saved.as_ref().unwrap_or(&self.rows) {
+            text.push_str(&format_text_row(path, tags));
+            text.push('\n');
+        }
+
+        let temp = self.file.with_extension("tsv.tmp");
+        fs::write(&temp, text)?;
+        fs::rename(&temp, &self.file)?;
+        Ok(())
+    }
+
+    /// Write the store out, unless a transaction will do it later.
+    fn changed(&self) -> Result<(), FtagError> {
+        match self.saved {
+            Some(_) => Ok(()),
+            None => self.write(),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl TagStore for TextStore {
+    fn get_tags(&self, path: &str) -> Result<Option<HashSet<String>>, FtagError> {
+        Ok(self.rows.get(path).map(|tags| tags.iter().cloned().collect()))
+    }
+
+    fn set_tags(&mut self, path: &str, tags: &HashSet<String>) -> Result<(), FtagError> {
+        self.rows.insert(path.to_string(), tags.iter().cloned().collect());
+        self.changed()
+    }
+
+    fn remove_path(&mut self, path: &str) -> Result<(), FtagError> {
+        if self.rows.remove(path).is_some() {
+            self.changed()?;
+        }
+        Ok(())
+    }
+
+    fn all_rows(&self) -> Result<Vec<(String, HashSet<String>)>, FtagError> {
+        Ok(self.rows.iter().map(|(path, tags)| (path.clone(), tags.iter().cloned().collect())).collect())
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>, FtagError> {
+        Ok(self.settings.get(key).cloned())
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), FtagError> {
+        // Settings are saved straight away, the same as with the other backends
+        self.settings.insert(key.to_string(), value.to_string());
+        self.write()
+    }
+
+    fn begin(&mut self) -> Result<(), FtagError> {
+        self.saved = Some(self.rows.clone());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), FtagError> {
+        self.saved = None;
+        self.write()
+    }
+
+    fn rollback(&mut self) -> Result<(), FtagError> {
+        if let Some(saved) = self.saved.take() {
+            self.rows = saved;
+        }
+        Ok(())
+    }
+}
+
+/// Where a row of the database is kept.
+#[cfg(feature = "full")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowLocation {
+    /// A row of the `tags` table, by id
+    Row(i64),
+    /// A file in the directory of a [`JsonDirStore`]
+    File(Utf8PathBuf),
+    /// A line of a [`TextStore`], counting from 1
+    Line(usize),
+}
+
+#[cfg(feature = "full")]
+impl std::fmt::Display for RowLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowLocation::Row(id) => write!(f, "row {}", id),
+            RowLocation::File(file) => write!(f, "{}", file),
+            RowLocation::Line(number) => write!(f, "line {}", number),
+        }
+    }
+}
+
+/// A row of the database exactly as it is kept, which may be damaged.
+#[cfg(feature = "full")]
+#[derive(Debug)]
+pub struct RawRow {
+    /// Where the row is kept
+    pub location: RowLocation,
+    /// Path the row is for, or `None` if it could not be read
+    pub path: Option<String>,
+    /// Tags of the row, or why they could not be read
+    pub tags: Result<HashSet<String>, String>,
+    /// Text the row is stored as, so damaged rows can be salvaged
+    pub text: Option<String>,
+}
+
+/// Read every row of the database without giving up at damaged ones, in the order they are kept.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or it could not be read at all.
+#[cfg(feature = "full")]
+pub fn raw_rows() -> Result<Vec<RawRow>, FtagError> {
+    let mut rows = vec![];
+    match current_backend()? {
+        Backend::Sqlite => {
+            let conn = open_sqlite("Reading raw rows")?;
+            let mut stmt = conn.prepare("SELECT id, path, tags FROM tags ORDER BY id")?;
+            let mut query = stmt.query(())?;
+            while let Some(row) = query.next()? {
+                let json: Option<String> = row.get(2)?;
+                let tags = match &json {
+                    Some(json) => parse_tags(json).map_err(|err| err.to_string()),
+                    None => Err("the tags are missing".to_string()),
+                };
+                rows.push(RawRow { location: RowLocation::Row(row.get(0)?), path: Some(row.get(1)?), tags, text: json });
+            }
+        },
+        Backend::Json => {
+            let mut files: Vec<Utf8PathBuf> = vec![];
+            for file in get_store_dir_path().join("paths").read_dir_utf8()? {
+                let file = file?.into_path();
+                if file.extension() == Some("json") {
+                    files.push(file);
+                }
+            }
+            files.sort();
+
+            for file in files {
+                let text = fs::read_to_string(&file)?;
+                let (path, tags) = match serde_json::from_str::<PathEntry>(&text) {
+                    Ok(entry) => (Some(entry.path), Ok(entry.tags.into_iter().collect())),
+                    Err(err) => (None, Err(err.to_string())),
+                };
+                rows.push(RawRow { location: RowLocation::File(file), path, tags, text: Some(text) });
+            }
+        },
+        Backend::Text => {
+            for (number, line) in fs::read_to_string(get_text_path())?.lines().enumerate() {
+                let location = RowLocation::Line(number + 1);
+                match parse_text_line(line) {
+                    Ok(Some(TextLine::Row(path, tags))) => {
+                        rows.push(RawRow { location, path: Some(path), tags: Ok(tags.into_iter().collect()), text: Some(line.to_string()) });
+                    },
+                    Ok(_) => {},
+                    Err(err) => {
+                        // The path may still be readable even if a tag isn't
+                        let path = line.split('\t').next().and_then(|path| unescape_field(path).ok()).filter(|path| !path.is_empty());
+                        rows.push(RawRow { location, path, tags: Err(err), text: Some(line.to_string()) });
+                    },
+                }
+            }
+        },
+    }
+
+    Ok(rows)
+}
+
+/// Fix damaged rows in one go: drop the rows at `drop`, then give each path in `changes` its tags, or remove it.
+///
+/// Paths are changed like any other change to tags, so the journal keeps track of them.
+///
+/// # Failure
+///
+/// Returns `Err` if there is no database or errors occur when changing it. A SQLite database is left as it was then.
+#[cfg(feature = "full")]
+pub fn repair_rows(drop: &[RowLocation], changes: &[(String, Option<HashSet<String>>)]) -> Result<(), FtagError> {
+    let apply = |store: &mut dyn TagStore| {
+        for (path, tags) in changes {
+            match tags {
+                Some(tags) => store.set_tags(path, tags)?,
+                None => store.remove_path(path)?,
+            }
+        }
+        Ok(())
+    };
+
+    match current_backend()? {
+        Backend::Sqlite => {
+            let mut store = SqliteStore::open(&get_db_path())?;
+            store.begin()?;
+            let dropped = drop.iter().try_for_each(|location| match location {
+                RowLocation::Row(id) => store.conn.execute("DELETE FROM tags WHERE id = ?", params![id]).map(|_| ()),
+                RowLocation::File(_) | RowLocation::Line(_) => Ok(()),
+            });
+            match dropped.map_err(FtagError::from).and_then(|_| apply(&mut store)) {
+                Ok(()) => store.commit(),
+                Err(err) => {
+                    store.rollback()?;
+                    Err(err)
+                },
+            }
+        },
+        Backend::Json => {
+            for location in drop {
+                if let RowLocation::File(file) = location {
+                    fs::remove_file(file)?;
+                }
+            }
+            apply(&mut JsonDirStore::open(&get_store_dir_path())?)
+        },
+        Backend::Text => {
+            // Leave out the dropped lines, then read what is left as a store to change
+            let text = fs::read_to_string(get_text_path())?;
+            let kept: Vec<&str> = text
+                .lines()
+                .enumerate()
+                .filter(|(number, _)| !drop.contains(&RowLocation::Line(number + 1)))
+                .map(|(_, line)| line)
+                .collect();
+            fs::write(get_text_path(), kept.join("\n") + "\n")?;
+
+            let mut store = TextStore::open(&get_text_path())?;
+            store.begin()?;
+            match apply(&mut store) {
+                Ok(()) => store.commit(),
+                Err(err) => {
+                    store.rollback()?;
+                    Err(err)
+                },
+            }
+        },
+    }
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn store() -> JsonDirStore {
+        JsonDirStore { dir: Utf8PathBuf::from(".ftag"), pending: None }
+    }
+
+    proptest! {
+        #[test]
+        fn entry_names_stay_in_directory(path in any::<String>()) {
+            // Every stored path names a file, so it is never empty
+            prop_assume!(!path.is_empty());
+            let entry = store().entry_path(&path);
+            let name = entry.file_name().unwrap();
+            prop_assert_eq!(entry.parent().unwrap(), Utf8Path::new(".ftag/paths"));
+            prop_assert!(!name.starts_with('.'));
+            prop_assert!(!name.contains('/') && !name.contains('\\'));
+            prop_assert!(name.len() <= MAX_ENTRY_NAME + 5);
+        }
+
+        #[test]
+        fn entry_names_are_distinct(a in any::<String>(), b in any::<String>()) {
+            prop_assume!(a != b);
+            prop_assert_ne!(store().entry_path(&a), store().entry_path(&b));
+        }
+
+        #[test]
+        fn never_panics(line in any::<String>()) {
+            let _ = parse_text_line(&line);
+        }
+
+        #[test]
+        fn text_rows_round_trip(path in any::<String>(), tags in any::<BTreeSet<String>>()) {
+            prop_assume!(!path.is_empty() && !tags.contains(""));
+            let line = format_text_row(&path, &tags);
+            prop_assert!(!line.contains('\n'));
+            prop_assert_eq!(parse_text_line(&line), Ok(Some(TextLine::Row(path, tags))));
+        }
+    }
+
+    #[test]
+    fn parses_examples() {
+        let row = |path: &str, tags: &[&str]| Ok(Some(TextLine::Row(path.to_string(), tags.iter().map(|tag| tag.to_string()).collect())));
+        assert_eq!(parse_text_line("photos/a.jpg\tholiday\tyear=2024"), row("photos/a.jpg", &["holiday", "year=2024"]));
+        assert_eq!(parse_text_line("untagged.txt"), row("untagged.txt", &[]));
+        assert_eq!(parse_text_line("\\#1.txt\ttab\\there"), row("#1.txt", &["tab\there"]));
+        assert_eq!(parse_text_line("#set\tcase-insensitive\ttrue"), Ok(Some(TextLine::Setting("case-insensitive".to_string(), "true".to_string()))));
+        assert_eq!(parse_text_line(TEXT_HEADER), Ok(None));
+        assert_eq!(parse_text_line(""), Ok(None));
+        assert!(parse_text_line("a.txt\t").is_err());
+        assert!(parse_text_line("a.txt\tbad\\q").is_err());
+        assert!(parse_text_line("#set\tkey").is_err());
+    }
+}
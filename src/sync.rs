@@ -0,0 +1,107 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+
+use crate::ftag::{get_db_path, FtagError, TagChange};
+use crate::journal::Change;
+use crate::store::{self, Backend, SqliteStore, TagStore};
+
+/// Results of syncing with another database.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// How the tags of each path here changed to match the other database, in path order
+    pub pulled: Vec<TagChange>,
+    /// How the tags of each path in the other database changed to match this one, in path order
+    pub pushed: Vec<TagChange>,
+    /// Paths whose newer tags weren't copied, each with the file that doesn't exist on that side yet
+    pub skipped: Vec<(String, Utf8PathBuf)>,
+}
+
+/// Where a path's newer tags have to be copied to.
+enum Direction {
+    Pull,
+    Push,
+}
+
+/// Give a path the tags it has in the other database, or remove it if it was removed there, returning how its tags
+/// changed.
+fn copy_tags(store: &mut dyn TagStore, path: &str, before: &Option<HashSet<String>>, after: &Option<HashSet<String>>) -> Result<TagChange, FtagError> {
+    match after {
+        Some(tags) => store.set_tags(path, tags)?,
+        None => store.remove_path(path)?,
+    }
+    Ok(TagChange::between(path, &before.clone().unwrap_or_default(), &after.clone().unwrap_or_default()))
+}
+
+/// Bring this database and another copy of it up to date with each other, in both directions.
+///
+/// Each path takes its tags from whichever database changed them last, including removing it if that was the last
+/// change. Paths only one database knows of are copied to the other. Tags are only copied to a database if the file
+/// exists next to it, so they aren't lost the next time it is pruned.
+///
+/// * `other` - The other database file, or the directory holding it
+/// * `dry_run` - Work out what would change without saving anything
+///
+/// # Failure
+///
+/// Returns `Err` if either database does not exist, doesn't use the sqlite backend, is the same database, or errors
+/// occur when interacting with them. Neither database is changed in that case.
+pub fn sync(other: &Utf8Path, dry_run: bool) -> Result<SyncReport, FtagError> {
+    if store::current_backend()? != Backend::Sqlite {
+        return Err(FtagError::ConfigError("Syncing is only supported by the sqlite backend".to_string()));
+    }
+    let other_db: Utf8PathBuf = match other.is_dir() {
+        true => other.join(get_db_path()),
+        false => other.to_path_buf(),
+    };
+    if !other_db.is_file() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    if other_db.canonicalize_utf8()? == get_db_path().canonicalize_utf8()? {
+        return Err(FtagError::ArgumentError(format!("{} is this database", other)));
+    }
+    let other_root = other_db.parent().unwrap_or(Utf8Path::new("."));
+
+    let here = SqliteStore::open(&get_db_path())?;
+    let there = SqliteStore::open(&other_db)?;
+    let by_path = |changes: Vec<Change>| -> BTreeMap<_, _> { changes.into_iter().map(|(time, path, tags)| (path, (time, tags))).collect() };
+    let (ours, theirs) = (by_path(here.last_changes()?), by_path(there.last_changes()?));
+    let (mut here, mut there): (Box<dyn TagStore>, Box<dyn TagStore>) = (Box::new(here), Box::new(there));
+
+    let sync_both = |here: &mut dyn TagStore, there: &mut dyn TagStore| {
+        let mut report = SyncReport::default();
+        for path in ours.keys().chain(theirs.keys()).collect::<BTreeSet<_>>() {
+            let (ours, theirs) = (ours.get(path), theirs.get(path));
+            let direction = match (ours, theirs) {
+                (Some((_, ours)), Some((_, theirs))) if ours == theirs => continue,
+                (Some((ours_time, _)), Some((theirs_time, _))) if theirs_time > ours_time => Direction::Pull,
+                (Some(_), Some(_)) | (Some((_, Some(_))), None) => Direction::Push,
+                (None, Some((_, Some(_)))) => Direction::Pull,
+                _ => continue,
+            };
+
+            let ours = ours.and_then(|(_, tags)| tags.clone());
+            let theirs = theirs.and_then(|(_, tags)| tags.clone());
+            let (before, after, file) = match direction {
+                Direction::Pull => (ours, theirs, Utf8PathBuf::from(path)),
+                Direction::Push => (theirs, ours, other_root.join(path)),
+            };
+            // Removing a path never needs its file, but tags are only worth copying next to the file they belong to
+            if after.is_some() && !file.exists() {
+                report.skipped.push((path.clone(), file));
+                continue;
+            }
+            match direction {
+                Direction::Pull => report.pulled.push(copy_tags(here, path, &before, &after)?),
+                Direction::Push => report.pushed.push(copy_tags(there, path, &before, &after)?),
+            }
+        }
+        Ok(report)
+    };
+
+    // Only save changes here once the other database has saved its own
+    match dry_run {
+        true => here.dry_run(|here| there.dry_run(|there| sync_both(here, there))),
+        false => here.batch(|here| there.batch(|there| sync_both(here, there))),
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::{DateTime, Local};
+use std::fs;
+
+use crate::ftag::FtagError;
+
+/// Separator between tags when `{tags}` doesn't choose one, the same as when tags are displayed.
+const DEFAULT_SEPARATOR: &str = "; ";
+
+/// Something an output template can fill in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// `{path}`, the path of the file
+    Path,
+    /// `{tag}`, a single tag
+    Tag,
+    /// `{tags}` or `{tags:SEPARATOR}`, the tags of the file, sorted
+    Tags,
+    /// `{count}`, the number of tags of a file, or of files with a tag
+    Count,
+    /// `{mtime}`, when the file was last modified
+    Mtime,
+}
+
+impl Field {
+    /// Name of the placeholder, as it is written in a template.
+    fn name(self) -> &'static str {
+        match self {
+            Field::Path => "path",
+            Field::Tag => "tag",
+            Field::Tags => "tags",
+            Field::Count => "count",
+            Field::Mtime => "mtime",
+        }
+    }
+}
+
+/// One piece of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    /// Text copied as it is
+    Text(String),
+    /// A placeholder, with the separator to put between tags
+    Field(Field, String),
+}
+
+/// What one line of output is filled in from. Fields a command doesn't have are `None` and can't be used.
+#[derive(Debug, Default)]
+pub struct Record<'a> {
+    /// Path of the file, as it should be printed
+    pub path: Option<&'a str>,
+    /// File the modification time is read from, if different from `path`
+    pub file: Option<&'a str>,
+    /// A single tag
+    pub tag: Option<&'a str>,
+    /// Tags of the file, sorted
+    pub tags: Option<&'a [String]>,
+    /// Number of tags, or of files
+    pub count: Option<usize>,
+}
+
+/// An output template like `{path}\t{tags:,}`, turning each result into one line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    /// Parse a template made of text and placeholders like `{path}`.
+    ///
+    /// `\t`, `\n`, and `\\` stand for a tab, a newline, and a backslash, and `{{` and `}}` for literal braces.
+    /// `{tags:SEPARATOR}` puts `SEPARATOR` between the tags instead of `; `.
+    ///
+    /// * `text` - The template as it was typed
+    /// * `fields` - Placeholders that can be filled in by the command using the template
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` if a brace is left unclosed, an escape is unknown, or a placeholder can't be filled in.
+    pub fn parse(text: &str, fields: &[Field]) -> Result<Template, FtagError> {
+        let invalid = |reason: String| FtagError::ArgumentError(format!("Invalid format {}: {}", text, reason));
+
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => return Err(invalid(format!("unknown escape \\{}", other))),
+                    None => return Err(invalid("a backslash ends the format".to_string())),
+                },
+                '}' => match chars.next() {
+                    Some('}') => literal.push('}'),
+                    _ => return Err(invalid("} without a matching {".to_string())),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err(invalid("{ without a matching }".to_string()));
+                    };
+                    let (name, separator) = match rest[..end].split_once(':') {
+                        Some((name, separator)) => (name, Some(separator)),
+                        None => (&rest[..end], None),
+                    };
+                    let Some(field) = fields.iter().copied().find(|field| field.name() == name) else {
+                        let known: Vec<String> = fields.iter().map(|field| format!("{{{}}}", field.name())).collect();
+                        return Err(invalid(format!("{{{}}} can't be used here, use {}", name, known.join(", "))));
+                    };
+                    if separator.is_some() && field != Field::Tags {
+                        return Err(invalid(format!("only {{tags}} takes a separator, not {{{}}}", name)));
+                    }
+
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut literal)));
+                    }
+                    let separator = separator.map(unescape).transpose().map_err(invalid)?;
+                    pieces.push(Piece::Field(field, separator.unwrap_or_else(|| DEFAULT_SEPARATOR.to_string())));
+                    chars = rest[end + 1..].chars();
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        Ok(Template { pieces })
+    }
+
+    /// Fill in the template for one result.
+    pub fn render(&self, record: &Record) -> String {
+        let mut line = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => line.push_str(text),
+                Piece::Field(Field::Path, _) => line.push_str(record.path.unwrap_or_default()),
+                Piece::Field(Field::Tag, _) => line.push_str(record.tag.unwrap_or_default()),
+                Piece::Field(Field::Tags, separator) => line.push_str(&record.tags.unwrap_or_default().join(separator)),
+                Piece::Field(Field::Count, _) => line.push_str(&record.count.unwrap_or_default().to_string()),
+                Piece::Field(Field::Mtime, _) => {
+                    // Files that are gone have no time to show
+                    let modified = record.file.or(record.path).and_then(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok());
+                    if let Some(modified) = modified {
+                        line.push_str(&DateTime::<Local>::from(modified).format("%Y-%m-%d %H:%M:%S").to_string());
+                    }
+                },
+            }
+        }
+        line
+    }
+}
+
+/// Undo the escapes allowed in a separator.
+fn unescape(text: &str) -> Result<String, String> {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => return Err(format!("unknown escape \\{}", other)),
+            None => return Err("a backslash ends the separator".to_string()),
+        }
+    }
+    Ok(unescaped)
+}
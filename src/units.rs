@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Units a size can be written in, largest first. Every unit is a power of 1024.
+const SIZE_UNITS: &[(&str, u64)] = &[("T", 1 << 40), ("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+
+/// Units a span of time can be written in, largest first.
+const TIME_UNITS: &[(&str, u64)] = &[("w", 7 * 86400), ("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+
+/// A number of bytes, written like `512`, `10M`, or `1.5G`.
+///
+/// Formatting a size and parsing it again always gives back the same size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid size {}, expected something like 512, 10M, or 1.5G", text);
+
+        // Split off the unit, allowing K, KB, and KiB alike
+        let trimmed = text.trim();
+        let without_bytes = trimmed.strip_suffix("iB").or_else(|| trimmed.strip_suffix('B')).unwrap_or(trimmed);
+        let (number, multiplier) = SIZE_UNITS
+            .iter()
+            .find_map(|(unit, multiplier)| {
+                without_bytes
+                    .strip_suffix(unit)
+                    .or_else(|| without_bytes.strip_suffix(&unit.to_lowercase()))
+                    .map(|number| (number, *multiplier))
+            })
+            .unwrap_or((without_bytes, 1));
+
+        // Whole numbers are kept exact, fractions are rounded to the nearest byte
+        if let Ok(count) = number.parse::<u64>() {
+            return count.checked_mul(multiplier).map(ByteSize).ok_or_else(invalid);
+        }
+        match number.parse::<f64>() {
+            Ok(count) if count.is_finite() && count >= 0.0 && count * (multiplier as f64) < u64::MAX as f64 => {
+                Ok(ByteSize((count * multiplier as f64).round() as u64))
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Use the largest unit that divides the size exactly, so nothing is lost
+        match SIZE_UNITS.iter().find(|(_, multiplier)| self.0 != 0 && self.0.is_multiple_of(*multiplier)) {
+            Some((unit, multiplier)) => write!(f, "{}{}", self.0 / multiplier, unit),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// A span of time, written like `90d`, `2w`, or `1w2d12h`. A bare number is a number of seconds.
+///
+/// Formatting a span and parsing it again always gives back the same span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeSpan(pub Duration);
+
+impl FromStr for TimeSpan {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid duration {}, expected something like 90d, 2w, or 1w2d", text);
+
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(invalid());
+        }
+        if let Ok(seconds) = text.parse() {
+            return Ok(TimeSpan(Duration::from_secs(seconds)));
+        }
+
+        // Add up each number and the unit after it
+        let mut seconds: u64 = 0;
+        let mut rest = text;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+            let count: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+
+            let unit_end = rest[digits..].find(|c: char| c.is_ascii_digit()).map_or(rest.len(), |end| digits + end);
+            let unit = &rest[digits..unit_end];
+            let multiplier = TIME_UNITS.iter().find(|(name, _)| *name == unit).map(|(_, multiplier)| *multiplier).ok_or_else(invalid)?;
+
+            seconds = count.checked_mul(multiplier).and_then(|added| seconds.checked_add(added)).ok_or_else(invalid)?;
+            rest = &rest[unit_end..];
+        }
+
+        Ok(TimeSpan(Duration::from_secs(seconds)))
+    }
+}
+
+impl fmt::Display for TimeSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut seconds = self.0.as_secs();
+        if seconds == 0 {
+            return write!(f, "0s");
+        }
+
+        // Write the largest units first, skipping any that don't appear
+        for (unit, multiplier) in TIME_UNITS {
+            if seconds >= *multiplier {
+                write!(f, "{}{}", seconds / multiplier, unit)?;
+                seconds %= multiplier;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sizes and spans are stored and printed as JSON strings in the same form they are typed in
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for TimeSpan {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeSpan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn sizes_never_panic(text in any::<String>()) {
+            let _ = text.parse::<ByteSize>();
+        }
+
+        #[test]
+        fn spans_never_panic(text in any::<String>()) {
+            let _ = text.parse::<TimeSpan>();
+        }
+
+        #[test]
+        fn sizes_round_trip(bytes in any::<u64>()) {
+            let size = ByteSize(bytes);
+            prop_assert_eq!(size.to_string().parse::<ByteSize>().unwrap(), size);
+        }
+
+        #[test]
+        fn spans_round_trip(seconds in any::<u64>()) {
+            let span = TimeSpan(Duration::from_secs(seconds));
+            prop_assert_eq!(span.to_string().parse::<TimeSpan>().unwrap(), span);
+        }
+    }
+
+    #[test]
+    fn parses_examples() {
+        assert_eq!("512".parse::<ByteSize>().unwrap(), ByteSize(512));
+        assert_eq!("10M".parse::<ByteSize>().unwrap(), ByteSize(10 << 20));
+        assert_eq!("1.5G".parse::<ByteSize>().unwrap(), ByteSize(3 << 29));
+        assert_eq!("2KiB".parse::<ByteSize>().unwrap(), ByteSize(2048));
+        assert_eq!("90d".parse::<TimeSpan>().unwrap(), TimeSpan(Duration::from_secs(90 * 86400)));
+        assert_eq!("1w2d".parse::<TimeSpan>().unwrap().to_string(), "1w2d");
+        assert!("2x".parse::<TimeSpan>().is_err());
+        assert!("d".parse::<TimeSpan>().is_err());
+    }
+}
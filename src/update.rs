@@ -0,0 +1,149 @@
+use serde::Deserialize;
+use std::{env, fs};
+
+use crate::ftag::FtagError;
+use crate::sandbox;
+
+/// Where to ask for the latest release, which can be changed at build time with `FTAG_UPDATE_URL`.
+const RELEASE_URL: &str = match option_env!("FTAG_UPDATE_URL") {
+    Some(url) => url,
+    None => "https://api.github.com/repos/almondheil/ftag/releases/latest",
+};
+
+/// Minisign public key that release binaries must be signed with, given at build time with `FTAG_UPDATE_KEY`.
+const PUBLIC_KEY: Option<&str> = option_env!("FTAG_UPDATE_KEY");
+
+/// Largest binary that will be downloaded, so a broken server can't fill the disk.
+const MAX_DOWNLOAD: u64 = 256 << 20;
+
+/// The parts of a release listed by the release endpoint that matter here.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+/// A file attached to a release.
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// What happened when looking for an update.
+#[derive(Debug)]
+pub enum UpdateStatus {
+    /// The running version is the latest one
+    UpToDate(String),
+    /// A newer version exists but was not installed
+    Available(String),
+    /// A newer version was downloaded, verified, and installed
+    Installed(String),
+}
+
+/// Name of the release asset holding the binary for this platform, like `ftag-x86_64-linux`.
+fn asset_name() -> String {
+    format!("ftag-{}-{}{}", env::consts::ARCH, env::consts::OS, env::consts::EXE_SUFFIX)
+}
+
+/// Split a version like `v1.2.3` into its numbers, so versions compare in the right order.
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.chars().take_while(char::is_ascii_digit).collect::<String>().parse().unwrap_or(0))
+        .collect()
+}
+
+/// Download `url` in full.
+///
+/// # Failure
+///
+/// Returns `Err` if the request fails or the response is too large.
+fn download(url: &str) -> Result<Vec<u8>, FtagError> {
+    ureq::get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().with_config().limit(MAX_DOWNLOAD).read_to_vec())
+        .map_err(|err| FtagError::UpdateError(format!("Could not download {}: {}", url, err)))
+}
+
+/// Check the signature of a downloaded binary against the key built into this ftag.
+///
+/// # Failure
+///
+/// Returns `Err` if this build has no key or the signature doesn't match.
+fn verify(binary: &[u8], signature: &[u8]) -> Result<(), FtagError> {
+    let key = PUBLIC_KEY.ok_or_else(|| {
+        FtagError::UpdateError("This build has no key to verify updates with, rebuild it with FTAG_UPDATE_KEY set".to_string())
+    })?;
+    let key = minisign_verify::PublicKey::from_base64(key)
+        .map_err(|err| FtagError::UpdateError(format!("Invalid update key: {}", err)))?;
+
+    let signature = String::from_utf8_lossy(signature);
+    let signature = minisign_verify::Signature::decode(&signature)
+        .map_err(|err| FtagError::UpdateError(format!("Invalid signature: {}", err)))?;
+    key.verify(binary, &signature, false)
+        .map_err(|err| FtagError::UpdateError(format!("Signature does not match, refusing to install: {}", err)))
+}
+
+/// Replace the running executable with `binary`.
+///
+/// The new binary is written next to the old one first, so the swap itself can't be left half done.
+///
+/// # Failure
+///
+/// Returns `Err` if the new binary could not be written or moved into place.
+fn install(binary: &[u8]) -> Result<(), FtagError> {
+    let exe = env::current_exe()?;
+    let staged = exe.with_file_name(format!(".ftag-update{}", env::consts::EXE_SUFFIX));
+    fs::write(&staged, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let replaced = self_replace::self_replace(&staged);
+    let _ = fs::remove_file(&staged);
+    replaced.map_err(|err| FtagError::UpdateError(format!("Could not replace {}: {}", exe.display(), err)))
+}
+
+/// Look for a newer release, and install it unless `check_only` is set.
+///
+/// Nothing is installed unless its signature matches the key this ftag was built with.
+///
+/// # Failure
+///
+/// Returns `Err` if network access was forbidden, the release could not be found or downloaded,
+/// its signature doesn't match, or it could not be installed.
+pub fn self_update(check_only: bool) -> Result<UpdateStatus, FtagError> {
+    sandbox::check_network("the release server")?;
+
+    let listing = download(RELEASE_URL)?;
+    let release: Release = serde_json::from_slice(&listing)?;
+    let current = env!("CARGO_PKG_VERSION");
+    if version_numbers(&release.tag_name) <= version_numbers(current) {
+        return Ok(UpdateStatus::UpToDate(current.to_string()));
+    }
+    if check_only {
+        return Ok(UpdateStatus::Available(release.tag_name));
+    }
+
+    // Every platform's binary comes with a minisign signature beside it
+    let name = asset_name();
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| FtagError::UpdateError(format!("Release {} has no {}", release.tag_name, name)))
+    };
+    let binary = download(&find(&name)?.browser_download_url)?;
+    let signature = download(&find(&format!("{}.minisig", name))?.browser_download_url)?;
+
+    verify(&binary, &signature)?;
+    install(&binary)?;
+
+    Ok(UpdateStatus::Installed(release.tag_name))
+}
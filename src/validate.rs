@@ -0,0 +1,145 @@
+use crate::ftag::{FtagError, KEY_SEPARATOR, TAG_SEPARATOR};
+
+/// What to do with new tags that break the naming rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagValidation {
+    /// Quietly fix the tag where possible
+    Normalize,
+    /// Refuse the tag with an error
+    Reject,
+    /// Accept any tag as it was typed
+    Off,
+}
+
+impl TagValidation {
+    /// Every value the `tag-validation` setting accepts.
+    pub const NAMES: &'static [&'static str] = &["normalize", "reject", "off"];
+
+    /// Parse the value of the `tag-validation` setting.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "normalize" => Some(TagValidation::Normalize),
+            "reject" => Some(TagValidation::Reject),
+            "off" => Some(TagValidation::Off),
+            _ => None,
+        }
+    }
+}
+
+/// Find the first naming rule a single level of a tag breaks, if any.
+fn problem_with_level(level: &str) -> Option<&'static str> {
+    if level.is_empty() {
+        Some("it has an empty level")
+    } else if level.chars().any(char::is_control) {
+        Some("it contains control characters")
+    } else if level.trim() != level {
+        Some("it starts or ends with whitespace")
+    } else if level.contains("  ") {
+        Some("it contains repeated spaces")
+    } else if level.starts_with('-') {
+        Some("it starts with a dash, which looks like an option")
+    } else {
+        None
+    }
+}
+
+/// Find the naming rule the key and value of a `key=value` tag break, if any.
+fn problem_with_key(tag: &str) -> Option<&'static str> {
+    let (key, value) = tag.split_once(KEY_SEPARATOR)?;
+    if key.is_empty() || value.is_empty() {
+        Some("it has an empty key or value")
+    } else if key.ends_with(char::is_whitespace) || value.starts_with(char::is_whitespace) {
+        Some("it has whitespace around =")
+    } else {
+        None
+    }
+}
+
+/// Fix a single level of a tag so it follows the naming rules.
+fn normalize_level(level: &str) -> String {
+    let without_control: String = level.chars().filter(|c| !c.is_control()).collect();
+    let collapsed = without_control.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed.trim_start_matches('-').to_string()
+}
+
+/// Check a new tag against the naming rules, returning the tag to store.
+///
+/// Every level of a hierarchical tag must be non-empty, free of control characters and repeated or surrounding
+/// whitespace, and must not start with a dash. A `key=value` tag needs both a key and a value, with no space around
+/// the `=`.
+///
+/// * `tag` - Tag as it was typed
+/// * `mode` - Whether to fix, reject, or ignore problems
+///
+/// # Failure
+///
+/// Returns `Err` if `mode` is `Reject` and the tag breaks a rule, or if the tag is empty once fixed.
+pub fn check_tag(tag: &str, mode: TagValidation) -> Result<String, FtagError> {
+    let levels: Vec<&str> = tag.split(TAG_SEPARATOR).collect();
+
+    match mode {
+        TagValidation::Off => Ok(tag.to_string()),
+        TagValidation::Reject => {
+            match levels.into_iter().find_map(problem_with_level).or_else(|| problem_with_key(tag)) {
+                Some(problem) => Err(FtagError::InvalidTagError(format!("{:?} is not allowed because {}", tag, problem))),
+                None => Ok(tag.to_string()),
+            }
+        },
+        TagValidation::Normalize => {
+            // Fix each level, dropping any that end up empty like the middle of `a//b`
+            let fixed: Vec<String> = levels
+                .into_iter()
+                .map(normalize_level)
+                .filter(|level| !level.is_empty())
+                .collect();
+
+            if fixed.is_empty() {
+                return Err(FtagError::InvalidTagError(format!("{:?} is empty once whitespace and dashes are removed", tag)));
+            }
+            let fixed = fixed.join(&TAG_SEPARATOR.to_string());
+
+            // Tighten `key = value` into `key=value`
+            match fixed.split_once(KEY_SEPARATOR) {
+                Some((key, value)) if key.trim_end().is_empty() || value.trim_start().is_empty() => {
+                    Err(FtagError::InvalidTagError(format!("{:?} has an empty key or value", tag)))
+                },
+                Some((key, value)) => Ok(format!("{}{}{}", key.trim_end(), KEY_SEPARATOR, value.trim_start())),
+                None => Ok(fixed),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn never_panics(tag in any::<String>()) {
+            for mode in [TagValidation::Normalize, TagValidation::Reject, TagValidation::Off] {
+                let _ = check_tag(&tag, mode);
+            }
+        }
+
+        #[test]
+        fn off_keeps_tag(tag in any::<String>()) {
+            prop_assert_eq!(check_tag(&tag, TagValidation::Off).unwrap(), tag);
+        }
+
+        #[test]
+        fn normalized_tags_pass_reject(tag in any::<String>()) {
+            if let Ok(normalized) = check_tag(&tag, TagValidation::Normalize) {
+                prop_assert_eq!(check_tag(&normalized, TagValidation::Reject).unwrap(), normalized.clone());
+                prop_assert_eq!(check_tag(&normalized, TagValidation::Normalize).unwrap(), normalized);
+            }
+        }
+
+        #[test]
+        fn reject_accepts_valid_tags(tag in "[a-z][a-z0-9 ]{0,8}[a-z0-9](/[a-z][a-z0-9]{0,8}){0,3}") {
+            prop_assume!(!tag.contains("  "));
+            prop_assert_eq!(check_tag(&tag, TagValidation::Reject).unwrap(), tag);
+        }
+    }
+}
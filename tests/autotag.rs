@@ -0,0 +1,29 @@
+//! Tagging files by the rules next to the database with `autotag`.
+#![cfg(feature = "full")]
+
+mod common;
+
+use camino::Utf8Path;
+use std::fs;
+use std::process::Command;
+
+use ftag::autotag;
+use ftag::ftag as core;
+use ftag::store::Backend;
+
+use common::TempDb;
+
+#[test]
+fn skips_git_and_ftag_files() {
+    let db = TempDb::new(Backend::Sqlite);
+    let status = Command::new("git").args(["init", "-q"]).status().unwrap();
+    assert!(status.success());
+    fs::write(autotag::RULES_FILE, "** -> all\n").unwrap();
+    fs::write(".ftagignore", "").unwrap();
+    db.touch("readme.md");
+    db.touch("notes/a.txt");
+
+    let taggers = autotag::taggers(&[]).unwrap();
+    autotag::autotag(Utf8Path::new("."), &taggers, false).unwrap();
+    assert_eq!(core::tracked_paths().unwrap(), ["notes/a.txt", "readme.md"]);
+}
@@ -0,0 +1,67 @@
+//! Setup shared by the tests that run against a real database.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::{env, fs, process};
+
+use ftag::store::{self, Backend};
+
+/// The database used is always the one in the current directory, which every test in a binary shares.
+static CURRENT_DIR: Mutex<()> = Mutex::new(());
+
+/// Number of the next temporary directory made by this test binary.
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A new, empty database in a temporary directory, which stays the current directory until this is dropped.
+pub struct TempDb {
+    dir: Utf8PathBuf,
+    previous: Utf8PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TempDb {
+    /// Make a database using `backend` in a new temporary directory, and enter it.
+    pub fn new(backend: Backend) -> Self {
+        // A failed test only poisons the lock, it leaves the current directory as it was
+        let lock = CURRENT_DIR.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = Utf8PathBuf::try_from(env::current_dir().unwrap()).unwrap();
+        let name = format!("ftag-test-{}-{}", process::id(), NEXT_DIR.fetch_add(1, Ordering::Relaxed));
+        let dir = Utf8PathBuf::try_from(env::temp_dir().join(name)).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        env::set_current_dir(&dir).unwrap();
+        store::init_store(backend).unwrap();
+        TempDb { dir, previous, _lock: lock }
+    }
+
+    /// Make an empty file, along with any directories it is in, returning its path.
+    #[allow(dead_code)]
+    pub fn touch(&self, path: &str) -> Utf8PathBuf {
+        if let Some(parent) = Utf8Path::new(path).parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, "").unwrap();
+        Utf8PathBuf::from(path)
+    }
+
+    /// Path of the temporary directory.
+    #[allow(dead_code)]
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = env::set_current_dir(&self.previous);
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Sort a file's tags, so they can be compared with a list.
+#[allow(dead_code)]
+pub fn sorted(tags: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags.into_iter().collect();
+    tags.sort();
+    tags
+}
@@ -0,0 +1,46 @@
+//! Searching for files by their tags.
+
+mod common;
+
+use ftag::ftag::{self as core, CaseMode, SortKey};
+use ftag::store::Backend;
+
+use common::TempDb;
+
+/// Collect every matching file one page at a time.
+fn all_pages(find: &[String], reverse: bool) -> Vec<String> {
+    let find = core::parse_queries(find, CaseMode::Smart, false).unwrap();
+    let mut paths = vec![];
+    loop {
+        let page = core::find_page(&find, &[], false, reverse, paths.len(), 2).unwrap();
+        if page.is_empty() {
+            return paths;
+        }
+        paths.extend(page.into_iter().map(|(path, _)| path));
+    }
+}
+
+#[test]
+fn pages_sort_like_lists() {
+    let db = TempDb::new(Backend::Sqlite);
+    // SQLite's NOCASE would put Öb before öa, since it only ignores the case of ASCII letters
+    for name in ["öa.txt", "Öb.txt", "b.txt", "A.txt", "a.txt", "Émile.txt", "édith.txt"] {
+        core::add_tags(&db.touch(name), vec!["photo".to_string()]).unwrap();
+    }
+    core::add_tags(&"b.txt".into(), vec!["draft".to_string()]).unwrap();
+
+    for reverse in [false, true] {
+        let mut files = core::find_tags(&[], &[], false).unwrap();
+        core::sort_files(&mut files, SortKey::Name, reverse);
+        let listed: Vec<String> = files.into_iter().map(|(path, _)| path).collect();
+
+        // Both without a search, which SQLite pages through itself, and with one, which is checked in batches
+        assert_eq!(all_pages(&[], reverse), listed);
+        assert_eq!(all_pages(&["photo".to_string()], reverse), listed);
+    }
+
+    let mut files = core::find_tags(&[], &[], false).unwrap();
+    core::sort_files(&mut files, SortKey::Name, false);
+    let listed: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(listed, ["A.txt", "a.txt", "b.txt", "édith.txt", "Émile.txt", "öa.txt", "Öb.txt"]);
+}
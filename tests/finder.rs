@@ -0,0 +1,51 @@
+//! Copying tags to and from macOS Finder.
+#![cfg(feature = "full")]
+
+mod common;
+
+use camino::Utf8Path;
+use ftag::finder;
+use ftag::ftag as core;
+use ftag::import::{self, ImportFormat};
+use ftag::meta;
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+#[cfg(target_os = "macos")]
+#[test]
+fn syncs_and_imports_finder_tags() {
+    use ftag::meta::TagColor;
+
+    let db = TempDb::new(Backend::Sqlite);
+    let photo = db.touch("photos/a.jpg");
+    db.touch("photos/untagged.jpg");
+    core::add_tags(&photo, vec!["holiday".to_string(), "beach".to_string()]).unwrap();
+    meta::set_tag_color("holiday", Some(TagColor::Red)).unwrap();
+
+    let all_meta = meta::get_all_tag_meta().unwrap();
+    let changes = finder::sync_finder(Utf8Path::new("photos"), &all_meta, false).unwrap();
+    assert_eq!(changes.len(), 1);
+    let written = finder::read_raw_tags(&photo).unwrap().and_then(|data| finder::parse_tags(&data)).unwrap();
+    assert_eq!(sorted(written), ["beach", "holiday"]);
+    assert!(finder::sync_finder(Utf8Path::new("photos"), &all_meta, false).unwrap().is_empty());
+
+    // Importing brings back tags removed from ftag but still in Finder
+    core::set_tags(&photo, vec![]).unwrap();
+    let report = import::import(ImportFormat::Finder, Utf8Path::new("photos"), false).unwrap();
+    assert_eq!(report.changes.len(), 1);
+    assert_eq!(sorted(core::get_file_tags(&photo).unwrap()), ["beach", "holiday"]);
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn finder_needs_macos() {
+    let db = TempDb::new(Backend::Sqlite);
+    let photo = db.touch("photos/a.jpg");
+    core::add_tags(&photo, vec!["holiday".to_string()]).unwrap();
+
+    let all_meta = meta::get_all_tag_meta().unwrap();
+    assert!(finder::sync_finder(Utf8Path::new("photos"), &all_meta, false).is_err());
+    assert!(import::import(ImportFormat::Finder, Utf8Path::new("photos"), false).is_err());
+    assert_eq!(sorted(core::get_file_tags(&photo).unwrap()), ["holiday"]);
+}
@@ -0,0 +1,64 @@
+//! Tagging files with parts of their names.
+#![cfg(feature = "full")]
+
+mod common;
+
+use camino::Utf8Path;
+use ftag::ftag as core;
+use ftag::harvest::{self, Pattern};
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+/// Return the sorted tags of `path`.
+fn tags_of(path: &str) -> Vec<String> {
+    sorted(core::get_file_tags(&path.into()).unwrap())
+}
+
+#[test]
+fn tags_matching_names() {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("notes/2024-ftag-plan-old.md");
+    db.touch("notes/2023-site-todo.md");
+    db.touch("notes/readme.md");
+    core::add_tags(&db.touch("notes/2022-misc-x.md"), vec!["kept".to_string()]).unwrap();
+
+    let pattern = Pattern::parse("{year}-{project}-{*}.md").unwrap();
+    let changes = harvest::harvest(Utf8Path::new("notes"), &pattern, false).unwrap();
+    let changed: Vec<&str> = changes.iter().map(|change| change.path.as_str()).collect();
+    assert_eq!(changed, ["notes/2022-misc-x.md", "notes/2023-site-todo.md", "notes/2024-ftag-plan-old.md"]);
+
+    assert_eq!(tags_of("notes/2024-ftag-plan-old.md"), ["project=ftag", "year=2024"]);
+    assert_eq!(tags_of("notes/2022-misc-x.md"), ["kept", "project=misc", "year=2022"]);
+    assert!(tags_of("notes/readme.md").is_empty());
+}
+
+#[test]
+fn matches_paths_and_custom_regexes() {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("music/Beatles/Abbey Road/01.flac");
+    db.touch("scans/2024-01-31_invoice.pdf");
+    db.touch("scans/2024-1-31_receipt.pdf");
+
+    harvest::harvest(Utf8Path::new("music"), &Pattern::parse("{artist}/{album}/{*}").unwrap(), false).unwrap();
+    assert_eq!(tags_of("music/Beatles/Abbey Road/01.flac"), ["album=Abbey Road", "artist=Beatles"]);
+
+    harvest::harvest(Utf8Path::new("scans"), &Pattern::parse(r"{date:\d{4}-\d{2}-\d{2}}_{}.pdf").unwrap(), false).unwrap();
+    assert_eq!(tags_of("scans/2024-01-31_invoice.pdf"), ["date=2024-01-31", "invoice"]);
+    assert!(tags_of("scans/2024-1-31_receipt.pdf").is_empty());
+}
+
+#[test]
+fn dry_run_saves_nothing() {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("2024-ftag.md");
+
+    let changes = harvest::harvest(db.dir(), &Pattern::parse("{year}-{}.md").unwrap(), true).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(sorted(changes[0].added.clone()), ["ftag", "year=2024"]);
+    assert!(core::tracked_paths().unwrap().is_empty());
+
+    for broken in ["plain.md", "{year", "year}", "{a=b}", "{year:(}", r"{year:(\d+)}"] {
+        assert!(Pattern::parse(broken).is_err(), "{:?} should not parse", broken);
+    }
+}
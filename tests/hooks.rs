@@ -0,0 +1,90 @@
+//! Running the user's after-change hook once for each file a command changes.
+#![cfg(all(feature = "full", feature = "cli", unix))]
+
+mod common;
+
+use camino::Utf8PathBuf;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use ftag::ftag as core;
+use ftag::store::Backend;
+
+use common::TempDb;
+
+/// Install a hook under `config` that writes the action and path of each change to a log, returning the log's path.
+fn install_hook(db: &TempDb) -> Utf8PathBuf {
+    let hooks = db.dir().join("config/ftag/hooks");
+    fs::create_dir_all(&hooks).unwrap();
+    let log = db.dir().join("config/hook.log");
+    let hook = hooks.join("after-change");
+    fs::write(&hook, format!("#!/bin/sh\necho \"$1 $2\" >> '{}'\n", log)).unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+    log
+}
+
+/// Run ftag in the database directory with the hook installed, returning whether it succeeded.
+fn ftag(db: &TempDb, args: &[&str]) -> bool {
+    let output = Command::new(env!("CARGO_BIN_EXE_ftag")).args(args).env("XDG_CONFIG_HOME", db.dir().join("config")).output().unwrap();
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    output.status.success()
+}
+
+/// Return each line the hook has logged since the last call, then clear the log.
+fn logged(log: &Utf8PathBuf) -> Vec<String> {
+    let lines = fs::read_to_string(log).unwrap_or_default().lines().map(str::to_string).collect();
+    let _ = fs::remove_file(log);
+    lines
+}
+
+#[test]
+fn runs_only_for_changed_files() {
+    let db = TempDb::new(Backend::Sqlite);
+    let log = install_hook(&db);
+    core::add_tags(&db.touch("a.jpg"), vec!["red".to_string()]).unwrap();
+    core::add_tags(&db.touch("b.jpg"), vec!["red".to_string(), "blue".to_string()]).unwrap();
+    db.touch("c.jpg");
+
+    // Setting the tags a file already has changes nothing
+    assert!(ftag(&db, &["set", "a.jpg", "red"]));
+    assert!(logged(&log).is_empty());
+    assert!(ftag(&db, &["set", "a.jpg", "red", "round"]));
+    assert_eq!(logged(&log), ["set a.jpg"]);
+
+    // A rename that can't be saved doesn't run it either
+    assert!(!ftag(&db, &["rename", "a.jpg", "red", " - "]));
+    assert!(logged(&log).is_empty());
+    assert!(ftag(&db, &["rename", "a.jpg", "round", "circle"]));
+    assert_eq!(logged(&log), ["rename a.jpg"]);
+}
+
+#[test]
+fn runs_once_for_each_file_of_bulk_commands() {
+    let db = TempDb::new(Backend::Sqlite);
+    let log = install_hook(&db);
+    core::add_tags(&db.touch("a.jpg"), vec!["red".to_string()]).unwrap();
+    core::add_tags(&db.touch("b.jpg"), vec!["red".to_string(), "blue".to_string()]).unwrap();
+    core::add_tags(&db.touch("c.jpg"), vec!["green".to_string()]).unwrap();
+
+    fs::write("mapping.tsv", "red\tcrimson\n").unwrap();
+    assert!(ftag(&db, &["rename", "--yes", "--map", "mapping.tsv"]));
+    assert_eq!(logged(&log), ["rename a.jpg", "rename b.jpg"]);
+
+    assert!(ftag(&db, &["merge-tags", "--yes", "blue", "crimson", "into", "warm"]));
+    assert_eq!(logged(&log), ["merge-tags a.jpg", "merge-tags b.jpg"]);
+
+    // b.jpg already has every tag added, so only a.jpg changes
+    core::add_tags(&"b.jpg".into(), vec!["bright".to_string()]).unwrap();
+    assert!(ftag(&db, &["tag-matching", "--yes", "warm", "--add", "bright"]));
+    assert_eq!(logged(&log), ["tag-matching a.jpg"]);
+
+    // Operations that cancel out leave c.jpg as it was
+    fs::write("script.txt", "add c.jpg dark\nrm c.jpg dark\nadd a.jpg dark\n").unwrap();
+    assert!(ftag(&db, &["apply", "--yes", "script.txt"]));
+    assert_eq!(logged(&log), ["apply a.jpg"]);
+
+    // Dry runs change nothing, so they don't run it
+    assert!(ftag(&db, &["merge-tags", "--yes", "--dry-run", "warm", "into", "hot"]));
+    assert!(logged(&log).is_empty());
+}
@@ -0,0 +1,60 @@
+//! Importing tags from TagSpaces sidecar files.
+#![cfg(feature = "full")]
+
+mod common;
+
+use camino::Utf8Path;
+use std::fs;
+
+use ftag::ftag as core;
+use ftag::import::{self, ImportFormat};
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+/// Write a TagSpaces sidecar with the given JSON.
+fn sidecar(db: &TempDb, path: &str, json: &str) {
+    fs::write(db.touch(path), json).unwrap();
+}
+
+/// Make a database next to a few files tagged by TagSpaces.
+fn tagspaces() -> TempDb {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("photos/beach.jpg");
+    db.touch("photos/broken.jpg");
+    sidecar(&db, "photos/.ts/beach.jpg.json", r#"{"appName": "TagSpaces", "tags": [{"title": "vacation", "type": "sidecar"}, {"title": " 2019 "}]}"#);
+    sidecar(&db, "photos/.ts/tsm.json", r#"{"tags": [{"title": "album"}]}"#);
+    sidecar(&db, "photos/.ts/broken.jpg.json", r#"{"tags": ["vacation"]}"#);
+    sidecar(&db, "photos/.ts/gone.jpg.json", r#"{"tags": [{"title": "lost"}]}"#);
+    db
+}
+
+#[test]
+fn imports_sidecars() {
+    let _db = tagspaces();
+    core::add_tags(&"photos/beach.jpg".into(), vec!["kept".to_string()]).unwrap();
+
+    let report = import::import(ImportFormat::Tagspaces, Utf8Path::new("photos"), false).unwrap();
+    let changed: Vec<&str> = report.changes.iter().map(|change| change.path.as_str()).collect();
+    assert_eq!(changed, ["photos", "photos/beach.jpg"]);
+    let mut skipped: Vec<&str> = report.skipped.iter().map(|(path, _)| path.as_str()).collect();
+    skipped.sort();
+    assert_eq!(skipped, ["photos/broken.jpg", "photos/gone.jpg"]);
+
+    assert_eq!(sorted(core::get_file_tags(&"photos/beach.jpg".into()).unwrap()), ["2019", "kept", "vacation"]);
+    assert_eq!(sorted(core::get_file_tags(&"photos".into()).unwrap()), ["album"]);
+    assert!(core::get_file_tags(&"photos/broken.jpg".into()).unwrap().is_empty());
+
+    // Importing again has nothing left to add
+    let report = import::import(ImportFormat::Tagspaces, Utf8Path::new("photos"), false).unwrap();
+    assert!(report.changes.iter().all(|change| change.added.is_empty()));
+}
+
+#[test]
+fn dry_run_saves_nothing() {
+    let _db = tagspaces();
+    let report = import::import(ImportFormat::Tagspaces, Utf8Path::new("photos"), true).unwrap();
+    assert_eq!(report.changes.len(), 2);
+    assert!(core::tracked_paths().unwrap().is_empty());
+    assert!(import::import(ImportFormat::Tagspaces, Utf8Path::new("missing"), false).is_err());
+}
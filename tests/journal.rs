@@ -0,0 +1,46 @@
+//! Looking back at earlier tags through the journal.
+#![cfg(feature = "full")]
+
+mod common;
+
+use ftag::ftag as core;
+use ftag::store::Backend;
+use ftag::{journal, schema};
+use rusqlite::Connection;
+
+use common::{sorted, TempDb};
+
+/// Return how SQLite plans to find the latest journal entry of a path.
+fn latest_entry_plan(conn: &Connection) -> String {
+    let mut stmt = conn.prepare("EXPLAIN QUERY PLAN SELECT MAX(id) FROM journal WHERE path = 'a.txt'").unwrap();
+    let details: Vec<String> = stmt.query_map((), |row| row.get(3)).unwrap().collect::<Result<_, _>>().unwrap();
+    details.join("\n")
+}
+
+#[test]
+fn looks_back_by_path() {
+    let db = TempDb::new(Backend::Sqlite);
+    let a = db.touch("a.txt");
+    core::add_tags(&a, vec!["red".to_string()]).unwrap();
+    let before = journal::now();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    core::set_tags(&a, vec!["blue".to_string()]).unwrap();
+
+    let rows = journal::rows_asof(before).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(sorted(rows[0].1.clone()), ["red"]);
+    assert!(latest_entry_plan(&Connection::open(".ftag.db").unwrap()).contains("journal_path"));
+}
+
+#[test]
+fn migrate_indexes_old_journals() {
+    let _db = TempDb::new(Backend::Sqlite);
+    {
+        let conn = Connection::open(".ftag.db").unwrap();
+        conn.execute_batch("DROP INDEX journal_path; PRAGMA user_version = 6").unwrap();
+        assert!(!latest_entry_plan(&conn).contains("journal_path"));
+    }
+
+    schema::migrate().unwrap();
+    assert!(latest_entry_plan(&Connection::open(".ftag.db").unwrap()).contains("journal_path"));
+}
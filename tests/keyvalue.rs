@@ -0,0 +1,53 @@
+//! Comparing the values of `key=value` tags in searches, like `year>=2020`.
+#![cfg(feature = "full")]
+
+mod common;
+
+use ftag::ftag::{self as core, CaseMode};
+use ftag::store::Backend;
+
+use common::TempDb;
+
+/// Return the sorted paths of the files with every tag in `find` and none in `exclude`.
+fn matching(find: &[&str], exclude: &[&str]) -> Vec<String> {
+    let parse = |tags: &[&str]| core::parse_queries(&tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>(), CaseMode::Smart, false).unwrap();
+    let mut paths: Vec<String> = core::find_tags(&parse(find), &parse(exclude), false).unwrap().into_iter().map(|(path, _)| path).collect();
+    paths.sort();
+    paths
+}
+
+/// Make a database with a few photos tagged with a year, a status, and when they were taken.
+fn photos() -> TempDb {
+    let db = TempDb::new(Backend::Sqlite);
+    for (name, tags) in [
+        ("old.jpg", ["year=1999", "status=done", "taken=1999-12-31"]),
+        ("new.jpg", ["year=2023", "status=Draft", "taken=2023-06-01"]),
+        ("newer.jpg", ["year=2024.0", "status=done", "taken=2024-01-31"]),
+    ] {
+        core::add_tags(&db.touch(name), tags.iter().map(|tag| tag.to_string()).collect()).unwrap();
+    }
+    core::add_tags(&db.touch("untagged.jpg"), vec!["photo".to_string()]).unwrap();
+    db
+}
+
+#[test]
+fn compares_numbers_and_dates() {
+    let _db = photos();
+    // Numbers compare as numbers, so 999 < 2020 and 2024.0 == 2024
+    assert_eq!(matching(&["year>=2020"], &[]), ["new.jpg", "newer.jpg"]);
+    assert_eq!(matching(&["year<2024"], &[]), ["new.jpg", "old.jpg"]);
+    assert_eq!(matching(&["year!=2024"], &[]), ["new.jpg", "old.jpg"]);
+    assert_eq!(matching(&["taken<2024-02-01", "taken>2000-01-01"], &[]), ["new.jpg", "newer.jpg"]);
+    assert_eq!(matching(&["photo"], &["year>0"]), ["untagged.jpg"]);
+}
+
+#[test]
+fn compares_text_ignoring_case() {
+    let _db = photos();
+    assert_eq!(matching(&["status != draft"], &[]), ["newer.jpg", "old.jpg"]);
+    // Smart case makes a search with capitals match case exactly
+    assert_eq!(matching(&["status<=Draft"], &[]), ["new.jpg"]);
+    // A plain key=value is an ordinary tag search
+    assert_eq!(matching(&["status=done"], &[]), ["newer.jpg", "old.jpg"]);
+    assert!(core::parse_queries(&["year<".to_string()], CaseMode::Smart, false).is_err());
+}
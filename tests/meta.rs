@@ -0,0 +1,57 @@
+//! Descriptions and colors of tags.
+#![cfg(feature = "full")]
+
+mod common;
+
+use ftag::meta::{self, TagColor};
+use ftag::schema;
+use ftag::store::Backend;
+use rusqlite::Connection;
+
+use common::TempDb;
+
+/// Return whether the database in the current directory has a `tag_meta` table.
+fn has_meta_table() -> bool {
+    let conn = Connection::open(".ftag.db").unwrap();
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'tag_meta'", (), |row| row.get::<_, i64>(0)).unwrap() == 1
+}
+
+/// Turn the database in the current directory back into one from before `tag_meta` existed.
+fn drop_meta_table() {
+    let conn = Connection::open(".ftag.db").unwrap();
+    conn.execute_batch("DROP TABLE tag_meta; PRAGMA user_version = 5").unwrap();
+}
+
+#[test]
+fn describes_and_colors_tags() {
+    let _db = TempDb::new(Backend::Sqlite);
+    assert!(has_meta_table());
+
+    meta::set_tag_description("holiday", Some("Trips away")).unwrap();
+    meta::set_tag_color("holiday", Some(TagColor::Blue)).unwrap();
+    meta::set_tag_color("work", Some(TagColor::Red)).unwrap();
+
+    let all_meta = meta::get_all_tag_meta().unwrap();
+    assert_eq!(all_meta["holiday"].description.as_deref(), Some("Trips away"));
+    assert_eq!(all_meta["holiday"].color, Some(TagColor::Blue));
+    assert_eq!(all_meta["work"].description, None);
+
+    // Clearing the last thing known about a tag drops it
+    meta::set_tag_color("work", None).unwrap();
+    assert_eq!(meta::remove_tag_meta(&["holiday".to_string()]).unwrap(), 1);
+    assert!(meta::get_all_tag_meta().unwrap().is_empty());
+}
+
+#[test]
+fn reading_leaves_old_databases_alone() {
+    let _db = TempDb::new(Backend::Sqlite);
+    drop_meta_table();
+    schema::migrate().unwrap();
+    assert!(has_meta_table());
+
+    // A database that is up to date but missing the table reads as having no metadata, without creating it
+    let conn = Connection::open(".ftag.db").unwrap();
+    conn.execute_batch("DROP TABLE tag_meta").unwrap();
+    assert!(meta::get_all_tag_meta().unwrap().is_empty());
+    assert!(!has_meta_table());
+}
@@ -0,0 +1,52 @@
+//! Turning directories into tags.
+#![cfg(feature = "full")]
+
+mod common;
+
+use camino::Utf8Path;
+use ftag::ftag as core;
+use ftag::migrate::{self, MigrationStep};
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+#[test]
+fn tags_and_flattens() {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("photos/2023/a.jpg");
+    db.touch("photos/b.jpg");
+
+    let steps = migrate::plan_migration(Utf8Path::new("photos"), 2, None, true).unwrap();
+    migrate::apply_migration(steps).unwrap();
+
+    assert!(Utf8Path::new("photos/a.jpg").is_file());
+    assert!(!Utf8Path::new("photos/2023/a.jpg").exists());
+    assert_eq!(sorted(core::get_file_tags(&"photos/a.jpg".into()).unwrap()), ["2023"]);
+}
+
+#[test]
+fn refuses_zero_levels() {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("photos/2023/a.jpg");
+
+    assert!(migrate::plan_migration(Utf8Path::new("photos"), 0, None, false).is_err());
+}
+
+#[test]
+fn bad_tag_changes_nothing() {
+    let db = TempDb::new(Backend::Sqlite);
+    let good = db.touch("good/a.txt");
+    let bad = db.touch("--/b.txt");
+
+    // A directory that normalizes to nothing is caught while planning
+    assert!(migrate::plan_migration(db.dir(), 1, None, true).is_err());
+
+    // And before the first move, even when it comes after a good step
+    let steps = vec![
+        MigrationStep { from: good.clone(), to: "a.txt".into(), tag: "good".to_string() },
+        MigrationStep { from: bad.clone(), to: "b.txt".into(), tag: "--".to_string() },
+    ];
+    assert!(migrate::apply_migration(steps).is_err());
+    assert!(good.is_file() && bad.is_file());
+    assert!(core::get_file_tags(&good).unwrap().is_empty());
+}
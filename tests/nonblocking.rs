@@ -0,0 +1,80 @@
+//! The async versions of the library calls, run on a tokio runtime.
+#![cfg(feature = "async")]
+
+mod common;
+
+use ftag::ftag::{self as core, CaseMode};
+use ftag::nonblocking;
+use ftag::store::{self, Backend};
+
+use common::{sorted, TempDb};
+
+/// Run a future to completion on a runtime with blocking threads.
+fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+#[test]
+fn changes_and_finds_tags() {
+    let db = TempDb::new(Backend::Sqlite);
+    let a = db.touch("a.txt");
+    let b = db.touch("docs/b.txt");
+
+    block_on(async {
+        let tags = nonblocking::add_tags(a.clone(), vec!["red".to_string(), "blue".to_string()]).await.unwrap();
+        assert_eq!(sorted(tags), ["blue", "red"]);
+        nonblocking::add_tags(b.clone(), vec!["red".to_string()]).await.unwrap();
+
+        let tags = nonblocking::remove_tags(a.clone(), vec!["blue".to_string()]).await.unwrap();
+        assert_eq!(sorted(tags), ["red"]);
+
+        let change = nonblocking::set_tags(b.clone(), vec!["green".to_string()]).await.unwrap();
+        assert_eq!(change.added, ["green"]);
+        assert_eq!(change.removed, ["red"]);
+
+        let red = core::parse_queries(&["red".to_string()], CaseMode::Smart, false).unwrap();
+        let found = nonblocking::find_tags(red, vec![], false).await.unwrap();
+        assert_eq!(found, [("a.txt".to_string(), vec!["red".to_string()])]);
+    });
+    assert_eq!(sorted(core::get_file_tags(&b).unwrap()), ["green"]);
+}
+
+#[test]
+fn visits_until_dropped() {
+    let db = TempDb::new(Backend::Sqlite);
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        core::add_tags(&db.touch(name), vec!["photo".to_string()]).unwrap();
+    }
+
+    block_on(async {
+        let mut files = nonblocking::visit_tags(vec![], vec![], false);
+        let mut found = vec![];
+        while let Some(file) = files.recv().await {
+            found.push(file.unwrap().0);
+        }
+        assert_eq!(sorted(found), ["a.txt", "b.txt", "c.txt"]);
+
+        // Dropping the receiver stops the search without an error
+        let mut files = nonblocking::visit_tags(vec![], vec![], false);
+        assert!(files.recv().await.unwrap().is_ok());
+    });
+}
+
+#[test]
+fn missing_files_fail() {
+    let _db = TempDb::new(Backend::Sqlite);
+    let err = block_on(nonblocking::add_tags("missing.txt".into(), vec!["red".to_string()])).unwrap_err();
+    assert!(err.is_not_found());
+}
+
+#[test]
+fn closes_connections_after_each_call() {
+    let db = TempDb::new(Backend::Sqlite);
+    let a = db.touch("a.txt");
+    store::close_connections();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(nonblocking::add_tags(a, vec!["red".to_string()])).unwrap();
+    // The blocking thread is still alive, but SQLite only drops the write-ahead log once nothing has it open
+    assert!(!db.dir().join(".ftag.db-wal").exists());
+}
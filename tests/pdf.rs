@@ -0,0 +1,46 @@
+//! Tagging PDFs by their title, authors, and keywords with `autotag --tagger pdf`.
+#![cfg(feature = "full")]
+
+mod common;
+
+use camino::Utf8Path;
+use std::fs;
+
+use ftag::autotag;
+use ftag::ftag as core;
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+/// A small PDF whose information dictionary has a title, two authors, and keywords written in UTF-16.
+const PAPER: &[u8] = b"%PDF-1.4\n1 0 obj\n<< /Title (Graph \\(theory\\)) /Author (Ada Lovelace and Charles Babbage)\n\
+    /Keywords <FEFF0067007200610070006800730020003B00200074007200650065> >>\nendobj\n\
+    trailer\n<< /Size 2 /Info 1 0 R >>\n%%EOF";
+
+/// Return the sorted tags of `path`.
+fn tags_of(path: &str) -> Vec<String> {
+    sorted(core::get_file_tags(&path.into()).unwrap())
+}
+
+#[test]
+fn tags_pdfs_by_metadata() {
+    let db = TempDb::new(Backend::Sqlite);
+    fs::write(db.touch("papers/graphs.PDF"), PAPER).unwrap();
+    fs::write(db.touch("papers/broken.pdf"), b"%PDF-1.4 not really").unwrap();
+    fs::write(db.touch("papers/encrypted.pdf"), b"1 0 obj << /Title (x) >> endobj trailer << /Info 1 0 R /Encrypt 2 0 R >>").unwrap();
+    fs::write(db.touch("papers/notes.txt"), PAPER).unwrap();
+
+    let taggers = autotag::taggers(&["pdf".to_string()]).unwrap();
+    let changes = autotag::autotag(Utf8Path::new("papers"), &taggers, false).unwrap();
+    let changed: Vec<&str> = changes.iter().map(|change| change.path.as_str()).collect();
+    assert_eq!(changed, ["papers/graphs.PDF"]);
+
+    assert_eq!(tags_of("papers/graphs.PDF"), ["author=Ada Lovelace", "author=Charles Babbage", "graphs", "title=Graph (theory)", "tree"]);
+    for untouched in ["papers/broken.pdf", "papers/encrypted.pdf", "papers/notes.txt"] {
+        assert!(tags_of(untouched).is_empty(), "{} should not be tagged", untouched);
+    }
+
+    // Tagging again changes nothing
+    let changes = autotag::autotag(Utf8Path::new("papers"), &taggers, false).unwrap();
+    assert!(changes.iter().all(|change| change.added.is_empty()));
+}
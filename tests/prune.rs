@@ -0,0 +1,78 @@
+//! Forgetting files that no longer exist.
+#![cfg(feature = "full")]
+
+mod common;
+
+use std::fs;
+
+use ftag::ftag as core;
+use ftag::store::{self, Backend};
+
+use common::TempDb;
+
+/// Return when the database in the current directory was last pruned.
+fn last_prune() -> Option<String> {
+    store::open_store().unwrap().get_setting("last-prune").unwrap()
+}
+
+/// Record a prune `ago` seconds in the past that left `entries` paths behind.
+fn pruned(ago: i64, entries: usize) -> String {
+    let last = (ftag::journal::now() - ago).to_string();
+    let mut store = store::open_store().unwrap();
+    store.set_setting("last-prune", &last).unwrap();
+    store.set_setting("prune-entries", &entries.to_string()).unwrap();
+    last
+}
+
+#[test]
+fn prunes_small_databases_every_time() {
+    let db = TempDb::new(Backend::Sqlite);
+    let kept = db.touch("kept.txt");
+    let gone = db.touch("gone.txt");
+    core::add_tags(&kept, vec!["red".to_string()]).unwrap();
+    core::add_tags(&gone, vec!["red".to_string()]).unwrap();
+
+    // A database that was never pruned is pruned right away, and remembers how many paths it kept
+    core::maybe_prune().unwrap();
+    assert!(last_prune().is_some());
+    assert_eq!(store::open_store().unwrap().get_setting("prune-entries").unwrap().as_deref(), Some("2"));
+
+    // A small one is pruned again even within the hour
+    fs::remove_file(&gone).unwrap();
+    core::maybe_prune().unwrap();
+    assert_eq!(core::tracked_paths().unwrap(), ["kept.txt"]);
+}
+
+#[test]
+fn prunes_large_databases_once_an_hour() {
+    let db = TempDb::new(Backend::Sqlite);
+    let kept = db.touch("kept.txt");
+    let gone = db.touch("gone.txt");
+    core::add_tags(&kept, vec!["red".to_string()]).unwrap();
+    core::add_tags(&gone, vec!["red".to_string()]).unwrap();
+
+    // Past the size limit the deleted file stays tracked until the hour is up
+    pruned(10, 5000);
+    fs::remove_file(&gone).unwrap();
+    core::maybe_prune().unwrap();
+    assert_eq!(core::tracked_paths().unwrap(), ["gone.txt", "kept.txt"]);
+
+    pruned(3600, 5000);
+    core::maybe_prune().unwrap();
+    assert_eq!(core::tracked_paths().unwrap(), ["kept.txt"]);
+}
+
+#[test]
+fn prune_without_changes_writes_nothing() {
+    let db = TempDb::new(Backend::Sqlite);
+    core::add_tags(&db.touch("kept.txt"), vec!["red".to_string()]).unwrap();
+
+    let recent = pruned(10, 1);
+    core::prune_db().unwrap();
+    assert_eq!(last_prune(), Some(recent.clone()));
+
+    // A stored count that no longer matches is updated, even with nothing to remove
+    core::add_tags(&db.touch("new.txt"), vec!["red".to_string()]).unwrap();
+    core::prune_db().unwrap();
+    assert_eq!(store::open_store().unwrap().get_setting("prune-entries").unwrap().as_deref(), Some("2"));
+}
@@ -0,0 +1,65 @@
+//! Searching with saved queries that combine tags with `and`, `or`, and `not`.
+#![cfg(feature = "full")]
+
+mod common;
+
+use ftag::ftag::{self as core, CaseMode};
+use ftag::query;
+use ftag::store::Backend;
+
+use common::TempDb;
+
+/// Tag a new file in the current directory with each of `tags`.
+fn tagged(db: &TempDb, name: &str, tags: &[&str]) {
+    core::add_tags(&db.touch(name), tags.iter().map(|tag| tag.to_string()).collect()).unwrap();
+}
+
+/// Return the sorted paths of the files matching `text`.
+fn matching(text: &str) -> Vec<String> {
+    let mut paths: Vec<String> = query::find_matching(&query::parse(text).unwrap(), CaseMode::Smart)
+        .unwrap()
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn combines_tags() {
+    let db = TempDb::new(Backend::Sqlite);
+    tagged(&db, "plan.txt", &["todo", "project/ftag"]);
+    tagged(&db, "notes.txt", &["todo", "done"]);
+    tagged(&db, "old.txt", &["archived", "project/other"]);
+    tagged(&db, "not.txt", &["not"]);
+
+    assert_eq!(matching("todo and not done"), ["plan.txt"]);
+    assert_eq!(matching("todo done or archived"), ["notes.txt", "old.txt"]);
+    assert_eq!(matching("project and not (done or archived)"), ["plan.txt"]);
+    assert_eq!(matching("\"not\""), ["not.txt"]);
+    assert_eq!(matching("proj*/ftag or done"), ["notes.txt", "plan.txt"]);
+
+    for broken in ["", "a and", "(a or b", "a )", "\"a"] {
+        assert!(query::parse(broken).is_err(), "{:?} should not parse", broken);
+    }
+}
+
+#[test]
+fn saves_and_deletes_queries() {
+    let _db = TempDb::new(Backend::Sqlite);
+    query::save_query("open", "todo and not done").unwrap();
+    query::save_query("old", "archived").unwrap();
+    query::save_query("old", "archived or done").unwrap();
+    assert!(query::save_query("broken", "todo and").is_err());
+
+    assert_eq!(query::get_query("old").unwrap(), "archived or done");
+    assert_eq!(
+        query::all_queries().unwrap(),
+        [("old".to_string(), "archived or done".to_string()), ("open".to_string(), "todo and not done".to_string())]
+    );
+
+    query::delete_query("old").unwrap();
+    assert!(query::get_query("old").is_err());
+    assert!(query::delete_query("old").is_err());
+    assert_eq!(query::all_queries().unwrap().len(), 1);
+}
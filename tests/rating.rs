@@ -0,0 +1,51 @@
+//! Rating files from 1 to 5 stars and finding them by rating.
+#![cfg(feature = "full")]
+
+mod common;
+
+use ftag::ftag as core;
+use ftag::rating::{self, RatingFilter};
+use ftag::store::Backend;
+
+use common::TempDb;
+
+#[test]
+fn rates_and_unrates() {
+    let db = TempDb::new(Backend::Sqlite);
+    db.touch("a.jpg");
+
+    rating::set_rating("a.jpg", 3).unwrap();
+    rating::set_rating("a.jpg", 5).unwrap();
+    assert_eq!(rating::get_rating("a.jpg").unwrap(), Some(5));
+    assert!(rating::set_rating("a.jpg", 6).is_err());
+    assert_eq!(rating::get_rating("a.jpg").unwrap(), Some(5));
+
+    rating::set_rating("a.jpg", 0).unwrap();
+    assert_eq!(rating::get_rating("a.jpg").unwrap(), None);
+    assert!(rating::all_ratings().unwrap().is_empty());
+}
+
+#[test]
+fn filters_found_files() {
+    let db = TempDb::new(Backend::Sqlite);
+    for (name, stars) in [("one.jpg", 1), ("four.jpg", 4), ("five.jpg", 5), ("unrated.jpg", 0)] {
+        core::add_tags(&db.touch(name), vec!["photo".to_string()]).unwrap();
+        rating::set_rating(name, stars).unwrap();
+    }
+
+    let rated = |filter: &str| {
+        let files = core::find_tags(&[], &[], false).unwrap();
+        let mut paths: Vec<String> = rating::filter_rated(files, filter.parse::<RatingFilter>().unwrap()).unwrap().into_iter().map(|(path, _)| path).collect();
+        paths.sort();
+        paths
+    };
+    assert_eq!(rated(">=4"), ["five.jpg", "four.jpg"]);
+    assert_eq!(rated("4"), ["four.jpg"]);
+    // Unrated files never match, even a search for anything but 1 star
+    assert_eq!(rated("!=1"), ["five.jpg", "four.jpg"]);
+    assert_eq!(rated("< 2"), ["one.jpg"]);
+
+    for broken in ["0", "6", ">=", "four"] {
+        assert!(broken.parse::<RatingFilter>().is_err(), "{:?} should not parse", broken);
+    }
+}
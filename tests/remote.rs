@@ -0,0 +1,66 @@
+//! Running commands against a database on another machine, with a stand-in for `ssh` that runs them here.
+#![cfg(all(feature = "full", feature = "cli", unix))]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output};
+
+use ftag::ftag as core;
+use ftag::remote::REMOTE_COMMAND_VAR;
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+/// Put an `ssh` in the database directory that logs the host it was given and runs the command locally.
+fn fake_ssh(db: &TempDb) {
+    let ssh = db.touch("bin/ssh");
+    fs::write(&ssh, "#!/bin/sh\n[ \"$1\" = \"--\" ] && shift\necho \"$1\" >> \"$0.log\"\nexec sh -c \"$2\"\n").unwrap();
+    fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Run ftag from outside the database with `args`, finding `ssh` in the database directory first.
+fn ftag(db: &TempDb, args: &[&str]) -> Output {
+    let path = format!("{}:{}", db.dir().join("bin"), std::env::var("PATH").unwrap_or_default());
+    Command::new(env!("CARGO_BIN_EXE_ftag"))
+        .args(args)
+        .env("PATH", path)
+        .env(REMOTE_COMMAND_VAR, env!("CARGO_BIN_EXE_ftag"))
+        .current_dir(std::env::temp_dir())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn runs_commands_remotely() {
+    let db = TempDb::new(Backend::Sqlite);
+    fake_ssh(&db);
+    let photo = db.touch("it's here.jpg");
+    let remote = format!("user@nas:{}", db.dir());
+
+    let output = ftag(&db, &["--remote", &remote, "--color=always", "add", "it's here.jpg", "red", "it's blue"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(sorted(core::get_file_tags(&photo).unwrap()), ["it's blue", "red"]);
+    assert_eq!(fs::read_to_string(db.dir().join("bin/ssh.log")).unwrap(), "user@nas\n");
+
+    // The exit status of the command on the other machine comes back
+    let output = ftag(&db, &[&format!("--remote={}", remote), "add", "missing.jpg", "red"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn refuses_when_restricted() {
+    let db = TempDb::new(Backend::Sqlite);
+    fake_ssh(&db);
+    let photo = db.touch("a.jpg");
+    let remote = format!("nas:{}", db.dir());
+
+    for restriction in ["--no-exec", "--no-network"] {
+        let output = ftag(&db, &[restriction, "--remote", &remote, "add", "a.jpg", "red"]);
+        assert!(!output.status.success());
+    }
+    assert_eq!(ftag(&db, &["--remote", "-oProxyCommand=x:/srv", "list"]).status.code(), Some(2));
+    assert!(core::get_file_tags(&photo).unwrap().is_empty());
+    assert!(!db.dir().join("bin/ssh.log").exists());
+}
@@ -0,0 +1,30 @@
+//! Keeping tags in each kind of store.
+#![cfg(feature = "full")]
+
+mod common;
+
+use ftag::ftag as core;
+use ftag::store::Backend;
+
+use common::{sorted, TempDb};
+
+#[test]
+fn json_store_keeps_long_paths() {
+    let db = TempDb::new(Backend::Json);
+    // Percent-encoding the slashes makes this one name far longer than the filesystem allows
+    let long = db.touch(&format!("{}/{}/{}.txt", "a".repeat(100), "b".repeat(100), "c".repeat(120)));
+    let other = db.touch(&format!("{}/{}/{}.txt", "a".repeat(100), "b".repeat(100), "d".repeat(120)));
+    assert!(long.as_str().len() > 300);
+
+    core::add_tags(&long, vec!["deep".to_string(), "long".to_string()]).unwrap();
+    core::add_tags(&other, vec!["other".to_string()]).unwrap();
+    assert_eq!(sorted(core::get_file_tags(&long).unwrap()), ["deep", "long"]);
+    assert_eq!(sorted(core::get_file_tags(&other).unwrap()), ["other"]);
+
+    let found = core::find_tags(&core::parse_queries(&["deep".to_string()], core::CaseMode::Smart, false).unwrap(), &[], false).unwrap();
+    assert_eq!(found, [(long.to_string(), vec!["deep".to_string(), "long".to_string()])]);
+
+    core::remove_tags(&long, vec!["deep".to_string(), "long".to_string()]).unwrap();
+    assert!(core::get_file_tags(&long).unwrap().is_empty());
+    assert_eq!(sorted(core::get_file_tags(&other).unwrap()), ["other"]);
+}
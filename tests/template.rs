@@ -0,0 +1,53 @@
+//! Printing the results of find and list with --format templates.
+#![cfg(all(feature = "full", feature = "cli"))]
+
+mod common;
+
+use std::process::Command;
+
+use ftag::ftag as core;
+use ftag::store::Backend;
+
+use common::TempDb;
+
+/// Run ftag in the database directory, returning what it printed, or what went wrong if it failed.
+fn ftag(args: &[&str]) -> Result<String, String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_ftag")).args(args).output().unwrap();
+    match output.status.success() {
+        true => Ok(String::from_utf8(output.stdout).unwrap()),
+        false => Err(String::from_utf8(output.stderr).unwrap()),
+    }
+}
+
+/// Make a database with two photos tagged differently.
+fn photos() -> TempDb {
+    let db = TempDb::new(Backend::Sqlite);
+    core::add_tags(&db.touch("a.jpg"), vec!["red".to_string(), "place/zion".to_string()]).unwrap();
+    core::add_tags(&db.touch("b.jpg"), vec!["red".to_string()]).unwrap();
+    db
+}
+
+#[test]
+fn formats_found_files() {
+    let _db = photos();
+    assert_eq!(ftag(&["find", "red", "--format", r"{path}\t{tags}"]).unwrap(), "a.jpg\tplace/zion; red\nb.jpg\tred\n");
+    assert_eq!(ftag(&["find", "red", "--format", "{path}: {tags:,} ({count})"]).unwrap(), "a.jpg: place/zion,red (2)\nb.jpg: red (1)\n");
+    assert_eq!(ftag(&["find", "place", "--format", "{{{path}}}"]).unwrap(), "{a.jpg}\n");
+
+    // The modification time is filled in from the file itself
+    let mtime = ftag(&["find", "place", "--format", "{mtime}"]).unwrap();
+    assert!(mtime.trim().len() >= "2024-01-01".len(), "{:?}", mtime);
+}
+
+#[test]
+fn formats_lists() {
+    let _db = photos();
+    assert_eq!(ftag(&["list", "--format", "{tag}={count}"]).unwrap(), "place/zion=1\nred=2\n");
+    assert_eq!(ftag(&["list", "a.jpg", "--format", r"{path}:{tags:\n}"]).unwrap(), "a.jpg:place/zion\nred\n");
+
+    // Placeholders a command can't fill in are refused before anything is printed
+    assert!(ftag(&["list", "--format", "{path}"]).is_err());
+    for broken in ["{nope}", "{path", "path}", "{path:,}", r"\q"] {
+        assert!(ftag(&["find", "red", "--format", broken]).is_err(), "{:?} should be refused", broken);
+    }
+}